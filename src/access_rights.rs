@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{BackupConfig, ResticConfig};
+
+/// restic versions the client relies on to store extended attributes and ACLs: POSIX ACLs are
+/// extended attributes on Unix, Windows security descriptors came with restic 0.17
+const MIN_VERSION_UNIX: (u32, u32, u32) = (0, 10, 0);
+const MIN_VERSION_WINDOWS: (u32, u32, u32) = (0, 17, 0);
+
+/// Extended attributes holding POSIX ACLs
+const ACL_ATTRIBUTES: &[&str] = &["system.posix_acl_access", "system.posix_acl_default"];
+
+/// What the job's files carry besides owner and mode, as found after a backup
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AccessRightsSummary {
+    /// Files and directories looked at
+    pub entries: usize,
+    pub with_xattrs: usize,
+    pub with_acls: usize,
+    /// Entries per attribute namespace, e.g. "user" or "security"
+    pub namespaces: BTreeMap<String, usize>,
+    /// Entries whose attributes couldn't be listed
+    pub unreadable: usize,
+    pub restic_version: Option<String>,
+    /// What could keep permissions from surviving a restore
+    pub notes: Vec<String>,
+}
+
+impl AccessRightsSummary {
+    pub fn describe(&self) -> String {
+        let mut out = format!(
+            "Access rights: {} entries, {} with ACLs, {} with extended attributes",
+            self.entries, self.with_acls, self.with_xattrs
+        );
+        if !self.namespaces.is_empty() {
+            let namespaces: Vec<String> = self.namespaces.iter().map(|(namespace, count)| format!("{}: {}", namespace, count)).collect();
+            out.push_str(&format!(" ({})", namespaces.join(", ")));
+        }
+        out
+    }
+}
+
+/// The installed restic's version, e.g. (0, 17, 3)
+pub async fn restic_version(restic_config: &ResticConfig) -> Option<(u32, u32, u32)> {
+    let output = tokio::process::Command::new(restic_config.program()).arg("version").output().await.ok()?;
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// "restic 0.17.3 compiled with go1.22.5 on linux/amd64"
+fn parse_version(text: &str) -> Option<(u32, u32, u32)> {
+    let version = text.split_whitespace().nth(1)?;
+    let mut parts = version.split(['.', '-']).map(|part| part.parse::<u32>().ok());
+    Some((parts.next()??, parts.next()??, parts.next().flatten().unwrap_or(0)))
+}
+
+/// Refuse to back up a job with `access_rights` using a restic too old to store them
+pub async fn check(job: &BackupConfig, restic_config: &ResticConfig) -> Result<()> {
+    if !job.access_rights {
+        return Ok(());
+    }
+    let minimum = if cfg!(windows) { MIN_VERSION_WINDOWS } else { MIN_VERSION_UNIX };
+    match restic_version(restic_config).await {
+        Some(version) if version < minimum => Err(anyhow::anyhow!(
+            "Job '{}' keeps extended attributes and ACLs, which needs restic {}.{}.{} or newer; this is {}.{}.{}",
+            job.name,
+            minimum.0,
+            minimum.1,
+            minimum.2,
+            version.0,
+            version.1,
+            version.2
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Names of the extended attributes of a path, not following symlinks; None if they can't be listed
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn xattr_names(path: &Path) -> Option<Vec<String>> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    #[cfg(target_os = "linux")]
+    let list = |buffer: *mut libc::c_char, size: usize| unsafe { libc::llistxattr(c_path.as_ptr(), buffer, size) };
+    #[cfg(target_os = "macos")]
+    let list = |buffer: *mut libc::c_char, size: usize| unsafe { libc::listxattr(c_path.as_ptr(), buffer, size, libc::XATTR_NOFOLLOW) };
+
+    let size = list(std::ptr::null_mut(), 0);
+    if size < 0 {
+        return None;
+    }
+    let mut buffer = vec![0u8; size as usize];
+    let size = list(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len());
+    if size < 0 {
+        return None;
+    }
+    buffer.truncate(size as usize);
+    Some(
+        buffer
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .collect(),
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn xattr_names(_path: &Path) -> Option<Vec<String>> {
+    None
+}
+
+/// Walk the job's directories, skipping excludes and not following symlinks, and count what
+/// carries extended attributes and ACLs
+pub fn summarize(job: &BackupConfig, restic_version: Option<(u32, u32, u32)>) -> AccessRightsSummary {
+    let mut summary = AccessRightsSummary {
+        restic_version: restic_version.map(|(major, minor, patch)| format!("{}.{}.{}", major, minor, patch)),
+        ..Default::default()
+    };
+    let excludes = crate::ignore_files::exclude_patterns(job);
+    let mut stack: Vec<PathBuf> = job.effective_directories().iter().map(|p| job.resolve_path(p)).collect();
+    let supported = cfg!(any(target_os = "linux", target_os = "macos"));
+
+    while let Some(path) = stack.pop() {
+        if crate::ignore_files::is_excluded(&excludes, &path) {
+            continue;
+        }
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else { continue };
+        if metadata.is_dir() {
+            if let Ok(entries) = std::fs::read_dir(&path) {
+                stack.extend(entries.flatten().map(|entry| entry.path()));
+            }
+        }
+        summary.entries += 1;
+        if !supported {
+            continue;
+        }
+        let Some(names) = xattr_names(&path) else {
+            summary.unreadable += 1;
+            continue;
+        };
+        if names.iter().any(|name| ACL_ATTRIBUTES.contains(&name.as_str())) {
+            summary.with_acls += 1;
+        }
+        if names.iter().any(|name| !ACL_ATTRIBUTES.contains(&name.as_str())) {
+            summary.with_xattrs += 1;
+        }
+        let mut namespaces: Vec<&str> = names
+            .iter()
+            .filter(|name| !ACL_ATTRIBUTES.contains(&name.as_str()))
+            .map(|name| name.split('.').next().unwrap_or(name))
+            .collect();
+        namespaces.sort();
+        namespaces.dedup();
+        for namespace in namespaces {
+            *summary.namespaces.entry(namespace.to_string()).or_default() += 1;
+        }
+    }
+
+    if !supported {
+        summary.notes.push(if cfg!(windows) {
+            "Security descriptors are backed up by restic 0.17 and newer when it runs as an administrator".to_string()
+        } else {
+            "Extended attributes can't be listed on this platform".to_string()
+        });
+    }
+    if !crate::permissions::is_root() {
+        summary.notes.push("Not running as root: trusted.* attributes can't be read, and restoring ownership, ACLs and security.* attributes needs root".to_string());
+    }
+    if summary.unreadable > 0 {
+        summary.notes.push(format!("The attributes of {} entries couldn't be read", summary.unreadable));
+    }
+    summary
+}
@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, pbkdf2};
+use serde::{Deserialize, Serialize};
+
+/// PBKDF2 rounds for new password hashes; stored hashes carry their own count
+const ITERATIONS: u32 = 100_000;
+const HASH_PREFIX: &str = "pbkdf2-sha256";
+
+/// What a web UI user may do. Each role can do everything the ones before it can.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Status, snapshots, logs and reports
+    Viewer,
+    /// Also trigger backups and restores, and pin snapshots
+    Operator,
+    /// Also edit the config and run destructive operations
+    Admin,
+}
+
+/// A web UI login
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UserAccount {
+    pub name: String,
+    pub role: Role,
+    /// From `better_restic_client hash-password`
+    pub password_hash: String,
+}
+
+/// The user a request was authenticated as
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+    pub name: String,
+    pub role: Role,
+}
+
+/// `pbkdf2-sha256$<iterations>$<salt>$<hash>` for a new random salt
+pub fn hash_password(password: &str) -> Result<String> {
+    let mut salt = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("No random numbers available for the salt"))?;
+    let mut hash = [0u8; digest::SHA256_OUTPUT_LEN];
+    let iterations = NonZeroU32::new(ITERATIONS).expect("non-zero");
+    pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, password.as_bytes(), &mut hash);
+    Ok(format!("{}${}${}${}", HASH_PREFIX, ITERATIONS, STANDARD.encode(salt), STANDARD.encode(hash)))
+}
+
+/// Split a stored hash into iterations, salt and hash
+fn parse_hash(stored: &str) -> Option<(NonZeroU32, Vec<u8>, Vec<u8>)> {
+    let mut parts = stored.split('$');
+    if parts.next()? != HASH_PREFIX {
+        return None;
+    }
+    let iterations = NonZeroU32::new(parts.next()?.parse().ok()?)?;
+    let salt = STANDARD.decode(parts.next()?).ok()?;
+    let hash = STANDARD.decode(parts.next()?).ok()?;
+    parts.next().is_none().then_some((iterations, salt, hash))
+}
+
+pub fn is_valid_hash(stored: &str) -> bool {
+    parse_hash(stored).is_some()
+}
+
+/// Compare in constant time against a stored hash
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    match parse_hash(stored) {
+        Some((iterations, salt, hash)) => {
+            pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, password.as_bytes(), &hash).is_ok()
+        }
+        None => false,
+    }
+}
+
+/// User name and password from an `Authorization: Basic ...` header value
+pub fn parse_basic_auth(header: &str) -> Option<(String, String)> {
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (name, password) = decoded.split_once(':')?;
+    Some((name.to_string(), password.to_string()))
+}
+
+/// Credentials that were already verified, so the browser's Basic auth on every request doesn't
+/// pay for PBKDF2 each time. Keyed by the header and the stored hash, so a password change takes effect.
+#[derive(Clone, Default)]
+pub struct AuthCache {
+    verified: Arc<Mutex<HashSet<Vec<u8>>>>,
+}
+
+impl AuthCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn key(header: &str, stored: &str) -> Vec<u8> {
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(header.as_bytes());
+        context.update(b"\0");
+        context.update(stored.as_bytes());
+        context.finish().as_ref().to_vec()
+    }
+
+    /// The account the Basic auth header belongs to, if its password is right
+    pub fn authenticate(&self, users: &[UserAccount], header: &str) -> Option<CurrentUser> {
+        let (name, password) = parse_basic_auth(header)?;
+        let account = users.iter().find(|user| user.name == name)?;
+        let key = Self::key(header, &account.password_hash);
+        let cached = self.verified.lock().unwrap().contains(&key);
+        if !cached {
+            if !verify_password(&password, &account.password_hash) {
+                return None;
+            }
+            self.verified.lock().unwrap().insert(key);
+        }
+        Some(CurrentUser {
+            name: account.name.clone(),
+            role: account.role,
+        })
+    }
+}
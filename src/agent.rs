@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{audit, reports, Config};
+
+/// Reports kept per agent on the server
+const RUNS_PER_AGENT: usize = 20;
+/// Reports sent on an agent's first check-in, so the dashboard isn't empty after a restart
+const INITIAL_REPORTS: usize = 5;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AgentConfig {
+    /// URL of the central web UI, e.g. `https://backups.example.com`
+    pub server: String,
+    /// API token with the `agent` scope
+    pub token: String,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+/// What an agent sends to the server on every check-in
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CheckIn {
+    pub hostname: String,
+    pub version: String,
+    pub repository: String,
+    pub jobs: Vec<String>,
+    pub interval_secs: u64,
+    /// Runs finished since the last check-in
+    #[serde(default)]
+    pub runs: Vec<reports::RunReport>,
+}
+
+/// The server's answer: jobs triggered from the dashboard since the last check-in
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct CheckInResponse {
+    pub jobs: Vec<String>,
+}
+
+/// The server's view of one agent
+#[derive(Debug, Serialize, Clone)]
+pub struct AgentStatus {
+    pub hostname: String,
+    pub version: String,
+    pub repository: String,
+    pub jobs: Vec<String>,
+    pub last_seen: DateTime<Local>,
+    /// Whether it checked in within three intervals
+    pub online: bool,
+    /// Jobs waiting for the next check-in
+    pub pending: Vec<String>,
+    /// Latest runs, newest first
+    pub runs: Vec<reports::RunReport>,
+    #[serde(skip)]
+    interval_secs: u64,
+}
+
+/// Agents known to the server since it started
+#[derive(Clone, Default)]
+pub struct AgentRegistry {
+    agents: Arc<Mutex<BTreeMap<String, AgentStatus>>>,
+}
+
+impl AgentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a check-in and hand over the agent's pending jobs
+    pub fn check_in(&self, check_in: CheckIn) -> CheckInResponse {
+        let mut agents = self.agents.lock().unwrap();
+        let agent = agents.entry(check_in.hostname.clone()).or_insert_with(|| AgentStatus {
+            hostname: check_in.hostname.clone(),
+            version: String::new(),
+            repository: String::new(),
+            jobs: Vec::new(),
+            last_seen: Local::now(),
+            online: true,
+            pending: Vec::new(),
+            runs: Vec::new(),
+            interval_secs: check_in.interval_secs,
+        });
+        agent.version = check_in.version;
+        agent.repository = check_in.repository;
+        agent.jobs = check_in.jobs;
+        agent.interval_secs = check_in.interval_secs;
+        agent.last_seen = Local::now();
+        for run in check_in.runs {
+            if !agent.runs.iter().any(|known| known.run_id == run.run_id && known.job == run.job) {
+                agent.runs.push(run);
+            }
+        }
+        agent.runs.sort_by_key(|run| std::cmp::Reverse(run.finished_at));
+        agent.runs.truncate(RUNS_PER_AGENT);
+        CheckInResponse {
+            jobs: std::mem::take(&mut agent.pending),
+        }
+    }
+
+    /// Queue a job (or all of the agent's jobs) for its next check-in.
+    /// Returns the queued job names, or None for an unknown agent or job.
+    pub fn trigger(&self, hostname: &str, job: Option<&str>) -> Option<Vec<String>> {
+        let mut agents = self.agents.lock().unwrap();
+        let agent = agents.get_mut(hostname)?;
+        let jobs = match job {
+            Some(job) if agent.jobs.iter().any(|known| known == job) => vec![job.to_string()],
+            Some(_) => return None,
+            None => agent.jobs.clone(),
+        };
+        for job in &jobs {
+            if !agent.pending.contains(job) {
+                agent.pending.push(job.clone());
+            }
+        }
+        Some(jobs)
+    }
+
+    pub fn list(&self) -> Vec<AgentStatus> {
+        let now = Local::now();
+        self.agents
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .map(|mut agent| {
+                agent.online = (now - agent.last_seen).num_seconds() < 3 * agent.interval_secs.max(1) as i64;
+                agent
+            })
+            .collect()
+    }
+}
+
+/// Reports finished after `since`, or the latest few on the first check-in
+fn new_reports(config: &Config, since: Option<DateTime<Local>>) -> Vec<reports::RunReport> {
+    let Some(ref reports_config) = config.reports else {
+        return Vec::new();
+    };
+    let all = reports::list(&crate::expand_tilde(&reports_config.directory));
+    match since {
+        Some(since) => all.into_iter().filter(|report| report.finished_at > since).collect(),
+        None => all.into_iter().take(INITIAL_REPORTS).collect(),
+    }
+}
+
+async fn check_in(client: &reqwest::Client, agent_config: &AgentConfig, body: &CheckIn) -> Result<CheckInResponse> {
+    let url = format!("{}/api/agents/checkin", agent_config.server.trim_end_matches('/'));
+    let response = client.post(&url).bearer_auth(&agent_config.token).json(body).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("{} answered {}", url, response.status()));
+    }
+    Ok(response.json().await?)
+}
+
+/// Check in with the central server every interval, reporting finished runs and running the jobs it hands back
+pub async fn run(config: &Config, dry_run: bool, verbose: bool) -> Result<()> {
+    let agent_config = config
+        .agent
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Agent mode needs an `agent` section with the server URL and token"))?;
+    if config.reports.is_none() {
+        warn!("No `reports` directory configured; the server won't see run results");
+    }
+    info!("Agent reporting to {} every {}s", agent_config.server, agent_config.interval_secs);
+
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?;
+    let mut reported_until: Option<DateTime<Local>> = None;
+    loop {
+        let checked_at = Local::now();
+        let body = CheckIn {
+            hostname: crate::hostname(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            repository: config.restic.repository_url(),
+            jobs: config.jobs().iter().map(|job| job.name.clone()).collect(),
+            interval_secs: agent_config.interval_secs,
+            runs: new_reports(config, reported_until),
+        };
+        let triggered = match check_in(&client, agent_config, &body).await {
+            Ok(response) => {
+                reported_until = Some(checked_at);
+                response.jobs
+            }
+            Err(e) => {
+                error!("Check-in with {} failed: {}", agent_config.server, e);
+                Vec::new()
+            }
+        };
+
+        let mut selected = Vec::new();
+        for name in &triggered {
+            match config.find_job(name) {
+                Some(job) => selected.push(job),
+                None => warn!("Server triggered unknown job '{}'", name),
+            }
+        }
+        let batch = crate::dependencies::order(&selected);
+        let mut failed = Vec::new();
+        for &job in &batch {
+            let name = &job.name;
+            if let Some(reason) = crate::dependencies::unmet(config, job, &batch, &failed) {
+                error!("Skipping job '{}': {}", name, reason);
+                failed.push(name.clone());
+                continue;
+            }
+            if let Some(reason) = crate::conditions::unmet(job).await {
+                info!("Skipping job '{}': {}", name, reason);
+                continue;
+            }
+            if let Some(reason) = crate::window::defer(config, job, dry_run) {
+                info!("Deferring job '{}' until its next run: {}", name, reason);
+                continue;
+            }
+            info!("Running job '{}' triggered by the server", name);
+            let result = crate::run_backup(config, job, dry_run, verbose, None).await;
+            if !dry_run {
+                let entry = audit::AuditEntry::new(audit::Source::Agent, Some(agent_config.server.clone()), "backup", Some(name.clone()));
+                audit::record(config, &entry.outcome(&result));
+            }
+            if let Err(e) = result {
+                error!("Job '{}' failed: {}", name, e);
+                failed.push(name.clone());
+            }
+        }
+
+        // Report triggered runs right away instead of an interval later
+        if triggered.is_empty() {
+            tokio::time::sleep(Duration::from_secs(agent_config.interval_secs.max(1))).await;
+        }
+    }
+}
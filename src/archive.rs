@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::Result;
+use log::info;
+use tokio::process::{Child, ChildStdout, Command};
+
+use crate::{mount, pins, restic, Config, ResticConfig};
+
+/// Archive formats `export SNAPSHOT PATH` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    /// restic's tar, compressed by `gzip`
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "tar" => Ok(ArchiveFormat::Tar),
+            "tar.gz" | "tgz" => Ok(ArchiveFormat::TarGz),
+            "zip" => Ok(ArchiveFormat::Zip),
+            other => Err(anyhow::anyhow!("Unknown archive format '{}'; supported: tar, tar.gz, zip", other)),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+
+    /// restic's `--archive` value
+    fn restic_archive(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            _ => "tar",
+        }
+    }
+}
+
+/// Default file name for an archive of `path`, e.g. "project-4f2a9c1e.tar.gz"
+pub fn file_name(snapshot_id: &str, path: &str, format: ArchiveFormat) -> String {
+    let name = Path::new(path).file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_else(|| "snapshot".to_string());
+    format!("{}-{}.{}", name, restic::short_id(snapshot_id), format.extension())
+}
+
+/// A running `restic dump`, piped through gzip for tar.gz
+pub struct Dump {
+    restic: Child,
+    gzip: Option<Child>,
+}
+
+impl Dump {
+    /// Start dumping `path` of the snapshot as an archive to `output`
+    pub fn spawn(restic_config: &ResticConfig, snapshot_id: &str, path: &str, format: ArchiveFormat, output: Stdio) -> Result<Dump> {
+        let mut cmd = crate::restic_command(restic_config, "dump");
+        cmd.arg("--archive").arg(format.restic_archive()).arg(snapshot_id).arg(path);
+        info!("Restic command: {:?}", cmd);
+        // A download the client gave up on stops the dump
+        cmd.stderr(Stdio::piped()).kill_on_drop(true);
+        if format != ArchiveFormat::TarGz {
+            let restic = cmd.stdout(output).spawn().map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+            return Ok(Dump { restic, gzip: None });
+        }
+
+        let mut restic = cmd.stdout(Stdio::piped()).spawn().map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+        let tar: Stdio = restic.stdout.take().expect("stdout is piped").try_into()?;
+        let gzip = Command::new("gzip")
+            .arg("-c")
+            .stdin(tar)
+            .stdout(output)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to run gzip for tar.gz, is it installed? ({})", e))?;
+        Ok(Dump { restic, gzip: Some(gzip) })
+    }
+
+    /// The archive, when spawned with piped output
+    pub fn stdout(&mut self) -> Option<ChildStdout> {
+        match self.gzip {
+            Some(ref mut gzip) => gzip.stdout.take(),
+            None => self.restic.stdout.take(),
+        }
+    }
+
+    /// Whether restic has already exited with an error; gzip still writes an empty archive then
+    pub fn failed(&mut self) -> bool {
+        matches!(self.restic.try_wait(), Ok(Some(status)) if !status.success())
+    }
+
+    /// Wait for the dump to finish and report whether it worked
+    pub async fn wait(self) -> Result<()> {
+        let output = self.restic.wait_with_output().await?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("restic dump failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        if let Some(mut gzip) = self.gzip {
+            let status = gzip.wait().await?;
+            if !status.success() {
+                return Err(anyhow::anyhow!("gzip failed ({})", status));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write the archive to `output`, replacing it only once the dump has succeeded
+async fn write(restic_config: &ResticConfig, snapshot_id: &str, path: &str, format: ArchiveFormat, output: &Path) -> Result<u64> {
+    let mut partial = output.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let file = std::fs::File::create(&partial).map_err(|e| anyhow::anyhow!("Failed to create {:?}: {}", partial, e))?;
+    let result = match Dump::spawn(restic_config, snapshot_id, path, format, file.into()) {
+        Ok(dump) => dump.wait().await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    std::fs::rename(&partial, output)?;
+    Ok(std::fs::metadata(output)?.len())
+}
+
+/// `export SNAPSHOT PATH [--format tar|tar.gz|zip] [--output FILE]`: write a subtree of a snapshot as an
+/// archive, by default to `<name>-<snapshot>.<format>` in the current directory, or to stdout with `--output -`
+pub async fn run(config: &Config, args: &[String]) -> Result<()> {
+    let positional = crate::positional_args(&args[2..]);
+    let (Some(snapshot_id), Some(path)) = (positional.first(), positional.get(1)) else {
+        return Err(anyhow::anyhow!("Usage: export SNAPSHOT PATH [--format tar|tar.gz|zip] [--output FILE]"));
+    };
+    if !pins::is_valid_snapshot_id(snapshot_id) {
+        return Err(anyhow::anyhow!("Invalid snapshot id '{}'", snapshot_id));
+    }
+    if !path.starts_with('/') {
+        return Err(anyhow::anyhow!("The path must be absolute, as in the snapshot, e.g. /home/me/project"));
+    }
+    let format = ArchiveFormat::parse(crate::flag_value(args, "--format").map(String::as_str).unwrap_or("tar.gz"))?;
+
+    let mounted = match config.restic.mount {
+        Some(ref mount_config) => mount::ensure_mounted(mount_config).await?,
+        None => false,
+    };
+    let result = match crate::flag_value(args, "--output").map(String::as_str) {
+        Some("-") => match Dump::spawn(&config.restic, snapshot_id, path, format, Stdio::inherit()) {
+            Ok(dump) => dump.wait().await,
+            Err(e) => Err(e),
+        },
+        output => {
+            let output = output.map(|output| crate::expand_tilde(Path::new(output))).unwrap_or_else(|| PathBuf::from(file_name(snapshot_id, path, format)));
+            write(&config.restic, snapshot_id, path, format, &output)
+                .await
+                .map(|size| println!("Wrote {} of {} to {:?}", restic::format_bytes(size), path, output))
+        }
+    };
+    if mounted {
+        if let Some(ref mount_config) = config.restic.mount {
+            mount::unmount(mount_config).await;
+        }
+    }
+    result
+}
@@ -0,0 +1,132 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::Config;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuditConfig {
+    /// JSON lines file; defaults to `audit.jsonl` in the log directory
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    /// Header an authenticating reverse proxy puts the user name in, e.g. `Remote-User`. Only trusted
+    /// without configured users, and only when set, since any client can send it.
+    #[serde(default)]
+    pub trusted_proxy_header: Option<String>,
+}
+
+/// Where an action came from
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Cli,
+    Web,
+    Schedule,
+    /// Run by `agent` on behalf of the central server
+    Agent,
+}
+
+impl Source {
+    /// `schedule` when started by a systemd timer (systemd sets `INVOCATION_ID` for its units), `cli` otherwise
+    pub fn current_process() -> Source {
+        if std::env::var_os("INVOCATION_ID").is_some() {
+            Source::Schedule
+        } else {
+            Source::Cli
+        }
+    }
+}
+
+/// One state-changing action
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Local>,
+    pub source: Source,
+    /// The OS user on the command line; in the web UI whoever the request was authenticated as, if known
+    pub user: Option<String>,
+    /// e.g. "backup", "restore", "maintain", "config.update"
+    pub action: String,
+    /// What the action was applied to: a job, snapshot, or config version
+    pub target: Option<String>,
+    pub success: bool,
+    /// Error message of a failed action
+    pub detail: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn new(source: Source, user: Option<String>, action: &str, target: Option<String>) -> Self {
+        AuditEntry {
+            timestamp: Local::now(),
+            source,
+            user,
+            action: action.to_string(),
+            target,
+            success: true,
+            detail: None,
+        }
+    }
+
+    /// An action taken by this process on behalf of the user running it
+    pub fn cli(action: &str, target: Option<String>) -> Self {
+        let user = std::env::var("SUDO_USER").or_else(|_| std::env::var("USER")).ok();
+        AuditEntry::new(Source::current_process(), user, action, target)
+    }
+
+    pub fn outcome<T>(mut self, result: &anyhow::Result<T>) -> Self {
+        if let Err(e) = result {
+            self.success = false;
+            self.detail = Some(e.to_string());
+        }
+        self
+    }
+}
+
+pub fn path(config: &Config) -> PathBuf {
+    let file = config.audit.as_ref().and_then(|audit| audit.file.clone());
+    crate::expand_tilde(&file.unwrap_or_else(|| config.logging.directory.join("audit.jsonl")))
+}
+
+/// Append an entry to the audit log. A failed write is logged, but never fails the action itself.
+pub fn record(config: &Config, entry: &AuditEntry) {
+    let path = path(config);
+    let written = (|| -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        // One write per entry, so concurrent writers can't interleave within a line
+        options.open(&path)?.write_all(line.as_bytes())?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        error!("Failed to write audit log {:?}: {}", path, e);
+    }
+}
+
+/// The last `limit` entries, newest first
+pub fn read(path: &Path, limit: usize) -> std::io::Result<Vec<AuditEntry>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries: Vec<AuditEntry> = BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect();
+    entries.reverse();
+    entries.truncate(limit);
+    Ok(entries)
+}
@@ -0,0 +1,245 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, warn};
+
+use crate::{preflight, rclone, ssh, ResticConfig};
+
+/// How long to wait for a remote backend to answer
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub type Check<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// Checks that one kind of restic backend is reachable and accepts the credentials, before restic
+/// starts and fails with a vaguer error. A new backend is one more implementation in `BACKENDS`.
+pub trait BackendPreflight: Sync {
+    /// Shown in run reports as the phase, e.g. "sftp"
+    fn name(&self) -> &'static str;
+    /// Whether the repository, with its placeholders filled in, uses this backend
+    fn handles(&self, repository: &str) -> bool;
+    fn check<'a>(&'a self, restic_config: &'a ResticConfig) -> Check<'a>;
+}
+
+/// The backends with checks, in the order they are asked; local paths come last as they match anything else
+static BACKENDS: &[&dyn BackendPreflight] = &[&Sftp, &Rclone, &S3, &Rest, &Local];
+
+/// The checks for the repository's backend; `None` for backends without any, e.g. b2: or azure:
+pub fn for_repository(repository: &str) -> Option<&'static dyn BackendPreflight> {
+    BACKENDS.iter().copied().find(|backend| backend.handles(repository))
+}
+
+struct Local;
+
+impl BackendPreflight for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn handles(&self, repository: &str) -> bool {
+        preflight::local_repository_path(repository).is_some()
+    }
+
+    /// The repository, or the directory it will be created in, must be there: a missing one is
+    /// usually a drive that isn't mounted
+    fn check<'a>(&'a self, restic_config: &'a ResticConfig) -> Check<'a> {
+        Box::pin(async move {
+            let Some(path) = preflight::local_repository_path(&restic_config.repository_url()) else { return Ok(()) };
+            if path.exists() {
+                return std::fs::read_dir(&path)
+                    .map(|_| ())
+                    .map_err(|e| anyhow::anyhow!("Can't read the repository at {:?}: {}", path, e));
+            }
+            match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() && !parent.exists() => Err(anyhow::anyhow!(
+                    "Neither the repository {:?} nor the directory holding it exists; is the drive mounted?",
+                    path
+                )),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+struct Sftp;
+
+impl BackendPreflight for Sftp {
+    fn name(&self) -> &'static str {
+        "sftp"
+    }
+
+    fn handles(&self, repository: &str) -> bool {
+        ssh::target(repository).is_some()
+    }
+
+    /// Opens an SFTP session with `restic.ssh`, see `ssh::check_connection`
+    fn check<'a>(&'a self, restic_config: &'a ResticConfig) -> Check<'a> {
+        Box::pin(ssh::check_connection(restic_config))
+    }
+}
+
+struct Rclone;
+
+impl BackendPreflight for Rclone {
+    fn name(&self) -> &'static str {
+        "rclone"
+    }
+
+    fn handles(&self, repository: &str) -> bool {
+        rclone::remote(repository).is_some()
+    }
+
+    /// Asks rclone for the remote with `restic.rclone`, see `rclone::check_remote`
+    fn check<'a>(&'a self, restic_config: &'a ResticConfig) -> Check<'a> {
+        Box::pin(rclone::check_remote(restic_config))
+    }
+}
+
+struct S3;
+
+impl S3 {
+    /// Host and port of `s3:https://host:port/bucket` or `s3:host/bucket`. IPv6 hosts are written `[::1]`.
+    fn endpoint(repository: &str) -> Option<(String, u16)> {
+        let rest = repository.strip_prefix("s3:")?;
+        let (default_port, rest) = match rest.split_once("://") {
+            Some(("http", rest)) => (80, rest),
+            Some((_, rest)) => (443, rest),
+            None => (443, rest),
+        };
+        let authority = rest.split('/').next().filter(|authority| !authority.is_empty())?;
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, after) = bracketed.split_once(']')?;
+                (host, after.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port.parse().ok()?,
+            None => default_port,
+        };
+        Some((host.to_string(), port))
+    }
+
+    /// Whether restic will find credentials; an instance role can't be seen from here, so this only warns
+    fn has_credentials() -> bool {
+        let set = |name: &str| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+        (set("AWS_ACCESS_KEY_ID") && set("AWS_SECRET_ACCESS_KEY"))
+            || set("AWS_PROFILE")
+            || set("AWS_WEB_IDENTITY_TOKEN_FILE")
+            || std::env::var_os("HOME").is_some_and(|home| std::path::Path::new(&home).join(".aws/credentials").is_file())
+    }
+}
+
+impl BackendPreflight for S3 {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn handles(&self, repository: &str) -> bool {
+        repository.starts_with("s3:")
+    }
+
+    /// Connects to the endpoint; the credentials are only checked to exist
+    fn check<'a>(&'a self, restic_config: &'a ResticConfig) -> Check<'a> {
+        Box::pin(async move {
+            let repository = restic_config.repository_url();
+            let Some((host, port)) = S3::endpoint(&repository) else {
+                return Err(anyhow::anyhow!("Can't tell the S3 endpoint of {}; expected s3:https://host/bucket", repository));
+            };
+            if !S3::has_credentials() {
+                warn!("No S3 credentials in AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY, AWS_PROFILE or ~/.aws/credentials; restic may fail to log in");
+            }
+            debug!("Connecting to the S3 endpoint {}:{}", host, port);
+            match tokio::time::timeout(CHECK_TIMEOUT, tokio::net::TcpStream::connect((host.as_str(), port))).await {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(e)) => Err(anyhow::anyhow!("Can't reach the S3 endpoint {}:{}: {}", host, port, e)),
+                Err(_) => Err(anyhow::anyhow!("The S3 endpoint {}:{} didn't answer within {}s", host, port, CHECK_TIMEOUT.as_secs())),
+            }
+        })
+    }
+}
+
+struct Rest;
+
+impl BackendPreflight for Rest {
+    fn name(&self) -> &'static str {
+        "rest"
+    }
+
+    fn handles(&self, repository: &str) -> bool {
+        repository.starts_with("rest:")
+    }
+
+    /// Asks the REST server for the repository's config file, which needs the same login as restic
+    fn check<'a>(&'a self, restic_config: &'a ResticConfig) -> Check<'a> {
+        Box::pin(async move {
+            let repository = restic_config.repository_url();
+            let mut url = reqwest::Url::parse(repository.trim_start_matches("rest:"))
+                .map_err(|e| anyhow::anyhow!("Invalid REST repository URL: {}", e))?;
+            // Like restic, the environment overrides credentials in the URL
+            let username = std::env::var("RESTIC_REST_USERNAME").ok().unwrap_or_else(|| url.username().to_string());
+            let password = std::env::var("RESTIC_REST_PASSWORD").ok().or_else(|| url.password().map(String::from));
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            if !url.path().ends_with('/') {
+                url.set_path(&format!("{}/", url.path()));
+            }
+            let config_url = url.join("config")?;
+
+            let client = reqwest::Client::builder().timeout(CHECK_TIMEOUT).build()?;
+            let mut request = client.head(config_url);
+            if !username.is_empty() {
+                request = request.basic_auth(username, password);
+            }
+            debug!("Checking the REST server at {}", url);
+            let response = request.send().await.map_err(|e| anyhow::anyhow!("Can't reach the REST server at {}: {}", url, e))?;
+            match response.status().as_u16() {
+                200..=299 => Ok(()),
+                401 | 403 => Err(anyhow::anyhow!(
+                    "The REST server at {} refused the login; check the user and password in restic.repository or RESTIC_REST_USERNAME/RESTIC_REST_PASSWORD",
+                    url
+                )),
+                // No repository yet; `bootstrap` or restic init creates it
+                404 => Ok(()),
+                status => Err(anyhow::anyhow!("The REST server at {} answered with HTTP {}", url, status)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(repository: &str) -> Option<(String, u16)> {
+        S3::endpoint(repository)
+    }
+
+    #[test]
+    fn s3_endpoint_defaults_the_port_from_the_scheme() {
+        assert_eq!(endpoint("s3:s3.amazonaws.com/bucket"), Some(("s3.amazonaws.com".to_string(), 443)));
+        assert_eq!(endpoint("s3:https://s3.example.com/bucket/path"), Some(("s3.example.com".to_string(), 443)));
+        assert_eq!(endpoint("s3:http://minio.lan/bucket"), Some(("minio.lan".to_string(), 80)));
+    }
+
+    #[test]
+    fn s3_endpoint_reads_explicit_ports() {
+        assert_eq!(endpoint("s3:http://minio.lan:9000/bucket"), Some(("minio.lan".to_string(), 9000)));
+        assert_eq!(endpoint("s3:minio.lan:9000/bucket"), Some(("minio.lan".to_string(), 9000)));
+        assert_eq!(endpoint("s3:https://[::1]:9000/bucket"), Some(("::1".to_string(), 9000)));
+        assert_eq!(endpoint("s3:https://[fd00::5]/bucket"), Some(("fd00::5".to_string(), 443)));
+    }
+
+    #[test]
+    fn s3_endpoint_rejects_what_it_cant_read() {
+        assert_eq!(endpoint("b2:bucket"), None);
+        assert_eq!(endpoint("s3:https:///bucket"), None);
+        assert_eq!(endpoint("s3:minio.lan:port/bucket"), None);
+        assert_eq!(endpoint("s3:https://[::1/bucket"), None);
+    }
+}
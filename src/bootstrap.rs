@@ -0,0 +1,219 @@
+use anyhow::Result;
+use log::info;
+
+use crate::maintenance::{StepResult, StepStatus};
+use crate::snapshots::Snapshot;
+use crate::style::{Painter, Tone};
+use crate::{mount, restic, BackupConfig, Config, ResticConfig};
+
+/// Snapshots listed in the summary
+const LISTED_SNAPSHOTS: usize = 10;
+
+pub fn step(name: &str, status: StepStatus, started: std::time::Instant, message: Option<String>) -> StepResult {
+    StepResult {
+        step: name.to_string(),
+        status,
+        duration_secs: started.elapsed().as_secs_f64(),
+        message,
+    }
+}
+
+pub fn skipped(name: &str, reason: &str) -> StepResult {
+    StepResult {
+        step: name.to_string(),
+        status: StepStatus::Skipped,
+        duration_secs: 0.0,
+        message: Some(reason.to_string()),
+    }
+}
+
+pub async fn restic_version(restic_config: &ResticConfig) -> Result<String, String> {
+    let output = tokio::process::Command::new(restic_config.program())
+        .arg("version")
+        .output()
+        .await
+        .map_err(|e| format!("restic isn't installed or not in PATH: {}", e))?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether the repository exists; an error for anything else, e.g. a wrong password
+async fn repository_exists(config: &Config) -> Result<bool, String> {
+    let output = crate::restic_command(&config.restic, "cat")
+        .arg("config")
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute restic: {}", e))?;
+    if output.status.success() {
+        return Ok(true);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.code() == Some(restic::EXIT_NO_REPOSITORY) || stderr.contains("Is there a repository at the following location?") {
+        return Ok(false);
+    }
+    Err(stderr.trim().to_string())
+}
+
+/// The steps' outcomes, one line each
+pub fn print_summary(steps: &[StepResult]) {
+    let painter = Painter::stdout();
+    println!("\n{}", painter.paint(Tone::Heading, "=== Summary ==="));
+    for step in steps {
+        let status = painter.paint_padded(step.status.tone(), step.status.label(), 7);
+        print!("  {:<20} {} ({:.1}s)", step.step, status, step.duration_secs);
+        if let Some(ref message) = step.message {
+            print!(": {}", message);
+        }
+        println!();
+    }
+}
+
+/// Run a restic command, returning its stdout or its error output
+pub async fn restic_step(restic_config: &ResticConfig, subcommand: &str, args: &[&str]) -> Result<Vec<u8>, String> {
+    let mut cmd = crate::restic_command(restic_config, subcommand);
+    cmd.args(args);
+    info!("Restic command: {:?}", cmd);
+    let output = cmd.output().await.map_err(|e| format!("Failed to execute restic: {}", e))?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        match String::from_utf8_lossy(&output.stderr).trim() {
+            "" => Err(format!("restic {} exited with {}", subcommand, output.status)),
+            stderr => Err(stderr.to_string()),
+        }
+    }
+}
+
+fn render_snapshots(snapshots: &[Snapshot]) -> String {
+    let mut out = String::new();
+    for snapshot in snapshots.iter().rev().take(LISTED_SNAPSHOTS) {
+        out.push_str(&format!(
+            "  {}  {}  {}\n",
+            restic::short_id(&snapshot.id),
+            snapshot.time.format("%Y-%m-%d %H:%M"),
+            snapshot.paths.join(", ")
+        ));
+    }
+    out
+}
+
+/// `bootstrap`: check restic, create the repository if needed, back up every selected job, check the
+/// repository and list its snapshots, then print what happened and what to do next
+pub async fn run(config: &Config, jobs: &[&BackupConfig], dry_run: bool) -> Result<()> {
+    let mut steps = Vec::new();
+    let mut failed = false;
+    let repository = config.restic.repository_url();
+    println!("Setting up backups to {}\n", repository);
+
+    let started = std::time::Instant::now();
+    match restic_version(&config.restic).await {
+        Ok(version) => steps.push(step("restic", StepStatus::Succeeded, started, Some(version))),
+        Err(e) => {
+            steps.push(step("restic", StepStatus::Failed, started, Some(e)));
+            failed = true;
+        }
+    }
+
+    let mounted = match (&config.restic.mount, failed) {
+        (Some(mount_config), false) => mount::ensure_mounted(mount_config).await?,
+        _ => false,
+    };
+
+    let started = std::time::Instant::now();
+    if failed {
+        steps.push(skipped("init", "an earlier step failed"));
+    } else {
+        println!("Checking the repository...");
+        match repository_exists(config).await {
+            Ok(true) => steps.push(step("init", StepStatus::Skipped, started, Some("the repository already exists".to_string()))),
+            Ok(false) if dry_run => steps.push(step("init", StepStatus::Skipped, started, Some("dry run; would create the repository".to_string()))),
+            Ok(false) => {
+                println!("Creating the repository...");
+                match restic_step(&config.restic, "init", &[]).await {
+                    Ok(_) => steps.push(step("init", StepStatus::Succeeded, started, Some("created the repository".to_string()))),
+                    Err(e) => {
+                        steps.push(step("init", StepStatus::Failed, started, Some(e)));
+                        failed = true;
+                    }
+                }
+            }
+            Err(e) => {
+                steps.push(step("init", StepStatus::Failed, started, Some(format!("can't open the repository: {}", e))));
+                failed = true;
+            }
+        }
+    }
+
+    // A dry run of a repository that doesn't exist yet can't go further
+    let repository_missing = steps.last().and_then(|step| step.message.as_deref()).is_some_and(|message| message.starts_with("dry run"));
+    for job in jobs {
+        let name = format!("backup {}", job.name);
+        if failed || repository_missing {
+            steps.push(skipped(&name, if failed { "an earlier step failed" } else { "the repository doesn't exist yet" }));
+            continue;
+        }
+        println!("Backing up job '{}'...", job.name);
+        let started = std::time::Instant::now();
+        match crate::run_backup(config, job, dry_run, false, None).await {
+            Ok(summary) => steps.push(step(&name, StepStatus::Succeeded, started, Some(summary.describe()))),
+            Err(e) => {
+                steps.push(step(&name, StepStatus::Failed, started, Some(e.to_string())));
+                failed = true;
+            }
+        }
+    }
+
+    let started = std::time::Instant::now();
+    if failed || repository_missing || dry_run {
+        steps.push(skipped("check", if dry_run { "dry run" } else { "an earlier step failed" }));
+    } else {
+        println!("Checking the repository's integrity...");
+        match restic_step(&config.restic, "check", &[]).await {
+            Ok(_) => steps.push(step("check", StepStatus::Succeeded, started, Some("no errors found".to_string()))),
+            Err(e) => {
+                steps.push(step("check", StepStatus::Failed, started, Some(e)));
+                failed = true;
+            }
+        }
+    }
+
+    let mut snapshots = Vec::new();
+    let started = std::time::Instant::now();
+    if failed || repository_missing {
+        steps.push(skipped("snapshots", if failed { "an earlier step failed" } else { "the repository doesn't exist yet" }));
+    } else {
+        match restic_step(&config.restic, "snapshots", &["--json"]).await {
+            Ok(stdout) => {
+                snapshots = serde_json::from_slice::<Vec<Snapshot>>(&stdout).unwrap_or_default();
+                steps.push(step("snapshots", StepStatus::Succeeded, started, Some(format!("{} in the repository", snapshots.len()))));
+            }
+            Err(e) => {
+                steps.push(step("snapshots", StepStatus::Failed, started, Some(e)));
+                failed = true;
+            }
+        }
+    }
+
+    if mounted {
+        if let Some(ref mount_config) = config.restic.mount {
+            mount::unmount(mount_config).await;
+        }
+    }
+
+    print_summary(&steps);
+    if !snapshots.is_empty() {
+        print!("\nLatest snapshots:\n{}", render_snapshots(&snapshots));
+    }
+
+    if failed {
+        return Err(anyhow::anyhow!("Bootstrap failed; fix the failed step above and run it again"));
+    }
+    if dry_run {
+        println!("\nDry run finished. Run `bootstrap` without --dry-run to set up the repository.");
+    } else {
+        println!("\nBackups work. Next steps:");
+        println!("  - schedule the jobs: install-service (or add the client to cron)");
+        println!("  - try a restore:     restore latest --target /tmp/restore-test");
+        println!("  - watch it:          --ui for the web dashboard");
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long parsed restic output is served from memory before restic is asked again
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CacheConfig {
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Refresh the snapshot list and repository stats in the background this often (web UI mode).
+    /// Requests are then always answered from the cache, marked stale once older than `ttl_secs`.
+    #[serde(default)]
+    pub refresh_secs: Option<u64>,
+}
+
+fn default_ttl_secs() -> u64 {
+    300
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            ttl_secs: default_ttl_secs(),
+            refresh_secs: None,
+        }
+    }
+}
+
+/// Identifies one restic query against one repository
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub repository: String,
+    /// restic subcommand, e.g. "snapshots"
+    pub command: String,
+    /// Snapshot the query is about; `None` for repository-wide queries
+    pub snapshot: Option<String>,
+    /// Remaining arguments, e.g. the path listed by `ls`
+    pub args: String,
+}
+
+impl CacheKey {
+    pub fn new(repository: &str, command: &str, snapshot: Option<&str>, args: &str) -> Self {
+        CacheKey {
+            repository: repository.to_string(),
+            command: command.to_string(),
+            snapshot: snapshot.map(str::to_string),
+            args: args.to_string(),
+        }
+    }
+}
+
+struct CacheEntry {
+    /// Shared rather than copied out, since a snapshot list can hold tens of thousands of entries.
+    /// Most commands keep restic's JSON; the snapshot list is kept typed.
+    value: Arc<dyn Any + Send + Sync>,
+    fetched_at: Instant,
+}
+
+/// In-memory cache of parsed restic output, shared by all web handlers
+#[derive(Clone, Default)]
+pub struct RepoCache {
+    entries: Arc<Mutex<HashMap<CacheKey, CacheEntry>>>,
+}
+
+impl RepoCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cached value and its age, if it is younger than `ttl`
+    pub fn get<T: Any + Send + Sync>(&self, key: &CacheKey, ttl: Duration) -> Option<(Arc<T>, Duration)> {
+        self.get_any(key).filter(|(_, age)| *age < ttl)
+    }
+
+    /// The cached value and its age, however old it is
+    pub fn get_any<T: Any + Send + Sync>(&self, key: &CacheKey) -> Option<(Arc<T>, Duration)> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+        let value = entry.value.clone().downcast::<T>().ok()?;
+        Some((value, entry.fetched_at.elapsed()))
+    }
+
+    pub fn insert<T: Any + Send + Sync>(&self, key: CacheKey, value: Arc<T>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.fetched_at.elapsed() < Duration::from_secs(24 * 60 * 60));
+        entries.insert(key, CacheEntry { value, fetched_at: Instant::now() });
+    }
+
+    /// Drop repository-wide results (snapshot list, repository stats) after the repository changed.
+    /// Results about a single snapshot stay valid since snapshots are immutable.
+    pub fn invalidate_repository(&self, repository: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|key, _| key.repository != repository || key.snapshot.is_some());
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
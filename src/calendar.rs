@@ -0,0 +1,86 @@
+use chrono::{DateTime, Local, NaiveDate, Timelike};
+use serde::Serialize;
+
+use crate::reports::RunReport;
+use crate::BackupConfig;
+
+/// Longest history the endpoint returns
+pub const MAX_DAYS: u32 = 3660;
+
+/// Runs of one job on one day
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct CalendarDay {
+    pub date: NaiveDate,
+    /// Succeeded runs, including those with warnings
+    pub succeeded: usize,
+    /// Of `succeeded`, the runs with warnings
+    pub warnings: usize,
+    pub failed: usize,
+    /// Scheduled runs that didn't happen
+    pub missed: usize,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct JobCalendar {
+    pub job: String,
+    pub days: Vec<CalendarDay>,
+}
+
+/// Count missed runs from the job's schedule. Nothing is missed before the job's first run, when it
+/// may not have been configured yet, and today only counts the hours that have passed.
+fn fill_missed(job: &BackupConfig, days: &mut [CalendarDay], run_dates: &[NaiveDate], now: DateTime<Local>) {
+    let (Some(interval), Some(&first_run)) = (job.interval(), run_dates.iter().min()) else { return };
+    let today = now.date_naive();
+    let interval_days = interval.num_days();
+    // Weekly and monthly jobs are due one interval after their last run, which may be before the window
+    let window_start = days.first().map(|day| day.date).unwrap_or(today);
+    let mut last_run = run_dates.iter().filter(|date| **date < window_start).max().copied().unwrap_or(first_run);
+
+    for day in days.iter_mut().filter(|day| day.date >= first_run) {
+        let runs = day.succeeded + day.failed;
+        if interval_days == 0 {
+            let expected = if day.date == today { now.hour() as usize } else { 24 };
+            day.missed = expected.saturating_sub(runs);
+        } else if runs > 0 {
+            last_run = day.date;
+        } else if day.date < today && (day.date - last_run).num_days() >= interval_days {
+            day.missed = 1;
+            last_run = day.date;
+        }
+    }
+}
+
+/// Per-day run counts for each job over the last `days` days, oldest first. Dry runs don't count.
+pub fn build(jobs: &[&BackupConfig], reports: &[RunReport], days: u32, now: DateTime<Local>) -> Vec<JobCalendar> {
+    let today = now.date_naive();
+    let start = today - chrono::Duration::days(days.saturating_sub(1) as i64);
+
+    jobs.iter()
+        .map(|job| {
+            let runs: Vec<&RunReport> = reports.iter().filter(|report| report.job == job.name && !report.dry_run).collect();
+            let mut calendar: Vec<CalendarDay> = start
+                .iter_days()
+                .take_while(|date| *date <= today)
+                .map(|date| CalendarDay { date, ..Default::default() })
+                .collect();
+            for report in &runs {
+                let Ok(index) = usize::try_from((report.started_at.date_naive() - start).num_days()) else { continue };
+                let Some(day) = calendar.get_mut(index) else { continue };
+                match report.status.as_str() {
+                    "failed" => day.failed += 1,
+                    "warning" => {
+                        day.succeeded += 1;
+                        day.warnings += 1;
+                    }
+                    _ => day.succeeded += 1,
+                }
+            }
+            let run_dates: Vec<NaiveDate> = runs.iter().map(|report| report.started_at.date_naive()).collect();
+            fill_missed(job, &mut calendar, &run_dates, now);
+            JobCalendar {
+                job: job.name.clone(),
+                days: calendar,
+            }
+        })
+        .collect()
+}
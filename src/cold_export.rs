@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::Result;
+use chrono::Local;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::archive::{ArchiveFormat, Dump};
+use crate::snapshots::Snapshot;
+use crate::{rclone, restic, BackupConfig, ResticConfig};
+
+/// Copy the latest snapshot of some paths out of the repository as gpg-encrypted tarballs, so they can be
+/// recovered with tar and gpg alone if the repository or restic is ever lost. The job backs nothing up.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ColdExportConfig {
+    /// Paths as they appear in this host's snapshots; one archive each
+    pub paths: Vec<String>,
+    /// Local directory the archives are written to, e.g. a mounted bucket or removable disk
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+    /// rclone `remote:path` the archives are uploaded to with `rclone rcat`, e.g. "glacier:bucket/restic-exports"
+    #[serde(default)]
+    pub rclone: Option<String>,
+    /// More rclone flags, e.g. ["--s3-storage-class", "DEEP_ARCHIVE"]
+    #[serde(default)]
+    pub rclone_args: Vec<String>,
+    /// gpg public key the archives are encrypted to; the secret key needn't be on this machine
+    #[serde(default)]
+    pub recipient: Option<String>,
+    /// File with a passphrase for symmetric encryption instead of a key
+    #[serde(default)]
+    pub passphrase_file: Option<PathBuf>,
+}
+
+/// Name of the archive of `path`, e.g. "cold-2024-03-01-home_me_documents.tar.gz.gpg"
+fn archive_name(job: &str, path: &str, date: &str) -> String {
+    let slug: String = path.trim_matches('/').chars().map(|c| if c.is_ascii_alphanumeric() || "-.".contains(c) { c } else { '_' }).collect();
+    let slug = if slug.is_empty() { "root".to_string() } else { slug };
+    format!("{}-{}-{}.{}.gpg", job, date, slug, ArchiveFormat::TarGz.extension())
+}
+
+/// This host's newest snapshot containing `path`
+fn latest_with<'a>(snapshots: &'a [Snapshot], path: &str) -> Option<&'a Snapshot> {
+    snapshots
+        .iter()
+        .filter(|snapshot| snapshot.paths.iter().any(|root| Path::new(path).starts_with(root)))
+        .max_by_key(|snapshot| snapshot.time)
+}
+
+fn gpg_command(config: &ColdExportConfig) -> Result<Command> {
+    let mut cmd = Command::new("gpg");
+    cmd.args(["--batch", "--yes", "--output", "-"]);
+    match (&config.recipient, &config.passphrase_file) {
+        (Some(recipient), _) => {
+            cmd.args(["--encrypt", "--trust-model", "always", "--recipient"]).arg(recipient);
+        }
+        (None, Some(passphrase_file)) => {
+            cmd.args(["--symmetric", "--cipher-algo", "AES256", "--pinentry-mode", "loopback", "--passphrase-file"])
+                .arg(crate::expand_tilde(passphrase_file));
+        }
+        (None, None) => return Err(anyhow::anyhow!("cold_export needs a recipient or a passphrase_file to encrypt the archives")),
+    }
+    cmd.kill_on_drop(true);
+    Ok(cmd)
+}
+
+fn rclone_command(restic_config: &ResticConfig, config: &ColdExportConfig, destination: &str) -> Command {
+    let mut cmd = match restic_config.rclone {
+        Some(ref rclone_config) => {
+            let mut cmd = Command::new(rclone::program(rclone_config));
+            if let Some(ref config_file) = rclone_config.config_file {
+                cmd.arg("--config").arg(crate::expand_tilde(config_file));
+            }
+            cmd
+        }
+        None => Command::new("rclone"),
+    };
+    cmd.arg("rcat").arg(destination).args(&config.rclone_args);
+    cmd.kill_on_drop(true);
+    cmd
+}
+
+async fn gpg_result(gpg: tokio::process::Child) -> Result<()> {
+    let output = gpg.wait_with_output().await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("gpg failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+/// restic dump | gzip | gpg into a file in `directory`, put in place once complete; returns its size
+async fn export_to_directory(restic_config: &ResticConfig, config: &ColdExportConfig, snapshot_id: &str, path: &str, output: &Path) -> Result<u64> {
+    let mut partial = output.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let file = std::fs::File::create(&partial).map_err(|e| anyhow::anyhow!("Failed to create {:?}: {}", partial, e))?;
+
+    let result = async {
+        let mut dump = Dump::spawn(restic_config, snapshot_id, path, ArchiveFormat::TarGz, Stdio::piped())?;
+        let archive: Stdio = dump.stdout().expect("stdout is piped").try_into()?;
+        let gpg = gpg_command(config)?.stdin(archive).stdout(file).stderr(Stdio::piped()).spawn()?;
+        let encrypted = gpg_result(gpg).await;
+        dump.wait().await?;
+        encrypted
+    }
+    .await;
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    std::fs::rename(&partial, output)?;
+    Ok(std::fs::metadata(output)?.len())
+}
+
+/// restic dump | gzip | gpg | rclone rcat; returns the bytes uploaded
+async fn export_to_rclone(restic_config: &ResticConfig, config: &ColdExportConfig, snapshot_id: &str, path: &str, destination: &str) -> Result<u64> {
+    let mut dump = Dump::spawn(restic_config, snapshot_id, path, ArchiveFormat::TarGz, Stdio::piped())?;
+    let archive: Stdio = dump.stdout().expect("stdout is piped").try_into()?;
+    let mut gpg = gpg_command(config)?.stdin(archive).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+    let mut rclone = rclone_command(restic_config, config, destination)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run rclone: {}", e))?;
+
+    let mut encrypted = gpg.stdout.take().expect("stdout is piped");
+    let mut upload = rclone.stdin.take().expect("stdin is piped");
+    let copied = tokio::io::copy(&mut encrypted, &mut upload).await;
+    drop(upload);
+    let uploaded = rclone.wait_with_output().await?;
+    let gpg_result = gpg_result(gpg).await;
+    dump.wait().await?;
+    gpg_result?;
+    if !uploaded.status.success() {
+        return Err(anyhow::anyhow!("rclone rcat {} failed: {}", destination, String::from_utf8_lossy(&uploaded.stderr).trim()));
+    }
+    Ok(copied?)
+}
+
+/// Export the newest snapshot of each path. A failed path doesn't stop the others, but fails the run.
+pub async fn run(job: &BackupConfig, config: &ColdExportConfig, restic_config: &ResticConfig, dry_run: bool) -> Result<restic::BackupSummary> {
+    let started = std::time::Instant::now();
+    let mut cmd = crate::restic_command(restic_config, "snapshots");
+    cmd.arg("--host").arg(crate::hostname()).arg("--json");
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("restic snapshots failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let snapshots: Vec<Snapshot> = serde_json::from_slice(&output.stdout)?;
+    let date = Local::now().format("%Y-%m-%d").to_string();
+
+    let mut summary = restic::BackupSummary::default();
+    let mut failed = Vec::new();
+    for path in &config.paths {
+        let Some(snapshot) = latest_with(&snapshots, path) else {
+            failed.push(format!("{}: no snapshot of this host contains it", path));
+            continue;
+        };
+        let name = archive_name(&job.name, path, &date);
+        let short_id = restic::short_id(&snapshot.id);
+        if dry_run {
+            info!("Dry run: would export {} from snapshot {} ({}) as {}", path, short_id, snapshot.time.with_timezone(&Local).format("%Y-%m-%d %H:%M"), name);
+            continue;
+        }
+        info!("Exporting {} from snapshot {} as {}", path, short_id, name);
+        let result = match (&config.directory, &config.rclone) {
+            (Some(directory), _) => {
+                let output = crate::expand_tilde(directory).join(&name);
+                export_to_directory(restic_config, config, &snapshot.id, path, &output).await
+            }
+            (None, Some(remote)) => {
+                let destination = format!("{}/{}", remote.trim_end_matches('/'), name);
+                export_to_rclone(restic_config, config, &snapshot.id, path, &destination).await
+            }
+            (None, None) => Err(anyhow::anyhow!("cold_export needs a directory or an rclone destination")),
+        };
+        match result {
+            Ok(bytes) => {
+                info!("Exported {} ({})", path, restic::format_bytes(bytes));
+                summary.total_files_processed += 1;
+                summary.total_bytes_processed += bytes;
+            }
+            Err(e) => failed.push(format!("{}: {}", path, e)),
+        }
+    }
+    summary.total_duration = started.elapsed().as_secs_f64();
+
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!("Cold export of job '{}' failed for {}", job.name, failed.join("; ")));
+    }
+    Ok(summary)
+}
@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::BackupConfig;
+
+/// How long the `command` condition may take before it counts as not met
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Conditions a job only runs under; all that are set must hold
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct OnlyIf {
+    /// Shell command that must exit 0, run with the job's environment
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Path that must exist, e.g. a file on a network share
+    #[serde(default)]
+    pub file_exists: Option<PathBuf>,
+    /// Hostname patterns, one of which must match; `*` and `?` are wildcards
+    #[serde(default)]
+    pub hostname: Vec<String>,
+    /// true: only on mains power; false: only on battery
+    #[serde(default)]
+    pub on_ac_power: Option<bool>,
+}
+
+/// `*` and `?` wildcards, ignoring case as hostnames do
+fn hostname_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| hostname_matches(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && hostname_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first().is_some_and(|t| t.eq_ignore_ascii_case(c)) && hostname_matches(rest, &text[1..]),
+    }
+}
+
+/// Whether the machine runs on mains power; machines without a battery always do. None when it can't be told.
+#[cfg(target_os = "linux")]
+pub fn on_ac_power() -> Option<bool> {
+    let supplies: Vec<PathBuf> = std::fs::read_dir("/sys/class/power_supply").ok()?.flatten().map(|entry| entry.path()).collect();
+    let read = |path: &PathBuf, file: &str| std::fs::read_to_string(path.join(file)).map(|value| value.trim().to_string()).unwrap_or_default();
+    let mains: Vec<&PathBuf> = supplies.iter().filter(|supply| read(supply, "type") == "Mains").collect();
+    if mains.iter().any(|supply| read(supply, "online") == "1") {
+        return Some(true);
+    }
+    let has_battery = supplies.iter().any(|supply| read(supply, "type") == "Battery");
+    Some(!has_battery)
+}
+
+/// `pmset -g batt` starts with "Now drawing from 'AC Power'" or "'Battery Power'"
+#[cfg(target_os = "macos")]
+pub fn on_ac_power() -> Option<bool> {
+    let output = std::process::Command::new("pmset").args(["-g", "batt"]).output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let first_line = text.lines().next()?;
+    Some(!first_line.contains("Battery Power"))
+}
+
+/// Win32_Battery's BatteryStatus is 2 when on AC; there's no instance without a battery
+#[cfg(windows)]
+pub fn on_ac_power() -> Option<bool> {
+    let output = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-Command", "(Get-CimInstance -ClassName Win32_Battery).BatteryStatus"])
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    match text.lines().map(str::trim).find(|line| !line.is_empty()) {
+        None => Some(true),
+        Some(status) => Some(status == "2"),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn on_ac_power() -> Option<bool> {
+    None
+}
+
+async fn command_succeeds(job: &BackupConfig, command: &str) -> Result<bool, String> {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    job.apply_environment(&mut cmd).map_err(|e| e.to_string())?;
+    cmd.stdin(std::process::Stdio::null()).stdout(std::process::Stdio::null()).stderr(std::process::Stdio::null()).kill_on_drop(true);
+    match tokio::time::timeout(COMMAND_TIMEOUT, cmd.status()).await {
+        Ok(Ok(status)) => Ok(status.success()),
+        Ok(Err(e)) => Err(format!("failed to run '{}': {}", command, e)),
+        Err(_) => Err(format!("'{}' took longer than {}s", command, COMMAND_TIMEOUT.as_secs())),
+    }
+}
+
+/// Why the job's `only_if` conditions don't hold right now, or its script wants the run skipped; None when it should run
+pub async fn unmet(job: &BackupConfig) -> Option<String> {
+    if let Some(ref conditions) = job.only_if {
+        if let Some(reason) = only_if_unmet(job, conditions).await {
+            return Some(reason);
+        }
+    }
+    crate::scripting::skip(job)
+}
+
+async fn only_if_unmet(job: &BackupConfig, conditions: &OnlyIf) -> Option<String> {
+    if !conditions.hostname.is_empty() {
+        let hostname = crate::hostname();
+        if !conditions.hostname.iter().any(|pattern| hostname_matches(pattern.as_bytes(), hostname.as_bytes())) {
+            return Some(format!("host '{}' doesn't match {}", hostname, conditions.hostname.join(", ")));
+        }
+    }
+    if let Some(ref path) = conditions.file_exists {
+        let path = job.resolve_path(path);
+        if !path.exists() {
+            return Some(format!("{:?} doesn't exist", path));
+        }
+    }
+    if let Some(wanted) = conditions.on_ac_power {
+        match on_ac_power() {
+            Some(on_ac) if on_ac != wanted => {
+                return Some(if on_ac { "running on AC power".to_string() } else { "running on battery".to_string() });
+            }
+            Some(_) => {}
+            None => return Some("can't tell whether the machine is on AC power".to_string()),
+        }
+    }
+    if let Some(ref command) = conditions.command {
+        debug!("Checking condition of job '{}': {}", job.name, command);
+        match command_succeeds(job, command).await {
+            Ok(true) => {}
+            Ok(false) => return Some(format!("'{}' didn't exit 0", command)),
+            Err(e) => return Some(e),
+        }
+    }
+    None
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use log::debug;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+/// Number of previous config versions kept next to the config file
+pub const CONFIG_HISTORY_LIMIT: usize = 10;
+
+/// Directory holding previous versions, e.g. `config.yaml` -> `.config.yaml.history/`
+pub fn history_dir(config_path: &str) -> PathBuf {
+    let path = Path::new(config_path);
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "config.yaml".to_string());
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    parent.join(format!(".{}.history", file_name))
+}
+
+/// Copy the current config file into the history directory and trim old versions.
+/// Returns the id of the saved version, or None if there was no file to save.
+pub fn save_version(config_path: &str) -> Result<Option<String>> {
+    let current = match std::fs::read_to_string(config_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    // Versions may hold the repository password, so only the owner may read them
+    let dir = history_dir(config_path);
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(&dir)?;
+    // Directories from older versions were created with the umask's mode
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+
+    // Millisecond timestamps sort lexically as long as they have the same width
+    let mut id = format!(
+        "{:013}",
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_millis()
+    );
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = loop {
+        match options.open(dir.join(format!("{}.yaml", id))) {
+            Ok(file) => break file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => id = format!("{:013}", id.parse::<u128>()? + 1),
+            Err(e) => return Err(e.into()),
+        }
+    };
+    debug!("Saving config version {} to {:?}", id, dir);
+    std::io::Write::write_all(&mut file, current.as_bytes())?;
+
+    // Drop the oldest versions beyond the limit
+    let versions = list_versions(config_path)?;
+    for old in versions.iter().skip(CONFIG_HISTORY_LIMIT) {
+        debug!("Removing old config version {}", old);
+        let _ = std::fs::remove_file(dir.join(format!("{}.yaml", old)));
+    }
+
+    Ok(Some(id))
+}
+
+/// List saved version ids, newest first
+pub fn list_versions(config_path: &str) -> Result<Vec<String>> {
+    let dir = history_dir(config_path);
+    let mut versions = Vec::new();
+
+    if let Ok(entries) = std::fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            if let Some(name) = entry.file_name().to_str() {
+                if let Some(id) = name.strip_suffix(".yaml") {
+                    if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+                        versions.push(id.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    versions.sort_by(|a, b| b.cmp(a));
+    Ok(versions)
+}
+
+/// Read the contents of a saved version
+pub fn read_version(config_path: &str, id: &str) -> Result<String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+        return Err(anyhow::anyhow!("Invalid config version id: {}", id));
+    }
+    let path = history_dir(config_path).join(format!("{}.yaml", id));
+    std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read config version {}: {}", id, e))
+}
+
+/// ETag for config file contents, used to detect concurrent modifications
+pub fn etag(contents: &str) -> String {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    contents.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Write the config file atomically: write a temp file in the same directory, fsync it,
+/// copy the original's permissions (and ownership on Unix), then rename over the original.
+/// The temp file gets a random name and mode 0600 when it's created, so the contents are never
+/// readable by others and a planted file or symlink can't redirect the write.
+pub fn write_config(config_path: &str, contents: &str) -> Result<()> {
+    use std::io::Write;
+
+    let path = Path::new(config_path);
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("Invalid config path: {}", config_path))?
+        .to_string_lossy()
+        .to_string();
+
+    let original_metadata = std::fs::metadata(path).ok();
+
+    // On failure the temp file is removed when it's dropped
+    let result = (|| -> Result<()> {
+        let mut file = tempfile::Builder::new()
+            .prefix(&format!(".{}.tmp.", file_name))
+            .tempfile_in(&parent)?;
+        file.write_all(contents.as_bytes())?;
+        file.as_file().sync_all()?;
+
+        if let Some(ref metadata) = original_metadata {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::MetadataExt;
+                // Only root can give files away; for everyone else the owner is already right
+                if let Err(e) = std::os::unix::fs::fchown(file.as_file(), Some(metadata.uid()), Some(metadata.gid())) {
+                    debug!("Could not preserve config ownership: {}", e);
+                }
+            }
+            file.as_file().set_permissions(metadata.permissions())?;
+        }
+
+        file.persist(path)?;
+
+        // Make the rename itself durable
+        #[cfg(unix)]
+        if let Ok(dir) = std::fs::File::open(&parent) {
+            let _ = dir.sync_all();
+        }
+
+        Ok(())
+    })();
+
+    result.map_err(|e| anyhow::anyhow!("Failed to write config file {}: {}", config_path, e))
+}
+
+/// Compute a structured diff between two YAML documents.
+/// Each entry has the dotted key path, the kind of change, and old/new values.
+pub fn diff_yaml(old_yaml: &str, new_yaml: &str) -> Result<Vec<serde_json::Value>> {
+    let old: serde_json::Value = serde_yaml::from_str(old_yaml).unwrap_or(serde_json::Value::Null);
+    let new: serde_json::Value = serde_yaml::from_str(new_yaml)?;
+    let mut changes = Vec::new();
+    diff_values("", &old, &new, &mut changes);
+    Ok(changes)
+}
+
+fn diff_values(path: &str, old: &serde_json::Value, new: &serde_json::Value, changes: &mut Vec<serde_json::Value>) {
+    use serde_json::Value;
+
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child = join_path(path, key);
+                match new_map.get(key) {
+                    Some(new_value) => diff_values(&child, old_value, new_value, changes),
+                    None => changes.push(json!({ "path": child, "change": "removed", "old": old_value })),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    changes.push(json!({ "path": join_path(path, key), "change": "added", "new": new_value }));
+                }
+            }
+        }
+        (Value::Array(old_items), Value::Array(new_items)) => {
+            for (i, old_item) in old_items.iter().enumerate() {
+                let child = format!("{}[{}]", path, i);
+                match new_items.get(i) {
+                    Some(new_item) => diff_values(&child, old_item, new_item, changes),
+                    None => changes.push(json!({ "path": child, "change": "removed", "old": old_item })),
+                }
+            }
+            for (i, new_item) in new_items.iter().enumerate().skip(old_items.len()) {
+                changes.push(json!({ "path": format!("{}[{}]", path, i), "change": "added", "new": new_item }));
+            }
+        }
+        _ if old != new => {
+            changes.push(json!({ "path": path, "change": "modified", "old": old, "new": new }));
+        }
+        _ => {}
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
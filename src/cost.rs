@@ -0,0 +1,99 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::reports::RunReport;
+use crate::restic::format_bytes;
+use crate::{quota, Config};
+
+/// Providers bill in decimal gigabytes
+const BYTES_PER_GB: f64 = 1e9;
+
+/// Storage pricing of a cloud repository, e.g. B2 at 0.006 per GB-month
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CostConfig {
+    /// Shown next to the estimate, e.g. "B2" or "S3 Glacier Instant Retrieval"
+    pub storage_class: String,
+    pub price_per_gb_month: f64,
+    /// Charged per GB uploaded; most providers don't
+    #[serde(default)]
+    pub upload_price_per_gb: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct CostEstimate {
+    pub storage_class: String,
+    pub currency: String,
+    pub repository_size: u64,
+    /// Storage cost of the repository at its current size for one month
+    pub monthly_storage: f64,
+    /// Bytes the run (or period) added to the repository
+    pub uploaded: u64,
+    pub upload: f64,
+}
+
+impl CostEstimate {
+    pub fn new(config: &CostConfig, repository_size: u64, uploaded: u64) -> Self {
+        CostEstimate {
+            storage_class: config.storage_class.clone(),
+            currency: config.currency.clone(),
+            repository_size,
+            monthly_storage: repository_size as f64 / BYTES_PER_GB * config.price_per_gb_month,
+            uploaded,
+            upload: uploaded as f64 / BYTES_PER_GB * config.upload_price_per_gb,
+        }
+    }
+
+    /// e.g. "B2: ~4.87 USD/month for 812.40 GiB; last upload 1.20 GiB, 0.00 USD"
+    pub fn describe(&self, upload_label: &str) -> String {
+        format!(
+            "{}: ~{:.2} {}/month for {}; {} {}, {:.2} {}",
+            self.storage_class,
+            self.monthly_storage,
+            self.currency,
+            format_bytes(self.repository_size),
+            upload_label,
+            format_bytes(self.uploaded),
+            self.upload,
+            self.currency
+        )
+    }
+}
+
+/// Repository size from the newest report that measured it, or from restic when none did
+pub async fn repository_size(config: &Config, reports: &[RunReport]) -> Option<u64> {
+    if let Some(size) = reports.iter().find_map(|report| report.repository_size) {
+        return Some(size);
+    }
+    match quota::repository_size(&config.restic).await {
+        Ok(size) => Some(size),
+        Err(e) => {
+            warn!("Couldn't measure the repository size for the cost estimate: {}", e);
+            None
+        }
+    }
+}
+
+/// Estimated storage cost and the cost of the last run's upload, if `cost` is configured
+pub async fn current(config: &Config, reports: &[RunReport]) -> Option<CostEstimate> {
+    config.cost.as_ref()?;
+    let size = repository_size(config, reports).await?;
+    estimate(config, reports, size)
+}
+
+/// The estimate for a repository of `size` bytes, for callers that measured the size themselves
+pub fn estimate(config: &Config, reports: &[RunReport], size: u64) -> Option<CostEstimate> {
+    let cost_config = config.cost.as_ref()?;
+    let last_upload = reports
+        .iter()
+        .filter(|report| !report.dry_run)
+        .find_map(|report| report.summary.as_ref())
+        .map(|summary| summary.data_added)
+        .unwrap_or(0);
+    Some(CostEstimate::new(cost_config, size, last_upload))
+}
@@ -0,0 +1,237 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::Config;
+
+/// How long `stop` waits for the daemon to exit after SIGTERM
+const STOP_TIMEOUT: Duration = Duration::from_secs(15);
+/// How long a detaching parent watches the child before reporting it started
+const STARTUP_GRACE: Duration = Duration::from_secs(1);
+
+/// Running the web UI or agent without systemd: detaching and a PID file
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DaemonConfig {
+    pub pid_file: PathBuf,
+    /// Detach from the terminal; `--foreground` keeps it attached anyway
+    #[serde(default = "default_detach")]
+    pub detach: bool,
+}
+
+fn default_detach() -> bool {
+    true
+}
+
+impl DaemonConfig {
+    pub fn pid_file(&self) -> PathBuf {
+        crate::expand_tilde(&self.pid_file)
+    }
+}
+
+/// Removes the PID file when the daemon exits normally. The file stays open with an exclusive
+/// `flock` while the daemon runs, which is what tells a live daemon from a reused PID.
+pub struct PidFile {
+    path: PathBuf,
+    _file: std::fs::File,
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if read_pid(&self.path) == Some(std::process::id()) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(unix)]
+pub fn process_alive(pid: u32) -> bool {
+    // Signal 0 only checks; EPERM means it exists but belongs to someone else
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+pub fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()))
+        .unwrap_or(false)
+}
+
+/// Try to `flock` the file without blocking; `false` if another process holds a conflicting lock
+#[cfg(unix)]
+fn try_lock(file: &std::fs::File, operation: libc::c_int) -> std::io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let error = std::io::Error::last_os_error();
+    match error.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(error),
+    }
+}
+
+/// Whether the daemon that wrote the PID file still runs: it holds the file's lock until it exits
+#[cfg(unix)]
+fn daemon_alive(path: &Path, pid: u32) -> bool {
+    let Ok(file) = std::fs::File::open(path) else { return false };
+    match try_lock(&file, libc::LOCK_SH) {
+        Ok(acquired) => !acquired,
+        // Filesystems without flock support fall back to asking for the PID
+        Err(_) => process_alive(pid),
+    }
+}
+
+#[cfg(not(unix))]
+fn daemon_alive(_path: &Path, pid: u32) -> bool {
+    process_alive(pid)
+}
+
+/// The PID of the running daemon; a PID file left behind by a crash is removed
+pub fn running_pid(daemon_config: &DaemonConfig) -> Option<u32> {
+    let path = daemon_config.pid_file();
+    let pid = read_pid(&path)?;
+    if daemon_alive(&path, pid) {
+        return Some(pid);
+    }
+    warn!("Removing stale PID file {:?} (process {} is gone)", path, pid);
+    let _ = std::fs::remove_file(&path);
+    None
+}
+
+fn write_pid_file(daemon_config: &DaemonConfig) -> Result<PidFile> {
+    if let Some(pid) = running_pid(daemon_config) {
+        return Err(anyhow::anyhow!("Already running with PID {} (see {:?})", pid, daemon_config.pid_file()));
+    }
+    let path = daemon_config.pid_file();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Not truncated on open: until the lock is ours, the file may belong to a daemon that just started
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to open PID file {:?}: {}", path, e))?;
+    #[cfg(unix)]
+    if !try_lock(&file, libc::LOCK_EX).map_err(|e| anyhow::anyhow!("Failed to lock PID file {:?}: {}", path, e))? {
+        let pid = read_pid(&path).map(|pid| pid.to_string()).unwrap_or_else(|| "unknown".to_string());
+        return Err(anyhow::anyhow!("Already running with PID {} (see {:?})", pid, path));
+    }
+    file.set_len(0)
+        .and_then(|_| std::io::Write::write_all(&mut file, format!("{}\n", std::process::id()).as_bytes()))
+        .map_err(|e| anyhow::anyhow!("Failed to write PID file {:?}: {}", path, e))?;
+    Ok(PidFile { path, _file: file })
+}
+
+/// Start this command again in a new session with no terminal, and exit once it's up
+#[cfg(unix)]
+fn detach(args: &[String]) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    use std::process::Stdio;
+
+    let mut cmd = std::process::Command::new(std::env::current_exe()?);
+    cmd.args(&args[1..]).arg("--foreground").stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    // setsid drops the controlling terminal, so closing it doesn't send SIGHUP
+    unsafe {
+        cmd.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn().map_err(|e| anyhow::anyhow!("Failed to start in the background: {}", e))?;
+    std::thread::sleep(STARTUP_GRACE);
+    if let Some(status) = child.try_wait()? {
+        return Err(anyhow::anyhow!("The background process exited right away ({}); run with --foreground to see why", status));
+    }
+    println!("Started in the background with PID {}", child.id());
+    std::process::exit(0);
+}
+
+#[cfg(not(unix))]
+fn detach(_args: &[String]) -> Result<()> {
+    Err(anyhow::anyhow!("Detaching isn't supported on this platform; set daemon.detach to false or pass --foreground"))
+}
+
+/// Called before the web UI or agent starts: detach unless told not to, then claim the PID file.
+/// Without a `daemon` section this does nothing.
+pub fn start(config: &Config, args: &[String]) -> Result<Option<PidFile>> {
+    let Some(daemon_config) = config.daemon.as_ref() else {
+        return Ok(None);
+    };
+    let foreground = args.iter().any(|arg| arg == "--foreground");
+    if daemon_config.detach && !foreground {
+        // Fail here rather than in the detached child, where nobody sees the error
+        if let Some(pid) = running_pid(daemon_config) {
+            return Err(anyhow::anyhow!("Already running with PID {} (see {:?})", pid, daemon_config.pid_file()));
+        }
+        detach(args)?;
+    }
+    let pid_file = write_pid_file(daemon_config)?;
+    info!("Running with PID {} (PID file {:?})", std::process::id(), pid_file.path);
+    Ok(Some(pid_file))
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> Result<()> {
+    if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+        return Err(anyhow::anyhow!("Failed to signal PID {}: {}", pid, std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn terminate(pid: u32) -> Result<()> {
+    let status = std::process::Command::new("taskkill").args(["/PID", &pid.to_string()]).status()?;
+    if !status.success() {
+        return Err(anyhow::anyhow!("taskkill failed for PID {}", pid));
+    }
+    Ok(())
+}
+
+/// `stop`: SIGTERM the daemon from the PID file and wait for it to exit
+pub fn stop(config: &Config) -> Result<()> {
+    let daemon_config = config
+        .daemon
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("No 'daemon' section in the config, so there's no PID file to act on"))?;
+    let Some(pid) = running_pid(daemon_config) else {
+        println!("Not running");
+        return Ok(());
+    };
+    // running_pid saw the daemon's lock, so the PID isn't a reused one of some other process
+    terminate(pid)?;
+    let started = std::time::Instant::now();
+    let path = daemon_config.pid_file();
+    while daemon_alive(&path, pid) {
+        if started.elapsed() > STOP_TIMEOUT {
+            return Err(anyhow::anyhow!("PID {} is still running {}s after SIGTERM", pid, STOP_TIMEOUT.as_secs()));
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    // Killed by a signal, it couldn't clean up after itself
+    let _ = std::fs::remove_file(&path);
+    println!("Stopped PID {}", pid);
+    Ok(())
+}
+
+/// One line for `status`
+pub fn describe(daemon_config: &DaemonConfig) -> String {
+    match running_pid(daemon_config) {
+        Some(pid) => format!("running with PID {}", pid),
+        None => "not running".to_string(),
+    }
+}
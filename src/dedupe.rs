@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::restic::format_bytes;
+use crate::snapshots::Snapshot;
+use crate::{reports, BackupConfig, Config};
+
+/// Snapshot ids passed to one `restic stats` call, to stay well below argument length limits
+const IDS_PER_CALL: usize = 500;
+
+/// The fields of `restic stats --json` used here; raw-data mode adds the uncompressed size (restic 0.14+)
+#[derive(Debug, Deserialize, Default)]
+struct Stats {
+    #[serde(default)]
+    total_size: u64,
+    #[serde(default)]
+    total_uncompressed_size: u64,
+}
+
+/// Sizes of one month's snapshots of a job or tag
+#[derive(Debug, Serialize)]
+pub struct DedupeRow {
+    /// `YYYY-MM`
+    pub month: String,
+    pub snapshots: usize,
+    /// What restoring all of them would write
+    pub restore_size: u64,
+    /// Unique data they reference, before compression
+    pub uncompressed_size: u64,
+    /// Unique data they reference, as stored
+    pub stored_size: u64,
+}
+
+impl DedupeRow {
+    /// How many times over the data would be stored without deduplication
+    pub fn dedupe_factor(&self) -> f64 {
+        ratio(self.restore_size, self.uncompressed_size)
+    }
+
+    pub fn compression_ratio(&self) -> f64 {
+        ratio(self.uncompressed_size, self.stored_size)
+    }
+
+    /// Share of the restore size that isn't stored thanks to both
+    pub fn saving(&self) -> f64 {
+        if self.restore_size == 0 {
+            0.0
+        } else {
+            1.0 - self.stored_size as f64 / self.restore_size as f64
+        }
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> f64 {
+    if denominator == 0 {
+        0.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DedupeGroup {
+    /// `job:<name>` or `tag:<tag>`
+    pub group: String,
+    pub months: Vec<DedupeRow>,
+}
+
+async fn restic_json<T: for<'de> Deserialize<'de>>(config: &Config, subcommand: &str, args: &[String]) -> Result<T> {
+    let mut cmd = crate::restic_command(&config.restic, subcommand);
+    cmd.args(args).arg("--json").arg("--no-lock");
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic {} failed: {}", subcommand, stderr.trim()));
+    }
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// `restic stats` in one mode over a set of snapshots. Batches are added up, so data shared
+/// between batches counts more than once; a month rarely has that many snapshots.
+async fn stats(config: &Config, mode: &str, ids: &[String]) -> Result<Stats> {
+    let mut total = Stats::default();
+    for batch in ids.chunks(IDS_PER_CALL) {
+        let mut args = vec!["--mode".to_string(), mode.to_string()];
+        args.extend(batch.iter().cloned());
+        let stats: Stats = restic_json(config, "stats", &args).await?;
+        total.total_size += stats.total_size;
+        total.total_uncompressed_size += stats.total_uncompressed_size;
+    }
+    Ok(total)
+}
+
+/// Sizes of the snapshots month by month
+async fn by_month(config: &Config, group: String, snapshots: Vec<&Snapshot>) -> Result<DedupeGroup> {
+    let mut months: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for snapshot in snapshots {
+        months.entry(snapshot.time.with_timezone(&Local).format("%Y-%m").to_string()).or_default().push(snapshot.id.clone());
+    }
+    let mut rows = Vec::new();
+    for (month, ids) in months {
+        let restore = stats(config, "restore-size", &ids).await?;
+        let raw = stats(config, "raw-data", &ids).await?;
+        rows.push(DedupeRow {
+            month,
+            snapshots: ids.len(),
+            restore_size: restore.total_size,
+            // Repositories without compression (format version 1) don't report it
+            uncompressed_size: if raw.total_uncompressed_size > 0 { raw.total_uncompressed_size } else { raw.total_size },
+            stored_size: raw.total_size,
+        });
+    }
+    Ok(DedupeGroup { group, months: rows })
+}
+
+/// Compare restore size with stored data per job (or per tag) and month
+pub async fn report(config: &Config, jobs: &[&BackupConfig], tags: &[&String]) -> Result<Vec<DedupeGroup>> {
+    let mut groups = Vec::new();
+
+    if !tags.is_empty() {
+        for tag in tags {
+            let args = vec!["--tag".to_string(), tag.to_string()];
+            let tagged: Vec<Snapshot> = restic_json(config, "snapshots", &args).await?;
+            groups.push(by_month(config, format!("tag:{}", tag), tagged.iter().collect()).await?);
+        }
+        return Ok(groups);
+    }
+
+    let reports_config = config
+        .reports
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Telling jobs' snapshots apart needs run reports; configure 'reports.directory' or pass --tag"))?;
+    let all_reports = reports::list(&crate::expand_tilde(&reports_config.directory));
+    let snapshots: Vec<Snapshot> = restic_json(config, "snapshots", &[]).await?;
+    for job in jobs {
+        let ids = reports::snapshot_ids(&all_reports, &job.name);
+        let job_snapshots = snapshots
+            .iter()
+            .filter(|snapshot| ids.iter().any(|id| snapshot.id.starts_with(id) || id.starts_with(&snapshot.id)))
+            .collect();
+        groups.push(by_month(config, format!("job:{}", job.name), job_snapshots).await?);
+    }
+    Ok(groups)
+}
+
+pub fn render_text(groups: &[DedupeGroup]) -> String {
+    let mut out = String::new();
+    for group in groups {
+        out.push_str(&format!("{}\n", group.group));
+        if group.months.is_empty() {
+            out.push_str("  no snapshots\n\n");
+            continue;
+        }
+        out.push_str(&format!(
+            "  {:<8} {:>9} {:>12} {:>12} {:>12} {:>7} {:>11} {:>7}\n",
+            "month", "snapshots", "restore", "unique", "stored", "dedupe", "compression", "saved"
+        ));
+        for row in &group.months {
+            out.push_str(&format!(
+                "  {:<8} {:>9} {:>12} {:>12} {:>12} {:>6.1}x {:>10.2}x {:>6.1}%\n",
+                row.month,
+                row.snapshots,
+                format_bytes(row.restore_size),
+                format_bytes(row.uncompressed_size),
+                format_bytes(row.stored_size),
+                row.dedupe_factor(),
+                row.compression_ratio(),
+                row.saving() * 100.0
+            ));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// `report dedupe [--job NAME] [--tag TAG] [--format text|json]`
+pub async fn run(config: &Config, jobs: &[&BackupConfig], args: &[String]) -> Result<()> {
+    let tags = crate::flag_values(args, "--tag");
+    let groups = report(config, jobs, &tags).await?;
+    match crate::flag_value(args, "--format").map(String::as_str).unwrap_or("text") {
+        "text" => print!("{}", render_text(&groups)),
+        "json" => println!("{}", serde_json::to_string_pretty(&groups)?),
+        other => return Err(anyhow::anyhow!("Unknown format '{}'; use text or json", other)),
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::{reports, BackupConfig, Config};
+
+/// Problems with the jobs' `after` lists: unknown jobs and cycles, each as (job, message)
+pub fn problems(config: &Config) -> Vec<(String, String)> {
+    let jobs = config.jobs();
+    let mut problems = Vec::new();
+    for job in &jobs {
+        for dependency in &job.after {
+            if config.find_job(dependency).is_none() {
+                problems.push((job.name.clone(), format!("'after' names unknown job '{}'", dependency)));
+            }
+        }
+    }
+
+    // Depth-first search from every job; reaching a job already on the path closes a cycle
+    let mut reported: HashSet<String> = HashSet::new();
+    for job in &jobs {
+        let mut path = vec![job.name.clone()];
+        if let Some(cycle) = find_cycle(config, &mut path) {
+            let mut members = cycle.clone();
+            members.sort();
+            if reported.insert(members.join(",")) {
+                problems.push((job.name.clone(), format!("Jobs depend on each other in a cycle: {}", cycle.join(" -> "))));
+            }
+        }
+    }
+    problems
+}
+
+fn find_cycle(config: &Config, path: &mut Vec<String>) -> Option<Vec<String>> {
+    let current = config.find_job(path.last()?)?;
+    for dependency in &current.after {
+        if let Some(start) = path.iter().position(|name| name == dependency) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(dependency.clone());
+            return Some(cycle);
+        }
+        path.push(dependency.clone());
+        let cycle = find_cycle(config, path);
+        path.pop();
+        if cycle.is_some() {
+            return cycle;
+        }
+    }
+    None
+}
+
+/// Refuse a config whose jobs can't be put in order
+pub fn check(config: &Config) -> Result<()> {
+    match problems(config).into_iter().next() {
+        Some((job, message)) => Err(anyhow::anyhow!("Job '{}': {}", job, message)),
+        None => Ok(()),
+    }
+}
+
+/// The jobs with every job after the ones it names, otherwise in the given order
+pub fn order<'a>(jobs: &[&'a BackupConfig]) -> Vec<&'a BackupConfig> {
+    let mut ordered: Vec<&BackupConfig> = Vec::with_capacity(jobs.len());
+    let mut remaining: Vec<&BackupConfig> = jobs.to_vec();
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .position(|job| {
+                job.after
+                    .iter()
+                    .all(|dependency| !remaining.iter().any(|other| &other.name == dependency))
+            })
+            // Only a cycle leaves nothing ready, and the config check rules those out
+            .unwrap_or(0);
+        ordered.push(remaining.remove(ready));
+    }
+    ordered
+}
+
+/// Why the job mustn't run: a job it comes after failed in this batch (`failed`), or, when that job
+/// isn't part of the batch, its latest run failed
+pub fn unmet(config: &Config, job: &BackupConfig, batch: &[&BackupConfig], failed: &[String]) -> Option<String> {
+    let latest_runs = config
+        .reports
+        .as_ref()
+        .map(|reports_config| reports::list(&crate::expand_tilde(&reports_config.directory)))
+        .unwrap_or_default();
+    for dependency in &job.after {
+        if batch.iter().any(|other| &other.name == dependency) {
+            if failed.contains(dependency) {
+                return Some(format!("job '{}' failed", dependency));
+            }
+        } else if let Some(report) = latest_runs.iter().find(|report| &report.job == dependency && !report.dry_run) {
+            if report.status == "failed" {
+                return Some(format!("the last run of job '{}' failed", dependency));
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,270 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::maintenance::{self, MaintenanceReport};
+use crate::notify::{self, RunEvent};
+use crate::reports::{self, RunReport};
+use crate::restic::format_bytes;
+use crate::{cost, Config};
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DigestConfig {
+    /// Number of days the digest covers
+    #[serde(default = "default_days")]
+    pub days: i64,
+    /// URL the digest is POSTed to as JSON
+    #[serde(default)]
+    pub webhook: Option<String>,
+    /// Names of notification targets (e.g. an email target) that receive the text digest
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+/// How long the webhook may take to accept the digest
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+fn default_days() -> i64 {
+    7
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobDigest {
+    pub job: String,
+    pub runs: usize,
+    pub succeeded: usize,
+    pub warnings: usize,
+    pub failed: usize,
+    pub success_rate: f64,
+    pub data_added: u64,
+    pub last_success: Option<DateTime<Local>>,
+    /// No successful run within twice the job's frequency
+    pub stale: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Digest {
+    pub hostname: String,
+    pub period_start: DateTime<Local>,
+    pub period_end: DateTime<Local>,
+    pub jobs: Vec<JobDigest>,
+    pub total_runs: usize,
+    pub total_data_added: u64,
+    pub stale_jobs: Vec<String>,
+    /// Stored size at the end of the period, from the last report that measured it
+    pub repository_size: Option<u64>,
+    /// Change in stored size over the period; negative when the repository shrank
+    pub repository_growth: Option<i64>,
+    /// Space prune freed during the period, from the maintenance reports
+    pub freed_by_prune: Option<u64>,
+    /// Storage cost now and upload cost of the period, if `cost` is configured
+    pub cost: Option<cost::CostEstimate>,
+}
+
+/// Aggregate the run reports of the last `days` days per configured job
+pub fn build(
+    config: &Config,
+    all_reports: &[RunReport],
+    maintenance_reports: &[MaintenanceReport],
+    days: i64,
+    now: DateTime<Local>,
+) -> Digest {
+    let period_start = now - Duration::days(days);
+    let mut jobs = Vec::new();
+
+    for job in config.jobs() {
+        let job_reports: Vec<&RunReport> = all_reports.iter().filter(|r| r.job == job.name && !r.dry_run).collect();
+        let in_period: Vec<&&RunReport> = job_reports.iter().filter(|r| r.finished_at >= period_start).collect();
+
+        let succeeded = in_period.iter().filter(|r| r.status == "succeeded").count();
+        let warnings = in_period.iter().filter(|r| r.status == "warning").count();
+        let failed = in_period.iter().filter(|r| r.status == "failed").count();
+        let data_added = in_period
+            .iter()
+            .filter_map(|r| r.summary.as_ref())
+            .map(|s| s.data_added)
+            .sum();
+
+        // Reports include runs before the period, so a stale job is caught even after a quiet week
+        let last_success = job_reports
+            .iter()
+            .filter(|r| r.status != "failed")
+            .map(|r| r.finished_at)
+            .max();
+        let stale = match (last_success, job.interval()) {
+            (Some(last), Some(interval)) => now - last > interval * 2,
+            (None, Some(_)) => true,
+            // Manual jobs aren't expected to run, so they are never stale
+            (_, None) => false,
+        };
+
+        jobs.push(JobDigest {
+            job: job.name.clone(),
+            runs: in_period.len(),
+            succeeded,
+            warnings,
+            failed,
+            success_rate: if in_period.is_empty() {
+                0.0
+            } else {
+                (succeeded + warnings) as f64 / in_period.len() as f64
+            },
+            data_added,
+            last_success,
+            stale,
+        });
+    }
+
+    // Growth is measured from the last size before the period, or the first one in it
+    let mut sizes: Vec<(DateTime<Local>, u64)> = all_reports
+        .iter()
+        .filter_map(|r| r.repository_size.map(|size| (r.finished_at, size)))
+        .filter(|(finished_at, _)| *finished_at <= now)
+        .collect();
+    sizes.sort_by_key(|(finished_at, _)| *finished_at);
+    let repository_size = sizes.last().map(|(_, size)| *size);
+    let baseline = sizes
+        .iter()
+        .rev()
+        .find(|(finished_at, _)| *finished_at <= period_start)
+        .or_else(|| sizes.first());
+    let repository_growth = match (baseline, sizes.last()) {
+        (Some((start, from)), Some((end, to))) if start < end => Some(*to as i64 - *from as i64),
+        _ => None,
+    };
+
+    let freed: Vec<u64> = maintenance_reports
+        .iter()
+        .filter(|r| r.finished_at >= period_start && r.finished_at <= now)
+        .filter_map(|r| r.freed_bytes)
+        .collect();
+    let freed_by_prune = if freed.is_empty() { None } else { Some(freed.iter().sum()) };
+
+    Digest {
+        hostname: crate::hostname(),
+        period_start,
+        period_end: now,
+        total_runs: jobs.iter().map(|j| j.runs).sum(),
+        total_data_added: jobs.iter().map(|j| j.data_added).sum(),
+        stale_jobs: jobs.iter().filter(|j| j.stale).map(|j| j.job.clone()).collect(),
+        repository_size,
+        repository_growth,
+        freed_by_prune,
+        cost: None,
+        jobs,
+    }
+}
+
+impl Digest {
+    pub fn title(&self) -> String {
+        format!(
+            "Backup digest for {} ({} - {})",
+            self.hostname,
+            self.period_start.format("%Y-%m-%d"),
+            self.period_end.format("%Y-%m-%d")
+        )
+    }
+
+    /// Plain-text body for email and chat targets
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!(
+            "{} runs, {} added to the repository\n\n",
+            self.total_runs,
+            format_bytes(self.total_data_added)
+        ));
+        if let Some(size) = self.repository_size {
+            out.push_str(&format!("Repository size: {}", format_bytes(size)));
+            match self.repository_growth {
+                Some(growth) if growth >= 0 => out.push_str(&format!(", grew by {}", format_bytes(growth as u64))),
+                Some(growth) => out.push_str(&format!(", shrank by {}", format_bytes(growth.unsigned_abs()))),
+                None => {}
+            }
+            out.push('\n');
+        }
+        if let Some(freed) = self.freed_by_prune {
+            out.push_str(&format!("Freed by prune: {}\n", format_bytes(freed)));
+        }
+        if self.repository_size.is_some() || self.freed_by_prune.is_some() {
+            out.push('\n');
+        }
+
+        for job in &self.jobs {
+            out.push_str(&format!(
+                "{}{}\n  runs: {} ({} ok, {} with warnings, {} failed), success rate {:.0}%\n  data added: {}\n  last success: {}\n",
+                job.job,
+                if job.stale { "  [STALE]" } else { "" },
+                job.runs,
+                job.succeeded,
+                job.warnings,
+                job.failed,
+                job.success_rate * 100.0,
+                format_bytes(job.data_added),
+                job.last_success
+                    .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "never".to_string()),
+            ));
+        }
+
+        if !self.stale_jobs.is_empty() {
+            out.push_str(&format!("\nStale jobs: {}\n", self.stale_jobs.join(", ")));
+        }
+        if let Some(ref cost) = self.cost {
+            out.push_str(&format!("\nEstimated cost: {}\n", cost.describe("uploaded this period")));
+        }
+        out
+    }
+}
+
+/// Build the digest from the reports directory, print it and deliver it to the configured webhook and
+/// targets. A failed delivery fails the command, so cron reports it.
+pub async fn run(config: &Config) -> Result<()> {
+    let digest_config = config
+        .digest
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("No 'digest' section in config"))?;
+    let reports_config = config
+        .reports
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("The digest is built from run reports; configure 'reports.directory' first"))?;
+
+    let all_reports = reports::list(&crate::expand_tilde(&reports_config.directory));
+    let maintenance_reports = maintenance::list(&crate::expand_tilde(&reports_config.directory));
+    let mut digest = build(config, &all_reports, &maintenance_reports, digest_config.days, Local::now());
+    if let Some(ref cost_config) = config.cost {
+        if let Some(size) = cost::repository_size(config, &all_reports).await {
+            digest.cost = Some(cost::CostEstimate::new(cost_config, size, digest.total_data_added));
+        }
+    }
+
+    println!("{}\n\n{}", digest.title(), digest.render_text());
+
+    let mut failed = Vec::new();
+    if let Some(ref url) = digest_config.webhook {
+        let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()?;
+        match client.post(url).json(&digest).send().await {
+            Ok(response) if response.status().is_success() => info!("Digest posted to {}", url),
+            Ok(response) => {
+                error!("Digest webhook {} returned {}", url, response.status());
+                failed.push("webhook".to_string());
+            }
+            Err(e) => {
+                error!("Failed to post digest to {}: {}", url, e.without_url());
+                failed.push("webhook".to_string());
+            }
+        }
+    }
+
+    if !digest_config.targets.is_empty() {
+        let event = if digest.stale_jobs.is_empty() { RunEvent::Succeeded } else { RunEvent::Warning };
+        failed.extend(
+            notify::send_message(&config.notifications, &digest_config.targets, &digest.title(), &digest.render_text(), event).await,
+        );
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!("The digest couldn't be delivered to: {}", failed.join(", ")));
+    }
+    Ok(())
+}
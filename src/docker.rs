@@ -0,0 +1,209 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::process::Command;
+
+use crate::{events, restic, BackupConfig, ResticConfig};
+
+/// Back up Docker or Podman named volumes, one snapshot per volume tagged `volume:<name>`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DockerVolumesConfig {
+    /// Container runtime CLI, `docker` or `podman`
+    #[serde(default = "default_runtime")]
+    pub runtime: String,
+    /// Only volumes with all of these labels, e.g. `backup=true`
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Only these volumes (all volumes matching `labels` when empty)
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    /// Containers stopped for a consistent backup and started again afterwards
+    #[serde(default)]
+    pub stop_containers: Vec<String>,
+    /// Containers paused (frozen, not stopped) during the backup
+    #[serde(default)]
+    pub pause_containers: Vec<String>,
+    /// How to reach the volume data on the host
+    #[serde(default)]
+    pub access: VolumeAccess,
+}
+
+fn default_runtime() -> String {
+    "docker".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VolumeAccess {
+    /// Read the volume's `Mountpoint` directly (needs root for Docker)
+    #[default]
+    Mountpoint,
+    /// Mount each volume temporarily with `<runtime> volume mount` and unmount it afterwards (Podman)
+    Mount,
+}
+
+async fn runtime_output(runtime: &str, args: &[&str]) -> Result<String> {
+    debug!("Running: {} {}", runtime, args.join(" "));
+    let output = Command::new(runtime)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", runtime, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("{} {} failed: {}", runtime, args.join(" "), stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Names of the volumes to back up
+pub async fn list_volumes(config: &DockerVolumesConfig) -> Result<Vec<String>> {
+    let mut args = vec!["volume".to_string(), "ls".to_string(), "--quiet".to_string()];
+    for label in &config.labels {
+        args.push("--filter".to_string());
+        args.push(format!("label={}", label));
+    }
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let listed = runtime_output(&config.runtime, &args).await?;
+
+    let mut volumes: Vec<String> = listed.lines().map(str::to_string).collect();
+    if !config.volumes.is_empty() {
+        for name in &config.volumes {
+            if !volumes.contains(name) {
+                return Err(anyhow::anyhow!("Volume '{}' not found (or doesn't match the labels)", name));
+            }
+        }
+        volumes.retain(|volume| config.volumes.contains(volume));
+    }
+    Ok(volumes)
+}
+
+async fn is_running(runtime: &str, container: &str) -> bool {
+    runtime_output(runtime, &["inspect", "--format", "{{.State.Running}}", container])
+        .await
+        .map(|running| running == "true")
+        .unwrap_or(false)
+}
+
+/// Stop and pause the configured containers that are running, recording what to undo afterwards
+async fn quiesce(config: &DockerVolumesConfig, stopped: &mut Vec<String>, paused: &mut Vec<String>) -> Result<()> {
+    for container in &config.stop_containers {
+        if is_running(&config.runtime, container).await {
+            info!("Stopping container {}", container);
+            runtime_output(&config.runtime, &["stop", container]).await?;
+            stopped.push(container.clone());
+        }
+    }
+    for container in &config.pause_containers {
+        if is_running(&config.runtime, container).await {
+            info!("Pausing container {}", container);
+            runtime_output(&config.runtime, &["pause", container]).await?;
+            paused.push(container.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Restart what `quiesce` stopped or paused, logging instead of failing so every container gets a chance
+async fn resume(runtime: &str, stopped: &[String], paused: &[String]) {
+    for container in paused {
+        info!("Unpausing container {}", container);
+        if let Err(e) = runtime_output(runtime, &["unpause", container]).await {
+            error!("Failed to unpause container {}: {}", container, e);
+        }
+    }
+    for container in stopped {
+        info!("Starting container {}", container);
+        if let Err(e) = runtime_output(runtime, &["start", container]).await {
+            error!("Failed to start container {}: {}", container, e);
+        }
+    }
+}
+
+/// Host path of a volume's data, and whether it was mounted just for this backup
+async fn volume_path(config: &DockerVolumesConfig, volume: &str) -> Result<(PathBuf, bool)> {
+    match config.access {
+        VolumeAccess::Mountpoint => {
+            let path = runtime_output(&config.runtime, &["volume", "inspect", "--format", "{{.Mountpoint}}", volume]).await?;
+            Ok((PathBuf::from(path), false))
+        }
+        VolumeAccess::Mount => {
+            let path = runtime_output(&config.runtime, &["volume", "mount", volume]).await?;
+            Ok((PathBuf::from(path), true))
+        }
+    }
+}
+
+async fn backup_volume(
+    job: &BackupConfig,
+    config: &DockerVolumesConfig,
+    volume: &str,
+    restic_config: &ResticConfig,
+    dry_run: bool,
+    verbose: bool,
+    reporter: Option<&events::RunReporter>,
+) -> Result<restic::BackupSummary> {
+    let (path, mounted) = volume_path(config, volume).await?;
+    info!("Backing up volume {} from {:?}", volume, path);
+
+    // A plain job for this one volume; the job's excludes and tags still apply
+    let mut volume_job = job.clone();
+    volume_job.directories = vec![path];
+    volume_job.profile = None;
+    volume_job.docker_volumes = None;
+    volume_job.tags.push(format!("volume:{}", volume));
+
+    let result = crate::execute_restic_backup(&volume_job, restic_config, dry_run, verbose, reporter).await;
+
+    if mounted {
+        if let Err(e) = runtime_output(&config.runtime, &["volume", "unmount", volume]).await {
+            error!("Failed to unmount volume {}: {}", volume, e);
+        }
+    }
+    result.map_err(|e| anyhow::anyhow!("Volume {}: {}", volume, e))
+}
+
+/// Back up every selected volume with the containers quiesced, carrying on past failed volumes.
+/// The returned summary adds up all volumes and carries the last snapshot id.
+pub async fn backup_volumes(
+    job: &BackupConfig,
+    config: &DockerVolumesConfig,
+    restic_config: &ResticConfig,
+    dry_run: bool,
+    verbose: bool,
+    reporter: Option<&events::RunReporter>,
+) -> Result<restic::BackupSummary> {
+    let volumes = list_volumes(config).await?;
+    if volumes.is_empty() {
+        return Err(anyhow::anyhow!("No {} volumes to back up", config.runtime));
+    }
+    info!("Backing up {} volume(s): {}", volumes.len(), volumes.join(", "));
+
+    let mut stopped = Vec::new();
+    let mut paused = Vec::new();
+    if let Err(e) = quiesce(config, &mut stopped, &mut paused).await {
+        // Bring back whatever was already stopped before giving up
+        resume(&config.runtime, &stopped, &paused).await;
+        return Err(e);
+    }
+
+    let mut summary = restic::BackupSummary::default();
+    let mut failed = Vec::new();
+    for volume in &volumes {
+        match backup_volume(job, config, volume, restic_config, dry_run, verbose, reporter).await {
+            Ok(volume_summary) => summary.merge(volume_summary),
+            Err(e) => {
+                error!("{}", e);
+                failed.push(e.to_string());
+            }
+        }
+    }
+
+    resume(&config.runtime, &stopped, &paused).await;
+
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!("{} of {} volumes failed: {}", failed.len(), volumes.len(), failed.join("; ")));
+    }
+    Ok(summary)
+}
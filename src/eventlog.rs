@@ -0,0 +1,64 @@
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+use log::{Level, LevelFilter, Record};
+
+/// Event source the entries appear under in the Application log
+pub const SOURCE: &str = "better-restic-client";
+/// `eventcreate` rejects longer descriptions
+const MAX_MESSAGE_LEN: usize = 4000;
+
+/// `logging.event_log`: "off", "error", "warn" or "info"
+pub fn parse_level(level: &str) -> Option<LevelFilter> {
+    LevelFilter::from_str(level).ok().filter(|level| *level <= LevelFilter::Info)
+}
+
+/// Write one entry to the Windows Application log. The first entry creates the event source,
+/// which needs an elevated prompt; `install-service` does that while it runs as administrator.
+pub fn write(level: Level, message: &str) -> std::io::Result<()> {
+    let (kind, id) = match level {
+        Level::Error => ("ERROR", "3"),
+        Level::Warn => ("WARNING", "2"),
+        _ => ("INFORMATION", "1"),
+    };
+    let message: String = message.chars().take(MAX_MESSAGE_LEN).collect();
+    let status = Command::new("eventcreate")
+        .args(["/L", "APPLICATION", "/SO", SOURCE, "/T", kind, "/ID", id, "/D", &message])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("eventcreate exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// Log target copying records at or above a level to the Event Log, next to the log files
+pub struct EventLogWriter {
+    level: LevelFilter,
+}
+
+impl EventLogWriter {
+    pub fn new(level: LevelFilter) -> Self {
+        EventLogWriter { level }
+    }
+}
+
+impl LogWriter for EventLogWriter {
+    fn write(&self, _now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        if record.level() > self.level {
+            return Ok(());
+        }
+        write(record.level(), &record.args().to_string())
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn max_log_level(&self) -> LevelFilter {
+        self.level
+    }
+}
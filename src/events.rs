@@ -0,0 +1,176 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::debug;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+/// Number of events buffered for slow subscribers before they start missing events
+const EVENT_BUFFER: usize = 256;
+
+/// Lifecycle events published to `/api/events` subscribers
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    JobStarted {
+        job: String,
+        run_id: String,
+        dry_run: bool,
+    },
+    JobProgress {
+        job: String,
+        run_id: String,
+        percent_done: f64,
+        files_done: u64,
+        total_files: u64,
+        bytes_done: u64,
+        total_bytes: u64,
+        /// Bytes processed per second over the last few seconds, 0 until known
+        bytes_per_sec: f64,
+    },
+    JobFinished {
+        job: String,
+        run_id: String,
+        duration_secs: f64,
+    },
+    JobFailed {
+        job: String,
+        run_id: String,
+        duration_secs: f64,
+        error: String,
+    },
+    /// The job's `only_if` conditions didn't hold, so it didn't run
+    JobSkipped {
+        job: String,
+        run_id: String,
+        reason: String,
+    },
+    ConfigChanged {
+        source: String,
+    },
+}
+
+impl Event {
+    /// SSE event name, matching the serialized `type` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::JobStarted { .. } => "job_started",
+            Event::JobProgress { .. } => "job_progress",
+            Event::JobFinished { .. } => "job_finished",
+            Event::JobFailed { .. } => "job_failed",
+            Event::JobSkipped { .. } => "job_skipped",
+            Event::ConfigChanged { .. } => "config_changed",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        EventBus { sender }
+    }
+
+    pub fn publish(&self, event: Event) {
+        debug!("Publishing event: {:?}", event);
+        // An error only means nobody is subscribed right now
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Create a reporter for a new run of the given job
+    pub fn reporter(&self, job: &str) -> RunReporter {
+        RunReporter {
+            bus: self.clone(),
+            job: job.to_string(),
+            run_id: new_run_id(),
+            started: std::time::Instant::now(),
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Publishes the lifecycle events of a single run
+#[derive(Clone)]
+pub struct RunReporter {
+    bus: EventBus,
+    pub job: String,
+    pub run_id: String,
+    started: std::time::Instant,
+}
+
+impl RunReporter {
+    pub fn started(&self, dry_run: bool) {
+        self.bus.publish(Event::JobStarted {
+            job: self.job.clone(),
+            run_id: self.run_id.clone(),
+            dry_run,
+        });
+    }
+
+    /// Publish progress from a restic `--json` status message and the current rate
+    pub fn progress(&self, status: &serde_json::Value, bytes_per_sec: Option<f64>) {
+        self.bus.publish(Event::JobProgress {
+            job: self.job.clone(),
+            run_id: self.run_id.clone(),
+            percent_done: status["percent_done"].as_f64().unwrap_or(0.0),
+            files_done: status["files_done"].as_u64().unwrap_or(0),
+            total_files: status["total_files"].as_u64().unwrap_or(0),
+            bytes_done: status["bytes_done"].as_u64().unwrap_or(0),
+            total_bytes: status["total_bytes"].as_u64().unwrap_or(0),
+            bytes_per_sec: bytes_per_sec.unwrap_or(0.0),
+        });
+    }
+
+    pub fn finished(&self) {
+        self.bus.publish(Event::JobFinished {
+            job: self.job.clone(),
+            run_id: self.run_id.clone(),
+            duration_secs: self.started.elapsed().as_secs_f64(),
+        });
+    }
+
+    pub fn skipped(&self, reason: &str) {
+        self.bus.publish(Event::JobSkipped {
+            job: self.job.clone(),
+            run_id: self.run_id.clone(),
+            reason: reason.to_string(),
+        });
+    }
+
+    pub fn failed(&self, error: &str) {
+        self.bus.publish(Event::JobFailed {
+            job: self.job.clone(),
+            run_id: self.run_id.clone(),
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            error: error.to_string(),
+        });
+    }
+}
+
+/// The last id `new_run_id` handed out
+static LAST_RUN_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Id for a run: start time in milliseconds, bumped past the last id so jobs started together
+/// don't share one and overwrite each other's reports and manifests
+pub fn new_run_id() -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let previous = LAST_RUN_ID
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| Some(now.max(last + 1)))
+        .expect("the update always returns Some");
+    now.max(previous + 1).to_string()
+}
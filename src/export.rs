@@ -0,0 +1,159 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::{ignore_files, shell_quote, templates, BackupConfig, Config};
+
+/// Quote a path, keeping a leading `~/` outside the quotes so the shell still expands it
+fn shell_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("~/{}", shell_quote(rest)),
+        None => shell_quote(&path),
+    }
+}
+
+/// Quote a value that may contain placeholders, leaving them for the script to fill in at run time
+fn shell_template(value: &str, job: Option<&str>) -> String {
+    if value.contains('{') {
+        templates::to_shell(value, job)
+    } else {
+        shell_quote(value)
+    }
+}
+
+/// The restic backup command for a job, as `execute_restic_backup` builds it
+fn backup_command(job: &BackupConfig, out: &mut String) {
+    let mut args = vec!["restic backup".to_string()];
+    let mut assignments: Vec<String> = job.env.iter().map(|(name, value)| format!("{}={}", name, shell_quote(value))).collect();
+    if let Some(ref tmp_dir) = job.tmp_dir {
+        let _ = writeln!(out, "mkdir -p {}", shell_path(tmp_dir));
+        assignments.push(format!("TMPDIR={}", shell_path(tmp_dir)));
+    }
+    if let Some(ref cache_dir) = job.cache_dir {
+        assignments.push(format!("RESTIC_CACHE_DIR={}", shell_path(cache_dir)));
+    }
+    if !assignments.is_empty() {
+        args[0] = format!("{} {}", assignments.join(" "), args[0]);
+    }
+    if let Some(ref user) = job.run_as {
+        // --preserve-env keeps the exported RESTIC_* variables
+        let env = if assignments.is_empty() { "" } else { "env " };
+        args[0] = format!("sudo --preserve-env -u {} {}{}", shell_quote(user), env, args[0]);
+    }
+    if let Some(ref workdir) = job.workdir {
+        let _ = writeln!(out, "pushd {} >/dev/null", shell_path(workdir));
+    }
+    if job.uses_one_file_system() {
+        args.push("--one-file-system".to_string());
+    }
+    for tag in &job.tags {
+        args.push(format!("--tag {}", shell_template(tag, Some(&job.name))));
+    }
+    for exclude in job.effective_excludes() {
+        args.push(format!("--exclude {}", shell_path(&exclude)));
+    }
+
+    // Ignore file patterns are captured now; rerun the export after changing the ignore files
+    let ignore_patterns = ignore_files::collect(job);
+    if !ignore_patterns.is_empty() {
+        let _ = writeln!(out, "excludes=$(mktemp)");
+        let _ = writeln!(out, "cat > \"$excludes\" <<'EXCLUDES'\n{}\nEXCLUDES", ignore_patterns.join("\n"));
+        args.push("--exclude-file \"$excludes\"".to_string());
+    }
+    for dir in job.effective_directories() {
+        args.push(shell_path(&dir));
+    }
+
+    // Exit code 3 means some files couldn't be read, which the client reports as a warning
+    let _ = writeln!(out, "{} || [ $? -eq 3 ]", args.join(" \\\n    "));
+    if !ignore_patterns.is_empty() {
+        let _ = writeln!(out, "rm -f \"$excludes\"");
+    }
+    if job.workdir.is_some() {
+        let _ = writeln!(out, "popd >/dev/null");
+    }
+}
+
+/// A standalone bash script running the same restic commands as the client: the backup of every job,
+/// then forget, prune and check as `maintain` would
+pub fn shell_script(config: &Config, jobs: &[&BackupConfig]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "#!/usr/bin/env bash");
+    let _ = writeln!(out, "# Generated by `better_restic_client export --format shell`");
+    let _ = writeln!(out, "set -euo pipefail\n");
+
+    let restic = &config.restic;
+    let _ = writeln!(out, "export RESTIC_REPOSITORY={}", shell_template(&restic.repository, None));
+    if let Some(ref command) = restic.password_command {
+        let _ = writeln!(out, "export RESTIC_PASSWORD_COMMAND={}", shell_quote(command));
+    } else if let Some(ref password_file) = restic.password_file {
+        let _ = writeln!(out, "export RESTIC_PASSWORD_FILE={}", shell_path(password_file));
+    } else if let Some(ref password) = restic.password {
+        let _ = writeln!(out, "# Plain-text password copied from config.yaml");
+        let _ = writeln!(out, "export RESTIC_PASSWORD={}", shell_quote(password));
+    }
+    // restic has no environment variables for these, so every restic call gets them
+    let mut options: Vec<String> = crate::ssh::sftp_command(restic).map(|command| format!("sftp.command={}", command)).into_iter().collect();
+    options.extend(crate::rclone::options(restic));
+    if !options.is_empty() || restic.binary.is_some() {
+        let program = restic.binary.as_deref().map_or_else(|| "command restic".to_string(), shell_path);
+        let options: Vec<String> = options.iter().map(|option| format!(" -o {}", shell_quote(option))).collect();
+        let _ = writeln!(out, "restic() {{ {}{} \"$@\"; }}", program, options.concat());
+    }
+    if let (None, Some(ref ssh_command)) = (&restic.ssh, &restic.ssh_command) {
+        let _ = writeln!(out, "export RESTIC_SSH_COMMAND={}", shell_quote(ssh_command));
+    }
+    if let Some(ref cache_dir) = restic.cache_dir {
+        let _ = writeln!(out, "export RESTIC_CACHE_DIR={}", shell_path(cache_dir));
+    }
+
+    if let Some(ref mount) = restic.mount {
+        let path = shell_path(&mount.path);
+        let _ = writeln!(out, "\n# The repository's drive must be mounted");
+        match mount.mount_command {
+            Some(ref command) => {
+                let _ = writeln!(out, "mountpoint -q {} || {}", path, command);
+            }
+            None => {
+                let _ = writeln!(out, "mountpoint -q {} || {{ echo \"{} is not mounted\" >&2; exit 1; }}", path, mount.path.display());
+            }
+        }
+    }
+
+    for job in jobs {
+        let _ = writeln!(out, "\n# Job '{}' ({} at {})", job.name, job.frequency, job.time);
+        if job.docker_volumes.is_some() || job.kubernetes.is_some() || job.fs_snapshot.is_some() || job.cold_export.is_some() {
+            let _ = writeln!(out, "# Not exported: container volume, Kubernetes, filesystem snapshot and cold export jobs need the client");
+            continue;
+        }
+        backup_command(job, &mut out);
+    }
+
+    let maintenance = config.maintenance.clone().unwrap_or_default();
+    let _ = writeln!(out, "\n# Maintenance");
+    if !maintenance.has_policy() {
+        let _ = writeln!(out, "# No retention policy configured, so nothing is forgotten or pruned");
+    } else {
+        for run in maintenance.forget_runs(&[]) {
+            let args: Vec<String> = run.args.iter().map(|arg| shell_quote(arg)).collect();
+            if !maintenance.groups.is_empty() {
+                let _ = writeln!(out, "# {}", run.label);
+            }
+            let _ = writeln!(out, "restic forget {}", args.join(" "));
+        }
+        if maintenance.needs_hosts() {
+            let _ = writeln!(out, "# The top-level policy is applied per host by the client, since groups select by host;");
+            let _ = writeln!(out, "# run it with --host for each host outside those groups");
+        }
+        let _ = writeln!(out, "restic prune");
+    }
+    match maintenance.read_data_subset {
+        Some(ref subset) => {
+            let _ = writeln!(out, "restic check --read-data-subset={}", shell_quote(subset));
+        }
+        None => {
+            let _ = writeln!(out, "restic check");
+        }
+    }
+    out
+}
@@ -0,0 +1,68 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::snapshots::Snapshot;
+
+/// One snapshot's copy of a file
+#[derive(Debug, Serialize, Clone)]
+pub struct FileVersion {
+    pub snapshot_id: String,
+    pub short_id: String,
+    /// When the snapshot was taken
+    pub time: Option<String>,
+    pub hostname: Option<String>,
+    pub size: Option<u64>,
+    pub mtime: Option<String>,
+    /// The first copy, or size or modification time differ from the previous one
+    pub changed: bool,
+}
+
+/// restic `find` pattern matching exactly this path, with glob characters escaped
+pub fn pattern(path: &str) -> String {
+    let mut pattern = String::with_capacity(path.len());
+    for c in path.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+/// Versions of `path` from `restic find --json` output, oldest first, with the time and host of each
+/// snapshot taken from `restic snapshots --json`
+pub fn build(path: &str, found: &Value, snapshots: &[Snapshot]) -> Vec<FileVersion> {
+    let mut versions: Vec<FileVersion> = found
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|result| {
+            let snapshot_id = result["snapshot"].as_str()?;
+            let node = result["matches"].as_array()?.iter().find(|node| node["path"].as_str() == Some(path))?;
+            let snapshot = snapshots.iter().find(|snapshot| snapshot.id == snapshot_id);
+            Some(FileVersion {
+                snapshot_id: snapshot_id.to_string(),
+                short_id: crate::restic::short_id(snapshot_id).to_string(),
+                time: snapshot.map(|snapshot| snapshot.time.to_rfc3339()),
+                hostname: snapshot.map(|snapshot| snapshot.hostname.clone()),
+                size: node["size"].as_u64(),
+                mtime: node["mtime"].as_str().map(str::to_string),
+                changed: false,
+            })
+        })
+        .collect();
+
+    // Snapshots missing from the list go last
+    versions.sort_by_key(|version| {
+        let time = version.time.as_deref().and_then(|time| chrono::DateTime::parse_from_rfc3339(time).ok());
+        (time.is_none(), time)
+    });
+    let mut previous: Option<(Option<u64>, Option<String>)> = None;
+    for version in &mut versions {
+        let current = (version.size, version.mtime.clone());
+        version.changed = previous.as_ref() != Some(&current);
+        previous = Some(current);
+    }
+    versions
+}
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{restic, Config};
+
+/// The ID a repository had when it was first backed up to
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PinnedRepository {
+    pub id: String,
+    pub since: DateTime<Local>,
+}
+
+/// Pinned repository IDs by repository URL, next to the logs
+pub fn path(config: &Config) -> PathBuf {
+    crate::expand_tilde(&config.logging.directory.join("repository-ids.json"))
+}
+
+pub fn pinned(config: &Config) -> BTreeMap<String, PinnedRepository> {
+    std::fs::read(path(config))
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(config: &Config, pinned: &BTreeMap<String, PinnedRepository>) -> Result<()> {
+    let path = path(config);
+    std::fs::write(&path, serde_json::to_vec_pretty(pinned)?).map_err(|e| anyhow::anyhow!("Couldn't write {:?}: {}", path, e))
+}
+
+/// The repository's ID from `restic cat config`
+async fn repository_id(config: &Config) -> Result<String> {
+    let output = crate::restic_command(&config.restic, "cat").arg("config").output().await?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("restic cat config failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let repository_config: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    repository_config["id"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("restic cat config didn't return a repository ID"))
+}
+
+/// Refuse a repository whose ID differs from the one pinned for its URL, e.g. one that was
+/// re-initialized or that a hijacked hostname points elsewhere. The first backup pins the ID;
+/// dry runs check it but don't pin it.
+pub async fn check(config: &Config, dry_run: bool) -> Result<()> {
+    let repository = config.restic.repository_url();
+    let id = match repository_id(config).await {
+        Ok(id) => id,
+        Err(e) => {
+            // restic fails the same way when it backs up, with the better message
+            warn!("Couldn't read the ID of repository {}: {}", repository, e);
+            return Ok(());
+        }
+    };
+
+    let mut repositories = pinned(config);
+    match repositories.get(&repository) {
+        Some(pinned) if pinned.id == id => Ok(()),
+        Some(pinned) => Err(anyhow::anyhow!(
+            "Repository {} has ID {}, but it had ID {} since {}. If it was re-created on purpose, run once with --accept-new-repo.",
+            repository,
+            restic::short_id(&id),
+            restic::short_id(&pinned.id),
+            pinned.since.format("%Y-%m-%d")
+        )),
+        None if dry_run => Ok(()),
+        None => {
+            info!("Pinning repository {} to ID {}", repository, restic::short_id(&id));
+            repositories.insert(repository, PinnedRepository { id, since: Local::now() });
+            save(config, &repositories)
+        }
+    }
+}
+
+/// Forget the repository's pinned ID, so the next backup pins whatever ID it has then
+pub fn accept_new(config: &Config) -> Result<()> {
+    let repository = config.restic.repository_url();
+    let mut repositories = pinned(config);
+    if let Some(previous) = repositories.remove(&repository) {
+        warn!("Accepting a new ID for repository {} (was {})", repository, restic::short_id(&previous.id));
+        save(config, &repositories)?;
+    }
+    Ok(())
+}
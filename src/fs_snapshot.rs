@@ -0,0 +1,201 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+use crate::{events, restic, BackupConfig, ResticConfig};
+
+/// Back up from a filesystem snapshot instead of the live filesystem, for crash-consistent backups of busy data
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FsSnapshotConfig {
+    #[serde(flatten)]
+    pub kind: SnapshotKind,
+    /// Where the snapshotted filesystem (LV, subvolume or dataset) is mounted; all job directories must be below it
+    pub source: PathBuf,
+    /// The snapshot is mounted at `<mount_root>/<job><source>`, so restic sees the same paths on every run
+    #[serde(default = "default_mount_root")]
+    pub mount_root: PathBuf,
+}
+
+fn default_mount_root() -> PathBuf {
+    PathBuf::from("/run/better-restic-client")
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SnapshotKind {
+    Lvm {
+        volume_group: String,
+        logical_volume: String,
+        /// Copy-on-write space reserved for changes during the backup
+        #[serde(default = "default_lvm_size")]
+        size: String,
+        /// Extra mount options, e.g. `nouuid` for XFS
+        #[serde(default)]
+        mount_options: Option<String>,
+    },
+    Btrfs,
+    Zfs {
+        dataset: String,
+    },
+}
+
+fn default_lvm_size() -> String {
+    "5G".to_string()
+}
+
+/// Name used for the snapshot volume, subvolume or ZFS snapshot of a job
+fn snapshot_name(job: &BackupConfig) -> String {
+    let name: String = job
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("restic-{}", name)
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<()> {
+    debug!("Running: {} {}", program, args.join(" "));
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("{} {} failed: {}", program, args.join(" "), stderr.trim()));
+    }
+    Ok(())
+}
+
+/// Where the snapshot of `source` is mounted for a job
+fn mount_point(job: &BackupConfig, config: &FsSnapshotConfig) -> PathBuf {
+    let relative = config.source.strip_prefix("/").unwrap_or(&config.source);
+    config.mount_root.join(&job.name).join(relative)
+}
+
+/// Path of a file under `source` as seen through the snapshot mount, or `None` if it isn't under `source`
+fn map_path(path: &Path, job: &BackupConfig, config: &FsSnapshotConfig) -> Option<PathBuf> {
+    let relative = crate::expand_tilde(path).strip_prefix(&config.source).ok()?.to_path_buf();
+    Some(mount_point(job, config).join(relative))
+}
+
+/// The btrfs snapshot lives next to the data, inside the same filesystem
+fn btrfs_snapshot_path(job: &BackupConfig, config: &FsSnapshotConfig) -> PathBuf {
+    config.source.join(format!(".{}", snapshot_name(job)))
+}
+
+async fn create(job: &BackupConfig, config: &FsSnapshotConfig, target: &Path) -> Result<()> {
+    let name = snapshot_name(job);
+    std::fs::create_dir_all(target).map_err(|e| anyhow::anyhow!("Failed to create {:?}: {}", target, e))?;
+    let target_str = target.to_string_lossy().to_string();
+
+    match config.kind {
+        SnapshotKind::Lvm {
+            ref volume_group,
+            ref logical_volume,
+            ref size,
+            ref mount_options,
+        } => {
+            let origin = format!("{}/{}", volume_group, logical_volume);
+            run("lvcreate", &["--snapshot", "--name", &name, "--size", size, &origin]).await?;
+            let options = match mount_options {
+                Some(extra) => format!("ro,{}", extra),
+                None => "ro".to_string(),
+            };
+            let device = format!("/dev/{}/{}", volume_group, name);
+            run("mount", &["-o", &options, &device, &target_str]).await?;
+        }
+        SnapshotKind::Btrfs => {
+            let snapshot = btrfs_snapshot_path(job, config).to_string_lossy().to_string();
+            let source = config.source.to_string_lossy().to_string();
+            run("btrfs", &["subvolume", "snapshot", "-r", &source, &snapshot]).await?;
+            run("mount", &["--bind", "-o", "ro", &snapshot, &target_str]).await?;
+        }
+        SnapshotKind::Zfs { ref dataset } => {
+            let snapshot = format!("{}@{}", dataset, name);
+            run("zfs", &["snapshot", &snapshot]).await?;
+            run("mount", &["-t", "zfs", "-o", "ro", &snapshot, &target_str]).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Unmount and remove the snapshot. Errors are logged unless `quiet`, which is used to clear
+/// leftovers of an interrupted run where most steps are expected to fail.
+async fn cleanup(job: &BackupConfig, config: &FsSnapshotConfig, target: &Path, quiet: bool) {
+    let name = snapshot_name(job);
+    let log = |result: Result<()>| {
+        if let Err(e) = result {
+            if quiet {
+                debug!("{}", e);
+            } else {
+                error!("Snapshot cleanup: {}", e);
+            }
+        }
+    };
+
+    if crate::mount::is_mountpoint(target) || !quiet {
+        log(run("umount", &[&target.to_string_lossy()]).await);
+    }
+    match config.kind {
+        SnapshotKind::Lvm { ref volume_group, .. } => {
+            log(run("lvremove", &["--yes", &format!("{}/{}", volume_group, name)]).await);
+        }
+        SnapshotKind::Btrfs => {
+            let snapshot = btrfs_snapshot_path(job, config);
+            if snapshot.exists() || !quiet {
+                log(run("btrfs", &["subvolume", "delete", &snapshot.to_string_lossy()]).await);
+            }
+        }
+        SnapshotKind::Zfs { ref dataset } => {
+            log(run("zfs", &["destroy", &format!("{}@{}", dataset, name)]).await);
+        }
+    }
+}
+
+/// Snapshot the source filesystem, back up the job's directories from the snapshot mount, then clean up
+pub async fn backup(
+    job: &BackupConfig,
+    config: &FsSnapshotConfig,
+    restic_config: &ResticConfig,
+    dry_run: bool,
+    verbose: bool,
+    reporter: Option<&events::RunReporter>,
+) -> Result<restic::BackupSummary> {
+    // Map paths first so a misconfigured job fails before anything is created
+    let mut directories = Vec::new();
+    for dir in job.effective_directories() {
+        let mapped = map_path(&dir, job, config)
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not on the snapshotted filesystem {:?}", dir, config.source))?;
+        directories.push(mapped);
+    }
+
+    let target = mount_point(job, config);
+    cleanup(job, config, &target, true).await;
+
+    info!("Creating filesystem snapshot of {:?} at {:?}", config.source, target);
+    if let Err(e) = create(job, config, &target).await {
+        cleanup(job, config, &target, true).await;
+        return Err(anyhow::anyhow!("Failed to create filesystem snapshot: {}", e));
+    }
+
+    // A plain job reading from the snapshot; excludes under the source are mapped too
+    let mut snapshot_job = job.clone();
+    snapshot_job.exclude = job
+        .effective_excludes()
+        .iter()
+        .map(|exclude| map_path(exclude, job, config).unwrap_or_else(|| exclude.clone()))
+        .collect();
+    snapshot_job.directories = directories;
+    snapshot_job.one_file_system = Some(job.uses_one_file_system());
+    snapshot_job.profile = None;
+    snapshot_job.fs_snapshot = None;
+
+    let result = crate::execute_restic_backup(&snapshot_job, restic_config, dry_run, verbose, reporter).await;
+
+    info!("Removing filesystem snapshot");
+    cleanup(job, config, &target, false).await;
+    result
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::restic::{format_bytes, short_id};
+use crate::{ls, reports, BackupConfig, Config};
+
+/// Paths listed per section without `--limit`
+const DEFAULT_LIMIT: usize = 20;
+
+#[derive(Debug, Deserialize, Default)]
+struct DiffCounts {
+    #[serde(default)]
+    bytes: u64,
+}
+
+/// The closing `statistics` message of `restic diff --json`
+#[derive(Debug, Deserialize, Default)]
+struct DiffStatistics {
+    #[serde(default)]
+    changed_files: u64,
+    #[serde(default)]
+    added: DiffCounts,
+    #[serde(default)]
+    removed: DiffCounts,
+}
+
+/// A file or directory that grew between the snapshots
+#[derive(Debug, Serialize)]
+pub struct GrownPath {
+    pub path: String,
+    /// Size in the newer snapshot
+    pub size: u64,
+    /// Growth since the older one; the whole size for new files
+    pub growth: u64,
+    pub new: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Growth {
+    pub job: String,
+    pub from: String,
+    pub to: String,
+    /// Data restic had to store for the newer snapshot
+    pub data_added: u64,
+    pub data_removed: u64,
+    pub changed_files: u64,
+    pub files: Vec<GrownPath>,
+    /// Growth added up per directory
+    pub directories: Vec<GrownPath>,
+}
+
+async fn diff_statistics(config: &Config, from: &str, to: &str) -> Result<DiffStatistics> {
+    let mut cmd = crate::restic_command(&config.restic, "diff");
+    cmd.args([from, to, "--json", "--no-lock"]);
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic diff failed: {}", stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|message| message["message_type"] == "statistics")
+        .and_then(|message| serde_json::from_value(message).ok())
+        .unwrap_or_default())
+}
+
+/// Compare two snapshots of a job: restic's diff statistics, plus the files that grew the most
+/// and the directories they're in, from both snapshots' listings
+pub async fn compare(config: &Config, job: &str, from: &str, to: &str, limit: usize) -> Result<Growth> {
+    let statistics = diff_statistics(config, from, to).await?;
+    let old_sizes: HashMap<String, u64> = ls::list_nodes(&config.restic, from, &[])
+        .await?
+        .into_iter()
+        .filter(|node| node.kind == "file")
+        .map(|node| (node.path, node.size))
+        .collect();
+
+    let mut files = Vec::new();
+    let mut directories: HashMap<String, u64> = HashMap::new();
+    for node in ls::list_nodes(&config.restic, to, &[]).await? {
+        if node.kind != "file" {
+            continue;
+        }
+        let old_size = old_sizes.get(&node.path).copied();
+        let growth = node.size.saturating_sub(old_size.unwrap_or(0));
+        if growth == 0 {
+            continue;
+        }
+        if let Some(parent) = Path::new(&node.path).parent() {
+            *directories.entry(parent.to_string_lossy().to_string()).or_default() += growth;
+        }
+        files.push(GrownPath {
+            path: node.path,
+            size: node.size,
+            growth,
+            new: old_size.is_none(),
+        });
+    }
+
+    files.sort_by_key(|file| std::cmp::Reverse(file.growth));
+    files.truncate(limit);
+    let mut directories: Vec<GrownPath> = directories
+        .into_iter()
+        .map(|(path, growth)| GrownPath {
+            path,
+            size: 0,
+            growth,
+            new: false,
+        })
+        .collect();
+    directories.sort_by_key(|directory| std::cmp::Reverse(directory.growth));
+    directories.truncate(limit);
+
+    Ok(Growth {
+        job: job.to_string(),
+        from: from.to_string(),
+        to: to.to_string(),
+        data_added: statistics.added.bytes,
+        data_removed: statistics.removed.bytes,
+        changed_files: statistics.changed_files,
+        files,
+        directories,
+    })
+}
+
+impl Growth {
+    pub fn render_text(&self) -> String {
+        let mut out = format!(
+            "{}: {} -> {}\n  {} added, {} removed, {} files changed\n",
+            self.job,
+            short_id(&self.from),
+            short_id(&self.to),
+            format_bytes(self.data_added),
+            format_bytes(self.data_removed),
+            self.changed_files
+        );
+        if self.files.is_empty() {
+            out.push_str("  no files grew\n");
+            return out;
+        }
+        out.push_str("  Biggest new and grown files:\n");
+        for file in &self.files {
+            let label = if file.new { "new".to_string() } else { format!("of {}", format_bytes(file.size)) };
+            out.push_str(&format!("    {:>12}  {}  ({})\n", format!("+{}", format_bytes(file.growth)), file.path, label));
+        }
+        out.push_str("  Directories that grew the most:\n");
+        for directory in &self.directories {
+            out.push_str(&format!("    {:>12}  {}\n", format!("+{}", format_bytes(directory.growth)), directory.path));
+        }
+        out
+    }
+}
+
+/// `report growth [--job NAME] [--limit N] [--format text|json] [OLD NEW]`: the two newest snapshots
+/// of each job, or the two given ones
+pub async fn run(config: &Config, jobs: &[&BackupConfig], args: &[String]) -> Result<()> {
+    let limit = match crate::flag_value(args, "--limit") {
+        Some(value) => value.parse().map_err(|_| anyhow::anyhow!("--limit needs a number, got '{}'", value))?,
+        None => DEFAULT_LIMIT,
+    };
+
+    let snapshots = crate::positional_args(&args[3..]);
+    let mut comparisons = Vec::new();
+    match snapshots.as_slice() {
+        [from, to] => comparisons.push(compare(config, "snapshots", from, to, limit).await?),
+        [] => {
+            let reports_config = config.reports.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("Finding a job's snapshots needs run reports; configure 'reports.directory' or pass two snapshot ids")
+            })?;
+            let all_reports = reports::list(&crate::expand_tilde(&reports_config.directory));
+            for job in jobs {
+                match reports::snapshot_ids(&all_reports, &job.name).as_slice() {
+                    [to, from, ..] => comparisons.push(compare(config, &job.name, from, to, limit).await?),
+                    _ => eprintln!("{}: fewer than two snapshots, nothing to compare", job.name),
+                }
+            }
+        }
+        _ => return Err(anyhow::anyhow!("Usage: report growth [--job NAME] [--limit N] [--format text|json] [OLD_SNAPSHOT NEW_SNAPSHOT]")),
+    }
+
+    match crate::flag_value(args, "--format").map(String::as_str).unwrap_or("text") {
+        "text" => {
+            for growth in &comparisons {
+                println!("{}", growth.render_text());
+            }
+        }
+        "json" => println!("{}", serde_json::to_string_pretty(&comparisons)?),
+        other => return Err(anyhow::anyhow!("Unknown format '{}'; use text or json", other)),
+    }
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::pin::Pin;
+
+use log::info;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+use crate::accounts::Role;
+use crate::tokens::Scope;
+use crate::web::{self, Access, AppState};
+use crate::{events, reports};
+
+pub mod proto {
+    tonic::include_proto!("better_restic_client.v1");
+}
+
+use proto::control_server::{Control, ControlServer};
+use proto::event::Kind;
+
+pub struct ControlService {
+    state: AppState,
+}
+
+impl ControlService {
+    /// The same checks as the REST routes: Basic auth for users, Bearer for API tokens.
+    /// Returns the user name for the audit log.
+    async fn authorize<T>(&self, request: &Request<T>, role: Role, scope: Scope) -> Result<Option<String>, Status> {
+        let users = self.state.config.read().await.users.clone();
+        if users.is_empty() {
+            return Ok(None);
+        }
+        let authorization = request.metadata().get("authorization").and_then(|value| value.to_str().ok()).unwrap_or("");
+        match Access::new(&self.state, role, Some(scope)).check(&users, authorization) {
+            Ok(user) => Ok(Some(user.name)),
+            Err(axum::http::StatusCode::FORBIDDEN) => Err(Status::permission_denied("Not allowed")),
+            Err(_) => Err(Status::unauthenticated("Missing or invalid credentials")),
+        }
+    }
+}
+
+fn last_run(report: &reports::RunReport) -> proto::LastRun {
+    proto::LastRun {
+        run_id: report.run_id.clone(),
+        status: report.status.clone(),
+        finished_at: report.finished_at.to_rfc3339(),
+        duration_secs: report.duration_secs,
+        snapshot_id: report.snapshot_id.clone().unwrap_or_default(),
+        error: report.error.clone().unwrap_or_default(),
+        note: report.note.clone().unwrap_or_default(),
+    }
+}
+
+fn to_proto(event: events::Event) -> proto::Event {
+    let (job, run_id, kind) = match event {
+        events::Event::JobStarted { job, run_id, dry_run } => (job, run_id, Kind::Started(proto::JobStarted { dry_run })),
+        events::Event::JobProgress {
+            job,
+            run_id,
+            percent_done,
+            files_done,
+            total_files,
+            bytes_done,
+            total_bytes,
+            bytes_per_sec,
+        } => (
+            job,
+            run_id,
+            Kind::Progress(proto::JobProgress {
+                percent_done,
+                files_done,
+                total_files,
+                bytes_done,
+                total_bytes,
+                bytes_per_sec,
+            }),
+        ),
+        events::Event::JobFinished { job, run_id, duration_secs } => (job, run_id, Kind::Finished(proto::JobFinished { duration_secs })),
+        events::Event::JobFailed {
+            job,
+            run_id,
+            duration_secs,
+            error,
+        } => (job, run_id, Kind::Failed(proto::JobFailed { duration_secs, error })),
+        events::Event::JobSkipped { job, run_id, reason } => (job, run_id, Kind::Skipped(proto::JobSkipped { reason })),
+        events::Event::ConfigChanged { source } => (String::new(), String::new(), Kind::ConfigChanged(proto::ConfigChanged { source })),
+    };
+    proto::Event { job, run_id, kind: Some(kind) }
+}
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    async fn get_status(&self, request: Request<proto::GetStatusRequest>) -> Result<Response<proto::GetStatusReply>, Status> {
+        self.authorize(&request, Role::Viewer, Scope::ReadStatus).await?;
+        let config = self.state.config.read().await;
+        let reports = config
+            .reports
+            .as_ref()
+            .map(|reports_config| reports::list(&crate::expand_tilde(&reports_config.directory)))
+            .unwrap_or_default();
+        let jobs = config
+            .jobs()
+            .iter()
+            .map(|job| proto::JobStatus {
+                name: job.name.clone(),
+                frequency: job.frequency.clone(),
+                time: job.time.clone(),
+                last_run: reports.iter().find(|report| report.job == job.name && !report.dry_run).map(last_run),
+            })
+            .collect();
+        Ok(Response::new(proto::GetStatusReply {
+            hostname: crate::hostname(),
+            repository: config.restic.repository_url(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            jobs,
+        }))
+    }
+
+    async fn trigger_backup(&self, request: Request<proto::TriggerBackupRequest>) -> Result<Response<proto::TriggerBackupReply>, Status> {
+        let user = self.authorize(&request, Role::Operator, Scope::TriggerBackup).await?;
+        let request = request.into_inner();
+        let config = self.state.config.read().await.clone();
+        let job_names: Vec<String> = match request.job {
+            Some(ref name) => {
+                let job = config.find_job(name).ok_or_else(|| Status::not_found(format!("Unknown job '{}'", name)))?;
+                vec![job.name.clone()]
+            }
+            None => config.jobs().iter().map(|job| job.name.clone()).collect(),
+        };
+        if let Some(ref note) = request.note {
+            crate::notes::tag(note).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        }
+        let runs = web::start_backups(&self.state, config, &job_names, request.dry_run, request.note.clone(), user)
+            .into_iter()
+            .map(|reporter| proto::Run {
+                job: reporter.job,
+                run_id: reporter.run_id,
+            })
+            .collect();
+        Ok(Response::new(proto::TriggerBackupReply { runs }))
+    }
+
+    type WatchEventsStream = Pin<Box<dyn Stream<Item = Result<proto::Event, Status>> + Send>>;
+
+    async fn watch_events(&self, request: Request<proto::WatchEventsRequest>) -> Result<Response<Self::WatchEventsStream>, Status> {
+        self.authorize(&request, Role::Viewer, Scope::ReadStatus).await?;
+        let filter = request.into_inner();
+        let stream = BroadcastStream::new(self.state.events.subscribe()).filter_map(move |event| {
+            // Lagged subscribers just skip the events they missed
+            let event = to_proto(event.ok()?);
+            let wanted = filter.run_id.as_ref().is_none_or(|run_id| *run_id == event.run_id)
+                && filter.job.as_ref().is_none_or(|job| *job == event.job);
+            wanted.then_some(Ok(event))
+        });
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+/// Serve the gRPC control API until the process exits
+pub async fn serve(state: AppState, listen: String) -> anyhow::Result<()> {
+    let address = listen.parse().map_err(|e| anyhow::anyhow!("Invalid gRPC address '{}': {}", listen, e))?;
+    info!("gRPC control API listening on {}", listen);
+    println!("🔌 gRPC control API available at {}", listen);
+    tonic::transport::Server::builder()
+        .add_service(ControlServer::new(ControlService { state }))
+        .serve(address)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::restic::BackupSummary;
+use crate::BackupConfig;
+
+/// Where hook executables are found and how long each may run. The section is optional:
+/// without it, `hooks.d` next to config.yaml is used.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HooksConfig {
+    #[serde(default = "default_directory")]
+    pub directory: PathBuf,
+    /// A hook still running after this long is killed, and counts as failed
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_directory() -> PathBuf {
+    PathBuf::from("hooks.d")
+}
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        HooksConfig { directory: default_directory(), timeout_secs: default_timeout_secs() }
+    }
+}
+
+/// When hooks run: `pre` before the backup, `post` after a successful one and `failure` after a failed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookPhase {
+    Pre,
+    Post,
+    Failure,
+}
+
+impl HookPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HookPhase::Pre => "pre",
+            HookPhase::Post => "post",
+            HookPhase::Failure => "failure",
+        }
+    }
+}
+
+/// The run, as JSON on each hook's stdin
+#[derive(Debug, Serialize)]
+pub struct RunContext<'a> {
+    pub hook: HookPhase,
+    pub run_id: &'a str,
+    pub job: &'a str,
+    pub hostname: String,
+    pub repository: String,
+    pub started_at: DateTime<Local>,
+    /// "succeeded", "warning" or "failed"; not known yet for pre hooks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<&'a BackupSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The phase's executables in lexical order, so `10-zabbix` runs before `20-cleanup`. Hidden files
+/// and editor backups (`name~`) are skipped.
+fn executables(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(directory) else { return Vec::new() };
+    let mut hooks: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            !name.starts_with('.') && !name.ends_with('~')
+        })
+        .filter(|path| {
+            let executable = is_executable(path);
+            if !executable && path.is_file() {
+                warn!("Hook {:?} isn't executable and is skipped", path);
+            }
+            executable
+        })
+        .collect();
+    hooks.sort();
+    hooks
+}
+
+/// Run one hook in the job's environment (`env`, `workdir`, `tmp_dir`, `cache_dir`) and as its `run_as`
+/// user, so it sees what the backup it wraps sees and has no more privileges than restic
+async fn run_hook(path: &Path, job: &BackupConfig, context: &[u8], timeout: Duration) -> Result<()> {
+    let mut cmd = Command::new(path);
+    job.apply_environment(&mut cmd)?;
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to run hook {:?}: {}", path, e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    // A hook that doesn't read its stdin closes the pipe early, which is fine
+    let _ = stdin.write_all(context).await;
+    drop(stdin);
+
+    let output = match tokio::time::timeout(timeout, child.wait_with_output()).await {
+        Ok(output) => output.map_err(|e| anyhow::anyhow!("Failed to run hook {:?}: {}", path, e))?,
+        Err(_) => return Err(anyhow::anyhow!("Hook {:?} took longer than {}s and was stopped", path, timeout.as_secs())),
+    };
+    for line in String::from_utf8_lossy(&output.stdout).lines().filter(|line| !line.trim().is_empty()) {
+        info!("Hook {:?}: {}", path.file_name().unwrap_or_default(), line);
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Hook {:?} failed ({}): {}", path, output.status, stderr.trim()));
+    }
+    Ok(())
+}
+
+/// Run the phase's hooks one after the other. A failing pre hook stops there and fails the run;
+/// post and failure hooks all run, and their failures are only logged.
+pub async fn run(hooks_config: Option<&HooksConfig>, job: &BackupConfig, context: &RunContext<'_>) -> Result<()> {
+    let hooks_config = hooks_config.cloned().unwrap_or_default();
+    // Absolute, as the hooks run in the job's workdir
+    let directory = std::path::absolute(crate::expand_tilde(&hooks_config.directory))?.join(context.hook.as_str());
+    let hooks = executables(&directory);
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    debug!("Running {} {} hook(s) from {:?}", hooks.len(), context.hook.as_str(), directory);
+    let input = serde_json::to_vec(context)?;
+    let timeout = Duration::from_secs(hooks_config.timeout_secs);
+    for hook in hooks {
+        match run_hook(&hook, job, &input, timeout).await {
+            Ok(()) => debug!("Hook {:?} succeeded", hook),
+            Err(e) if context.hook == HookPhase::Pre => return Err(e),
+            Err(e) => warn!("{}", e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use anyhow::Result;
+use log::{debug, warn};
+use std::path::{Path, PathBuf};
+use std::io::Write;
+
+use crate::BackupConfig;
+
+/// Turn one line of an ignore file in `base` into a restic exclude pattern, using gitignore rules:
+/// a pattern with a slash is relative to `base`, one without matches at any depth below it,
+/// and `!` re-includes. Blank lines and `#` comments give `None`.
+pub fn translate(base: &Path, line: &str) -> Option<String> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negate, pattern) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    // restic can't tell directories from files, so `build/` simply becomes `build`
+    let pattern = pattern.trim_end_matches('/');
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let anchored = pattern.starts_with('/') || pattern.contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    let base = base.to_string_lossy();
+    let base = base.trim_end_matches('/');
+    let translated = if anchored {
+        format!("{}/{}", base, pattern)
+    } else {
+        format!("{}/**/{}", base, pattern)
+    };
+    Some(if negate { format!("!{}", translated) } else { translated })
+}
+
+/// Whether a restic include/exclude pattern matches the path or one of its parent directories.
+/// Patterns without a leading `/` can match anywhere; `*` and `?` stay within a path component, `**` spans several.
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let components = |value: &str| value.split('/').filter(|part| !part.is_empty()).map(str::to_string).collect::<Vec<_>>();
+    let (pattern_parts, path_parts) = (components(pattern), components(path));
+    if pattern.starts_with('/') {
+        prefix_matches(&pattern_parts, &path_parts)
+    } else {
+        (0..path_parts.len()).any(|start| prefix_matches(&pattern_parts, &path_parts[start..]))
+    }
+}
+
+/// Whether the pattern's components match the first components of the path
+fn prefix_matches(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => true,
+        Some((first, rest)) if first == "**" => (0..=path.len()).any(|skip| prefix_matches(rest, &path[skip..])),
+        Some((first, rest)) => {
+            path.first().is_some_and(|component| glob_matches(first.as_bytes(), component.as_bytes())) && prefix_matches(rest, &path[1..])
+        }
+    }
+}
+
+/// `*` and `?` wildcards within one path component
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob_matches(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_matches(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_matches(rest, &text[1..]),
+    }
+}
+
+/// The job's exclude patterns with `~` expanded, for [`is_excluded`]
+pub fn exclude_patterns(job: &BackupConfig) -> Vec<String> {
+    job.effective_excludes().iter().map(|p| crate::expand_tilde(p).to_string_lossy().to_string()).collect()
+}
+
+/// Whether restic skips the path for one of the patterns, matching globs such as `*.cache` or `**/node_modules`
+pub fn is_excluded(patterns: &[String], path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    patterns.iter().any(|pattern| pattern_matches(pattern, &path))
+}
+
+/// Add the patterns of one ignore file; `optional` files may be missing
+fn read_patterns(file: &Path, base: &Path, patterns: &mut Vec<String>, optional: bool) {
+    match std::fs::read_to_string(file) {
+        Ok(content) => {
+            debug!("Reading ignore file {:?}", file);
+            patterns.extend(content.lines().filter_map(|line| translate(base, line)));
+        }
+        Err(e) if optional && e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warn!("Skipping ignore file {:?}: {}", file, e),
+    }
+}
+
+/// Exclude patterns from the job's `ignore_files` found anywhere in its directories, and with `gitignore`
+/// from the `.gitignore` files of git repositories. Excluded paths and symlinks are not followed.
+pub fn collect(job: &BackupConfig) -> Vec<String> {
+    let mut patterns = Vec::new();
+    if job.ignore_files.is_empty() && !job.gitignore {
+        return patterns;
+    }
+
+    // Re-includes for every repository's .git, added last so no ignore rule can drop the history
+    let mut keep = Vec::new();
+    let excludes = exclude_patterns(job);
+    let mut stack: Vec<(PathBuf, bool)> =
+        job.effective_directories().iter().map(|p| (crate::expand_tilde(p), false)).collect();
+    while let Some((dir, mut in_repo)) = stack.pop() {
+        if is_excluded(&excludes, &dir) {
+            continue;
+        }
+        if job.gitignore && !in_repo && dir.join(".git").exists() {
+            debug!("Found git repository {:?}", dir);
+            in_repo = true;
+            read_patterns(&dir.join(".git/info/exclude"), &dir, &mut patterns, true);
+            let git_dir = dir.join(".git").to_string_lossy().to_string();
+            keep.push(format!("!{}", git_dir));
+            keep.push(format!("!{}/**", git_dir));
+        }
+
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        let mut subdirs = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            match entry.file_type() {
+                // Nothing inside .git is ignored, so there's no need to look there
+                Ok(file_type) if file_type.is_dir() && !(in_repo && name == ".git") => subdirs.push(entry.path()),
+                Ok(file_type) if file_type.is_file() => {
+                    let is_ignore_file = (in_repo && name == ".gitignore")
+                        || job.ignore_files.iter().any(|ignore_file| name.to_str() == Some(ignore_file.as_str()));
+                    if is_ignore_file {
+                        read_patterns(&entry.path(), &dir, &mut patterns, false);
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Deeper ignore files come later so their `!` rules win, as in git
+        subdirs.sort();
+        stack.extend(subdirs.into_iter().rev().map(|subdir| (subdir, in_repo)));
+    }
+    patterns.extend(keep);
+    patterns
+}
+
+/// A generated `--exclude-file`, removed again when dropped. It gets a random name and mode 0600,
+/// and is created only if nothing is there yet, so a symlink planted in /tmp can't redirect it.
+pub struct ExcludeFile {
+    file: tempfile::NamedTempFile,
+}
+
+impl ExcludeFile {
+    pub fn write(job_name: &str, patterns: &[String]) -> Result<ExcludeFile> {
+        let mut file = tempfile::Builder::new()
+            .prefix(&format!("better-restic-client-{}-", job_name.replace('/', "_")))
+            .suffix(".exclude")
+            .tempfile()
+            .map_err(|e| anyhow::anyhow!("Failed to create an exclude file: {}", e))?;
+        let mut content = patterns.join("\n");
+        content.push('\n');
+        file.write_all(content.as_bytes())
+            .and_then(|_| file.flush())
+            .map_err(|e| anyhow::anyhow!("Failed to write exclude file {:?}: {}", file.path(), e))?;
+        Ok(ExcludeFile { file })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pattern_matches_anchored_and_floating_patterns() {
+        assert!(pattern_matches("/home/alice", "/home/alice/notes.txt"));
+        assert!(!pattern_matches("/alice", "/home/alice"));
+        assert!(pattern_matches("alice", "/home/alice/notes.txt"));
+        assert!(pattern_matches("home/alice", "/home/alice"));
+        assert!(!pattern_matches("/home/alice", "/home/alicex"));
+    }
+
+    #[test]
+    fn pattern_matches_wildcards() {
+        assert!(pattern_matches("*.cache", "/home/alice/.thumbnails.cache/x"));
+        assert!(pattern_matches("/home/*/Downloads", "/home/bob/Downloads/file.iso"));
+        assert!(!pattern_matches("/home/*/Downloads", "/home/bob/x/Downloads"));
+        assert!(pattern_matches("/home/**/node_modules", "/home/bob/src/app/node_modules/left-pad"));
+        assert!(pattern_matches("**/node_modules", "/node_modules"));
+        assert!(pattern_matches("/var/log/?.log", "/var/log/a.log"));
+        assert!(!pattern_matches("/var/log/?.log", "/var/log/ab.log"));
+    }
+
+    #[test]
+    fn is_excluded_uses_globs() {
+        let patterns = vec!["*.cache".to_string(), "/srv/tmp".to_string()];
+        assert!(is_excluded(&patterns, Path::new("/home/alice/.npm.cache")));
+        assert!(is_excluded(&patterns, Path::new("/srv/tmp/upload")));
+        assert!(!is_excluded(&patterns, Path::new("/srv/www")));
+    }
+
+    #[test]
+    fn translate_follows_gitignore_rules() {
+        let base = Path::new("/home/alice/project/");
+        assert_eq!(translate(base, "target/").as_deref(), Some("/home/alice/project/**/target"));
+        assert_eq!(translate(base, "/build").as_deref(), Some("/home/alice/project/build"));
+        assert_eq!(translate(base, "docs/*.pdf").as_deref(), Some("/home/alice/project/docs/*.pdf"));
+        assert_eq!(translate(base, "!keep.log").as_deref(), Some("!/home/alice/project/**/keep.log"));
+        assert_eq!(translate(base, "\\#hash").as_deref(), Some("/home/alice/project/**/#hash"));
+        assert_eq!(translate(base, "# comment"), None);
+        assert_eq!(translate(base, "   "), None);
+    }
+}
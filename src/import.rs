@@ -0,0 +1,787 @@
+use anyhow::Result;
+use serde::Serialize;
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::maintenance::RetentionPolicy;
+
+/// A job as written to the generated config; empty fields are left out
+#[derive(Debug, Serialize, Default, Clone)]
+struct ImportedJob {
+    name: String,
+    frequency: String,
+    time: String,
+    directories: Vec<String>,
+    exclude: Vec<String>,
+    tags: Vec<String>,
+    one_file_system: Option<bool>,
+    /// Whether `frequency` and `time` came from the source rather than the default
+    #[serde(skip)]
+    scheduled: bool,
+}
+
+#[derive(Debug, Serialize, Default)]
+struct ImportedRestic {
+    repository: String,
+    password: Option<String>,
+    password_command: Option<String>,
+    password_file: Option<String>,
+    cache_dir: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Imported {
+    restic: ImportedRestic,
+    jobs: Vec<ImportedJob>,
+    policy: RetentionPolicy,
+    /// Things that couldn't be carried over, written as comments at the top of the config
+    notes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct LoggingSection {
+    directory: &'static str,
+    max_size: &'static str,
+}
+
+#[derive(Serialize)]
+struct ImportedConfig<'a> {
+    version: u32,
+    backup: &'a ImportedJob,
+    jobs: &'a [ImportedJob],
+    logging: LoggingSection,
+    restic: &'a ImportedRestic,
+    maintenance: &'a RetentionPolicy,
+}
+
+/// restic flags that take a value, so the value isn't mistaken for a backup path
+const VALUE_FLAGS: &[&str] = &[
+    "-r", "--repo", "--repository-file", "-p", "--password-file", "--password-command", "--cache-dir", "-o",
+    "--option", "-e", "--exclude", "--iexclude", "--exclude-file", "--iexclude-file", "--tag", "-H", "--host",
+    "--files-from", "--files-from-raw", "--files-from-verbatim", "--exclude-larger-than", "--exclude-if-present",
+    "--limit-upload", "--limit-download", "--pack-size", "--compression", "--read-concurrency", "--parent",
+    "--time", "--stdin-filename", "-g", "--group-by", "--keep-last", "--keep-hourly", "--keep-daily",
+    "--keep-weekly", "--keep-monthly", "--keep-yearly", "--keep-within", "--keep-tag", "--path", "--max-unused",
+    "--max-repack-size", "--read-data-subset", "--retry-lock", "-l",
+];
+
+impl Imported {
+    fn note(&mut self, note: impl Into<String>) {
+        let note = note.into();
+        if !self.notes.contains(&note) {
+            self.notes.push(note);
+        }
+    }
+
+    /// Take over a `RESTIC_*` variable; other variables are only mentioned
+    fn apply_env(&mut self, name: &str, value: &str) {
+        match name {
+            "RESTIC_REPOSITORY" => self.restic.repository = value.to_string(),
+            "RESTIC_REPOSITORY_FILE" => match std::fs::read_to_string(crate::expand_tilde(Path::new(value))) {
+                Ok(repository) => self.restic.repository = repository.trim().to_string(),
+                Err(e) => self.note(format!("Couldn't read RESTIC_REPOSITORY_FILE {}: {}", value, e)),
+            },
+            "RESTIC_PASSWORD" => self.restic.password = Some(value.to_string()),
+            "RESTIC_PASSWORD_FILE" => self.restic.password_file = Some(value.to_string()),
+            "RESTIC_PASSWORD_COMMAND" => self.restic.password_command = Some(value.to_string()),
+            "RESTIC_CACHE_DIR" => self.restic.cache_dir = Some(value.to_string()),
+            _ if is_backend_variable(name) => self.note(format!(
+                "{} is a backend credential; keep it in the environment of the service running the backups",
+                name
+            )),
+            _ => {}
+        }
+    }
+
+    fn set_policy(&mut self, flag: &str, value: &str) {
+        let count = value.parse().ok();
+        let (field, name) = match flag.trim_start_matches('-') {
+            "keep-last" => (&mut self.policy.keep_last, "keep_last"),
+            "keep-hourly" => (&mut self.policy.keep_hourly, "keep_hourly"),
+            "keep-daily" => (&mut self.policy.keep_daily, "keep_daily"),
+            "keep-weekly" => (&mut self.policy.keep_weekly, "keep_weekly"),
+            "keep-monthly" => (&mut self.policy.keep_monthly, "keep_monthly"),
+            "keep-yearly" => (&mut self.policy.keep_yearly, "keep_yearly"),
+            "keep-within" => {
+                self.policy.keep_within = Some(value.to_string());
+                return;
+            }
+            "keep-tag" => {
+                self.note(format!("--keep-tag {} is not imported; use `pin` for snapshots to keep forever", value));
+                return;
+            }
+            _ => return,
+        };
+        if field.is_some() && *field != count {
+            self.note(format!("Sources disagree on {}; the first value was kept", name));
+            return;
+        }
+        *field = count;
+    }
+
+    fn add_job(&mut self, mut job: ImportedJob) {
+        if job.name.is_empty() {
+            job.name = job
+                .directories
+                .first()
+                .and_then(|dir| Path::new(dir).file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| "backup".to_string());
+        }
+        let base = job.name.clone();
+        let mut n = 2;
+        while self.jobs.iter().any(|existing| existing.name == job.name) {
+            job.name = format!("{}-{}", base, n);
+            n += 1;
+        }
+        if !job.scheduled {
+            job.frequency = "daily".to_string();
+            job.time = "02:00".to_string();
+            self.note(format!("No schedule found for job '{}'; it was set to daily at 02:00", job.name));
+        }
+        self.jobs.push(job);
+    }
+
+    fn set_repository(&mut self, repository: String, source: &str) {
+        if self.restic.repository.is_empty() {
+            self.restic.repository = repository;
+        } else if self.restic.repository != repository {
+            self.note(format!(
+                "{} uses repository {}; only {} was imported, since all jobs share one repository",
+                source, repository, self.restic.repository
+            ));
+        }
+    }
+
+    /// The generated config.yaml, with notes as comments at the top
+    fn render(&self, source: &str) -> Result<String> {
+        let (first, rest) = self
+            .jobs
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("No backup jobs found in {}", source))?;
+        if self.restic.repository.is_empty() {
+            return Err(anyhow::anyhow!("No repository found in {}", source));
+        }
+        let config = ImportedConfig {
+            version: crate::migrations::CURRENT_CONFIG_VERSION,
+            backup: first,
+            jobs: rest,
+            logging: LoggingSection {
+                directory: "~/.local/log/restic",
+                max_size: "10MB",
+            },
+            restic: &self.restic,
+            maintenance: &self.policy,
+        };
+        let value = prune_empty(serde_yaml::to_value(&config)?);
+        let yaml = serde_yaml::to_string(&value)?;
+        let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml);
+
+        let mut out = format!("# Imported from {} by `better_restic_client import`\n", source);
+        for note in &self.notes {
+            out.push_str(&format!("# NOTE: {}\n", note));
+        }
+        out.push_str(yaml);
+        Ok(out)
+    }
+}
+
+/// Drop nulls, empty lists and empty maps so the output only has what was imported.
+/// Builds a new value so keys keep their order.
+fn prune_empty(value: Value) -> Value {
+    let is_empty = |value: &Value| match value {
+        Value::Null => true,
+        Value::Sequence(items) => items.is_empty(),
+        Value::Mapping(entries) => entries.is_empty(),
+        _ => false,
+    };
+    match value {
+        Value::Mapping(mapping) => {
+            let mut pruned = serde_yaml::Mapping::new();
+            for (key, child) in mapping {
+                let child = prune_empty(child);
+                if !is_empty(&child) {
+                    pruned.insert(key, child);
+                }
+            }
+            Value::Mapping(pruned)
+        }
+        Value::Sequence(items) => Value::Sequence(items.into_iter().map(prune_empty).collect()),
+        other => other,
+    }
+}
+
+fn is_backend_variable(name: &str) -> bool {
+    const PREFIXES: &[&str] = &["AWS_", "B2_", "AZURE_", "GOOGLE_", "OS_", "ST_", "RCLONE_", "RESTIC_REST_"];
+    PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Split a shell line into words, honouring quotes and backslashes. `;`, `&` and `|` become
+/// separate words so commands can be told apart; a `#` starting a word ends the line.
+fn shell_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(escaped) = chars.next() {
+                                word.push(escaped);
+                            }
+                        }
+                        _ => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+            }
+            '#' if !in_word => break,
+            ';' | '&' | '|' => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+                words.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Substitute `$NAME` and `${NAME}` with values assigned earlier in the script; `$HOME` becomes `~`
+fn expand_variables(word: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut chars = word.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                name.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        if braced && chars.peek() == Some(&'}') {
+            chars.next();
+        }
+        match (name.as_str(), vars.get(&name)) {
+            ("HOME", _) => out.push('~'),
+            (_, Some(value)) => out.push_str(value),
+            _ if braced => out.push_str(&format!("${{{}}}", name)),
+            _ => out.push_str(&format!("${}", name)),
+        }
+    }
+    out
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        None => false,
+    }
+}
+
+/// Frequency and time for a cron expression, when it maps onto one
+fn schedule_from_cron(expr: &str) -> Option<(String, String)> {
+    match expr.trim() {
+        "@hourly" => return Some(("hourly".to_string(), "00:00".to_string())),
+        "@daily" | "@midnight" => return Some(("daily".to_string(), "00:00".to_string())),
+        "@weekly" => return Some(("weekly".to_string(), "00:00".to_string())),
+        "@monthly" => return Some(("monthly".to_string(), "00:00".to_string())),
+        _ => {}
+    }
+    let fields: Vec<&str> = expr.split_whitespace().collect();
+    let [minute, hour, day, month, weekday] = fields[..] else { return None };
+    let minute: u32 = minute.parse().ok()?;
+    if month != "*" {
+        return None;
+    }
+    if hour == "*" {
+        return Some(("hourly".to_string(), format!("00:{:02}", minute)));
+    }
+    let time = format!("{:02}:{:02}", hour.parse::<u32>().ok()?, minute);
+    let frequency = match (day, weekday) {
+        ("*", "*") => "daily",
+        ("*", _) => "weekly",
+        (_, "*") => "monthly",
+        _ => return None,
+    };
+    Some((frequency.to_string(), time))
+}
+
+/// Frequency and time for a systemd calendar expression as used by resticprofile's `schedule`
+fn schedule_from_calendar(expr: &str) -> Option<(String, String)> {
+    let expr = expr.trim();
+    match expr {
+        "hourly" | "daily" | "weekly" | "monthly" => return Some((expr.to_string(), "00:00".to_string())),
+        _ => {}
+    }
+    let mut parts: Vec<&str> = expr.split_whitespace().collect();
+    let clock = parts.pop()?;
+    let clock_fields: Vec<&str> = clock.split(':').collect();
+    if clock_fields.len() < 2 {
+        return None;
+    }
+    let minute: u32 = clock_fields[1].parse().ok()?;
+    if clock_fields[0] == "*" {
+        return Some(("hourly".to_string(), format!("00:{:02}", minute)));
+    }
+    let time = format!("{:02}:{:02}", clock_fields[0].parse::<u32>().ok()?, minute);
+    let weekday = parts.first().filter(|part| part.chars().next().is_some_and(|c| c.is_ascii_alphabetic()));
+    if weekday.is_some() {
+        return Some(("weekly".to_string(), time));
+    }
+    match parts.last().map(|date| date.rsplit('-').next().unwrap_or("*")) {
+        None | Some("*") => Some(("daily".to_string(), time)),
+        Some(_) => Some(("monthly".to_string(), time)),
+    }
+}
+
+/// Interpret one `restic ...` invocation from a script
+fn import_restic_command(imported: &mut Imported, words: &[String], schedule: Option<(String, String)>) {
+    let mut subcommand = None;
+    let mut positional = Vec::new();
+    let mut job = ImportedJob::default();
+    if let Some((frequency, time)) = schedule {
+        job.frequency = frequency;
+        job.time = time;
+        job.scheduled = true;
+    }
+
+    let mut i = 0;
+    while i < words.len() {
+        let word = &words[i];
+        let (flag, inline_value) = match word.split_once('=') {
+            Some((flag, value)) if flag.starts_with('-') => (flag, Some(value.to_string())),
+            _ => (word.as_str(), None),
+        };
+        if !flag.starts_with('-') || flag == "-" {
+            if subcommand.is_none() {
+                subcommand = Some(word.clone());
+            } else {
+                positional.push(word.clone());
+            }
+            i += 1;
+            continue;
+        }
+        let value = if VALUE_FLAGS.contains(&flag) {
+            match inline_value {
+                Some(value) => Some(value),
+                None => {
+                    i += 1;
+                    words.get(i).cloned()
+                }
+            }
+        } else {
+            None
+        };
+        i += 1;
+        let value = value.unwrap_or_default();
+
+        match flag {
+            "-r" | "--repo" => imported.set_repository(value, "A restic command"),
+            "--repository-file" => imported.apply_env("RESTIC_REPOSITORY_FILE", &value),
+            "-p" | "--password-file" => imported.apply_env("RESTIC_PASSWORD_FILE", &value),
+            "--password-command" => imported.apply_env("RESTIC_PASSWORD_COMMAND", &value),
+            "--cache-dir" => imported.apply_env("RESTIC_CACHE_DIR", &value),
+            "-e" | "--exclude" => job.exclude.push(value),
+            "--tag" => job.tags.extend(value.split(',').map(str::to_string)),
+            "-x" | "--one-file-system" => job.one_file_system = Some(true),
+            "--exclude-file" | "--iexclude" | "--iexclude-file" | "--exclude-if-present" | "--files-from" => {
+                imported.note(format!("{} {} is not supported and was left out", flag, value))
+            }
+            _ if flag.starts_with("--keep-") => imported.set_policy(flag, &value),
+            _ => {}
+        }
+    }
+
+    match subcommand.as_deref() {
+        Some("backup") => {
+            job.directories = positional;
+            imported.add_job(job);
+        }
+        Some("forget") => {
+            if !positional.is_empty() {
+                imported.note("restic forget with explicit snapshot ids was left out");
+            }
+        }
+        Some("check") | Some("prune") | Some("unlock") | Some("snapshots") | Some("init") | None => {}
+        Some(other) => imported.note(format!("restic {} has no equivalent in the config and was left out", other)),
+    }
+}
+
+/// A shell script or crontab that exports `RESTIC_*` variables and runs restic
+fn import_script(imported: &mut Imported, content: &str) {
+    let mut vars: HashMap<String, String> = HashMap::new();
+    let mut logical = String::new();
+    for line in content.lines() {
+        // Join continuation lines
+        if let Some(stripped) = line.strip_suffix('\\') {
+            logical.push_str(stripped);
+            logical.push(' ');
+            continue;
+        }
+        logical.push_str(line);
+        let line = std::mem::take(&mut logical);
+
+        let mut words = shell_words(&line);
+        // Crontab entries: five schedule fields or an @keyword before the command
+        let mut schedule = None;
+        if words.first().is_some_and(|word| word.starts_with('@')) {
+            schedule = schedule_from_cron(&words[0]);
+            words.remove(0);
+        } else if words.len() > 5 && words[..5].iter().all(|w| w.chars().all(|c| c.is_ascii_digit() || "*/,-".contains(c))) {
+            schedule = schedule_from_cron(&words[..5].join(" "));
+            if schedule.is_none() {
+                imported.note(format!("Cron schedule '{}' has no equivalent; check the job's frequency", words[..5].join(" ")));
+            }
+            words.drain(..5);
+        }
+
+        for command in words.split(|word| matches!(word.as_str(), ";" | "&" | "|")) {
+            let mut command: Vec<String> = command.iter().map(|word| expand_variables(word, &vars)).collect();
+            if command.first().map(String::as_str) == Some("export") {
+                command.remove(0);
+            }
+            while command.first().is_some_and(|word| is_assignment(word)) {
+                let assignment = command.remove(0);
+                let (name, value) = assignment.split_once('=').unwrap_or_default();
+                vars.insert(name.to_string(), value.to_string());
+                imported.apply_env(name, value);
+            }
+            let is_restic = command
+                .first()
+                .is_some_and(|program| Path::new(program).file_name().is_some_and(|name| name == "restic"));
+            if is_restic {
+                import_restic_command(imported, &command[1..], schedule.clone());
+            }
+        }
+    }
+}
+
+/// `RESTIC_*` variables of the current environment, for jobs given on the command line
+fn import_env(imported: &mut Imported, directories: Vec<String>) {
+    let mut vars: Vec<(String, String)> = std::env::vars().collect();
+    vars.sort();
+    for (name, value) in vars {
+        imported.apply_env(&name, &value);
+    }
+    if directories.is_empty() {
+        imported.note("No directories given; add them to the job or rerun with `import env DIR...`");
+        imported.add_job(ImportedJob {
+            name: "backup".to_string(),
+            directories: vec!["~".to_string()],
+            ..Default::default()
+        });
+    } else {
+        imported.add_job(ImportedJob {
+            name: "backup".to_string(),
+            directories,
+            ..Default::default()
+        });
+    }
+}
+
+fn yaml_strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => vec![s.clone()],
+        Value::Sequence(items) => items.iter().filter_map(|item| item.as_str().map(str::to_string)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn yaml_str<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    value.get(key).and_then(Value::as_str)
+}
+
+/// Apply a map of `keep-*` options, as used by resticprofile and autorestic
+fn import_policy(imported: &mut Imported, section: Option<&Value>) {
+    let Some(Value::Mapping(options)) = section else { return };
+    for (key, value) in options {
+        let Some(key) = key.as_str() else { continue };
+        let value = match value {
+            Value::Number(n) => n.to_string(),
+            Value::String(s) => s.clone(),
+            _ => continue,
+        };
+        imported.set_policy(key, &value);
+    }
+}
+
+/// resticprofile's profiles.yaml: every profile with a `backup` section becomes a job
+fn import_resticprofile(imported: &mut Imported, content: &str) -> Result<()> {
+    let root: Value = serde_yaml::from_str(content)?;
+    let profiles = match root.get("profiles") {
+        Some(profiles) => profiles.clone(),
+        None => root.clone(),
+    };
+    let Value::Mapping(ref profiles) = profiles else {
+        return Err(anyhow::anyhow!("No profiles found"));
+    };
+
+    // Resolve `inherit` by merging each parent's keys (and one level of sections) under the child's
+    let resolve = |name: &str| -> Value {
+        let mut chain = Vec::new();
+        let mut current = Some(name.to_string());
+        while let Some(name) = current.take() {
+            if chain.len() > 10 {
+                break;
+            }
+            let Some(profile) = profiles.get(&Value::String(name)) else { break };
+            current = yaml_str(profile, "inherit").map(str::to_string);
+            chain.push(profile.clone());
+        }
+        let mut merged = serde_yaml::Mapping::new();
+        for profile in chain.into_iter().rev() {
+            let Value::Mapping(profile) = profile else { continue };
+            for (key, value) in profile {
+                match (merged.get_mut(&key), value) {
+                    (Some(Value::Mapping(existing)), Value::Mapping(section)) => {
+                        for (k, v) in section {
+                            existing.insert(k, v);
+                        }
+                    }
+                    (_, value) => {
+                        merged.insert(key, value);
+                    }
+                }
+            }
+        }
+        Value::Mapping(merged)
+    };
+
+    for (name, _) in profiles {
+        let Some(name) = name.as_str() else { continue };
+        if matches!(name, "global" | "groups" | "includes" | "version") {
+            continue;
+        }
+        let profile = resolve(name);
+        let Some(backup) = profile.get("backup") else { continue };
+
+        if let Some(repository) = yaml_str(&profile, "repository") {
+            imported.set_repository(repository.to_string(), &format!("Profile '{}'", name));
+        }
+        if let Some(file) = yaml_str(&profile, "password-file") {
+            imported.apply_env("RESTIC_PASSWORD_FILE", file);
+        }
+        if let Some(dir) = yaml_str(&profile, "cache-dir") {
+            imported.apply_env("RESTIC_CACHE_DIR", dir);
+        }
+        if let Some(Value::Mapping(env)) = profile.get("env") {
+            for (key, value) in env {
+                if let (Some(key), Some(value)) = (key.as_str(), value.as_str()) {
+                    imported.apply_env(&key.to_uppercase(), value);
+                }
+            }
+        }
+        import_policy(imported, profile.get("retention").or_else(|| profile.get("forget")));
+
+        let mut job = ImportedJob {
+            name: name.to_string(),
+            directories: yaml_strings(backup.get("source").unwrap_or(&Value::Null)),
+            exclude: yaml_strings(backup.get("exclude").unwrap_or(&Value::Null)),
+            tags: yaml_strings(backup.get("tag").unwrap_or(&Value::Null)),
+            one_file_system: backup.get("one-file-system").and_then(Value::as_bool),
+            ..Default::default()
+        };
+        if backup.get("exclude-file").is_some() {
+            imported.note(format!("Profile '{}': exclude-file is not supported and was left out", name));
+        }
+        if let Some(schedule) = backup.get("schedule") {
+            let calendars = yaml_strings(schedule);
+            match calendars.first().and_then(|calendar| schedule_from_calendar(calendar)) {
+                Some((frequency, time)) => {
+                    job.frequency = frequency;
+                    job.time = time;
+                    job.scheduled = true;
+                }
+                None => imported.note(format!("Profile '{}': schedule {:?} has no equivalent", name, calendars)),
+            }
+        }
+        imported.add_job(job);
+    }
+    Ok(())
+}
+
+/// autorestic's .autorestic.yml: every location becomes a job backed up to its first backend
+fn import_autorestic(imported: &mut Imported, content: &str) -> Result<()> {
+    let root: Value = serde_yaml::from_str(content)?;
+    let Some(Value::Mapping(locations)) = root.get("locations") else {
+        return Err(anyhow::anyhow!("No locations found"));
+    };
+    let backends = root.get("backends");
+
+    for (name, location) in locations {
+        let Some(name) = name.as_str() else { continue };
+        let targets = yaml_strings(location.get("to").unwrap_or(&Value::Null));
+        if targets.len() > 1 {
+            imported.note(format!("Location '{}' goes to several backends; only {} was imported", name, targets[0]));
+        }
+        if let Some(backend) = targets.first().and_then(|target| backends.and_then(|b| b.get(target.as_str()))) {
+            let kind = yaml_str(backend, "type").unwrap_or("local");
+            let path = yaml_str(backend, "path").unwrap_or_default();
+            let repository = if kind == "local" { path.to_string() } else { format!("{}:{}", kind, path) };
+            imported.set_repository(repository, &format!("Location '{}'", name));
+            if let Some(key) = yaml_str(backend, "key") {
+                imported.apply_env("RESTIC_PASSWORD", key);
+            }
+            if let Some(Value::Mapping(env)) = backend.get("env") {
+                for (key, value) in env {
+                    if let (Some(key), Some(value)) = (key.as_str(), value.as_str()) {
+                        imported.apply_env(&key.to_uppercase(), value);
+                    }
+                }
+            }
+        }
+
+        let options = location.get("options");
+        let backup = options.and_then(|options| options.get("backup"));
+        let option = |key: &str| backup.and_then(|backup| backup.get(key)).map(yaml_strings).unwrap_or_default();
+        let mut job = ImportedJob {
+            name: name.to_string(),
+            directories: yaml_strings(location.get("from").unwrap_or(&Value::Null)),
+            exclude: option("exclude"),
+            tags: option("tag"),
+            one_file_system: backup.and_then(|backup| backup.get("one-file-system")).and_then(Value::as_bool),
+            ..Default::default()
+        };
+        if let Some(cron) = yaml_str(location, "cron") {
+            match schedule_from_cron(cron) {
+                Some((frequency, time)) => {
+                    job.frequency = frequency;
+                    job.time = time;
+                    job.scheduled = true;
+                }
+                None => imported.note(format!("Location '{}': cron '{}' has no equivalent", name, cron)),
+            }
+        }
+        import_policy(imported, options.and_then(|options| options.get("forget")));
+        imported.add_job(job);
+    }
+    Ok(())
+}
+
+fn read_source(path: Option<&String>, kind: &str) -> Result<(String, String)> {
+    let path = path.ok_or_else(|| anyhow::anyhow!("Usage: import {} FILE [--output FILE]", kind))?;
+    let path = crate::expand_tilde(Path::new(path));
+    let content = std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Failed to read {:?}: {}", path, e))?;
+    Ok((content, path.display().to_string()))
+}
+
+/// `import env|script|resticprofile|autorestic ...`: print (or write with `--output`) an equivalent config.yaml
+pub fn run(args: &[String]) -> Result<()> {
+    let kind = args.get(2).map(String::as_str).unwrap_or_default();
+    let output = crate::flag_value(args, "--output");
+    // Positional arguments after the source kind, without --output and its value
+    let mut positional = Vec::new();
+    let mut rest = args.iter().skip(3);
+    while let Some(arg) = rest.next() {
+        if arg == "--output" {
+            rest.next();
+        } else if !arg.starts_with('-') {
+            positional.push(arg.clone());
+        }
+    }
+
+    let mut imported = Imported::default();
+    let source = match kind {
+        "env" => {
+            import_env(&mut imported, positional);
+            "the environment".to_string()
+        }
+        "script" => {
+            let (content, source) = read_source(positional.first(), kind)?;
+            import_script(&mut imported, &content);
+            source
+        }
+        "resticprofile" => {
+            let (content, source) = read_source(positional.first(), kind)?;
+            import_resticprofile(&mut imported, &content)?;
+            source
+        }
+        "autorestic" => {
+            let (content, source) = read_source(positional.first(), kind)?;
+            import_autorestic(&mut imported, &content)?;
+            source
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Usage: import env [DIR...] | import script FILE | import resticprofile FILE | import autorestic FILE [--output FILE]"
+            ))
+        }
+    };
+    if imported.restic.password.is_some() {
+        imported.note("The repository password was imported in plain text; consider password_command instead");
+    }
+
+    let yaml = imported.render(&source)?;
+    let (_, _, report) = crate::validation::validate_yaml(&yaml);
+    for issue in report.errors.iter().chain(&report.warnings) {
+        eprintln!("warning: {}: {}", issue.path, issue.message);
+    }
+
+    match output {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            // The config may hold the imported password, so only its owner may read it
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::OpenOptionsExt;
+                options.mode(0o600);
+            }
+            let mut file = options.open(&path).map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => anyhow::anyhow!("{:?} already exists; not overwriting it", path),
+                _ => anyhow::anyhow!("Failed to create {:?}: {}", path, e),
+            })?;
+            file.write_all(yaml.as_bytes()).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))?;
+            eprintln!("Wrote {:?} with {} job(s)", path, imported.jobs.len());
+        }
+        None => print!("{}", yaml),
+    }
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use log::{debug, info, warn};
+
+use crate::{run_as, BackupConfig};
+
+/// Prefix of restic's temporary pack files
+const TEMP_PACK_PREFIX: &str = "restic-temp-pack-";
+/// Temporary files older than this belong to a run that crashed or was killed
+const STALE_AFTER: Duration = Duration::from_secs(24 * 3600);
+
+/// Delete restic temp files a previous run left behind; a running backup's files are younger
+fn remove_stale_temp_files(dir: &Path) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else { return 0 };
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with(TEMP_PACK_PREFIX) {
+            continue;
+        }
+        let modified = entry.metadata().and_then(|metadata| metadata.modified());
+        let stale = modified.map(|modified| now.duration_since(modified).unwrap_or_default() > STALE_AFTER).unwrap_or(false);
+        if !stale {
+            continue;
+        }
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => warn!("Couldn't remove stale temp file {:?}: {}", entry.path(), e),
+        }
+    }
+    removed
+}
+
+/// Create the job's `tmp_dir` and `cache_dir`, owned by its `run_as` user, and clear stale temp files out of `tmp_dir`
+pub fn prepare(job: &BackupConfig) -> Result<()> {
+    for (key, dir) in [("tmp_dir", &job.tmp_dir), ("cache_dir", &job.cache_dir)] {
+        if let Some(dir) = dir {
+            let dir = job.resolve_path(dir);
+            std::fs::create_dir_all(&dir).map_err(|e| anyhow::anyhow!("Failed to create {}.{} {:?}: {}", job.name, key, dir, e))?;
+            if let Some(ref user) = job.run_as {
+                run_as::chown(&dir, user)?;
+            }
+        }
+    }
+    if let Some(ref tmp_dir) = job.tmp_dir {
+        let tmp_dir = job.resolve_path(tmp_dir);
+        match remove_stale_temp_files(&tmp_dir) {
+            0 => debug!("No stale temp files in {:?}", tmp_dir),
+            removed => info!("Removed {} stale restic temp file(s) from {:?}", removed, tmp_dir),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,273 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::process::Command;
+
+use crate::{events, restic, templates, BackupConfig, ResticConfig};
+
+/// Back up applications in a Kubernetes cluster through `kubectl exec`: database dumps and PVC contents
+/// are streamed into restic, one snapshot each, tagged with the namespace, pod and PVC
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct KubernetesConfig {
+    /// kubeconfig to use, e.g. /etc/rancher/k3s/k3s.yaml; kubectl's default otherwise
+    #[serde(default)]
+    pub kubeconfig: Option<PathBuf>,
+    #[serde(default)]
+    pub context: Option<String>,
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    /// Label selector picking the pods, e.g. `app=postgres`
+    pub selector: String,
+    /// Commands run in each pod whose stdout is backed up as a file
+    #[serde(default)]
+    pub dumps: Vec<DumpConfig>,
+    /// Also back up every PVC mounted by the pods as a tar stream (needs `tar` in the container)
+    #[serde(default)]
+    pub backup_pvcs: bool,
+}
+
+fn default_namespace() -> String {
+    "default".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DumpConfig {
+    /// Used in the file name (`<pod>-<name>.dump`) and the `dump:<name>` tag
+    pub name: String,
+    /// Container to exec in; the pod's default container otherwise
+    #[serde(default)]
+    pub container: Option<String>,
+    /// Shell command writing the dump to stdout, e.g. `pg_dumpall -U postgres`
+    pub command: String,
+    /// Name of the file in the snapshot; besides `{hostname}`, `{date}` and `{job}`, `{pod}` and `{name}` are filled in
+    #[serde(default = "default_dump_file_name")]
+    pub file_name: String,
+}
+
+fn default_dump_file_name() -> String {
+    "{pod}-{name}.dump".to_string()
+}
+
+/// A running pod matching the selector and the PVCs it mounts
+#[derive(Debug, Clone)]
+pub struct Pod {
+    pub name: String,
+    pub pvcs: Vec<PvcMount>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PvcMount {
+    pub claim: String,
+    pub container: String,
+    pub mount_path: String,
+}
+
+/// kubectl invocation with the configured kubeconfig, context and namespace
+fn kubectl_args(config: &KubernetesConfig) -> Vec<String> {
+    let mut args = vec!["kubectl".to_string()];
+    if let Some(ref kubeconfig) = config.kubeconfig {
+        args.push("--kubeconfig".to_string());
+        args.push(crate::expand_tilde(kubeconfig).to_string_lossy().to_string());
+    }
+    if let Some(ref context) = config.context {
+        args.push("--context".to_string());
+        args.push(context.clone());
+    }
+    args.push("--namespace".to_string());
+    args.push(config.namespace.clone());
+    args
+}
+
+/// Running pods matching the selector, with the PVCs each one mounts
+pub async fn list_pods(job: &BackupConfig, config: &KubernetesConfig) -> Result<Vec<Pod>> {
+    let args = kubectl_args(config);
+    debug!("Listing pods: {} get pods -l {}", args.join(" "), config.selector);
+    let mut cmd = Command::new(&args[0]);
+    job.apply_environment(&mut cmd)?;
+    let output = cmd
+        .args(&args[1..])
+        .args(["get", "pods", "--selector", &config.selector, "--output", "json"])
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run kubectl: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("kubectl get pods failed: {}", stderr.trim()));
+    }
+
+    let list: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let pods = list["items"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter(|pod| pod["status"]["phase"] == "Running")
+        .filter_map(|pod| {
+            let name = pod["metadata"]["name"].as_str()?.to_string();
+            let volumes = pod["spec"]["volumes"].as_array().map(Vec::as_slice).unwrap_or_default();
+            let containers = pod["spec"]["containers"].as_array().map(Vec::as_slice).unwrap_or_default();
+
+            let mut pvcs = Vec::new();
+            for volume in volumes {
+                let Some(claim) = volume["persistentVolumeClaim"]["claimName"].as_str() else { continue };
+                // The first container mounting the volume tells us where to read it
+                let mount = containers.iter().find_map(|container| {
+                    let mounts = container["volumeMounts"].as_array()?;
+                    let mount = mounts.iter().find(|mount| mount["name"] == volume["name"])?;
+                    Some((container["name"].as_str()?.to_string(), mount["mountPath"].as_str()?.to_string()))
+                });
+                if let Some((container, mount_path)) = mount {
+                    pvcs.push(PvcMount {
+                        claim: claim.to_string(),
+                        container,
+                        mount_path,
+                    });
+                }
+            }
+            Some(Pod { name, pvcs })
+        })
+        .collect();
+    Ok(pods)
+}
+
+/// Back up the stdout of a command run in a pod as a single file, using restic's `--stdin-from-command`
+/// so a failing command fails the backup instead of leaving a truncated snapshot
+#[allow(clippy::too_many_arguments)]
+async fn backup_from_pod(
+    job: &BackupConfig,
+    config: &KubernetesConfig,
+    restic_config: &ResticConfig,
+    pod: &str,
+    container: Option<&str>,
+    command: &str,
+    file_name: &str,
+    tags: &[String],
+    dry_run: bool,
+    reporter: Option<&events::RunReporter>,
+) -> Result<restic::BackupSummary> {
+    let mut cmd = crate::restic_command(restic_config, "backup");
+    // restic starts the dump command, which inherits the job's environment from it
+    job.apply_environment(&mut cmd)?;
+    if let Some(ref cache_dir) = job.cache_dir {
+        // The last --cache-dir wins over the repository-wide one
+        cmd.arg("--cache-dir").arg(job.resolve_path(cache_dir));
+    }
+    cmd.arg("--stdin-filename").arg(file_name);
+    for tag in job.expanded_tags().iter().chain(tags) {
+        cmd.arg("--tag").arg(tag);
+    }
+    cmd.arg("--json");
+    if dry_run {
+        cmd.arg("--dry-run");
+    }
+
+    cmd.arg("--stdin-from-command").arg("--");
+    cmd.args(kubectl_args(config));
+    cmd.arg("exec").arg(pod);
+    if let Some(container) = container {
+        cmd.arg("--container").arg(container);
+    }
+    cmd.args(["--", "sh", "-c", command]);
+    info!("Restic command: {:?}", cmd);
+
+    let run = crate::run_restic_with_progress(&mut cmd, reporter, crate::window::deadline(job, dry_run))
+        .await
+        .map_err(|e| crate::window::stopped(e).unwrap_or_else(|e| anyhow::anyhow!("Failed to execute restic: {}", e)))?;
+
+    let summary = run.backup_summary();
+    let output = run.output;
+    if output.status.success() || output.status.code() == Some(restic::EXIT_INCOMPLETE_SNAPSHOT) {
+        info!("{}: {}", file_name, summary.describe());
+        Ok(summary)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow::anyhow!("{} failed (exit code: {:?}): {}", file_name, output.status.code(), stderr.trim()))
+    }
+}
+
+/// Run every configured dump and PVC backup for the matching pods, carrying on past failures.
+/// The returned summary adds up all snapshots and carries the last snapshot id.
+pub async fn backup(
+    job: &BackupConfig,
+    config: &KubernetesConfig,
+    restic_config: &ResticConfig,
+    dry_run: bool,
+    reporter: Option<&events::RunReporter>,
+) -> Result<restic::BackupSummary> {
+    let pods = list_pods(job, config).await?;
+    if pods.is_empty() {
+        return Err(anyhow::anyhow!(
+            "No running pods match '{}' in namespace {}",
+            config.selector,
+            config.namespace
+        ));
+    }
+    info!("Backing up {} pod(s) in namespace {}", pods.len(), config.namespace);
+
+    let namespace_tag = format!("namespace:{}", config.namespace);
+    let mut summary = restic::BackupSummary::default();
+    let mut failed = Vec::new();
+    let mut seen_claims = std::collections::HashSet::new();
+    let mut record = |result: Result<restic::BackupSummary>, summary: &mut restic::BackupSummary| match result {
+        Ok(item) => summary.merge(item),
+        Err(e) => {
+            error!("{}", e);
+            failed.push(e.to_string());
+        }
+    };
+
+    for pod in &pods {
+        let pod_tag = format!("pod:{}", pod.name);
+
+        for dump in &config.dumps {
+            let file_name = templates::expand(&dump.file_name.replace("{pod}", &pod.name).replace("{name}", &dump.name), Some(&job.name));
+            let tags = [namespace_tag.clone(), pod_tag.clone(), format!("dump:{}", dump.name)];
+            let result = backup_from_pod(
+                job,
+                config,
+                restic_config,
+                &pod.name,
+                dump.container.as_deref(),
+                &dump.command,
+                &file_name,
+                &tags,
+                dry_run,
+                reporter,
+            )
+            .await;
+            record(result, &mut summary);
+        }
+
+        if config.backup_pvcs {
+            for pvc in &pod.pvcs {
+                // ReadWriteMany claims can be mounted by several pods; back each one up once
+                if !seen_claims.insert(pvc.claim.clone()) {
+                    continue;
+                }
+                let file_name = format!("{}.tar", pvc.claim);
+                let tags = [namespace_tag.clone(), pod_tag.clone(), format!("pvc:{}", pvc.claim)];
+                let command = format!("tar cf - -C '{}' .", pvc.mount_path.replace('\'', "'\\''"));
+                let result = backup_from_pod(
+                    job,
+                    config,
+                    restic_config,
+                    &pod.name,
+                    Some(&pvc.container),
+                    &command,
+                    &file_name,
+                    &tags,
+                    dry_run,
+                    reporter,
+                )
+                .await;
+                record(result, &mut summary);
+            }
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!("{} backup(s) failed: {}", failed.len(), failed.join("; ")));
+    }
+    Ok(summary)
+}
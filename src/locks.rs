@@ -0,0 +1,124 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::ResticConfig;
+
+/// First wait between lock checks; doubled after every check up to `MAX_BACKOFF`
+const INITIAL_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LockWaitConfig {
+    /// Give up waiting after this long and let restic try anyway
+    #[serde(default = "default_max_wait_secs")]
+    pub max_wait_secs: u64,
+    /// Locks older than this are left over from crashed runs and don't count; restic itself uses 30 minutes
+    #[serde(default = "default_stale_after_secs")]
+    pub stale_after_secs: u64,
+}
+
+fn default_max_wait_secs() -> u64 {
+    600
+}
+
+fn default_stale_after_secs() -> u64 {
+    1800
+}
+
+/// A lock file in the repository, as printed by `restic cat lock`
+#[derive(Debug, Deserialize)]
+pub struct Lock {
+    pub time: DateTime<Local>,
+    #[serde(default)]
+    pub exclusive: bool,
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub pid: u32,
+}
+
+impl Lock {
+    fn describe(&self) -> String {
+        format!(
+            "{} lock by {}@{} (pid {}) since {}",
+            if self.exclusive { "exclusive" } else { "shared" },
+            self.username,
+            self.hostname,
+            self.pid,
+            self.time.format("%Y-%m-%d %H:%M:%S")
+        )
+    }
+}
+
+async fn restic_stdout(restic_config: &ResticConfig, subcommand: &str, args: &[&str]) -> Result<String> {
+    let mut cmd = crate::restic_command(restic_config, subcommand);
+    cmd.args(args).arg("--no-lock");
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic {} failed: {}", subcommand, stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Locks in the repository younger than `stale_after`
+pub async fn fresh_locks(restic_config: &ResticConfig, stale_after: Duration) -> Result<Vec<Lock>> {
+    let ids = restic_stdout(restic_config, "list", &["locks", "--json"]).await?;
+    let mut locks = Vec::new();
+    for id in ids.lines().map(str::trim).filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit())) {
+        // A lock can disappear between listing and reading it
+        let Ok(content) = restic_stdout(restic_config, "cat", &["lock", id]).await else {
+            continue;
+        };
+        let lock: Lock = serde_json::from_str(&content).map_err(|e| anyhow::anyhow!("Unreadable lock {}: {}", id, e))?;
+        let age = (Local::now() - lock.time).to_std().unwrap_or_default();
+        if age < stale_after {
+            locks.push(lock);
+        }
+    }
+    Ok(locks)
+}
+
+/// Wait, with backoff, while another process holds a fresh lock on the repository, e.g. a second
+/// machine backing up to it. After `max_wait_secs`, or if the locks can't be listed, the backup
+/// goes ahead and restic decides.
+pub async fn wait_for_locks(restic_config: &ResticConfig, wait_config: &LockWaitConfig) {
+    let started = Instant::now();
+    let max_wait = Duration::from_secs(wait_config.max_wait_secs);
+    let mut backoff = INITIAL_BACKOFF;
+    let mut waited = false;
+    loop {
+        let locks = match fresh_locks(restic_config, Duration::from_secs(wait_config.stale_after_secs)).await {
+            Ok(locks) => locks,
+            Err(e) => {
+                warn!("Couldn't check repository locks, backing up anyway: {}", e);
+                return;
+            }
+        };
+        if locks.is_empty() {
+            if waited {
+                info!("Repository lock released after {}s", started.elapsed().as_secs());
+            }
+            return;
+        }
+        let elapsed = started.elapsed();
+        if elapsed >= max_wait {
+            warn!("Repository still locked after {}s, backing up anyway", elapsed.as_secs());
+            return;
+        }
+        for lock in &locks {
+            info!("Repository is locked: {}", lock.describe());
+        }
+        let wait = backoff.min(max_wait - elapsed);
+        info!("Waiting {}s for the repository lock", wait.as_secs());
+        tokio::time::sleep(wait).await;
+        waited = true;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
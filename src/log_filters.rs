@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::str::FromStr;
+
+use flexi_logger::filter::{LogLineFilter, LogLineWriter};
+use flexi_logger::DeferredNow;
+use log::{LevelFilter, Record};
+use serde::{Deserialize, Serialize};
+
+/// `logging.filters`: which records are logged, and where
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct LogFilters {
+    /// Levels per module or crate, like RUST_LOG, e.g. `hyper: warn` or `better_restic_client::web: debug`
+    #[serde(default)]
+    pub modules: BTreeMap<String, String>,
+    /// Records whose message contains any of these stay out of the log file and the Event Log,
+    /// but still reach the console
+    #[serde(default)]
+    pub file_exclude: Vec<String>,
+    /// Also print records at this level and above to stderr; `duplicate_to_stderr` takes precedence
+    #[serde(default)]
+    pub console: Option<String>,
+}
+
+/// `logging.duplicate_to_stderr`: records also printed to stderr, e.g. for cron to mail them
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DuplicateToStderr {
+    /// Lowest level printed, independent of the log file's: off, error, warn, info, debug or trace
+    #[serde(default = "default_stderr_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: StderrFormat,
+}
+
+fn default_stderr_level() -> String {
+    "warn".to_string()
+}
+
+/// How records look on stderr; the log file always uses the detailed format
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StderrFormat {
+    /// `WARN [module] message`
+    #[default]
+    Plain,
+    /// `2024-03-05 14:00:01 WARN message`
+    Compact,
+    /// Like the log file, with the time and source location
+    Detailed,
+}
+
+impl StderrFormat {
+    fn write(&self, out: &mut Vec<u8>, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        match self {
+            StderrFormat::Plain => flexi_logger::default_format(out, now, record),
+            StderrFormat::Compact => write!(out, "{} {} {}", now.format("%Y-%m-%d %H:%M:%S"), record.level(), record.args()),
+            StderrFormat::Detailed => flexi_logger::detailed_format(out, now, record),
+        }
+    }
+}
+
+/// A level name in a filter: off, error, warn, info, debug or trace
+pub fn parse_level(level: &str) -> Option<LevelFilter> {
+    LevelFilter::from_str(level).ok()
+}
+
+impl LogFilters {
+    /// flexi_logger spec for the default level and the module levels, e.g. "info, hyper=warn"
+    pub fn spec(&self, default_level: &str) -> String {
+        std::iter::once(default_level.to_string())
+            .chain(self.modules.iter().map(|(module, level)| format!("{}={}", module, level)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The level and format of records printed to stderr: `duplicate_to_stderr`, or `console` as plain lines
+    fn stderr(&self, duplicate: Option<&DuplicateToStderr>) -> Option<(LevelFilter, StderrFormat)> {
+        let (level, format) = match duplicate {
+            Some(duplicate) => (parse_level(&duplicate.level)?, duplicate.format),
+            None => (parse_level(self.console.as_deref()?)?, StderrFormat::Plain),
+        };
+        (level != LevelFilter::Off).then_some((level, format))
+    }
+
+    /// The logger spec and, if needed, the filter applying `file_exclude` and the stderr output.
+    /// Records more verbose than the file's level pass the spec when stderr wants them, and the
+    /// filter keeps them out of the file.
+    pub fn setup(&self, file_level: &str, duplicate: Option<&DuplicateToStderr>) -> (String, Option<LineFilter>) {
+        let stderr = self.stderr(duplicate);
+        let file_filter = parse_level(file_level).unwrap_or(LevelFilter::Info);
+        let more_verbose = stderr.map(|(level, _)| level).filter(|level| *level > file_filter);
+        let spec = self.spec(&more_verbose.map_or(file_level.to_string(), |level| level.to_string().to_lowercase()));
+        if self.file_exclude.is_empty() && stderr.is_none() {
+            return (spec, None);
+        }
+        let filter = LineFilter {
+            file_exclude: self.file_exclude.clone(),
+            file_level: more_verbose.map(|_| file_filter),
+            stderr,
+        };
+        (spec, Some(filter))
+    }
+}
+
+/// Prints records to stderr, then keeps excluded ones, and ones only stderr asked for, from the file
+pub struct LineFilter {
+    file_exclude: Vec<String>,
+    file_level: Option<LevelFilter>,
+    stderr: Option<(LevelFilter, StderrFormat)>,
+}
+
+impl LogLineFilter for LineFilter {
+    fn write(&self, now: &mut DeferredNow, record: &Record, log_line_writer: &dyn LogLineWriter) -> std::io::Result<()> {
+        if let Some((_, format)) = self.stderr.filter(|(level, _)| record.level() <= *level) {
+            let mut line = Vec::new();
+            format.write(&mut line, now, record)?;
+            eprintln!("{}", String::from_utf8_lossy(&line));
+        }
+        if self.file_level.is_some_and(|level| record.level() > level) {
+            return Ok(());
+        }
+        let message = record.args().to_string();
+        if self.file_exclude.iter().any(|pattern| message.contains(pattern.as_str())) {
+            return Ok(());
+        }
+        log_line_writer.write(now, record)
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use log::Level;
+use serde::{Deserialize, Serialize};
+
+/// Entries returned when the query doesn't set a limit, and the most it may ask for
+const DEFAULT_LIMIT: usize = 500;
+const MAX_LIMIT: usize = 5000;
+/// Longer entries, like restic's output dumped after a failure, are cut so a search stays small
+const MAX_ENTRY_BYTES: usize = 8192;
+
+/// `GET /api/logs` filters; without any, the endpoint returns the latest log file as before
+#[derive(Debug, Deserialize, Default)]
+pub struct LogQuery {
+    /// Lowest level shown: error, warn, info, debug or trace
+    pub level: Option<String>,
+    /// RFC 3339, "2024-03-05T14:00" or "2024-03-05", in local time unless an offset is given
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// Lines logged while one of the job's runs was in progress, or mentioning it
+    pub job: Option<String>,
+    /// Case-insensitive text the entry must contain
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    pub fn is_empty(&self) -> bool {
+        self.level.is_none() && self.since.is_none() && self.until.is_none() && self.job.is_none() && self.q.is_none()
+    }
+}
+
+/// One record of the log files, with the lines that followed it
+#[derive(Debug, Serialize, Clone)]
+pub struct LogEntry {
+    pub time: DateTime<FixedOffset>,
+    pub level: String,
+    pub module: String,
+    pub message: String,
+    pub file: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    /// The newest matching entries, oldest first
+    pub entries: Vec<LogEntry>,
+    pub matched: usize,
+    /// Whether older matches were left out because of the limit
+    pub truncated: bool,
+}
+
+/// A query with its values parsed
+pub struct Filter {
+    level: Option<Level>,
+    since: Option<DateTime<FixedOffset>>,
+    until: Option<DateTime<FixedOffset>>,
+    job: Option<String>,
+    /// When the job's runs started and finished, from the run reports
+    runs: Vec<(DateTime<Local>, DateTime<Local>)>,
+    text: Option<String>,
+    limit: usize,
+}
+
+/// A time in a query: RFC 3339, "2024-03-05T14:00" or "2024-03-05" (its start, or its end with `end_of_day`)
+pub fn parse_time(value: &str, end_of_day: bool) -> Result<DateTime<FixedOffset>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time);
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|date| if end_of_day { date.and_hms_opt(23, 59, 59) } else { date.and_hms_opt(0, 0, 0) }.unwrap())
+        })
+        .map_err(|_| format!("Invalid time '{}', expected e.g. 2024-03-05T14:00 or 2024-03-05", value))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|time| time.fixed_offset())
+        .ok_or_else(|| format!("'{}' doesn't exist in local time", value))
+}
+
+impl Filter {
+    pub fn new(query: &LogQuery, runs: Vec<(DateTime<Local>, DateTime<Local>)>) -> Result<Filter, String> {
+        let level = match query.level.as_deref() {
+            Some(level) => Some(Level::from_str(level).map_err(|_| format!("Unknown level '{}', expected error, warn, info, debug or trace", level))?),
+            None => None,
+        };
+        Ok(Filter {
+            level,
+            since: query.since.as_deref().map(|since| parse_time(since, false)).transpose()?,
+            until: query.until.as_deref().map(|until| parse_time(until, true)).transpose()?,
+            job: query.job.clone(),
+            runs,
+            text: query.q.as_ref().map(|text| text.to_lowercase()),
+            limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        })
+    }
+
+    fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(level) = self.level {
+            if Level::from_str(&entry.level).map_or(true, |entry_level| entry_level > level) {
+                return false;
+            }
+        }
+        if self.since.is_some_and(|since| entry.time < since) || self.until.is_some_and(|until| entry.time > until) {
+            return false;
+        }
+        if let Some(ref job) = self.job {
+            let during_run = self.runs.iter().any(|(started, finished)| entry.time >= *started && entry.time <= *finished);
+            if !during_run && !entry.message.contains(&format!("'{}'", job)) {
+                return false;
+            }
+        }
+        match self.text {
+            Some(ref text) => entry.message.to_lowercase().contains(text) || entry.module.to_lowercase().contains(text),
+            None => true,
+        }
+    }
+}
+
+/// Parse a line written with flexi_logger's detailed format:
+/// `[2024-03-05 14:00:01.123456 +01:00] INFO [module] src/file.rs:12: message`
+fn parse_line(line: &str, file: &str) -> Option<LogEntry> {
+    let (time, rest) = line.strip_prefix('[')?.split_once("] ")?;
+    let time = DateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.f %:z").ok()?;
+    let (level, rest) = rest.split_once(' ')?;
+    let (module, rest) = rest.strip_prefix('[')?.split_once("] ")?;
+    // The source location is left out of the message
+    let message = rest.split_once(": ").map_or(rest, |(_, message)| message);
+    Some(LogEntry {
+        time,
+        level: level.to_string(),
+        module: module.to_string(),
+        message: message.to_string(),
+        file: file.to_string(),
+    })
+}
+
+/// The log files, oldest first, leaving out those last written before `since`
+fn log_files(directory: &Path, since: Option<DateTime<FixedOffset>>) -> Vec<(PathBuf, String)> {
+    let mut files: Vec<(std::time::SystemTime, PathBuf, String)> = std::fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            (name.starts_with("restic_backup") && name.ends_with(".log")).then_some((modified, entry.path(), name))
+        })
+        .filter(|(modified, _, _)| since.is_none_or(|since| DateTime::<Local>::from(*modified) >= since))
+        .collect();
+    files.sort_by_key(|(modified, _, _)| *modified);
+    files.into_iter().map(|(_, path, name)| (path, name)).collect()
+}
+
+/// Search the current and rotated log files line by line, keeping only the newest `limit` matches in memory
+pub fn search(directory: &Path, filter: &Filter) -> SearchResult {
+    let mut entries: VecDeque<LogEntry> = VecDeque::new();
+    let mut matched = 0;
+    let mut consider = |entry: LogEntry| {
+        if filter.matches(&entry) {
+            matched += 1;
+            if entries.len() == filter.limit {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    };
+
+    for (path, name) in log_files(directory, filter.since) {
+        let Ok(file) = std::fs::File::open(&path) else { continue };
+        let mut current: Option<LogEntry> = None;
+        for line in std::io::BufReader::new(file).split(b'\n').map_while(Result::ok) {
+            let line = String::from_utf8_lossy(&line);
+            match parse_line(&line, &name) {
+                Some(entry) => {
+                    if let Some(previous) = current.replace(entry) {
+                        consider(previous);
+                    }
+                }
+                // Lines without a header continue the entry before them
+                None => {
+                    if let Some(ref mut entry) = current {
+                        if entry.message.len() < MAX_ENTRY_BYTES {
+                            entry.message.push('\n');
+                            entry.message.extend(line.chars().take(MAX_ENTRY_BYTES));
+                        }
+                    }
+                }
+            }
+        }
+        if let Some(entry) = current {
+            consider(entry);
+        }
+    }
+
+    SearchResult {
+        truncated: matched > entries.len(),
+        entries: entries.into(),
+        matched,
+    }
+}
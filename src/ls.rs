@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{pins, restic, Config, ResticConfig};
+
+/// Tree levels shown by `ls --tree` without `--depth`
+const DEFAULT_TREE_DEPTH: usize = 2;
+
+/// One line of `restic ls --json`; the leading snapshot line doesn't parse as a node
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Node {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub path: String,
+    #[serde(default)]
+    pub size: u64,
+    pub mtime: Option<DateTime<Local>>,
+}
+
+/// Every node of the snapshot, or everything below the given paths
+pub async fn list_nodes(restic_config: &ResticConfig, snapshot_id: &str, paths: &[&String]) -> Result<Vec<Node>> {
+    let mut cmd = crate::restic_command(restic_config, "ls");
+    cmd.arg(snapshot_id).arg("--json");
+    if !paths.is_empty() {
+        cmd.arg("--recursive").args(paths);
+    }
+    info!("Restic command: {:?}", cmd);
+
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic ls failed: {}", stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// A directory (with the total size and file count of everything below it) or a file
+#[derive(Debug, Serialize)]
+struct TreeEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    size: u64,
+    files: usize,
+    mtime: Option<DateTime<Local>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    children: BTreeMap<String, TreeEntry>,
+}
+
+impl TreeEntry {
+    fn directory() -> Self {
+        TreeEntry {
+            kind: "dir".to_string(),
+            size: 0,
+            files: 0,
+            mtime: None,
+            children: BTreeMap::new(),
+        }
+    }
+
+    /// Build the tree of the nodes below `root`
+    fn build(root: &str, nodes: &[Node]) -> Self {
+        let mut tree = TreeEntry::directory();
+        for node in nodes {
+            let Some(relative) = relative_path(root, &node.path) else {
+                if node.path == root {
+                    tree.mtime = node.mtime;
+                }
+                continue;
+            };
+            tree.insert(&relative.split('/').collect::<Vec<_>>(), node);
+        }
+        tree
+    }
+
+    fn insert(&mut self, components: &[&str], node: &Node) {
+        if node.kind == "file" {
+            self.size += node.size;
+            self.files += 1;
+        }
+        let Some((name, rest)) = components.split_first() else {
+            return;
+        };
+        let child = self.children.entry(name.to_string()).or_insert_with(TreeEntry::directory);
+        if rest.is_empty() {
+            child.kind = node.kind.clone();
+            child.mtime = node.mtime;
+            if node.kind == "file" {
+                child.size = node.size;
+                child.files = 1;
+            }
+        } else {
+            child.insert(rest, node);
+        }
+    }
+
+    /// Drop everything more than `depth` levels down; directories keep their totals
+    fn prune(&mut self, depth: usize) {
+        if depth == 0 {
+            self.children.clear();
+        }
+        for child in self.children.values_mut() {
+            child.prune(depth.saturating_sub(1));
+        }
+    }
+
+    fn describe(&self, name: &str) -> String {
+        let mtime = self.mtime.map(|time| time.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+        if self.kind == "dir" {
+            let slash = if name.ends_with('/') { "" } else { "/" };
+            format!("{}{}  {}, {} file(s)  {}", name, slash, restic::format_bytes(self.size), self.files, mtime)
+        } else {
+            format!("{}  {}  {}", name, restic::format_bytes(self.size), mtime)
+        }
+    }
+
+    fn print_children(&self, prefix: &str) {
+        let count = self.children.len();
+        for (index, (name, child)) in self.children.iter().enumerate() {
+            let last = index + 1 == count;
+            println!("{}{}{}", prefix, if last { "└── " } else { "├── " }, child.describe(name).trim_end());
+            child.print_children(&format!("{}{}", prefix, if last { "    " } else { "│   " }));
+        }
+    }
+}
+
+/// `path` relative to `root`, or None if it isn't below it
+fn relative_path<'a>(root: &str, path: &'a str) -> Option<&'a str> {
+    let relative = if root == "/" {
+        path.strip_prefix('/')
+    } else {
+        path.strip_prefix(root).and_then(|rest| rest.strip_prefix('/'))
+    };
+    relative.filter(|relative| !relative.is_empty())
+}
+
+/// List a snapshot, or one directory of it: a flat listing, or with `--tree` a tree limited to `--depth` levels.
+/// `--output json` prints the same as JSON.
+pub async fn run(config: &Config, args: &[String]) -> Result<()> {
+    let positional = crate::positional_args(&args[2..]);
+    let Some(snapshot_id) = positional.first() else {
+        return Err(anyhow::anyhow!("Usage: ls SNAPSHOT [PATH] [--tree] [--depth N] [--output json]"));
+    };
+    if !pins::is_valid_snapshot_id(snapshot_id) {
+        return Err(anyhow::anyhow!("Invalid snapshot id '{}'", snapshot_id));
+    }
+    let root = positional.get(1).map(|path| path.trim_end_matches('/')).filter(|path| !path.is_empty()).unwrap_or("/");
+    let tree_view = args.iter().any(|arg| arg == "--tree");
+    let depth = match crate::flag_value(args, "--depth") {
+        Some(depth) => Some(depth.parse::<usize>().map_err(|_| anyhow::anyhow!("--depth needs a number, got '{}'", depth))?),
+        None => None,
+    };
+    let json = match crate::flag_value(args, "--output").map(String::as_str) {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => return Err(anyhow::anyhow!("Unknown output format '{}'; supported: text, json", other)),
+    };
+
+    let root_arg = root.to_string();
+    let paths = if root == "/" { Vec::new() } else { vec![&root_arg] };
+    let nodes = list_nodes(&config.restic, snapshot_id, &paths).await?;
+
+    if tree_view {
+        let mut tree = TreeEntry::build(root, &nodes);
+        tree.prune(depth.unwrap_or(DEFAULT_TREE_DEPTH));
+        if json {
+            println!("{}", serde_json::to_string_pretty(&tree)?);
+        } else {
+            println!("{}", tree.describe(root).trim_end());
+            tree.print_children("");
+        }
+        return Ok(());
+    }
+
+    // Flat listing, optionally limited to `--depth` levels below the path
+    let nodes: Vec<&Node> = nodes
+        .iter()
+        .filter(|node| match (depth, relative_path(root, &node.path)) {
+            (_, None) => node.path == root,
+            (None, Some(_)) => true,
+            (Some(depth), Some(relative)) => relative.split('/').count() <= depth,
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&nodes)?);
+        return Ok(());
+    }
+    for node in nodes {
+        let mtime = node.mtime.map(|time| time.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_default();
+        let size = if node.kind == "file" { restic::format_bytes(node.size) } else { String::new() };
+        let suffix = if node.kind == "dir" { "/" } else { "" };
+        println!("{:<16}  {:>10}  {}{}", mtime, size, node.path, suffix);
+    }
+    Ok(())
+}
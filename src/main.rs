@@ -1,64 +1,754 @@
+mod access_rights;
+mod accounts;
+mod agent;
+mod archive;
+mod audit;
+mod backends;
+mod bootstrap;
+mod cache;
+mod calendar;
+mod cold_export;
+mod conditions;
+mod config_history;
+mod cost;
+mod daemon;
+mod dedupe;
+mod dependencies;
+mod diagnostics;
+mod digest;
+mod docker;
+mod eventlog;
+mod events;
+mod export;
+mod file_history;
+mod fingerprint;
+mod fs_snapshot;
+mod grpc;
+mod growth;
+mod hooks;
+mod ignore_files;
+mod import;
+mod job_dirs;
+mod kubernetes;
+mod locks;
+mod log_filters;
+mod log_search;
+mod ls;
+mod maintenance;
+mod manifest;
+mod metrics;
+mod migrations;
+mod notes;
+mod mount;
+mod notify;
+mod object_lock;
+mod overrides;
+mod password;
+mod permissions;
+mod pins;
+mod prediction;
+mod preflight;
+mod problems;
+mod profiles;
+mod quota;
+mod rclone;
+mod repo_health;
+mod reports;
+mod restic;
+mod resources;
+mod restore;
+mod resume;
+mod retention;
+mod run_as;
+mod run_journal;
+mod safety;
+mod sandbox;
+mod scheduled_task;
+mod scripting;
+mod self_test;
+mod service;
+mod snapshots;
+mod ssh;
+mod staging;
+mod style;
+mod tcc;
+mod templates;
+mod terminal;
+mod throughput;
+mod tokens;
+mod validation;
 mod web;
+mod window;
 
 use anyhow::Result;
-use log::{info, error, debug};
+use log::{info, warn, error, debug};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use tokio::process::Command;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
+    /// Schema version; older configs are upgraded on load
+    #[serde(default)]
+    version: u32,
     backup: BackupConfig,
     logging: LoggingConfig,
     restic: ResticConfig,
+    #[serde(default)]
+    metrics: Option<metrics::MetricsConfig>,
+    #[serde(default)]
+    notifications: Vec<notify::NotificationTarget>,
+    #[serde(default)]
+    reports: Option<reports::ReportsConfig>,
+    #[serde(default)]
+    digest: Option<digest::DigestConfig>,
+    #[serde(default)]
+    preflight: Option<preflight::PreflightConfig>,
+    #[serde(default)]
+    maintenance: Option<maintenance::MaintenanceConfig>,
+    /// Storage pricing for estimating what a cloud repository costs
+    #[serde(default)]
+    cost: Option<cost::CostConfig>,
+    /// How long the web UI caches restic output
+    #[serde(default)]
+    cache: Option<cache::CacheConfig>,
+    #[serde(default)]
+    safety: Option<safety::SafetyConfig>,
+    /// Where `restore --stage` keeps files until they are committed
+    #[serde(default)]
+    staging: Option<staging::StagingConfig>,
+    /// Where state-changing actions are recorded
+    #[serde(default)]
+    audit: Option<audit::AuditConfig>,
+    #[serde(default)]
+    web: Option<web::WebConfig>,
+    /// Central server this machine reports to in `agent` mode
+    #[serde(default)]
+    agent: Option<agent::AgentConfig>,
+    /// Power and network settings of the tasks `install-service` creates on Windows
+    #[serde(default)]
+    scheduled_task: Option<scheduled_task::ScheduledTaskConfig>,
+    /// PID file and detaching for the web UI and agent when not run by systemd
+    #[serde(default)]
+    daemon: Option<daemon::DaemonConfig>,
+    /// Where the executables in hooks.d/{pre,post,failure} are, if not next to config.yaml
+    #[serde(default)]
+    hooks: Option<hooks::HooksConfig>,
+    /// Web UI logins; without any, the UI is open to everyone who can reach it
+    #[serde(default)]
+    users: Vec<accounts::UserAccount>,
+    /// Additional backup jobs besides `backup`, sharing the same repository
+    #[serde(default)]
+    jobs: Vec<BackupConfig>,
+}
+
+impl Config {
+    /// All backup jobs: the main `backup` section first, then `jobs` in order
+    pub fn jobs(&self) -> Vec<&BackupConfig> {
+        std::iter::once(&self.backup).chain(self.jobs.iter()).collect()
+    }
+
+    pub fn find_job(&self, name: &str) -> Option<&BackupConfig> {
+        self.jobs().into_iter().find(|job| job.name == name)
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct BackupConfig {
+    /// Job name used in logs, events and notifications
+    #[serde(default = "default_job_name")]
+    pub name: String,
     pub frequency: String,
     pub time: String,
+    /// Directories to back up; may be left out when a `profile` provides them
+    #[serde(default)]
     pub directories: Vec<PathBuf>,
+    #[serde(default)]
     pub exclude: Vec<PathBuf>,
+    /// Names of per-directory ignore files (e.g. `.backupignore`) whose gitignore-style patterns are excluded
+    #[serde(default)]
+    pub ignore_files: Vec<String>,
+    /// Exclude what `.gitignore` ignores in git repositories, always keeping `.git` itself
+    #[serde(default)]
+    pub gitignore: bool,
+    /// Preset supplying default directories, excludes and flags
+    #[serde(default)]
+    pub profile: Option<profiles::Profile>,
+    /// Set to false to use only `exclude` instead of adding it to the profile's excludes
+    #[serde(default = "default_profile_excludes")]
+    pub profile_excludes: bool,
+    /// Pass `--one-file-system` to restic; defaults to the profile's setting
+    #[serde(default)]
+    pub one_file_system: Option<bool>,
+    /// Tags added to every snapshot of this job; may contain `{hostname}`, `{date}` and `{job}`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Back up container volumes instead of `directories`
+    #[serde(default)]
+    pub docker_volumes: Option<docker::DockerVolumesConfig>,
+    /// Back up database dumps and PVCs of Kubernetes pods instead of `directories`
+    #[serde(default)]
+    pub kubernetes: Option<kubernetes::KubernetesConfig>,
+    /// Back up `directories` from an LVM, Btrfs or ZFS snapshot
+    #[serde(default)]
+    pub fs_snapshot: Option<fs_snapshot::FsSnapshotConfig>,
+    /// Export the latest snapshot of some paths as encrypted tarballs instead of backing up
+    #[serde(default)]
+    pub cold_export: Option<cold_export::ColdExportConfig>,
+    /// Which notification targets this job uses and at what level
+    #[serde(default)]
+    pub notifications: Option<notify::JobNotifications>,
+    /// Extra environment variables for the commands this job runs, e.g. `PGPASSFILE` for a dump
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Directory the job's commands run in, so relative paths resolve against it
+    #[serde(default)]
+    pub workdir: Option<PathBuf>,
+    /// restic's temporary files (TMPDIR), e.g. on a disk when /tmp is a small tmpfs
+    #[serde(default)]
+    pub tmp_dir: Option<PathBuf>,
+    /// restic cache for this job, instead of `restic.cache_dir`
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    /// Local user the job's commands run as when the client runs as root
+    #[serde(default)]
+    pub run_as: Option<String>,
+    /// Record size, mtime and hash of every backed-up file after each run, for `verify`
+    #[serde(default)]
+    pub manifest: bool,
+    /// Check that restic keeps extended attributes and ACLs, and summarize them in the run report
+    #[serde(default)]
+    pub access_rights: bool,
+    /// Jobs that run before this one when they run together; it is skipped if one of them failed
+    #[serde(default)]
+    pub after: Vec<String>,
+    /// Only run on matching hosts, on AC power, or when a file exists or a command succeeds
+    #[serde(default)]
+    pub only_if: Option<conditions::OnlyIf>,
+    /// Rhai script whose `skip(run)` and `prepare(run)` can skip a run or add excludes and tags to it
+    #[serde(default)]
+    pub script: Option<PathBuf>,
+    /// Hours the job may run in; later runs are deferred and running ones stopped at the end
+    #[serde(default)]
+    pub window: Option<window::RunWindow>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl BackupConfig {
+    /// `directories`, or the profile's defaults when none are listed
+    pub fn effective_directories(&self) -> Vec<PathBuf> {
+        match self.profile {
+            Some(profile) if self.directories.is_empty() => profile.default_directories(),
+            _ => self.directories.clone(),
+        }
+    }
+
+    /// The profile's excludes (unless turned off) followed by the job's own
+    pub fn effective_excludes(&self) -> Vec<PathBuf> {
+        let mut excludes = match self.profile {
+            Some(profile) if self.profile_excludes => profile.default_excludes(),
+            _ => Vec::new(),
+        };
+        excludes.extend(self.exclude.iter().cloned());
+        excludes
+    }
+
+    /// A one-off job for paths given on the command line, named `adhoc`
+    pub fn adhoc(directories: Vec<PathBuf>, exclude: Vec<PathBuf>, tags: Vec<String>) -> Result<BackupConfig> {
+        // Built through serde so every other field gets its usual default
+        let job = serde_json::json!({
+            "name": "adhoc",
+            "frequency": "manual",
+            "time": "00:00",
+            "directories": directories,
+            "exclude": exclude,
+            "tags": tags,
+        });
+        Ok(serde_json::from_value(job)?)
+    }
+
+    /// Set the job's `env`, `workdir`, `tmp_dir`, `cache_dir` and `run_as` user on a command it runs,
+    /// leaving our own environment alone
+    pub fn apply_environment(&self, cmd: &mut Command) -> Result<()> {
+        if let Some(ref user) = self.run_as {
+            run_as::apply(cmd, user)?;
+        }
+        cmd.envs(&self.env);
+        if let Some(ref workdir) = self.workdir {
+            cmd.current_dir(expand_tilde(workdir));
+        }
+        if let Some(ref tmp_dir) = self.tmp_dir {
+            let tmp_dir = self.resolve_path(tmp_dir);
+            cmd.env("TMPDIR", &tmp_dir);
+            if cfg!(windows) {
+                cmd.env("TMP", &tmp_dir).env("TEMP", &tmp_dir);
+            }
+        }
+        if let Some(ref cache_dir) = self.cache_dir {
+            cmd.env("RESTIC_CACHE_DIR", self.resolve_path(cache_dir));
+        }
+        Ok(())
+    }
+
+    /// A path as the job's commands see it: relative paths are below `workdir`
+    pub fn resolve_path(&self, path: &std::path::Path) -> PathBuf {
+        let path = expand_tilde(path);
+        match self.workdir {
+            Some(ref workdir) if path.is_relative() => expand_tilde(workdir).join(path),
+            _ => path,
+        }
+    }
+
+    /// `tags` with their placeholders filled in for this run
+    pub fn expanded_tags(&self) -> Vec<String> {
+        self.tags.iter().map(|tag| templates::expand(tag, Some(&self.name))).collect()
+    }
+
+    pub fn uses_one_file_system(&self) -> bool {
+        self.one_file_system
+            .unwrap_or_else(|| self.profile.map(|profile| profile.one_file_system()).unwrap_or(false))
+    }
+
+    /// Expected time between runs, derived from `frequency`
+    pub fn interval(&self) -> Option<chrono::Duration> {
+        match self.frequency.to_lowercase().as_str() {
+            "hourly" => Some(chrono::Duration::hours(1)),
+            "daily" => Some(chrono::Duration::days(1)),
+            "weekly" => Some(chrono::Duration::weeks(1)),
+            "monthly" => Some(chrono::Duration::days(31)),
+            _ => None,
+        }
+    }
+}
+
+fn default_profile_excludes() -> bool {
+    true
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_job_name() -> String {
+    "backup".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 struct LoggingConfig {
     directory: PathBuf,
     max_size: String, // e.g., "10MB", "100KB"
+    /// Also write records at this level and above to the Windows Event Log: off, error, warn or info
+    #[serde(default)]
+    event_log: Option<String>,
+    /// Levels per module, and lines kept out of the file but shown on the console
+    #[serde(default)]
+    filters: log_filters::LogFilters,
+    /// Also print records to stderr, with their own level and format
+    #[serde(default)]
+    duplicate_to_stderr: Option<log_filters::DuplicateToStderr>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ResticConfig {
+    /// May contain `{hostname}` and `{date}`, see `repository_url`
     pub repository: String,
+    /// The restic executable, e.g. a specific version; `restic` from PATH otherwise
+    #[serde(default)]
+    pub binary: Option<PathBuf>,
     #[serde(default)]
     pub ssh_command: Option<String>,
+    /// SSH options for an sftp: repository, passed to restic as `-o sftp.command=...`
+    #[serde(default)]
+    pub ssh: Option<ssh::SshConfig>,
+    /// How restic runs rclone for an rclone: repository
+    #[serde(default)]
+    pub rclone: Option<rclone::RcloneConfig>,
     #[serde(default)]
     pub password_command: Option<String>,
     #[serde(default)]
     pub password: Option<String>,
+    /// File holding the password, passed as `--password-file`; it shouldn't be readable by everyone
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+    /// Passed to restic as `--cache-dir`; restic's default location is used otherwise
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    /// Check that the backend is reachable and accepts the login before each backup, see `backends`
+    #[serde(default = "default_true")]
+    pub check_backend: bool,
+    /// Mountpoint the repository lives on, required (and optionally mounted) before each run
+    #[serde(default)]
+    pub mount: Option<mount::MountConfig>,
+    /// Wait for other processes' locks on the repository before backing up
+    #[serde(default)]
+    pub lock_wait: Option<locks::LockWaitConfig>,
+    /// Warn once the repository's stored data reaches this size, e.g. "800GB"
+    #[serde(default)]
+    pub size_warning: Option<String>,
+    /// Size the repository must not grow beyond, e.g. a B2 budget or the NAS disk; exceeding it is reported after each backup
+    #[serde(default)]
+    pub size_limit: Option<String>,
+    /// Restrict what the restic backup process can reach (Linux)
+    #[serde(default)]
+    pub sandbox: Option<sandbox::SandboxConfig>,
+}
+
+impl ResticConfig {
+    /// The repository with its placeholders filled in, e.g. `sftp:nas:/restic/{hostname}` for this machine
+    pub fn repository_url(&self) -> String {
+        templates::expand(&self.repository, None)
+    }
+
+    pub fn program(&self) -> PathBuf {
+        self.binary.as_ref().map(|binary| expand_tilde(binary)).unwrap_or_else(|| PathBuf::from("restic"))
+    }
+}
+
+/// Whether the command runs restic against the repository, and so needs its password
+fn uses_repository(subcommand: Option<&str>, args: &[String]) -> bool {
+    let has = |flag: &str| args.iter().any(|arg| arg == flag);
+    match subcommand {
+        Some("backup" | "bootstrap" | "ls" | "snapshots" | "maintain" | "pin" | "unpin" | "restore" | "retention") => true,
+        Some("report") => matches!(args.get(2).map(String::as_str), Some("dedupe" | "growth")),
+        // `export SNAPSHOT PATH` reads the repository, unlike exporting the config as a script
+        Some("export") => !positional_args(&args[2..]).is_empty(),
+        // So does a test restore, unlike comparing a manifest with the files on disk
+        Some("verify") => has("--restore-test"),
+        Some(_) => false,
+        // The scheduled run backs up, unless it only lists excludes or unreadable files
+        None => !has("--check-permissions") && !has("--print-excludes"),
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     // Check for command-line flags
     let args: Vec<String> = std::env::args().collect();
+    let subcommand = args.get(1).map(String::as_str).filter(|arg| !arg.starts_with('-'));
     let dry_run = args.iter().any(|arg| arg == "--dry-run" || arg == "-n");
     let verbose = args.iter().any(|arg| arg == "--verbose" || arg == "-v");
     let ui_mode = args.iter().any(|arg| arg == "--ui" || arg == "-u");
+    let check_permissions = args.iter().any(|arg| arg == "--check-permissions");
+    let print_excludes = args.iter().any(|arg| arg == "--print-excludes");
+    let catch_up = args.iter().any(|arg| arg == "--catch-up");
+    let accept_new_repo = args.iter().any(|arg| arg == "--accept-new-repo");
+    let selected_jobs: Vec<&String> = args
+        .iter()
+        .zip(args.iter().skip(1))
+        .filter(|(flag, _)| *flag == "--job" || *flag == "-j")
+        .map(|(_, name)| name)
+        .collect();
+
+    // Importing creates a config, so it can't need one
+    if subcommand == Some("import") {
+        return import::run(&args);
+    }
+    if subcommand == Some("hash-password") {
+        return hash_password();
+    }
+    // The self-test brings its own repository, so it works before there's a config
+    if subcommand == Some("self-test") {
+        return self_test::run(&args).await;
+    }
 
     // Read config from YAML file
     let config_path = "config.yaml";
     debug!("Reading config from: {}", config_path);
-    let config_content = std::fs::read_to_string(config_path)?;
-    let config: Config = serde_yaml::from_str(&config_content)?;
+    let config_content = migrations::load(config_path)?;
+    let mut config: Config = if overrides::present(&args) {
+        let mut document: serde_yaml::Value = serde_yaml::from_str(&config_content)?;
+        overrides::apply(&mut document, &args)?;
+        serde_yaml::from_value(document).map_err(|e| anyhow::anyhow!("Config with command line overrides is invalid: {}", e))?
+    } else {
+        serde_yaml::from_str(&config_content)?
+    };
+    dependencies::check(&config)?;
     debug!("Config loaded successfully");
 
     // Set up rolling logs (needed for both UI and CLI modes)
     let log_level = if verbose { "debug" } else { "info" };
     setup_logging(&config.logging, log_level)?;
+    if accept_new_repo {
+        fingerprint::accept_new(&config)?;
+    }
+    if ui_mode || subcommand == Some("agent") {
+        // They keep running and reload the config, which would lose a typed password
+        password::warn_if_missing(&config);
+    } else if uses_repository(subcommand, &args) {
+        password::ensure(&mut config)?;
+    }
+
+    // The selected jobs, or all of them
+    let mut jobs = Vec::new();
+    for name in &selected_jobs {
+        let job = config
+            .find_job(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown job '{}'", name))?;
+        jobs.push(job);
+    }
+    if jobs.is_empty() {
+        jobs = config.jobs();
+    }
+
+    match subcommand {
+        Some("install-service") => {
+            let user = flag_value(&args, "--service-user")
+                .cloned()
+                .or_else(|| std::env::var("SUDO_USER").ok())
+                .or_else(|| std::env::var("USER").ok())
+                .or_else(|| std::env::var("USERNAME").ok())
+                .ok_or_else(|| anyhow::anyhow!("Can't tell which user to run as; pass --service-user"))?;
+            let options = service::ServiceOptions {
+                read_access: service::read_access(&user, &jobs),
+                user,
+                binary: std::env::current_exe()?,
+                working_directory: std::env::current_dir()?,
+            };
+            let print = args.iter().any(|arg| arg == "--print");
+            let result = if cfg!(windows) || args.iter().any(|arg| arg == "--scheduled-task") {
+                let output = flag_value(&args, "--output").map(PathBuf::from);
+                scheduled_task::install(&config, &jobs, &options, print, output.as_deref())
+            } else {
+                let output = (!print).then(|| PathBuf::from(flag_value(&args, "--output").map(String::as_str).unwrap_or(service::DEFAULT_UNIT_DIR)));
+                service::install(&config, &jobs, &options, output.as_deref())
+            };
+            if !print {
+                audit::record(&config, &audit::AuditEntry::cli("install-service", Some(options.user.clone())).outcome(&result));
+            }
+            result?;
+            return Ok(());
+        }
+        Some("agent") => {
+            let _pid_file = daemon::start(&config, &args)?;
+            let interrupted = run_journal::recover(&config).await;
+            tokio::spawn(resume::follow_up(config.clone(), interrupted, move |config, jobs| resume::run_jobs(config, jobs, verbose)));
+            agent::run(&config, dry_run, verbose).await?;
+            return Ok(());
+        }
+        Some("backup") => {
+            let mut directories = Vec::new();
+            for path in positional_args(&args[2..]) {
+                let path = expand_tilde(std::path::Path::new(path));
+                if !path.exists() {
+                    return Err(anyhow::anyhow!("{:?} does not exist", path));
+                }
+                directories.push(std::path::absolute(&path)?);
+            }
+            if directories.is_empty() {
+                return Err(anyhow::anyhow!("Usage: backup PATH... [--tag TAG] [--exclude PATTERN] [--note TEXT]"));
+            }
+            let exclude = flag_values(&args, "--exclude").into_iter().map(PathBuf::from).collect();
+            let mut tags: Vec<String> = flag_values(&args, "--tag").into_iter().cloned().collect();
+            if let Some(note) = flag_value(&args, "--note") {
+                tags.push(notes::tag(note)?);
+            }
+            let job = BackupConfig::adhoc(directories, exclude, tags)?;
+            info!("Ad-hoc backup of {:?} to {}", job.directories, config.restic.repository_url());
+            let result = run_backup(&config, &job, dry_run, verbose, None).await;
+            if !dry_run {
+                let target = job.directories.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(" ");
+                audit::record(&config, &audit::AuditEntry::cli("backup", Some(target)).outcome(&result));
+            }
+            result?;
+            return Ok(());
+        }
+        Some("bootstrap") => {
+            let result = bootstrap::run(&config, &jobs, dry_run).await;
+            if !dry_run {
+                audit::record(&config, &audit::AuditEntry::cli("bootstrap", Some(config.restic.repository_url())).outcome(&result));
+            }
+            result?;
+            return Ok(());
+        }
+        Some("digest") => {
+            digest::run(&config).await?;
+            return Ok(());
+        }
+        Some("diagnostics") => {
+            diagnostics::run(&config, config_path, &args).await?;
+            return Ok(());
+        }
+        Some("export") if !positional_args(&args[2..]).is_empty() => {
+            archive::run(&config, &args).await?;
+            return Ok(());
+        }
+        Some("export") => {
+            match flag_value(&args, "--format").map(String::as_str).unwrap_or("shell") {
+                "shell" => print!("{}", export::shell_script(&config, &jobs)),
+                other => return Err(anyhow::anyhow!("Unknown export format '{}'; supported: shell", other)),
+            }
+            return Ok(());
+        }
+        Some("ls") => {
+            ls::run(&config, &args).await?;
+            return Ok(());
+        }
+        Some("snapshots") => {
+            snapshots::run(&config, &args).await?;
+            return Ok(());
+        }
+        Some("maintain") => {
+            safety::confirm(config.safety.as_ref(), &config.restic.repository_url(), "forget and prune snapshots", &args)?;
+            let report = maintenance::run(&config).await;
+            let mut entry = audit::AuditEntry::cli("maintain", Some(config.restic.repository_url())).outcome(&report);
+            if let Ok(ref report) = report {
+                entry.success = report.is_success();
+            }
+            audit::record(&config, &entry);
+            let report = report?;
+            let painter = style::Painter::stdout();
+            let tone = if report.is_success() { style::Tone::Success } else { style::Tone::Failure };
+            println!("{}\n\n{}", painter.status(tone, &report.title()), report.render(painter));
+            if !report.is_success() {
+                return Err(anyhow::anyhow!("Repository maintenance failed"));
+            }
+            return Ok(());
+        }
+        Some(command @ ("pin" | "unpin")) => {
+            let snapshot_id = args
+                .get(2)
+                .filter(|arg| !arg.starts_with('-'))
+                .ok_or_else(|| anyhow::anyhow!("Usage: {} <snapshot-id>", command))?;
+            let result = pins::set_pinned(&config.restic, snapshot_id, command == "pin").await;
+            audit::record(&config, &audit::AuditEntry::cli(command, Some(snapshot_id.clone())).outcome(&result));
+            result?;
+            println!(
+                "Snapshot {} {} (restic assigns tagged snapshots a new id)",
+                snapshot_id,
+                if command == "pin" { "pinned" } else { "unpinned" }
+            );
+            return Ok(());
+        }
+        Some("restore") => {
+            let result = restore::run(&config, &args, dry_run).await;
+            if !dry_run {
+                audit::record(&config, &audit::AuditEntry::cli("restore", Some(args[2..].join(" "))).outcome(&result));
+            }
+            result?;
+            return Ok(());
+        }
+        Some("report") => {
+            match args.get(2).map(String::as_str) {
+                Some("dedupe") => dedupe::run(&config, &jobs, &args).await?,
+                Some("growth") => growth::run(&config, &jobs, &args).await?,
+                Some("problems") => problems::run(&config, &jobs, &args).await?,
+                _ => return Err(anyhow::anyhow!("Usage: report dedupe|growth|problems [--job NAME] [--format text|json]")),
+            }
+            return Ok(());
+        }
+        Some("retention") => {
+            match args.get(2).map(String::as_str) {
+                Some("simulate") => retention::simulate(&config, &jobs, &args).await?,
+                _ => return Err(anyhow::anyhow!("Usage: retention simulate [--keep-daily N ...]")),
+            }
+            return Ok(());
+        }
+        Some("repo-health") => {
+            let health_config = config
+                .preflight
+                .as_ref()
+                .and_then(|preflight| preflight.repo_health.clone())
+                .unwrap_or_default();
+            let report = repo_health::inspect_repository(&config.restic.repository_url(), &health_config)?;
+            println!("Repository: {:?}", report.path);
+            for check in &report.checks {
+                let (tone, label) = if check.passed { (style::Tone::Success, "[ ok ]") } else { (style::Tone::Failure, "[FAIL]") };
+                println!("  {} {}", style::Painter::stdout().paint(tone, label), check.message);
+            }
+            if !report.is_healthy() {
+                return Err(anyhow::anyhow!("Repository is not healthy: {}", report.failures()));
+            }
+            return Ok(());
+        }
+        Some("status") => {
+            let reports = config
+                .reports
+                .as_ref()
+                .map(|reports_config| reports::list(&expand_tilde(&reports_config.directory)))
+                .unwrap_or_default();
+            let painter = style::Painter::stdout();
+            println!("Host: {}", hostname());
+            println!("Repository: {}", config.restic.repository_url());
+            // On a terminal the jobs line up in columns; piped output keeps one sentence per job
+            let name_width = jobs.iter().map(|job| job.name.chars().count()).max().unwrap_or(0);
+            let schedule_width = jobs.iter().map(|job| job.frequency.len() + job.time.len() + 4).max().unwrap_or(0);
+            for job in &jobs {
+                let last_report = reports.iter().find(|report| report.job == job.name && !report.dry_run);
+                let last_run = match last_report {
+                    Some(report) => match report.note {
+                        Some(ref note) => format!("{} at {} ({})", report.status, report.finished_at.format("%Y-%m-%d %H:%M"), note),
+                        None => format!("{} at {}", report.status, report.finished_at.format("%Y-%m-%d %H:%M")),
+                    },
+                    None if config.reports.is_none() => "unknown (no reports directory)".to_string(),
+                    None => "never".to_string(),
+                };
+                if painter.is_terminal() {
+                    let last_run = match last_report {
+                        Some(report) => painter.status(style::Tone::of_status(&report.status), &last_run),
+                        None => painter.paint(style::Tone::Muted, &last_run),
+                    };
+                    let schedule = format!("{} at {}", job.frequency, job.time);
+                    println!("  {:<name_width$}  {:<schedule_width$}  {}", job.name, schedule, last_run);
+                } else {
+                    println!("  {} ({} at {}): last run {}", job.name, job.frequency, job.time, last_run);
+                }
+            }
+            if let Some(estimate) = cost::current(&config, &reports).await {
+                println!("Cost: {}", estimate.describe("last upload"));
+            }
+            if let Some(daemon_config) = &config.daemon {
+                println!("Daemon: {}", daemon::describe(daemon_config));
+            }
+            if let Some(pinned) = fingerprint::pinned(&config).get(&config.restic.repository_url()) {
+                println!("Repository ID: {} (pinned since {})", restic::short_id(&pinned.id), pinned.since.format("%Y-%m-%d"));
+            }
+            if config.maintenance.as_ref().is_some_and(|maintenance| maintenance.read_data_rotation.is_some()) {
+                match maintenance::rotation(&config) {
+                    Some(rotation) => println!("Data check: {}", rotation.describe()),
+                    None => println!("Data check: no subset verified yet"),
+                }
+            }
+            for (job, catch_up) in window::pending(&config) {
+                let line = format!("Catch-up pending: {} since {} ({})", job, catch_up.since.format("%Y-%m-%d %H:%M"), catch_up.reason);
+                println!("{}", painter.status(style::Tone::Warning, &line));
+            }
+            return Ok(());
+        }
+        Some("staging") => {
+            let result = staging::run(&config, &args, dry_run);
+            if let Some(action @ ("commit" | "discard")) = args.get(2).map(String::as_str) {
+                if !dry_run {
+                    audit::record(&config, &audit::AuditEntry::cli(&format!("staging.{}", action), Some(args[3..].join(" "))).outcome(&result));
+                }
+            }
+            result?;
+            return Ok(());
+        }
+        Some("stop") => {
+            daemon::stop(&config)?;
+            return Ok(());
+        }
+        Some("verify") => {
+            manifest::run(&config, &jobs, &args).await?;
+            return Ok(());
+        }
+        Some("token") => {
+            let result = tokens::run(config_path, &args);
+            if let Some(action @ ("create" | "revoke")) = args.get(2).map(String::as_str) {
+                let target = result.as_ref().ok().cloned().flatten();
+                audit::record(&config, &audit::AuditEntry::cli(&format!("token.{}", action), target).outcome(&result));
+            }
+            result?;
+            return Ok(());
+        }
+        Some(other) => return Err(anyhow::anyhow!("Unknown command '{}'", other)),
+        None => {}
+    }
 
     // If UI mode, start web server
     if ui_mode {
+        let _pid_file = daemon::start(&config, &args)?;
+        let interrupted = run_journal::recover(&config).await;
         // Expand tilde in log directory
         let log_dir = if config.logging.directory.to_string_lossy().starts_with("~") {
             let home = std::env::var("HOME")
@@ -72,42 +762,196 @@ async fn main() -> Result<()> {
         use tokio::sync::RwLock;
         use std::sync::Arc;
         
+        let resume_config = config.clone();
         let app_state = web::AppState {
             config: Arc::new(RwLock::new(config)),
             config_path: config_path.to_string(),
             log_dir,
+            events: events::EventBus::new(),
+            cache: cache::RepoCache::new(),
+            auth: accounts::AuthCache::new(),
+            agents: agent::AgentRegistry::new(),
         };
+        let follow_up_state = app_state.clone();
+        tokio::spawn(resume::follow_up(resume_config, interrupted, move |config, jobs| {
+            let state = follow_up_state.clone();
+            async move {
+                web::start_backups(&state, config, &jobs, false, None, None);
+            }
+        }));
 
         return web::run_web_server(app_state).await.map_err(|e| anyhow::anyhow!("Web server error: {}", e));
     }
 
+    if print_excludes {
+        for job in &jobs {
+            println!("# Job '{}'{}", job.name, job.profile.map(|p| format!(" (profile: {})", p.as_str())).unwrap_or_default());
+            for exclude in job.effective_excludes() {
+                println!("{}", exclude.display());
+            }
+            let ignore_patterns = ignore_files::collect(job);
+            if !ignore_patterns.is_empty() {
+                let mut sources = job.ignore_files.clone();
+                if job.gitignore {
+                    sources.push(".gitignore".to_string());
+                }
+                println!("# From {}", sources.join(", "));
+                for pattern in ignore_patterns {
+                    println!("{}", pattern);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if check_permissions {
+        let mut total = 0;
+        for job in &jobs {
+            let unreadable = permissions::unreadable_paths(job);
+            println!("Job '{}': {} unreadable path(s)", job.name, unreadable.len());
+            for entry in unreadable.iter().take(50) {
+                println!("  {:?}: {}", entry.path, entry.error);
+            }
+            if unreadable.len() > 50 {
+                println!("  ... and {} more", unreadable.len() - 50);
+            }
+            total += unreadable.len();
+            let blocked = if cfg!(target_os = "macos") { tcc::blocked_folders(job) } else { Vec::new() };
+            if !blocked.is_empty() {
+                println!("  {}", tcc::describe(&blocked));
+                println!("  {}", tcc::guidance());
+            }
+        }
+        if total > 0 {
+            println!("\nRun as root, or grant CAP_DAC_READ_SEARCH (see `install-service`), to back these up.");
+            return Err(anyhow::anyhow!("{} path(s) in the backup set are not readable", total));
+        }
+        return Ok(());
+    }
+
     info!("Better Restic Client starting up");
-    info!("Backup frequency: {}", config.backup.frequency);
-    info!("Backup time: {}", config.backup.time);
-    info!("Backup directories: {:?}", config.backup.directories);
-    info!("Exclude directories: {:?}", config.backup.exclude);
+    for job in &jobs {
+        info!("Job '{}': {} at {}", job.name, job.frequency, job.time);
+        if let Some(profile) = job.profile {
+            info!("  Profile: {}", profile.as_str());
+        }
+        info!("  Backup directories: {:?}", job.effective_directories());
+        info!("  Exclude directories: {:?}", job.effective_excludes());
+    }
     info!("Log directory: {:?}", config.logging.directory);
     info!("Max log size: {}", config.logging.max_size);
     info!("Dry run mode: {}", dry_run);
     info!("Verbose mode: {}", verbose);
-    info!("Restic repository: {}", config.restic.repository);
+    info!("Restic repository: {}", config.restic.repository_url());
     
     if let Some(ref ssh_cmd) = config.restic.ssh_command {
         debug!("SSH command configured: {}", ssh_cmd);
     }
+    if let Some(sftp_cmd) = ssh::sftp_command(&config.restic) {
+        debug!("SFTP command: {}", sftp_cmd);
+    }
     if let Some(ref pwd_cmd) = config.restic.password_command {
         debug!("Password command configured: {}", pwd_cmd);
     }
+    if let Some(ref password_file) = config.restic.password_file {
+        debug!("Password file configured: {:?}", password_file);
+    }
     if config.restic.password.is_some() {
         debug!("Direct password configured (from config.yaml)");
     }
 
-    // Execute restic backup
-    execute_restic_backup(&config.backup, &config.restic, dry_run, verbose).await?;
+    // Execute restic backup for each job, carrying on past failures
+    let note = flag_value(&args, "--note");
+    let mut failed = Vec::new();
+    let resumed = if dry_run {
+        Vec::new()
+    } else {
+        run_journal::recover(&config).await;
+        resume::pending(&config)
+    };
+    if catch_up {
+        let pending = window::pending(&config);
+        jobs.retain(|job| pending.contains_key(&job.name));
+        info!("Catching up on {} deferred job(s)", jobs.len());
+    }
+    let batch = dependencies::order(&jobs);
+    for &job in &batch {
+        if let Some(reason) = dependencies::unmet(&config, job, &batch, &failed) {
+            error!("Skipping job '{}': {}", job.name, reason);
+            failed.push(job.name.clone());
+            continue;
+        }
+        if let Some(reason) = conditions::unmet(job).await {
+            info!("Skipping job '{}': {}", job.name, reason);
+            continue;
+        }
+        if let Some(reason) = window::defer(&config, job, dry_run) {
+            info!("Deferring job '{}' until its next run: {}", job.name, reason);
+            continue;
+        }
+        // The first backup after an interrupted one is tagged, so its snapshot can be told apart
+        let tagged = resumed.contains(&job.name).then(|| resume::tag(job));
+        let job = tagged.as_ref().unwrap_or(job);
+        let annotated = note.map(|note| notes::annotate(job, note)).transpose()?;
+        let job = annotated.as_ref().unwrap_or(job);
+        let result = run_backup(&config, job, dry_run, verbose, None).await;
+        if !dry_run {
+            audit::record(&config, &audit::AuditEntry::cli("backup", Some(job.name.clone())).outcome(&result));
+        }
+        if let Err(e) = result {
+            error!("Job '{}' failed: {}", job.name, e);
+            failed.push(job.name.clone());
+        }
+    }
+
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!("Failed jobs: {}", failed.join(", ")));
+    }
 
     Ok(())
 }
 
+/// Read a password from stdin and print its hash for a `users` entry
+fn hash_password() -> Result<()> {
+    use std::io::{BufRead, IsTerminal};
+    if std::io::stdin().is_terminal() {
+        eprint!("Password (shown as you type): ");
+    }
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(anyhow::anyhow!("The password must not be empty"));
+    }
+    println!("{}", accounts::hash_password(password)?);
+    Ok(())
+}
+
+/// Value following a `--flag value` pair on the command line
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter().zip(args.iter().skip(1)).find(|(arg, _)| *arg == flag).map(|(_, value)| value)
+}
+
+/// Arguments that are neither flags nor flag values
+fn positional_args(args: &[String]) -> Vec<&String> {
+    const VALUE_FLAGS: &[&str] = &["--job", "-j", "--tag", "--exclude", "--repo", "--restic-binary", "--set", "--format", "--output", "--service-user", "--target", "--include", "--at", "--host", "--path", "--depth", "--limit", "--runs", "--min-runs", "--note"];
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if VALUE_FLAGS.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') {
+            positional.push(arg);
+        }
+    }
+    positional
+}
+
+/// Values of a flag that can be given several times
+fn flag_values<'a>(args: &'a [String], flag: &str) -> Vec<&'a String> {
+    args.iter().zip(args.iter().skip(1)).filter(|(arg, _)| *arg == flag).map(|(_, value)| value).collect()
+}
+
 fn setup_logging(logging_config: &LoggingConfig, log_level: &str) -> Result<()> {
     use flexi_logger::{FileSpec, Logger, Criterion, Naming, Cleanup};
 
@@ -136,9 +980,27 @@ fn setup_logging(logging_config: &LoggingConfig, log_level: &str) -> Result<()>
     let max_size_bytes = parse_size(&logging_config.max_size)?;
     debug!("Log max size: {} bytes", max_size_bytes);
 
+    let file_spec = FileSpec::default().directory(&log_dir).basename("restic_backup");
+    let event_log_level = match logging_config.event_log.as_deref() {
+        Some(level) if cfg!(windows) => eventlog::parse_level(level),
+        _ => None,
+    };
+    // RUST_LOG still takes precedence over the configured levels
+    let (spec, line_filter) = logging_config.filters.setup(log_level, logging_config.duplicate_to_stderr.as_ref());
+    let logger = Logger::try_with_env_or_str(&spec).map_err(|e| anyhow::anyhow!("Invalid logging.filters '{}': {}", spec, e))?;
+    let logger = match line_filter {
+        Some(filter) => logger.filter(Box::new(filter)),
+        None => logger,
+    };
+    let logger = match event_log_level {
+        Some(level) if level != log::LevelFilter::Off => {
+            logger.log_to_file_and_writer(file_spec, Box::new(eventlog::EventLogWriter::new(level)))
+        }
+        _ => logger.log_to_file(file_spec),
+    };
+
     // Configure flexi_logger with size-based rotation
-    Logger::try_with_env_or_str(log_level)?
-        .log_to_file(FileSpec::default().directory(&log_dir).basename("restic_backup"))
+    logger
         .rotate(
             Criterion::Size(max_size_bytes),
             Naming::Numbers,
@@ -147,7 +1009,7 @@ fn setup_logging(logging_config: &LoggingConfig, log_level: &str) -> Result<()>
         .format(flexi_logger::detailed_format)
         .start()?;
 
-    debug!("Logging initialized with level: {}", log_level);
+    debug!("Logging initialized with level: {}", spec);
     Ok(())
 }
 
@@ -168,8 +1030,13 @@ fn parse_size(size_str: &str) -> Result<u64> {
             size_str[..size_str.len() - 2].trim(),
             "GB",
         )
+    } else if size_str.ends_with("TB") {
+        (
+            size_str[..size_str.len() - 2].trim(),
+            "TB",
+        )
     } else {
-        return Err(anyhow::anyhow!("Invalid size format. Use MB, KB, or GB"));
+        return Err(anyhow::anyhow!("Invalid size format. Use KB, MB, GB or TB"));
     };
 
     let number: u64 = number.parse()?;
@@ -177,27 +1044,452 @@ fn parse_size(size_str: &str) -> Result<u64> {
         "KB" => number * 1024,
         "MB" => number * 1024 * 1024,
         "GB" => number * 1024 * 1024 * 1024,
+        "TB" => number * 1024 * 1024 * 1024 * 1024,
         _ => return Err(anyhow::anyhow!("Invalid unit")),
     };
 
     Ok(bytes)
 }
 
-pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config: &ResticConfig, dry_run: bool, verbose: bool) -> Result<()> {
+/// Reach the repository before backing up: its backend's checks, then the repository's ID
+async fn check_repository_access(config: &Config, dry_run: bool, phases: &mut reports::PhaseTimer) -> Result<()> {
+    let repository = config.restic.repository_url();
+    if let (true, Some(backend)) = (config.restic.check_backend, backends::for_repository(&repository)) {
+        phases.start(backend.name());
+        backend.check(&config.restic).await?;
+    }
+    phases.start("fingerprint");
+    fingerprint::check(config, dry_run).await
+}
+
+/// Run a backup job and report the outcome to the configured metrics, notification and report targets
+pub async fn run_backup(
+    config: &Config,
+    job: &BackupConfig,
+    dry_run: bool,
+    verbose: bool,
+    reporter: Option<&events::RunReporter>,
+) -> Result<restic::BackupSummary> {
+    let routing = job.notifications.as_ref();
+    let run_id = reporter.map(|r| r.run_id.clone()).unwrap_or_else(events::new_run_id);
+    let started_at = chrono::Local::now();
+    let started = std::time::Instant::now();
+    let mut phases = reports::PhaseTimer::new();
+    let mut journal = run_journal::Journal::begin(config, &run_id, &job.name, dry_run);
+
+    if !dry_run && !config.notifications.is_empty() {
+        phases.start("notify_start");
+        notify::send_all(&config.notifications, routing, &notify::Notification::started(&job.name)).await;
+    }
+
+    let context = |hook, status, duration_secs, summary, error| hooks::RunContext {
+        hook,
+        run_id: &run_id,
+        job: &job.name,
+        hostname: hostname(),
+        repository: config.restic.repository_url(),
+        started_at,
+        status,
+        duration_secs,
+        summary,
+        error,
+    };
+    // The job's script may add excludes and tags; an error in it fails the run like a failed pre hook
+    let (scripted, script) = match job.script {
+        Some(_) => {
+            phases.start("script");
+            match scripting::prepare(job) {
+                Ok(prepared) => (prepared, Ok(())),
+                Err(e) => (None, Err(e)),
+            }
+        }
+        None => (None, Ok(())),
+    };
+    let job = scripted.as_ref().unwrap_or(job);
+    // Hooks are for real runs, like notifications
+    let pre_hooks = match script {
+        Ok(()) if !dry_run => {
+            phases.start("hooks_pre");
+            hooks::run(config.hooks.as_ref(), job, &context(hooks::HookPhase::Pre, None, None, None, None)).await
+        }
+        other => other,
+    };
+
+    // A failed script or pre hook, missing mount or failed preflight check fails the run like a failed backup, so it is reported the same way
+    let mounted = match (&pre_hooks, &config.restic.mount) {
+        (Err(_), _) => Ok(false),
+        (Ok(()), Some(mount_config)) => {
+            phases.start("mount");
+            mount::ensure_mounted(mount_config).await
+        }
+        (Ok(()), None) => Ok(false),
+    };
+
+    let we_mounted = matches!(mounted, Ok(true));
+
+    let preflight = match (pre_hooks.and(mounted), &config.preflight) {
+        (Err(e), _) => Err(e),
+        (Ok(_), Some(ref preflight_config)) => {
+            phases.start("preflight");
+            preflight::check(preflight_config, &config.restic, Some(job))
+        }
+        (Ok(_), None) => Ok(()),
+    };
+    let preflight = preflight.and_then(|()| job_dirs::prepare(job));
+    // Protected macOS folders restic can't read are reported up front instead of as one warning per file
+    let protected_data = config.preflight.as_ref().map(|preflight| preflight.protected_data).unwrap_or_default();
+    let preflight = preflight.and_then(|()| tcc::check(job, protected_data));
+    let preflight = match preflight {
+        Ok(blocked) => match access_rights::check(job, &config.restic).await {
+            Ok(()) => check_repository_access(config, dry_run, &mut phases).await.map(|()| blocked),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    let adjusted_job;
+    let job = match preflight {
+        Ok(ref blocked) if !blocked.is_empty() => {
+            let mut without_blocked = job.clone();
+            without_blocked.exclude.extend(blocked.iter().cloned());
+            adjusted_job = without_blocked;
+            &adjusted_job
+        }
+        _ => job,
+    };
+
+    let result = match preflight {
+        Ok(_) => {
+            if let Some(ref wait_config) = config.restic.lock_wait {
+                phases.start("lock_wait");
+                locks::wait_for_locks(&config.restic, wait_config).await;
+            }
+            phases.start("backup");
+            journal.advance(run_journal::RunState::ResticRunning);
+            if let Some(ref docker_config) = job.docker_volumes {
+                docker::backup_volumes(job, docker_config, &config.restic, dry_run, verbose, reporter).await
+            } else if let Some(ref kubernetes_config) = job.kubernetes {
+                kubernetes::backup(job, kubernetes_config, &config.restic, dry_run, reporter).await
+            } else if let Some(ref snapshot_config) = job.fs_snapshot {
+                fs_snapshot::backup(job, snapshot_config, &config.restic, dry_run, verbose, reporter).await
+            } else if let Some(ref export_config) = job.cold_export {
+                cold_export::run(job, export_config, &config.restic, dry_run).await
+            } else {
+                execute_restic_backup(job, &config.restic, dry_run, verbose, reporter).await
+            }
+        }
+        Err(e) => {
+            error!("Preflight check failed for job '{}': {}", job.name, e);
+            Err(e)
+        }
+    };
+    journal.advance(run_journal::RunState::ResticFinished);
+
+    let plain_directories = job.docker_volumes.is_none() && job.kubernetes.is_none() && job.cold_export.is_none();
+    if let (Ok(ref summary), true, false) = (&result, job.manifest && plain_directories, dry_run) {
+        phases.start("manifest");
+        manifest::record(config, job, &run_id, summary.snapshot_id.clone()).await;
+    }
+    let access_rights = if let (Ok(_), true, false) = (&result, job.access_rights && plain_directories, dry_run) {
+        phases.start("access_rights");
+        let version = access_rights::restic_version(&config.restic).await;
+        let scanned_job = job.clone();
+        match tokio::task::spawn_blocking(move || access_rights::summarize(&scanned_job, version)).await {
+            Ok(summary) => {
+                info!("{}", summary.describe());
+                for note in &summary.notes {
+                    info!("Access rights: {}", note);
+                }
+                Some(summary)
+            }
+            Err(e) => {
+                error!("Couldn't summarize the access rights of job '{}': {}", job.name, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Measure the repository while it's still mounted, once the backup has added to it
+    let (repository_size, repository_warning) =
+        if !dry_run && result.is_ok() && (config.restic.size_warning.is_some() || config.restic.size_limit.is_some() || config.cost.is_some()) {
+            phases.start("quota");
+            quota::check(&config.restic).await
+        } else {
+            (None, None)
+        };
+
+    if let (Some(ref mount_config), true) = (&config.restic.mount, we_mounted) {
+        phases.start("unmount");
+        mount::unmount(mount_config).await;
+    }
+    journal.advance(run_journal::RunState::PostRunDone);
+    let backup_duration = started.elapsed();
+
+    if !dry_run {
+        match result {
+            Ok(_) => window::clear(config, &job.name),
+            Err(ref e) if e.is::<window::WindowClosed>() => window::mark(config, &job.name, &e.to_string()),
+            Err(_) => {}
+        }
+    }
+
+    // A dry run's summary is kept and compared with the next backup of the job
+    let prediction = match result {
+        Ok(_) if job.cold_export.is_some() => None,
+        Ok(ref summary) if dry_run => {
+            prediction::record(config, &job.name, &run_id, summary);
+            None
+        }
+        Ok(ref summary) => prediction::compare(config, &job.name, summary),
+        Err(_) => None,
+    };
+    let prediction_warning = prediction
+        .as_ref()
+        .filter(|check| check.discrepancy)
+        .map(|check| format!("{}; files may have changed while it ran", check.describe()));
+    if let Some(ref warning) = prediction_warning {
+        warn!("Job '{}': {}", job.name, warning);
+    }
+
+    let status = match result {
+        Ok(ref summary) if !summary.warnings.is_empty() || repository_warning.is_some() || prediction_warning.is_some() => notify::RunEvent::Warning,
+        Ok(_) => notify::RunEvent::Succeeded,
+        Err(_) => notify::RunEvent::Failed,
+    };
+
+    // Dry runs don't back anything up, so they shouldn't count as the last backup
+    if !dry_run {
+        phases.start("hooks_post");
+        let (hook, error) = match result {
+            Ok(_) => (hooks::HookPhase::Post, None),
+            Err(ref e) => (hooks::HookPhase::Failure, Some(e.to_string())),
+        };
+        let summary = result.as_ref().ok();
+        if let Err(e) = hooks::run(config.hooks.as_ref(), job, &context(hook, Some(status.as_str()), Some(backup_duration.as_secs_f64()), summary, error)).await {
+            warn!("Couldn't run the {} hooks: {}", hook.as_str(), e);
+        }
+
+        if let Some(ref metrics_config) = config.metrics {
+            phases.start("metrics");
+            metrics::export(metrics_config, &metrics::RunMetrics::new(&job.name, result.is_ok(), backup_duration)).await;
+        }
+
+        if !config.notifications.is_empty() {
+            phases.start("notify");
+            let notification = notify::Notification {
+                job: job.name.clone(),
+                event: status,
+                duration_secs: backup_duration.as_secs_f64(),
+                error: result.as_ref().err().map(|e| e.to_string()),
+                summary: result.as_ref().ok().cloned(),
+                repository_warning: repository_warning.clone(),
+                prediction_warning: prediction_warning.clone(),
+            };
+            notify::send_all(&config.notifications, routing, &notification).await;
+        }
+    }
+    journal.advance(run_journal::RunState::Notified);
+
+    if let Some(ref reports_config) = config.reports {
+        let summary = result.as_ref().ok().cloned();
+        let report = reports::RunReport {
+            run_id,
+            job: job.name.clone(),
+            hostname: hostname(),
+            dry_run,
+            status: status.as_str().to_string(),
+            started_at,
+            finished_at: chrono::Local::now(),
+            duration_secs: started.elapsed().as_secs_f64(),
+            snapshot_id: summary.as_ref().and_then(|s| s.snapshot_id.clone()),
+            note: notes::from_tags(job.tags.iter().map(String::as_str)),
+            warnings: summary
+                .as_ref()
+                .map(|s| s.warnings.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .chain(repository_warning)
+                .chain(prediction_warning)
+                .collect(),
+            repository_size,
+            access_rights,
+            prediction,
+            summary,
+            error: result.as_ref().err().map(|e| e.to_string()),
+            phases: phases.finish(),
+        };
+        reports::save(reports_config, &report);
+    }
+    journal.complete();
+
+    result
+}
+
+/// Expand a leading `~` to the user's home directory
+pub fn expand_tilde(path: &std::path::Path) -> PathBuf {
+    let path_str = path.to_string_lossy();
+    if let Some(rest) = path_str.strip_prefix('~') {
+        if let Ok(home) = std::env::var("HOME") {
+            return PathBuf::from(format!("{}{}", home, rest));
+        }
+    }
+    path.to_path_buf()
+}
+
+/// Quote a value for a POSIX shell, leaving plain words and paths as they are
+pub fn shell_quote(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || "/._-~=:@".contains(c)) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+/// Name of this machine, used in notifications
+pub fn hostname() -> String {
+    gethostname::gethostname().to_string_lossy().to_string()
+}
+
+/// Build a restic command for the given subcommand with repository, password and SSH settings applied
+pub fn restic_command(restic_config: &ResticConfig, subcommand: &str) -> Command {
+    let mut cmd = Command::new(restic_config.program());
+    cmd.arg(subcommand);
+    cmd.arg("--repo").arg(restic_config.repository_url());
+
+    // Handle password: password_command takes precedence over password_file and direct password
+    if let Some(ref password_cmd) = restic_config.password_command {
+        cmd.arg("--password-command").arg(password_cmd);
+    } else if let Some(ref password_file) = restic_config.password_file {
+        cmd.arg("--password-file").arg(expand_tilde(password_file));
+    } else if let Some(ref password) = restic_config.password {
+        cmd.env("RESTIC_PASSWORD", password);
+    }
+
+    ssh::apply(&mut cmd, restic_config);
+    rclone::apply(&mut cmd, restic_config);
+
+    if let Some(ref cache_dir) = restic_config.cache_dir {
+        cmd.arg("--cache-dir").arg(expand_tilde(cache_dir));
+    }
+
+    cmd
+}
+
+/// A finished restic run, with what was measured while it ran
+pub struct ResticRun {
+    pub output: std::process::Output,
+    pub resources: Option<resources::ResourceUsage>,
+    pub progress: throughput::Meter,
+}
+
+impl ResticRun {
+    /// The summary of a `restic backup --json` run, with the errors it reported and the measurements
+    pub fn backup_summary(&self) -> restic::BackupSummary {
+        let mut summary = restic::BackupSummary::from_output(&self.output.stdout).unwrap_or_default();
+        summary.warnings = restic::errors_from_output(&self.output.stdout);
+        summary.resources = self.resources.clone();
+        summary.throughput = self.progress.finish(summary.data_added, summary.total_bytes_processed, summary.total_duration);
+        summary
+    }
+}
+
+/// Run a restic command, forwarding `--json` status lines on stdout as progress events, and sample what it uses.
+/// Without a reporter, progress and the current rate are shown on a terminal instead.
+/// With a deadline, restic is stopped then, and the error is a `window::WindowClosed`.
+pub async fn run_restic_with_progress(
+    cmd: &mut Command,
+    reporter: Option<&events::RunReporter>,
+    deadline: Option<(tokio::time::Instant, String)>,
+) -> std::io::Result<ResticRun> {
+    use std::io::IsTerminal;
+    use std::process::Stdio;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = cmd.spawn()?;
+    let sampler = resources::Sampler::start(child.id());
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let live = reporter.is_none() && std::io::stderr().is_terminal();
+
+    let read_stdout = async {
+        let mut collected = Vec::new();
+        let mut meter = throughput::Meter::new();
+        let mut shown = false;
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) {
+                if message["message_type"] == "status" {
+                    let rate = meter.observe(&message);
+                    if let Some(reporter) = reporter {
+                        reporter.progress(&message, rate);
+                    } else if live {
+                        eprint!(
+                            "\r⏳ {:.1}% of {}, {}    ",
+                            message["percent_done"].as_f64().unwrap_or(0.0) * 100.0,
+                            restic::format_bytes(message["total_bytes"].as_u64().unwrap_or(0)),
+                            rate.map(throughput::rate).unwrap_or_else(|| "measuring speed".to_string()),
+                        );
+                        shown = true;
+                    }
+                    continue; // Status lines are only useful while the run is in flight
+                }
+            }
+            collected.extend_from_slice(line.as_bytes());
+            collected.push(b'\n');
+        }
+        if shown {
+            eprintln!();
+        }
+        Ok::<_, std::io::Error>((collected, meter))
+    };
+    let read_stderr = async {
+        let mut collected = Vec::new();
+        stderr.read_to_end(&mut collected).await?;
+        Ok::<_, std::io::Error>(collected)
+    };
+
+    let ((stdout, progress), stderr, (status, closed)) = tokio::try_join!(read_stdout, read_stderr, window::wait_until(&mut child, deadline))?;
+    let resources = sampler.finish();
+    if let Some(ref usage) = resources {
+        info!("{}", usage.describe());
+    }
+    if let Some(closed) = closed {
+        return Err(std::io::Error::other(closed));
+    }
+    Ok(ResticRun {
+        output: std::process::Output { status, stdout, stderr },
+        resources,
+        progress,
+    })
+}
+
+pub async fn execute_restic_backup(
+    backup_config: &BackupConfig,
+    restic_config: &ResticConfig,
+    dry_run: bool,
+    verbose: bool,
+    reporter: Option<&events::RunReporter>,
+) -> Result<restic::BackupSummary> {
     debug!("Building restic backup command");
     
     // Build restic backup command
-    let mut cmd = Command::new("restic");
+    let mut cmd = Command::new(restic_config.program());
     cmd.arg("backup");
+    backup_config.apply_environment(&mut cmd)?;
 
     // Add repository using --repo flag
-    debug!("Setting repository: {}", restic_config.repository);
-    cmd.arg("--repo").arg(&restic_config.repository);
+    debug!("Setting repository: {}", restic_config.repository_url());
+    cmd.arg("--repo").arg(restic_config.repository_url());
 
-    // Handle password: password_command takes precedence over direct password
+    // Handle password: password_command takes precedence over password_file and direct password
     if let Some(ref password_cmd) = restic_config.password_command {
         debug!("Using password command for authentication");
         cmd.arg("--password-command").arg(password_cmd);
+    } else if let Some(ref password_file) = restic_config.password_file {
+        debug!("Using password file {:?}", password_file);
+        cmd.arg("--password-file").arg(expand_tilde(password_file));
     } else if let Some(ref password) = restic_config.password {
         debug!("Using direct password from config (RESTIC_PASSWORD environment variable)");
         cmd.env("RESTIC_PASSWORD", password);
@@ -205,10 +1497,18 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
         debug!("No password configured - restic will prompt or use default");
     }
 
-    // Set SSH command as environment variable if provided (restic doesn't have a direct flag for this)
-    if let Some(ref ssh_cmd) = restic_config.ssh_command {
-        debug!("Setting SSH command environment variable: {}", ssh_cmd);
-        cmd.env("RESTIC_SSH_COMMAND", ssh_cmd);
+    // Structured SSH options become restic's sftp.command, a plain ssh_command the RESTIC_SSH_COMMAND variable
+    ssh::apply(&mut cmd, restic_config);
+    rclone::apply(&mut cmd, restic_config);
+
+    // A job's own cache_dir reaches restic as RESTIC_CACHE_DIR, which --cache-dir would override
+    if let (Some(ref cache_dir), None) = (&restic_config.cache_dir, &backup_config.cache_dir) {
+        debug!("Using cache directory: {:?}", cache_dir);
+        cmd.arg("--cache-dir").arg(expand_tilde(cache_dir));
+    }
+
+    if let Some(ref sandbox_config) = restic_config.sandbox {
+        sandbox::apply(&mut cmd, sandbox_config, backup_config, restic_config)?;
     }
 
     // Add verbose flag if enabled
@@ -218,25 +1518,51 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
     }
 
     // Add directories to backup
-    debug!("Adding {} directories to backup", backup_config.directories.len());
-    for dir in &backup_config.directories {
+    let directories = backup_config.effective_directories();
+    debug!("Adding {} directories to backup", directories.len());
+    for dir in &directories {
         debug!("  - Adding directory: {:?}", dir);
         cmd.arg(dir);
     }
 
     // Add exclude patterns
-    debug!("Adding {} exclude patterns", backup_config.exclude.len());
-    for exclude_path in &backup_config.exclude {
+    let excludes = backup_config.effective_excludes();
+    debug!("Adding {} exclude patterns", excludes.len());
+    for exclude_path in &excludes {
         debug!("  - Excluding: {:?}", exclude_path);
         cmd.arg("--exclude").arg(exclude_path);
     }
 
+    // Patterns from ignore files go through a temporary --exclude-file, kept until restic is done
+    let ignore_patterns = ignore_files::collect(backup_config);
+    let exclude_file = if ignore_patterns.is_empty() {
+        None
+    } else {
+        debug!("Adding {} patterns from ignore files", ignore_patterns.len());
+        let file = ignore_files::ExcludeFile::write(&backup_config.name, &ignore_patterns)?;
+        cmd.arg("--exclude-file").arg(file.path());
+        Some(file)
+    };
+
+    if backup_config.uses_one_file_system() {
+        debug!("Staying on the backed up directories' filesystems");
+        cmd.arg("--one-file-system");
+    }
+
+    let tags = backup_config.expanded_tags();
+    for tag in &tags {
+        cmd.arg("--tag").arg(tag);
+    }
+
     // Add dry-run flag if enabled
     if dry_run {
         debug!("Adding dry-run flag");
         cmd.arg("--dry-run");
     }
 
+    // JSON output gives us progress messages and a machine-readable summary
+    cmd.arg("--json");
+
     // Print the command that would be executed
     let cmd_string = format!("{:?}", cmd);
     info!("Restic command: {}", cmd_string);
@@ -244,53 +1570,72 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
     
     // In verbose mode, show a more readable command format
     if verbose {
-        let mut readable_cmd = format!("restic backup --repo {}", restic_config.repository);
+        let mut readable_cmd = format!("restic backup --repo {}", restic_config.repository_url());
         if let Some(ref pwd_cmd) = restic_config.password_command {
             readable_cmd.push_str(&format!(" --password-command '{}'", pwd_cmd));
+        } else if let Some(ref password_file) = restic_config.password_file {
+            readable_cmd.push_str(&format!(" --password-file {:?}", expand_tilde(password_file)));
         }
         if verbose {
             readable_cmd.push_str(" --verbose");
         }
-        for dir in &backup_config.directories {
+        for dir in &directories {
             readable_cmd.push_str(&format!(" {:?}", dir));
         }
-        for exclude_path in &backup_config.exclude {
+        for exclude_path in &excludes {
             readable_cmd.push_str(&format!(" --exclude {:?}", exclude_path));
         }
+        if let Some(ref file) = exclude_file {
+            readable_cmd.push_str(&format!(" --exclude-file {:?}", file.path()));
+        }
+        if backup_config.uses_one_file_system() {
+            readable_cmd.push_str(" --one-file-system");
+        }
+        for tag in &tags {
+            readable_cmd.push_str(&format!(" --tag {}", tag));
+        }
         if dry_run {
             readable_cmd.push_str(" --dry-run");
         }
         debug!("Readable command: {}", readable_cmd);
     }
 
-    if dry_run {
+    let summary = if dry_run {
         info!("DRY RUN MODE: Executing restic backup with --dry-run flag");
         debug!("Executing command and capturing output");
         debug!("Command: {:?}", cmd);
         
         // Execute the command
-        let output = cmd.output().await.map_err(|e| {
+        let run = run_restic_with_progress(&mut cmd, reporter, window::deadline(backup_config, dry_run)).await.map_err(|e| {
+            let e = match window::stopped(e) {
+                Ok(stopped) => return stopped,
+                Err(e) => e,
+            };
             let error_msg = format!(
                 "Failed to execute restic command: {}. \
                 Make sure 'restic' is installed and available in your PATH. \
                 Command attempted: {:?}",
                 e, cmd
             );
-            eprintln!("\n=== COMMAND EXECUTION ERROR ===");
+            eprintln!("\n{}", style::Painter::stderr().paint(style::Tone::Failure, "=== COMMAND EXECUTION ERROR ==="));
             eprintln!("{}", error_msg);
             eprintln!("===============================\n");
             anyhow::anyhow!(error_msg)
         })?;
         
+        let summary = run.backup_summary();
+        let output = run.output;
         debug!("Command exit status: {:?}", output.status.code());
         debug!("Stdout length: {} bytes", output.stdout.len());
         debug!("Stderr length: {} bytes", output.stderr.len());
         
-        if output.status.success() {
+
+        // Exit code 3 means the snapshot was created but some source files could not be read
+        if output.status.success() || output.status.code() == Some(restic::EXIT_INCOMPLETE_SNAPSHOT) {
             info!("Dry run completed successfully");
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            if !stdout.is_empty() {
-                info!("Output:\n{}", stdout);
+            info!("{}", summary.describe());
+            for warning in &summary.warnings {
+                info!("Warning: {}", warning);
             }
             
             // In verbose mode, also show stderr even if successful (might contain warnings)
@@ -308,7 +1653,7 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
             error!("Dry run failed with exit code: {:?}", exit_code);
             
             // Always print detailed error information to stderr for visibility
-            eprintln!("\n=== RESTIC DRY RUN FAILED ===");
+            eprintln!("\n{}", style::Painter::stderr().paint(style::Tone::Failure, "=== RESTIC DRY RUN FAILED ==="));
             eprintln!("Exit code: {:?}", exit_code);
             eprintln!("\nCommand executed: {}", cmd_string);
             
@@ -333,11 +1678,13 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
             // Provide helpful suggestion for repository initialization
             if is_repo_error {
                 eprintln!("\n💡 SUGGESTION:");
-                eprintln!("   The repository at '{}' does not exist or is not accessible.", restic_config.repository);
+                eprintln!("   The repository at '{}' does not exist or is not accessible.", restic_config.repository_url());
                 eprintln!("   Initialize it first with:");
-                eprintln!("   restic init --repo {}", restic_config.repository);
+                eprintln!("   restic init --repo {}", restic_config.repository_url());
                 if let Some(ref pwd_cmd) = restic_config.password_command {
                     eprintln!("   (with RESTIC_PASSWORD_COMMAND='{}')", pwd_cmd);
+                } else if let Some(ref password_file) = restic_config.password_file {
+                    eprintln!("   (with RESTIC_PASSWORD_FILE={:?})", expand_tilde(password_file));
                 } else if restic_config.password.is_some() {
                     eprintln!("   (with RESTIC_PASSWORD set from config)");
                 }
@@ -346,19 +1693,32 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
                 }
             }
             
+            let rclone_hint = rclone::explain(&restic_config.repository_url(), &stderr);
+            if let Some(ref hint) = rclone_hint {
+                eprintln!("\n💡 RCLONE ERROR:");
+                eprintln!("   {}", hint);
+            }
+            if let Some(hint) = object_lock::explain(&stderr) {
+                eprintln!("\n💡 OBJECT LOCK:");
+                eprintln!("   {}", hint);
+            }
+
             // Check for password-related errors
             if stderr_lower.contains("empty password") || stderr_lower.contains("password") {
                 eprintln!("\n💡 PASSWORD ERROR:");
                 eprintln!("   Restic requires a password. Make sure you have configured either:");
                 eprintln!("   - 'password' field in config.yaml (direct password)");
                 eprintln!("   - 'password_command' field in config.yaml (command to retrieve password)");
+                eprintln!("   - 'password_file' field in config.yaml (file holding the password)");
                 eprintln!("   - Or set RESTIC_PASSWORD environment variable");
             }
             
             eprintln!("=============================\n");
             
             // Create a detailed error message
-            let error_msg = if !stderr.is_empty() {
+            let error_msg = if let Some(hint) = rclone_hint {
+                format!("Restic dry run failed (exit code: {:?}): {}", exit_code, hint)
+            } else if !stderr.is_empty() {
                 format!("Restic dry run failed (exit code: {:?}): {}", exit_code, stderr.trim())
             } else if !stdout.is_empty() {
                 format!("Restic dry run failed (exit code: {:?}): {}", exit_code, stdout.trim())
@@ -368,35 +1728,54 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
             
             return Err(anyhow::anyhow!(error_msg));
         }
+
+        summary
     } else {
         // Execute the actual backup (not dry-run)
         info!("EXECUTING BACKUP: Running restic backup");
         println!("Executing: {}", cmd_string);
         
         // Execute the command and stream output
-        let output = cmd.output().await.map_err(|e| {
+        let run = run_restic_with_progress(&mut cmd, reporter, window::deadline(backup_config, dry_run)).await.map_err(|e| {
+            let e = match window::stopped(e) {
+                Ok(stopped) => return stopped,
+                Err(e) => e,
+            };
             let error_msg = format!(
                 "Failed to execute restic command: {}. \
                 Make sure 'restic' is installed and available in your PATH. \
                 Command attempted: {:?}",
                 e, cmd
             );
-            eprintln!("\n=== COMMAND EXECUTION ERROR ===");
+            eprintln!("\n{}", style::Painter::stderr().paint(style::Tone::Failure, "=== COMMAND EXECUTION ERROR ==="));
             eprintln!("{}", error_msg);
             eprintln!("===============================\n");
             anyhow::anyhow!(error_msg)
         })?;
         
+        let summary = run.backup_summary();
+        let output = run.output;
         debug!("Command exit status: {:?}", output.status.code());
         debug!("Stdout length: {} bytes", output.stdout.len());
         debug!("Stderr length: {} bytes", output.stderr.len());
         
-        if output.status.success() {
+
+        // Exit code 3 means the snapshot was created but some source files could not be read
+        if output.status.success() || output.status.code() == Some(restic::EXIT_INCOMPLETE_SNAPSHOT) {
             info!("Backup completed successfully");
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            if !stdout.is_empty() {
-                println!("\n{}", stdout);
-                info!("Output:\n{}", stdout);
+            let painter = style::Painter::stdout();
+            println!("\n{}", painter.status(style::Tone::Success, &summary.describe()));
+            info!("{}", summary.describe());
+            if let Some(ref resources) = summary.resources {
+                println!("{}", resources.describe());
+            }
+            if let Some(ref throughput) = summary.throughput {
+                println!("Speed: {}", throughput.describe());
+                info!("Speed: {}", throughput.describe());
+            }
+            for warning in &summary.warnings {
+                println!("{}", painter.status(style::Tone::Warning, &format!("Warning: {}", warning)));
+                info!("Warning: {}", warning);
             }
             
             // In verbose mode, also show stderr even if successful (might contain warnings)
@@ -415,7 +1794,7 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
             error!("Backup failed with exit code: {:?}", exit_code);
             
             // Always print detailed error information to stderr for visibility
-            eprintln!("\n=== RESTIC BACKUP FAILED ===");
+            eprintln!("\n{}", style::Painter::stderr().paint(style::Tone::Failure, "=== RESTIC BACKUP FAILED ==="));
             eprintln!("Exit code: {:?}", exit_code);
             eprintln!("\nCommand executed: {}", cmd_string);
             
@@ -440,11 +1819,13 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
             // Provide helpful suggestion for repository initialization
             if is_repo_error {
                 eprintln!("\n💡 SUGGESTION:");
-                eprintln!("   The repository at '{}' does not exist or is not accessible.", restic_config.repository);
+                eprintln!("   The repository at '{}' does not exist or is not accessible.", restic_config.repository_url());
                 eprintln!("   Initialize it first with:");
-                eprintln!("   restic init --repo {}", restic_config.repository);
+                eprintln!("   restic init --repo {}", restic_config.repository_url());
                 if let Some(ref pwd_cmd) = restic_config.password_command {
                     eprintln!("   (with RESTIC_PASSWORD_COMMAND='{}')", pwd_cmd);
+                } else if let Some(ref password_file) = restic_config.password_file {
+                    eprintln!("   (with RESTIC_PASSWORD_FILE={:?})", expand_tilde(password_file));
                 } else if restic_config.password.is_some() {
                     eprintln!("   (with RESTIC_PASSWORD set from config)");
                 }
@@ -453,19 +1834,32 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
                 }
             }
             
+            let rclone_hint = rclone::explain(&restic_config.repository_url(), &stderr);
+            if let Some(ref hint) = rclone_hint {
+                eprintln!("\n💡 RCLONE ERROR:");
+                eprintln!("   {}", hint);
+            }
+            if let Some(hint) = object_lock::explain(&stderr) {
+                eprintln!("\n💡 OBJECT LOCK:");
+                eprintln!("   {}", hint);
+            }
+
             // Check for password-related errors
             if stderr_lower.contains("empty password") || stderr_lower.contains("password") {
                 eprintln!("\n💡 PASSWORD ERROR:");
                 eprintln!("   Restic requires a password. Make sure you have configured either:");
                 eprintln!("   - 'password' field in config.yaml (direct password)");
                 eprintln!("   - 'password_command' field in config.yaml (command to retrieve password)");
+                eprintln!("   - 'password_file' field in config.yaml (file holding the password)");
                 eprintln!("   - Or set RESTIC_PASSWORD environment variable");
             }
             
             eprintln!("=============================\n");
             
             // Create a detailed error message
-            let error_msg = if !stderr.is_empty() {
+            let error_msg = if let Some(hint) = rclone_hint {
+                format!("Restic backup failed (exit code: {:?}): {}", exit_code, hint)
+            } else if !stderr.is_empty() {
                 format!("Restic backup failed (exit code: {:?}): {}", exit_code, stderr.trim())
             } else if !stdout.is_empty() {
                 format!("Restic backup failed (exit code: {:?}): {}", exit_code, stdout.trim())
@@ -475,7 +1869,9 @@ pub async fn execute_restic_backup(backup_config: &BackupConfig, restic_config:
             
             return Err(anyhow::anyhow!(error_msg));
         }
-    }
 
-    Ok(())
+        summary
+    };
+
+    Ok(summary)
 }
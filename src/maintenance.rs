@@ -0,0 +1,629 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::notify::{self, RunEvent};
+use crate::object_lock::{self, ObjectLockConfig};
+use crate::style::{Painter, Tone};
+use crate::{mount, preflight, Config};
+
+/// `keep_*` rules for `restic forget`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct RetentionPolicy {
+    #[serde(default)]
+    pub keep_last: Option<u32>,
+    #[serde(default)]
+    pub keep_hourly: Option<u32>,
+    #[serde(default)]
+    pub keep_daily: Option<u32>,
+    #[serde(default)]
+    pub keep_weekly: Option<u32>,
+    #[serde(default)]
+    pub keep_monthly: Option<u32>,
+    #[serde(default)]
+    pub keep_yearly: Option<u32>,
+    /// Keep everything newer than this, e.g. "30d" or "1y6m"
+    #[serde(default)]
+    pub keep_within: Option<String>,
+}
+
+impl RetentionPolicy {
+    /// The `--keep-*` arguments; empty when no rule is set
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        let counts = [
+            ("--keep-last", self.keep_last),
+            ("--keep-hourly", self.keep_hourly),
+            ("--keep-daily", self.keep_daily),
+            ("--keep-weekly", self.keep_weekly),
+            ("--keep-monthly", self.keep_monthly),
+            ("--keep-yearly", self.keep_yearly),
+        ];
+        for (flag, count) in counts {
+            if let Some(count) = count {
+                args.push(flag.to_string());
+                args.push(count.to_string());
+            }
+        }
+        if let Some(ref within) = self.keep_within {
+            args.push("--keep-within".to_string());
+            args.push(within.clone());
+        }
+        args
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args().is_empty()
+    }
+}
+
+/// Snapshots kept by their own policy, e.g. hourly database dumps in a repository that also holds weekly photo archives
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct RetentionGroup {
+    /// Snapshots with all of these tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Snapshots of this host
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(flatten)]
+    pub policy: RetentionPolicy,
+}
+
+impl RetentionGroup {
+    pub fn describe(&self) -> String {
+        match (self.tags.is_empty(), &self.host) {
+            (false, Some(host)) => format!("tags {} on host {}", self.tags.join(","), host),
+            (false, None) => format!("tags {}", self.tags.join(",")),
+            (true, Some(host)) => format!("host {}", host),
+            (true, None) => "every snapshot".to_string(),
+        }
+    }
+
+    /// Whether the group's snapshots may include some of the host's
+    fn covers_host(&self, host: &str) -> bool {
+        self.host.as_deref().is_none_or(|own| own == host)
+    }
+}
+
+/// One `restic forget` of the retention policy
+#[derive(Debug, Clone)]
+pub struct ForgetRun {
+    /// Which snapshots it applies to
+    pub label: String,
+    pub args: Vec<String>,
+}
+
+/// Retention policy and verification settings for `better_restic_client maintain`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct MaintenanceConfig {
+    /// The policy for snapshots outside the `groups`
+    #[serde(flatten)]
+    pub retention: RetentionPolicy,
+    /// How restic groups snapshots before applying a policy, e.g. "host,tags"; restic's default is "host,paths"
+    #[serde(default)]
+    pub group_by: Option<String>,
+    /// Policies for snapshots selected by tag or host
+    #[serde(default)]
+    pub groups: Vec<RetentionGroup>,
+    /// Passed to `restic check --read-data-subset`, e.g. "5%" or "1/12"; only metadata is checked when unset
+    #[serde(default)]
+    pub read_data_subset: Option<String>,
+    /// Read the pack data in this many parts, the next one on every run (`--read-data-subset n/N`), so the whole
+    /// repository is verified over that many runs; takes precedence over `read_data_subset`
+    #[serde(default)]
+    pub read_data_rotation: Option<u32>,
+    /// Names of notification targets that receive the combined report
+    #[serde(default)]
+    pub targets: Vec<String>,
+    /// The repository's bucket has object lock, so forget and prune wait for retention to pass
+    #[serde(default)]
+    pub object_lock: Option<ObjectLockConfig>,
+}
+
+impl MaintenanceConfig {
+    /// Whether any snapshot would be forgotten
+    pub fn has_policy(&self) -> bool {
+        !self.retention.is_empty() || self.groups.iter().any(|group| !group.policy.is_empty())
+    }
+
+    /// Whether forgetting needs the repository's hosts, to keep the top-level policy off groups selected by host
+    pub fn needs_hosts(&self) -> bool {
+        !self.retention.is_empty() && self.groups.iter().any(|group| group.host.is_some())
+    }
+
+    /// Arguments every forget gets: the policy, the grouping, and the pin tag so pinned snapshots are always kept
+    fn forget_args(&self, policy: &RetentionPolicy) -> Vec<String> {
+        let mut args = policy.args();
+        if let Some(ref group_by) = self.group_by {
+            args.push("--group-by".to_string());
+            args.push(group_by.clone());
+        }
+        args.push("--keep-tag".to_string());
+        args.push(crate::pins::PIN_TAG.to_string());
+        args
+    }
+
+    /// The `restic forget` runs of the policy: one per group, then the top-level policy for everything else.
+    /// Snapshots of tag groups are kept out of the top-level run with `--keep-tag`. Groups selected by host
+    /// need the top-level policy run once per other host, so `hosts` lists the repository's hosts then.
+    pub fn forget_runs(&self, hosts: &[String]) -> Vec<ForgetRun> {
+        let mut runs = Vec::new();
+        for group in self.groups.iter().filter(|group| !group.policy.is_empty()) {
+            let mut args = Vec::new();
+            if !group.tags.is_empty() {
+                args.push("--tag".to_string());
+                args.push(group.tags.join(","));
+            }
+            if let Some(ref host) = group.host {
+                args.push("--host".to_string());
+                args.push(host.clone());
+            }
+            args.extend(self.forget_args(&group.policy));
+            runs.push(ForgetRun { label: group.describe(), args });
+        }
+        if self.retention.is_empty() {
+            return runs;
+        }
+
+        let tag_groups = |host: Option<&str>| -> Vec<String> {
+            self.groups
+                .iter()
+                .filter(|group| !group.tags.is_empty() && host.is_none_or(|host| group.covers_host(host)))
+                .flat_map(|group| ["--keep-tag".to_string(), group.tags.join(",")])
+                .collect()
+        };
+        if !self.needs_hosts() {
+            let mut args = self.forget_args(&self.retention);
+            args.extend(tag_groups(None));
+            let label = if self.groups.is_empty() { "all snapshots" } else { "everything else" };
+            runs.push(ForgetRun { label: label.to_string(), args });
+            return runs;
+        }
+        for host in hosts {
+            // A group with this host and no tags already has all of its snapshots
+            if self.groups.iter().any(|group| group.tags.is_empty() && group.host.as_deref() == Some(host.as_str())) {
+                continue;
+            }
+            let mut args = vec!["--host".to_string(), host.clone()];
+            args.extend(self.forget_args(&self.retention));
+            args.extend(tag_groups(Some(host)));
+            runs.push(ForgetRun { label: format!("everything else on host {}", host), args });
+        }
+        runs
+    }
+}
+
+/// Hosts with snapshots in the repository
+pub async fn repository_hosts(config: &Config) -> Result<Vec<String>> {
+    let mut hosts: Vec<String> = crate::snapshots::list(config).await?.into_iter().map(|snapshot| snapshot.hostname).collect();
+    hosts.sort();
+    hosts.dedup();
+    Ok(hosts)
+}
+
+/// The forget runs for the repository, listing its hosts when the policy needs them
+pub async fn forget_runs(config: &Config, maintenance_config: &MaintenanceConfig) -> Result<Vec<ForgetRun>> {
+    let hosts = if maintenance_config.needs_hosts() { repository_hosts(config).await? } else { Vec::new() };
+    Ok(maintenance_config.forget_runs(&hosts))
+}
+
+/// The part of the pack data the last successful check read
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CheckRotation {
+    pub subsets: u32,
+    pub last: u32,
+    pub verified_at: DateTime<Local>,
+}
+
+impl CheckRotation {
+    pub fn describe(&self) -> String {
+        format!("subset {}/{} verified {}", self.last, self.subsets, self.verified_at.format("%Y-%m-%d"))
+    }
+}
+
+fn rotation_path(config: &Config) -> PathBuf {
+    crate::expand_tilde(&config.logging.directory.join("check-rotation.json"))
+}
+
+fn load_rotations(config: &Config) -> BTreeMap<String, CheckRotation> {
+    std::fs::read(rotation_path(config))
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Where the repository's check rotation stands, if it has started
+pub fn rotation(config: &Config) -> Option<CheckRotation> {
+    load_rotations(config).remove(&config.restic.repository_url())
+}
+
+/// The subset the next check reads, counting from 1; starts over when the number of subsets changed
+fn next_subset(config: &Config, subsets: u32) -> u32 {
+    match rotation(config) {
+        Some(rotation) if rotation.subsets == subsets => rotation.last % subsets + 1,
+        _ => 1,
+    }
+}
+
+fn record_subset(config: &Config, subsets: u32, subset: u32) {
+    let mut rotations = load_rotations(config);
+    rotations.insert(config.restic.repository_url(), CheckRotation { subsets, last: subset, verified_at: Local::now() });
+    let path = rotation_path(config);
+    let written = serde_json::to_vec_pretty(&rotations)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(std::fs::write(&path, content)?));
+    if let Err(e) = written {
+        warn!("Couldn't update {:?}: {}", path, e);
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+impl StepStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            StepStatus::Succeeded => "ok",
+            StepStatus::Failed => "FAILED",
+            StepStatus::Skipped => "skipped",
+        }
+    }
+
+    pub fn tone(&self) -> Tone {
+        match self {
+            StepStatus::Succeeded => Tone::Success,
+            StepStatus::Failed => Tone::Failure,
+            StepStatus::Skipped => Tone::Muted,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StepResult {
+    pub step: String,
+    pub status: StepStatus,
+    pub duration_secs: f64,
+    /// Why the step failed or was skipped
+    pub message: Option<String>,
+}
+
+/// Combined outcome of one `maintain` run
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MaintenanceReport {
+    pub run_id: String,
+    pub hostname: String,
+    pub repository: String,
+    pub started_at: DateTime<Local>,
+    pub finished_at: DateTime<Local>,
+    pub steps: Vec<StepResult>,
+    /// Space prune removed from the repository, from its "total prune" line
+    #[serde(default)]
+    pub freed_bytes: Option<u64>,
+}
+
+impl MaintenanceReport {
+    pub fn is_success(&self) -> bool {
+        self.steps.iter().all(|step| step.status != StepStatus::Failed)
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("maintenance-{}.json", self.run_id)
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "Repository maintenance {} on {}",
+            if self.is_success() { "succeeded" } else { "FAILED" },
+            self.hostname
+        )
+    }
+
+    pub fn render_text(&self) -> String {
+        self.render(Painter::plain())
+    }
+
+    /// The report with colored step results when `painter` writes to a terminal
+    pub fn render(&self, painter: Painter) -> String {
+        let mut out = format!("Repository: {}\n", self.repository);
+        for step in &self.steps {
+            let status = painter.paint(step.status.tone(), step.status.label());
+            out.push_str(&format!("  {:<7} {} ({:.1}s)", step.step, status, step.duration_secs));
+            if let Some(ref message) = step.message {
+                out.push_str(&format!(": {}", message));
+            }
+            out.push('\n');
+        }
+        if let Some(freed) = self.freed_bytes {
+            out.push_str(&format!("Freed: {}\n", crate::restic::format_bytes(freed)));
+        }
+        out
+    }
+}
+
+/// The maintenance reports in the reports directory, newest first
+pub fn list(directory: &std::path::Path) -> Vec<MaintenanceReport> {
+    let mut reports: Vec<MaintenanceReport> = std::fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    name.starts_with("maintenance-") && name.ends_with(".json")
+                })
+                .filter_map(|entry| std::fs::read(entry.path()).ok())
+                .filter_map(|content| serde_json::from_slice(&content).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    reports.sort_by_key(|report| std::cmp::Reverse(report.finished_at));
+    reports
+}
+
+/// What prune removed, from the "total prune: 3 blobs / 1.048 MiB" line of its output
+fn pruned_bytes(output: &str) -> Option<u64> {
+    let line = output.lines().find_map(|line| line.trim().strip_prefix("total prune:"))?;
+    let (number, unit) = line.split('/').nth(1)?.trim().split_once(' ')?;
+    let factor: u64 = match unit.trim() {
+        "B" => 1,
+        "KiB" => 1 << 10,
+        "MiB" => 1 << 20,
+        "GiB" => 1 << 30,
+        "TiB" => 1 << 40,
+        _ => return None,
+    };
+    Some((number.parse::<f64>().ok()? * factor as f64) as u64)
+}
+
+/// Run one restic maintenance command, returning the error message if it failed
+/// Run one restic step, returning its output
+async fn run_step(config: &Config, subcommand: &str, args: &[String]) -> Result<String, String> {
+    let mut cmd = crate::restic_command(&config.restic, subcommand);
+    cmd.args(args);
+    info!("Restic command: {:?}", cmd);
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|e| format!("Failed to execute restic: {}", e))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match object_lock::explain(&stderr) {
+            Some(hint) => Err(format!("exit code {:?}: {} ({})", output.status.code(), stderr.trim(), hint)),
+            None => Err(format!("exit code {:?}: {}", output.status.code(), stderr.trim())),
+        }
+    }
+}
+
+/// Forget per group and for everything else, stopping at the first failure
+async fn forget(config: &Config, maintenance_config: &MaintenanceConfig) -> Result<String, String> {
+    let runs = forget_runs(config, maintenance_config).await.map_err(|e| e.to_string())?;
+    for mut run in runs {
+        if let Some(ref lock) = maintenance_config.object_lock {
+            object_lock::align_forget(&mut run.args, lock);
+        }
+        info!("Forgetting snapshots: {}", run.label);
+        run_step(config, "forget", &run.args).await?;
+    }
+    Ok(String::new())
+}
+
+/// Unlock stale locks, forget per policy, prune and check, in that order.
+/// A failed step skips the rest, since e.g. pruning after a failed forget would be pointless.
+pub async fn run(config: &Config) -> Result<MaintenanceReport> {
+    let maintenance_config = config.maintenance.clone().unwrap_or_default();
+    let started_at = Local::now();
+
+    // Prune rewrites packs and needs space on the cache and repository filesystems
+    let mounted = match config.restic.mount {
+        Some(ref mount_config) => mount::ensure_mounted(mount_config).await?,
+        None => false,
+    };
+    let preflight = match config.preflight {
+        Some(ref preflight_config) => preflight::check(preflight_config, &config.restic, None),
+        None => Ok(()),
+    };
+
+    let prune_args = match maintenance_config.object_lock {
+        Some(_) => object_lock::prune_args(),
+        None => Vec::new(),
+    };
+    let mut check_args = Vec::new();
+    // A subset that wasn't verified, because the check failed or didn't run, is tried again next time
+    let rotation = maintenance_config.read_data_rotation.filter(|subsets| *subsets > 0).map(|subsets| (next_subset(config, subsets), subsets));
+    if let Some((subset, subsets)) = rotation {
+        check_args.push(format!("--read-data-subset={}/{}", subset, subsets));
+    } else if let Some(ref subset) = maintenance_config.read_data_subset {
+        check_args.push(format!("--read-data-subset={}", subset));
+    }
+    let pipeline: [(&str, &str, Vec<String>); 4] = [
+        ("unlock", "unlock", Vec::new()),
+        // The forget runs are worked out when the step starts, as they may need the repository's hosts
+        ("forget", "forget", Vec::new()),
+        ("prune", "prune", prune_args),
+        ("check", "check", check_args),
+    ];
+
+    let mut steps = Vec::new();
+    let mut freed_bytes = None;
+    let mut failed = preflight.as_ref().err().map(|e| e.to_string());
+    if let Some(ref e) = failed {
+        steps.push(StepResult {
+            step: "preflight".to_string(),
+            status: StepStatus::Failed,
+            duration_secs: 0.0,
+            message: Some(e.clone()),
+        });
+    }
+
+    for (step, subcommand, args) in pipeline {
+        if failed.is_some() {
+            steps.push(StepResult {
+                step: step.to_string(),
+                status: StepStatus::Skipped,
+                duration_secs: 0.0,
+                message: Some("an earlier step failed".to_string()),
+            });
+            continue;
+        }
+        if step == "forget" && !maintenance_config.has_policy() {
+            warn!("No retention policy configured, not forgetting any snapshots");
+            steps.push(StepResult {
+                step: step.to_string(),
+                status: StepStatus::Skipped,
+                duration_secs: 0.0,
+                message: Some("no keep_* policy in the maintenance section".to_string()),
+            });
+            continue;
+        }
+        if let Some(reason) = maintenance_config.object_lock.as_ref().filter(|_| step == "prune").and_then(|lock| object_lock::prune_skipped(config, lock)) {
+            info!("Not pruning: {}", reason);
+            steps.push(StepResult {
+                step: step.to_string(),
+                status: StepStatus::Skipped,
+                duration_secs: 0.0,
+                message: Some(reason),
+            });
+            continue;
+        }
+
+        info!("Maintenance: {}", step);
+        let started = std::time::Instant::now();
+        let result = match step {
+            "forget" => forget(config, &maintenance_config).await,
+            _ => run_step(config, subcommand, &args).await,
+        };
+        if let (Ok(output), "prune") = (&result, step) {
+            freed_bytes = pruned_bytes(output);
+        }
+        if let Err(ref e) = result {
+            error!("Maintenance step {} failed: {}", step, e);
+            failed = Some(e.clone());
+        } else if step == "prune" && maintenance_config.object_lock.is_some() {
+            object_lock::record_prune(config);
+        } else if let (true, Some((subset, subsets))) = (step == "check", rotation) {
+            info!("Pack data subset {}/{} verified", subset, subsets);
+            record_subset(config, subsets, subset);
+        }
+        steps.push(StepResult {
+            step: step.to_string(),
+            status: if result.is_ok() { StepStatus::Succeeded } else { StepStatus::Failed },
+            duration_secs: started.elapsed().as_secs_f64(),
+            message: result.err(),
+        });
+    }
+
+    if mounted {
+        if let Some(ref mount_config) = config.restic.mount {
+            mount::unmount(mount_config).await;
+        }
+    }
+
+    let report = MaintenanceReport {
+        run_id: crate::events::new_run_id(),
+        hostname: crate::hostname(),
+        repository: config.restic.repository_url(),
+        started_at,
+        finished_at: Local::now(),
+        steps,
+        freed_bytes,
+    };
+
+    if let Some(ref reports_config) = config.reports {
+        let directory = crate::expand_tilde(&reports_config.directory);
+        let path = directory.join(report.file_name());
+        let written = std::fs::create_dir_all(&directory)
+            .and_then(|_| std::fs::write(&path, serde_json::to_string_pretty(&report)?));
+        match written {
+            Ok(()) => info!("Maintenance report written to {:?}", path),
+            Err(e) => error!("Failed to write maintenance report {:?}: {}", path, e),
+        }
+    }
+
+    if !maintenance_config.targets.is_empty() {
+        let event = if report.is_success() { RunEvent::Succeeded } else { RunEvent::Failed };
+        notify::send_message(&config.notifications, &maintenance_config.targets, &report.title(), &report.render_text(), event).await;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn maintenance_config(yaml: &str) -> MaintenanceConfig {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn forget_runs_without_groups_is_one_run() {
+        let runs = maintenance_config("keep_daily: 7\ngroup_by: host\n").forget_runs(&[]);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].label, "all snapshots");
+        assert_eq!(runs[0].args, strings(&["--keep-daily", "7", "--group-by", "host", "--keep-tag", "keep-forever"]));
+    }
+
+    #[test]
+    fn forget_runs_keep_tag_groups_out_of_the_top_level_run() {
+        let config = maintenance_config("keep_weekly: 8\ngroups:\n  - tags: [db, hourly]\n    keep_hourly: 48\n");
+        let runs = config.forget_runs(&[]);
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].label, "tags db,hourly");
+        assert_eq!(runs[0].args, strings(&["--tag", "db,hourly", "--keep-hourly", "48", "--keep-tag", "keep-forever"]));
+        assert_eq!(runs[1].label, "everything else");
+        assert_eq!(
+            runs[1].args,
+            strings(&["--keep-weekly", "8", "--keep-tag", "keep-forever", "--keep-tag", "db,hourly"])
+        );
+    }
+
+    #[test]
+    fn forget_runs_per_host_skip_hosts_with_their_own_group() {
+        let config = maintenance_config(
+            "keep_weekly: 8\ngroups:\n  - host: db1\n    keep_daily: 30\n  - tags: [logs]\n    host: web\n    keep_last: 3\n",
+        );
+        assert!(config.needs_hosts());
+        let runs = config.forget_runs(&strings(&["db1", "laptop", "web"]));
+        let labels: Vec<&str> = runs.iter().map(|run| run.label.as_str()).collect();
+        assert_eq!(labels, ["host db1", "tags logs on host web", "everything else on host laptop", "everything else on host web"]);
+        // The tag group only covers web, so laptop's run has no --keep-tag logs
+        assert_eq!(runs[2].args, strings(&["--host", "laptop", "--keep-weekly", "8", "--keep-tag", "keep-forever"]));
+        assert_eq!(
+            runs[3].args,
+            strings(&["--host", "web", "--keep-weekly", "8", "--keep-tag", "keep-forever", "--keep-tag", "logs"])
+        );
+    }
+
+    #[test]
+    fn forget_runs_without_a_top_level_policy_only_run_groups() {
+        let config = maintenance_config("groups:\n  - host: db1\n    keep_daily: 30\n  - tags: [empty]\n");
+        assert!(!config.needs_hosts());
+        let runs = config.forget_runs(&[]);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].args, strings(&["--host", "db1", "--keep-daily", "30", "--keep-tag", "keep-forever"]));
+    }
+
+    #[test]
+    fn pruned_bytes_reads_total_prune_line() {
+        let output = include_str!("../tests/fixtures/restic/prune.txt");
+        assert_eq!(pruned_bytes(output), Some((1.048 * (1u64 << 20) as f64) as u64));
+        assert_eq!(pruned_bytes("total prune: 0 blobs / 0 B"), Some(0));
+        assert_eq!(pruned_bytes("nothing to do"), None);
+    }
+}
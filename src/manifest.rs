@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh64::Xxh64;
+
+use crate::{BackupConfig, Config};
+
+/// Manifests kept per job; older ones are deleted when a new one is written
+const KEPT_PER_JOB: usize = 5;
+/// Paths listed per category in the text output
+const LISTED_FILES: usize = 20;
+
+/// One regular file of the backup set as it was when the snapshot was taken
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Modification time in nanoseconds since the Unix epoch
+    pub mtime: i128,
+    /// XXH64 of the contents, as 16 hex digits
+    pub hash: String,
+}
+
+/// Size, mtime and content hash of every file a run backed up
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Manifest {
+    pub run_id: String,
+    pub job: String,
+    pub snapshot_id: Option<String>,
+    pub created_at: DateTime<Local>,
+    pub files: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn file_name(&self) -> String {
+        format!("manifest-{}.json", self.run_id)
+    }
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    // XXH64 with seed 0: fast enough to hash the whole backup set, and only meant to catch
+    // accidental corruption
+    let mut hasher = Xxh64::new(0);
+    let mut buffer = vec![0u8; 256 * 1024];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+    Ok(format!("{:016x}", hasher.digest()))
+}
+
+fn mtime(metadata: &std::fs::Metadata) -> i128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_nanos() as i128)
+        .unwrap_or(0)
+}
+
+/// Regular files in the job's directories, skipping excludes and not following symlinks
+fn files(job: &BackupConfig) -> Vec<(PathBuf, std::fs::Metadata)> {
+    let excludes: Vec<PathBuf> = job.effective_excludes().iter().map(|p| job.resolve_path(p)).collect();
+    let mut stack: Vec<PathBuf> = job.effective_directories().iter().map(|p| job.resolve_path(p)).collect();
+    let mut files = Vec::new();
+
+    while let Some(path) = stack.pop() {
+        if excludes.iter().any(|exclude| path.starts_with(exclude)) {
+            continue;
+        }
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else { continue };
+        if metadata.is_dir() {
+            if let Ok(entries) = std::fs::read_dir(&path) {
+                stack.extend(entries.flatten().map(|entry| entry.path()));
+            }
+        } else if metadata.is_file() {
+            files.push((path, metadata));
+        }
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    files
+}
+
+/// Build the job's manifest. Files whose size and mtime match `previous` keep its hash instead of
+/// being read again, so a file that rots in place keeps the hash it was backed up with.
+pub fn build(job: &BackupConfig, run_id: &str, snapshot_id: Option<String>, previous: Option<&Manifest>) -> Manifest {
+    let known: HashMap<&Path, &ManifestEntry> = previous
+        .map(|manifest| manifest.files.iter().map(|entry| (entry.path.as_path(), entry)).collect())
+        .unwrap_or_default();
+    let mut entries = Vec::new();
+    for (path, metadata) in files(job) {
+        let (size, mtime) = (metadata.len(), mtime(&metadata));
+        let hash = match known.get(path.as_path()) {
+            Some(entry) if entry.size == size && entry.mtime == mtime => entry.hash.clone(),
+            _ => match hash_file(&path) {
+                Ok(hash) => hash,
+                // restic couldn't read it either; it's in the run's warnings
+                Err(_) => continue,
+            },
+        };
+        entries.push(ManifestEntry { path, size, mtime, hash });
+    }
+    Manifest {
+        run_id: run_id.to_string(),
+        job: job.name.clone(),
+        snapshot_id,
+        created_at: Local::now(),
+        files: entries,
+    }
+}
+
+fn directory(config: &Config) -> Option<PathBuf> {
+    config.reports.as_ref().map(|reports_config| crate::expand_tilde(&reports_config.directory))
+}
+
+/// The job's manifests, newest first
+pub fn list(directory: &Path, job: &str) -> Vec<Manifest> {
+    let mut manifests: Vec<Manifest> = std::fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    name.starts_with("manifest-") && name.ends_with(".json")
+                })
+                .filter_map(|entry| std::fs::read(entry.path()).ok())
+                .filter_map(|content| serde_json::from_slice::<Manifest>(&content).ok())
+                .filter(|manifest| manifest.job == job)
+                .collect()
+        })
+        .unwrap_or_default();
+    manifests.sort_by_key(|manifest| std::cmp::Reverse(manifest.created_at));
+    manifests
+}
+
+/// Write the manifest for a finished backup into the reports directory and drop the job's oldest ones
+pub async fn record(config: &Config, job: &BackupConfig, run_id: &str, snapshot_id: Option<String>) {
+    let Some(directory) = directory(config) else {
+        warn!("Job '{}' has 'manifest' set but no 'reports.directory' to keep it in", job.name);
+        return;
+    };
+    let (job, run_id) = (job.clone(), run_id.to_string());
+    let result = tokio::task::spawn_blocking(move || -> Result<Manifest> {
+        let mut existing = list(&directory, &job.name);
+        let manifest = build(&job, &run_id, snapshot_id, existing.first());
+        std::fs::create_dir_all(&directory)?;
+        let path = directory.join(manifest.file_name());
+        std::fs::write(&path, serde_json::to_vec(&manifest)?).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))?;
+        for old in existing.drain(..).skip(KEPT_PER_JOB - 1) {
+            let _ = std::fs::remove_file(directory.join(old.file_name()));
+        }
+        Ok(manifest)
+    })
+    .await;
+    match result {
+        Ok(Ok(manifest)) => info!("Recorded a manifest of {} files for job '{}'", manifest.files.len(), manifest.job),
+        Ok(Err(e)) => warn!("Couldn't record the manifest: {}", e),
+        Err(e) => warn!("Couldn't record the manifest: {}", e),
+    }
+}
+
+/// How the files of a manifest compare to what's on disk now
+#[derive(Debug, Default, Serialize)]
+pub struct Verification {
+    pub job: String,
+    pub run_id: String,
+    pub snapshot_id: Option<String>,
+    /// Source directories, or the directory a snapshot was restored into
+    pub checked: String,
+    pub matching: usize,
+    /// Changed through normal use: different size or mtime
+    pub modified: Vec<PathBuf>,
+    pub missing: Vec<PathBuf>,
+    /// Source mode: same size and mtime but different contents, i.e. silent corruption on the source disk
+    pub bitrot: Vec<PathBuf>,
+    /// Restore mode: the restored copy's contents differ from what was backed up
+    pub restore_mismatches: Vec<PathBuf>,
+    pub unreadable: Vec<String>,
+    /// The snapshot was restored by `--restore-test`, so every file of the manifest must be there
+    pub restore_test: bool,
+}
+
+impl Verification {
+    pub fn render_text(&self) -> String {
+        let mut out = format!(
+            "{} (run {}, snapshot {}) against {}: {} matching, {} modified, {} missing\n",
+            self.job,
+            self.run_id,
+            self.snapshot_id.as_deref().map(crate::restic::short_id).unwrap_or("unknown"),
+            self.checked,
+            self.matching,
+            self.modified.len(),
+            self.missing.len()
+        );
+        let not_listed = Vec::new();
+        let lists = [
+            ("BITROT (contents changed, size and mtime didn't)", &self.bitrot),
+            ("RESTORED COPY DIFFERS", &self.restore_mismatches),
+            ("MISSING FROM RESTORE", if self.restore_test { &self.missing } else { &not_listed }),
+        ];
+        for (label, paths) in lists {
+            for path in paths.iter().take(LISTED_FILES) {
+                out.push_str(&format!("  {}: {}\n", label, path.display()));
+            }
+            if paths.len() > LISTED_FILES {
+                out.push_str(&format!("  ... and {} more\n", paths.len() - LISTED_FILES));
+            }
+        }
+        for error in self.unreadable.iter().take(LISTED_FILES) {
+            out.push_str(&format!("  Unreadable: {}\n", error));
+        }
+        out
+    }
+
+    /// Files the restore didn't bring back as they were backed up. A restore with `--restored` may
+    /// have been partial, so only a test restore counts missing and unreadable files.
+    pub fn restore_failures(&self) -> usize {
+        match self.restore_test {
+            true => self.restore_mismatches.len() + self.missing.len() + self.unreadable.len(),
+            false => self.restore_mismatches.len(),
+        }
+    }
+}
+
+/// Re-hash the source files of a manifest. Only files with unchanged size and mtime are read;
+/// a different hash for those can't be an edit.
+pub fn verify_source(manifest: &Manifest) -> Verification {
+    let mut verification = Verification {
+        job: manifest.job.clone(),
+        run_id: manifest.run_id.clone(),
+        snapshot_id: manifest.snapshot_id.clone(),
+        checked: "source".to_string(),
+        ..Default::default()
+    };
+    for entry in &manifest.files {
+        let metadata = match std::fs::symlink_metadata(&entry.path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                verification.missing.push(entry.path.clone());
+                continue;
+            }
+        };
+        if metadata.len() != entry.size || mtime(&metadata) != entry.mtime {
+            verification.modified.push(entry.path.clone());
+            continue;
+        }
+        match hash_file(&entry.path) {
+            Ok(hash) if hash == entry.hash => verification.matching += 1,
+            Ok(_) => verification.bitrot.push(entry.path.clone()),
+            Err(e) => verification.unreadable.push(format!("{}: {}", entry.path.display(), e)),
+        }
+    }
+    verification
+}
+
+/// Compare a restore of the manifest's snapshot under `target` with the hashes taken at backup time
+pub fn verify_restored(manifest: &Manifest, target: &Path) -> Verification {
+    let mut verification = Verification {
+        job: manifest.job.clone(),
+        run_id: manifest.run_id.clone(),
+        snapshot_id: manifest.snapshot_id.clone(),
+        checked: target.display().to_string(),
+        ..Default::default()
+    };
+    for entry in &manifest.files {
+        let restored = target.join(entry.path.strip_prefix("/").unwrap_or(&entry.path));
+        match hash_file(&restored) {
+            Ok(hash) if hash == entry.hash => verification.matching += 1,
+            Ok(_) => verification.restore_mismatches.push(restored),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => verification.missing.push(restored),
+            Err(e) => verification.unreadable.push(format!("{}: {}", restored.display(), e)),
+        }
+    }
+    verification
+}
+
+/// Restore the manifest's snapshot into the staging directory, compare it with the manifest and remove
+/// it again. Paths are reported as they were backed up, not below the throwaway directory.
+async fn restore_test(config: &Config, manifest: Manifest) -> Result<Verification> {
+    let snapshot_id = manifest
+        .snapshot_id
+        .clone()
+        .ok_or_else(|| anyhow::anyhow!("The manifest of run {} has no snapshot to restore", manifest.run_id))?;
+    let target = crate::staging::directory(config).join(format!("restore-test-{}", crate::events::new_run_id()));
+    std::fs::create_dir_all(&target).map_err(|e| anyhow::anyhow!("Failed to create {:?}: {}", target, e))?;
+    info!("Restoring snapshot {} of job '{}' into {:?}", crate::restic::short_id(&snapshot_id), manifest.job, target);
+    let result = match crate::restore::restore_all(config, &snapshot_id, &target).await {
+        Ok(errors) => {
+            let root = target.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut verification = verify_restored(&manifest, &root);
+                verification.unreadable.extend(errors);
+                verification
+            })
+            .await
+            .map_err(anyhow::Error::from)
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = std::fs::remove_dir_all(&target) {
+        warn!("Couldn't remove the test restore {:?}: {}", target, e);
+    }
+    let mut verification = result?;
+    let original = |path: &mut PathBuf| {
+        if let Ok(relative) = path.strip_prefix(&target) {
+            *path = Path::new("/").join(relative);
+        }
+    };
+    verification.restore_mismatches.iter_mut().for_each(original);
+    verification.missing.iter_mut().for_each(original);
+    let prefix = target.display().to_string();
+    verification.unreadable.iter_mut().for_each(|error| *error = error.replace(&prefix, ""));
+    verification.checked = "a test restore".to_string();
+    verification.restore_test = true;
+    Ok(verification)
+}
+
+/// Tell the job's notification targets, or all of them if it doesn't pick any, that its test restore failed
+async fn alert(config: &Config, job: &BackupConfig, body: &str) {
+    let targets: Vec<String> = match job.notifications.as_ref().and_then(|routing| routing.targets.clone()) {
+        Some(targets) => targets,
+        None => config.notifications.iter().map(|target| target.name().to_string()).collect(),
+    };
+    let title = format!("Restore test of {} on {} FAILED", job.name, crate::hostname());
+    crate::notify::send_message(&config.notifications, &targets, &title, body, crate::notify::RunEvent::Failed).await;
+}
+
+/// `verify [--job NAME] [--snapshot ID] [--restored DIR | --restore-test] [--format text|json]`
+pub async fn run(config: &Config, jobs: &[&BackupConfig], args: &[String]) -> Result<()> {
+    let directory = directory(config).ok_or_else(|| anyhow::anyhow!("Manifests are kept in 'reports.directory'; configure it"))?;
+    let snapshot = crate::flag_value(args, "--snapshot");
+    let restored = crate::flag_value(args, "--restored").map(|path| crate::expand_tilde(Path::new(path)));
+    let test_restore = args.iter().any(|arg| arg == "--restore-test");
+    let format = crate::flag_value(args, "--format").map(String::as_str).unwrap_or("text");
+    if !matches!(format, "text" | "json") {
+        return Err(anyhow::anyhow!("Unknown format '{}'; use text or json", format));
+    }
+    if test_restore && restored.is_some() {
+        return Err(anyhow::anyhow!("--restored and --restore-test can't be combined"));
+    }
+
+    let mut verifications = Vec::new();
+    for job in jobs.iter().filter(|job| job.manifest) {
+        let manifests = list(&directory, &job.name);
+        let manifest = match snapshot {
+            Some(id) => manifests.iter().find(|manifest| manifest.snapshot_id.as_deref().is_some_and(|snapshot_id| snapshot_id.starts_with(id.as_str()))),
+            None => manifests.first(),
+        };
+        let Some(manifest) = manifest else {
+            if snapshot.is_none() {
+                println!("{}: no manifest recorded yet", job.name);
+            }
+            continue;
+        };
+        let manifest = manifest.clone();
+        if test_restore {
+            let verification = match restore_test(config, manifest).await {
+                Ok(verification) => verification,
+                Err(e) => {
+                    alert(config, job, &format!("The test restore of job '{}' failed: {}", job.name, e)).await;
+                    return Err(e);
+                }
+            };
+            if verification.restore_failures() > 0 {
+                alert(config, job, &verification.render_text()).await;
+            }
+            verifications.push(verification);
+            continue;
+        }
+        let target = restored.clone();
+        let verification = tokio::task::spawn_blocking(move || match target {
+            Some(ref target) => verify_restored(&manifest, target),
+            None => verify_source(&manifest),
+        })
+        .await?;
+        verifications.push(verification);
+    }
+    if verifications.is_empty() {
+        return Err(match snapshot {
+            Some(id) => anyhow::anyhow!("No manifest for snapshot {}", id),
+            None => anyhow::anyhow!("No manifests to verify; set 'manifest: true' on a job"),
+        });
+    }
+
+    if format == "json" {
+        println!("{}", serde_json::to_string_pretty(&verifications)?);
+    } else {
+        for verification in &verifications {
+            print!("{}", verification.render_text());
+        }
+    }
+    let bitrot: usize = verifications.iter().map(|verification| verification.bitrot.len()).sum();
+    let failures: usize = verifications.iter().map(Verification::restore_failures).sum();
+    match (bitrot, failures) {
+        (0, 0) => Ok(()),
+        (0, _) if test_restore => Err(anyhow::anyhow!("{} file(s) weren't restored as they were backed up", failures)),
+        (0, _) => Err(anyhow::anyhow!("{} restored file(s) differ from what was backed up", failures)),
+        _ => Err(anyhow::anyhow!("{} source file(s) changed without their size or mtime changing", bitrot)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_file_matches_xxh64_vectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        for (content, expected) in [(&b""[..], "ef46db3751d8e999"), (b"a", "d24ec4f1a98c6e5b"), (b"abc", "44bc2cf5ad770999")] {
+            std::fs::write(&path, content).unwrap();
+            assert_eq!(hash_file(&path).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn hash_file_reads_across_buffer_boundaries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        let content: Vec<u8> = (0..600 * 1024).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &content).unwrap();
+        assert_eq!(hash_file(&path).unwrap(), format!("{:016x}", xxhash_rust::xxh64::xxh64(&content, 0)));
+    }
+}
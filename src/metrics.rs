@@ -0,0 +1,156 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Where to export metrics after a run; short-lived runs can't be scraped, so they are pushed/written
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MetricsConfig {
+    /// node_exporter textfile collector file, e.g. /var/lib/node_exporter/textfile/restic.prom.
+    /// Each job writes its own file with the job name inserted before the extension (restic.<job>.prom).
+    #[serde(default)]
+    pub textfile: Option<PathBuf>,
+    /// Pushgateway base URL, e.g. http://pushgateway:9091
+    #[serde(default)]
+    pub pushgateway: Option<String>,
+    /// Value of the `job` label (Pushgateway grouping key)
+    #[serde(default = "default_job")]
+    pub job: String,
+}
+
+fn default_job() -> String {
+    "better_restic_client".to_string()
+}
+
+/// Outcome of a single run, as exported to Prometheus
+#[derive(Debug, Clone)]
+pub struct RunMetrics {
+    pub job: String,
+    pub success: bool,
+    pub finished_at: u64,
+    pub duration_secs: f64,
+}
+
+impl RunMetrics {
+    pub fn new(job: &str, success: bool, duration: std::time::Duration) -> Self {
+        RunMetrics {
+            job: job.to_string(),
+            success,
+            finished_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            duration_secs: duration.as_secs_f64(),
+        }
+    }
+}
+
+/// Render run metrics in the Prometheus text exposition format
+pub fn render(run: &RunMetrics) -> String {
+    let mut out = String::new();
+    let labels = format!("{{backup_job=\"{}\"}}", run.job.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut gauge = |name: &str, help: &str, value: String| {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n{}{} {}\n", name, help, name, name, labels, value));
+    };
+
+    gauge(
+        "restic_backup_last_run_timestamp_seconds",
+        "Unix time the last backup run finished",
+        run.finished_at.to_string(),
+    );
+    gauge(
+        "restic_backup_last_run_success",
+        "Whether the last backup run succeeded (1) or failed (0)",
+        if run.success { "1" } else { "0" }.to_string(),
+    );
+    gauge(
+        "restic_backup_last_run_duration_seconds",
+        "Duration of the last backup run",
+        format!("{:.3}", run.duration_secs),
+    );
+    if run.success {
+        gauge(
+            "restic_backup_last_success_timestamp_seconds",
+            "Unix time of the last successful backup run",
+            run.finished_at.to_string(),
+        );
+    }
+
+    out
+}
+
+/// Write the metrics file atomically so node_exporter never reads a partial file
+pub fn write_textfile(path: &Path, contents: &str) -> Result<()> {
+    let tmp_path = path.with_extension(format!("prom.tmp.{}", std::process::id()));
+    std::fs::write(&tmp_path, contents)
+        .map_err(|e| anyhow::anyhow!("Failed to write metrics file {:?}: {}", tmp_path, e))?;
+    std::fs::rename(&tmp_path, path)
+        .map_err(|e| anyhow::anyhow!("Failed to move metrics file into place at {:?}: {}", path, e))?;
+    Ok(())
+}
+
+/// Per-job textfile path: restic.prom -> restic.<job>.prom
+pub fn job_textfile(path: &Path, job: &str) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!("{}.{}.prom", stem, job))
+}
+
+/// Pushgateway URL of a job's group. Job names become path segments, so '/' or '?' in them are percent-encoded.
+fn push_url(base_url: &str, job: &str, backup_job: &str) -> Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse(base_url).map_err(|e| anyhow::anyhow!("Invalid Pushgateway URL {}: {}", base_url, e))?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("Invalid Pushgateway URL {}", base_url))?
+        .pop_if_empty()
+        .extend(["metrics", "job", job, "backup_job", backup_job]);
+    Ok(url)
+}
+
+/// Push metrics to a Pushgateway, replacing the previous values for this job's group
+pub async fn push(base_url: &str, job: &str, backup_job: &str, contents: &str) -> Result<()> {
+    let url = push_url(base_url, job, backup_job)?;
+    debug!("Pushing metrics to {}", url);
+
+    let response = reqwest::Client::new()
+        .put(url.clone())
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(contents.to_string())
+        .send()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to push metrics to {}: {}", url, e))?;
+
+    if !response.status().is_success() {
+        return Err(anyhow::anyhow!("Pushgateway at {} returned {}", url, response.status()));
+    }
+    Ok(())
+}
+
+/// Export run metrics to all configured targets. Failures are logged, never fatal to the backup.
+pub async fn export(config: &MetricsConfig, run: &RunMetrics) {
+    let contents = render(run);
+
+    if let Some(ref path) = config.textfile {
+        let path = job_textfile(path, &run.job);
+        match write_textfile(&path, &contents) {
+            Ok(()) => info!("Metrics written to {:?}", path),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    if let Some(ref url) = config.pushgateway {
+        match push(url, &config.job, &run.job, &contents).await {
+            Ok(()) => info!("Metrics pushed to {}", url),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_url_encodes_job_names() {
+        let url = push_url("http://pushgateway:9091/", "better_restic_client", "home/photos?x").unwrap();
+        assert_eq!(url.as_str(), "http://pushgateway:9091/metrics/job/better_restic_client/backup_job/home%2Fphotos%3Fx");
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+
+use crate::config_history;
+
+/// Schema version written by this release. Bump it together with a new entry in `MIGRATIONS`.
+pub const CURRENT_CONFIG_VERSION: u32 = 2;
+
+/// Upgrade of the raw YAML from `to - 1` to `to`, e.g. renaming keys or moving sections
+struct Migration {
+    to: u32,
+    description: &'static str,
+    apply: fn(&mut Mapping),
+}
+
+/// In order; configs without `version` are version 0
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: 1,
+        description: "record the schema version",
+        apply: |_| {},
+    },
+    Migration {
+        to: 2,
+        description: "rename notification level `all` to `success`",
+        apply: rename_notify_all,
+    },
+];
+
+fn version_key() -> Value {
+    Value::String("version".to_string())
+}
+
+fn key(name: &str) -> Value {
+    Value::String(name.to_string())
+}
+
+/// Targets' `on: all` from before jobs, and the same value in jobs' `notifications`, which only
+/// an alias kept working
+fn rename_notify_all(document: &mut Mapping) {
+    fn rename(section: Option<&mut Value>) {
+        let Some(Value::Mapping(section)) = section else { return };
+        if let Some(on) = section.get_mut(&key("on")) {
+            if on.as_str() == Some("all") {
+                *on = key("success");
+            }
+        }
+    }
+
+    if let Some(Value::Sequence(targets)) = document.get_mut(&key("notifications")) {
+        targets.iter_mut().for_each(|target| rename(Some(target)));
+    }
+    if let Some(Value::Mapping(backup)) = document.get_mut(&key("backup")) {
+        rename(backup.get_mut(&key("notifications")));
+    }
+    if let Some(Value::Sequence(jobs)) = document.get_mut(&key("jobs")) {
+        for job in jobs {
+            if let Value::Mapping(job) = job {
+                rename(job.get_mut(&key("notifications")));
+            }
+        }
+    }
+}
+
+/// Schema version of a parsed config document
+fn version_of(document: &Mapping) -> Result<u32> {
+    match document.get(&version_key()) {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| anyhow::anyhow!("Config version must be a number, got {:?}", version)),
+    }
+}
+
+/// Set `version:` in the original text, keeping its comments and layout
+fn stamp_version(yaml: &str, version: u32) -> String {
+    let line = format!("version: {}", version);
+    let mut lines: Vec<&str> = yaml.lines().collect();
+    match lines.iter().position(|l| l.starts_with("version:")) {
+        Some(index) => {
+            lines[index] = &line;
+            let mut stamped = lines.join("\n");
+            stamped.push('\n');
+            stamped
+        }
+        // After a document start marker, if there is one
+        None => match yaml.strip_prefix("---\n") {
+            Some(rest) => format!("---\n{}\n{}", line, rest),
+            None => format!("{}\n{}", line, yaml),
+        },
+    }
+}
+
+/// Bring config YAML up to `CURRENT_CONFIG_VERSION`. Returns the (possibly unchanged) YAML and
+/// the descriptions of the migrations applied. The text is only re-serialized, losing comments,
+/// when a migration changed more than the version.
+pub fn migrate_yaml(yaml: &str) -> Result<(String, Vec<&'static str>)> {
+    let Value::Mapping(original) = serde_yaml::from_str(yaml)? else {
+        return Ok((yaml.to_string(), Vec::new()));
+    };
+    let version = version_of(&original)?;
+    if version > CURRENT_CONFIG_VERSION {
+        return Err(anyhow::anyhow!(
+            "Config version {} is newer than this client supports ({}); upgrade better_restic_client",
+            version,
+            CURRENT_CONFIG_VERSION
+        ));
+    }
+
+    let mut document = original.clone();
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|migration| migration.to > version) {
+        (migration.apply)(&mut document);
+        applied.push(migration.description);
+    }
+    if applied.is_empty() {
+        return Ok((yaml.to_string(), applied));
+    }
+
+    let mut unversioned = original;
+    unversioned.remove(&version_key());
+    document.remove(&version_key());
+    if document == unversioned {
+        return Ok((stamp_version(yaml, CURRENT_CONFIG_VERSION), applied));
+    }
+
+    let mut migrated = Mapping::new();
+    migrated.insert(version_key(), Value::Number(CURRENT_CONFIG_VERSION.into()));
+    for (key, value) in document {
+        migrated.insert(key, value);
+    }
+    let migrated = serde_yaml::to_string(&Value::Mapping(migrated))?;
+    Ok((migrated.strip_prefix("---\n").unwrap_or(&migrated).to_string(), applied))
+}
+
+/// Read the config file, upgrading it in place first if it's from an older version.
+/// The original is saved to the config history, so it can be restored with a rollback.
+/// A read-only config is upgraded in memory only.
+pub fn load(config_path: &str) -> Result<String> {
+    let yaml = std::fs::read_to_string(config_path)?;
+    let (migrated, applied) = migrate_yaml(&yaml)?;
+    if applied.is_empty() {
+        return Ok(yaml);
+    }
+
+    let written = config_history::save_version(config_path)
+        .and_then(|saved| config_history::write_config(config_path, &migrated).map(|_| saved));
+    match written {
+        Ok(saved) => eprintln!(
+            "Upgraded {} to config version {} ({}); the original was saved as version {} in {:?}",
+            config_path,
+            CURRENT_CONFIG_VERSION,
+            applied.join(", "),
+            saved.unwrap_or_default(),
+            config_history::history_dir(config_path)
+        ),
+        Err(e) => eprintln!(
+            "{} is from an older config version but couldn't be upgraded on disk ({}); using the upgraded config for this run",
+            config_path, e
+        ),
+    }
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The single-job layout from before `jobs` and `version`, when targets took `on: all`
+    const PRE_JOBS: &str = "backup:
+  frequency: daily
+  time: \"02:00\"
+  directories: [/srv]
+logging:
+  directory: /tmp/logs
+  max_size: 10MB
+restic:
+  repository: /tmp/repo
+notifications:
+  - type: desktop
+    on: all
+  - type: ntfy
+    topic: backups
+    on: failure
+";
+
+    #[test]
+    fn upgrades_the_pre_jobs_layout() {
+        let (yaml, applied) = migrate_yaml(PRE_JOBS).unwrap();
+        assert_eq!(applied.len(), 2);
+        let config: crate::Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config.version, CURRENT_CONFIG_VERSION);
+        assert_eq!(config.jobs().iter().map(|job| job.name.as_str()).collect::<Vec<_>>(), ["backup"]);
+        let levels: Vec<_> = config.notifications.iter().map(|target| target.on).collect();
+        assert_eq!(levels, [crate::notify::NotifyOn::Success, crate::notify::NotifyOn::Failure]);
+    }
+
+    #[test]
+    fn renames_all_in_job_notifications() {
+        let yaml = format!("version: 1\n{}jobs:\n  - name: docs\n    frequency: daily\n    time: \"03:00\"\n    notifications:\n      on: all\n", PRE_JOBS.replace("on: all", "on: warning"));
+        let (yaml, applied) = migrate_yaml(&yaml).unwrap();
+        assert_eq!(applied, ["rename notification level `all` to `success`"]);
+        let config: crate::Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config.jobs[0].notifications.as_ref().and_then(|routing| routing.on), Some(crate::notify::NotifyOn::Success));
+        assert_eq!(config.notifications[0].on, crate::notify::NotifyOn::Warning);
+    }
+
+    #[test]
+    fn keeps_comments_when_only_the_version_changes() {
+        let yaml = format!("# my backups\nversion: 1\n{}", PRE_JOBS.replace("on: all", "on: success"));
+        let (migrated, applied) = migrate_yaml(&yaml).unwrap();
+        assert_eq!(applied.len(), 1);
+        assert!(migrated.starts_with("# my backups\nversion: 2\n"));
+    }
+}
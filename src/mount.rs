@@ -0,0 +1,177 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::{Child, Command};
+
+/// Require the backup target to be mounted, so a missing USB disk or NFS share
+/// doesn't mean silently writing to the local root filesystem
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MountConfig {
+    /// Directory that must be a mountpoint before restic runs
+    pub path: PathBuf,
+    /// Command run through `sh -c` when the path is not mounted, e.g. `mount /mnt/backup`
+    /// or `udisksctl mount -b /dev/disk/by-uuid/...`
+    #[serde(default)]
+    pub mount_command: Option<String>,
+    /// Command to unmount again; defaults to `umount <path>`
+    #[serde(default)]
+    pub unmount_command: Option<String>,
+    /// Unmount after the run if we mounted it
+    #[serde(default = "default_unmount_after")]
+    pub unmount_after: bool,
+    /// How long to wait for the mount to appear (automounts, slow NFS)
+    #[serde(default)]
+    pub wait_secs: u64,
+}
+
+fn default_unmount_after() -> bool {
+    true
+}
+
+/// Whether `path` is the root of a mounted filesystem
+pub fn is_mountpoint(path: &Path) -> bool {
+    let path = match std::fs::canonicalize(path) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    if crate::repo_health::mounts().iter().any(|mount| mount.mount_point == path) {
+        return true;
+    }
+
+    // Without /proc (macOS), a mountpoint is on a different device than its parent
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let (Ok(own), Some(Ok(parent))) = (std::fs::metadata(&path), path.parent().map(std::fs::metadata)) {
+            return own.dev() != parent.dev();
+        }
+    }
+    false
+}
+
+async fn run_shell(command: &str) -> Result<()> {
+    debug!("Running: {}", command);
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run '{}': {}", command, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("'{}' failed ({:?}): {}", command, output.status.code(), stderr.trim()));
+    }
+    Ok(())
+}
+
+async fn wait_for_mount(path: &Path, wait_secs: u64) -> bool {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(wait_secs);
+    loop {
+        if is_mountpoint(path) {
+            return true;
+        }
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+}
+
+/// Make sure the target is mounted. Returns whether we mounted it (and should unmount it afterwards).
+pub async fn ensure_mounted(config: &MountConfig) -> Result<bool> {
+    let path = crate::expand_tilde(&config.path);
+
+    if wait_for_mount(&path, config.wait_secs).await {
+        debug!("{:?} is mounted", path);
+        return Ok(false);
+    }
+
+    let Some(ref mount_command) = config.mount_command else {
+        return Err(anyhow::anyhow!(
+            "{:?} is not mounted; refusing to back up to the local filesystem underneath it",
+            path
+        ));
+    };
+
+    info!("Mounting {:?}", path);
+    let (mut child, mut stderr) = spawn_mount(mount_command)?;
+    // Give udisks/automount a moment to show the mount. FUSE mounts (sshfs, rclone mount) may keep
+    // running to serve it, so the command doesn't have to exit first.
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(config.wait_secs.max(5));
+    loop {
+        if is_mountpoint(&path) {
+            info!("Mounted {:?}", path);
+            return Ok(true);
+        }
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                let mut output = String::new();
+                let _ = stderr.seek(SeekFrom::Start(0)).and_then(|_| stderr.read_to_string(&mut output));
+                return Err(anyhow::anyhow!("'{}' failed ({:?}): {}", mount_command, status.code(), output.trim()));
+            }
+        }
+        if std::time::Instant::now() >= deadline {
+            abort_mount(config, &path, &mut child).await;
+            return Err(anyhow::anyhow!("Ran '{}' but {:?} is still not a mountpoint", mount_command, path));
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    }
+}
+
+/// Start the mount command in its own process group, with stderr going to a temporary file
+/// rather than a pipe nobody reads once a FUSE mount runs in the background
+fn spawn_mount(command: &str) -> Result<(Child, std::fs::File)> {
+    debug!("Running: {}", command);
+    let stderr = tempfile::tempfile()?;
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(stderr.try_clone()?);
+    #[cfg(unix)]
+    cmd.process_group(0);
+    let child = cmd.spawn().map_err(|e| anyhow::anyhow!("Failed to run '{}': {}", command, e))?;
+    Ok((child, stderr))
+}
+
+/// After a timeout: stop the mount command and whatever it started, and undo a mount that came
+/// up half-way, so repeated runs don't pile up stray mount processes
+async fn abort_mount(config: &MountConfig, path: &Path, child: &mut Child) {
+    if child.try_wait().ok().flatten().is_none() {
+        #[cfg(unix)]
+        if let Some(pid) = child.id() {
+            unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGTERM) };
+        }
+        let _ = child.start_kill();
+        let _ = child.wait().await;
+    }
+    let quoted = path.to_string_lossy().replace('\'', "'\\''");
+    let command = config
+        .unmount_command
+        .clone()
+        .unwrap_or_else(|| format!("fusermount -u '{0}' 2>/dev/null || umount '{0}'", quoted));
+    if let Err(e) = run_shell(&command).await {
+        debug!("Nothing to unmount at {:?}: {}", path, e);
+    }
+}
+
+/// Unmount a target we mounted, logging instead of failing the run
+pub async fn unmount(config: &MountConfig) {
+    if !config.unmount_after {
+        return;
+    }
+    let path = crate::expand_tilde(&config.path);
+    let command = config
+        .unmount_command
+        .clone()
+        .unwrap_or_else(|| format!("umount '{}'", path.to_string_lossy().replace('\'', "'\\''")));
+
+    match run_shell(&command).await {
+        Ok(()) => info!("Unmounted {:?}", path),
+        Err(e) => error!("Failed to unmount {:?}: {}", path, e),
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::Result;
+
+use crate::BackupConfig;
+
+/// Snapshot tags starting with this carry the run's note
+pub const NOTE_TAG_PREFIX: &str = "note:";
+/// Long enough for a sentence; restic shows tags on one line
+const MAX_LENGTH: usize = 200;
+
+/// The tag for a note. restic splits `--tag` values at commas, so commas become semicolons.
+pub fn tag(note: &str) -> Result<String> {
+    let note = note.trim();
+    if note.is_empty() {
+        return Err(anyhow::anyhow!("The note must not be empty"));
+    }
+    if note.chars().count() > MAX_LENGTH {
+        return Err(anyhow::anyhow!("The note is longer than {} characters", MAX_LENGTH));
+    }
+    if note.chars().any(char::is_control) {
+        return Err(anyhow::anyhow!("The note must be a single line"));
+    }
+    Ok(format!("{}{}", NOTE_TAG_PREFIX, note.replace(',', ";")))
+}
+
+/// The note among a snapshot's or job's tags
+pub fn from_tags<'a>(tags: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    tags.into_iter().find_map(|tag| tag.strip_prefix(NOTE_TAG_PREFIX)).map(str::to_string)
+}
+
+/// The job with the note added to its tags, for a single run
+pub fn annotate(job: &BackupConfig, note: &str) -> Result<BackupConfig> {
+    let mut annotated = job.clone();
+    annotated.tags.retain(|tag| !tag.starts_with(NOTE_TAG_PREFIX));
+    annotated.tags.push(tag(note)?);
+    Ok(annotated)
+}
@@ -0,0 +1,409 @@
+use anyhow::Result;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::restic::{self, BackupSummary};
+
+/// How long a notification may take; the start notification is sent before the backup runs
+const SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Minimum run outcome a target is told about
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyOn {
+    /// Every run, successful or not
+    #[default]
+    Success,
+    /// Runs that finished with warnings (unreadable files) or failed
+    Warning,
+    /// Only failed runs
+    Failure,
+}
+
+/// Per-job override of notification routing
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct JobNotifications {
+    /// Names of the targets this job notifies; all targets when not set
+    #[serde(default)]
+    pub targets: Option<Vec<String>>,
+    /// Level override applied to every selected target
+    #[serde(default)]
+    pub on: Option<NotifyOn>,
+}
+
+/// A notification target: a backend selected by `type`, plus filtering and message templates
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NotificationTarget {
+    /// Name jobs use to route to this target; defaults to the backend type
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub backend: Backend,
+    #[serde(default)]
+    pub on: NotifyOn,
+    /// Handlebars template for the subject/title, e.g. "[{{status}}] {{job}} on {{hostname}}"
+    #[serde(default)]
+    pub title_template: Option<String>,
+    /// Handlebars template for the message body
+    #[serde(default)]
+    pub body_template: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Backend {
+    /// ntfy.sh (or self-hosted ntfy) topic
+    Ntfy {
+        #[serde(default = "default_ntfy_url")]
+        url: String,
+        topic: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    /// Gotify server with an application token
+    Gotify { url: String, token: String },
+    /// Telegram bot sending to a chat
+    Telegram { bot_token: String, chat_id: String },
+    /// Email over SMTP
+    Email(EmailBackend),
+    /// Native desktop notification on the machine running the backup
+    Desktop {
+        /// Also notify when a backup starts
+        #[serde(default)]
+        on_start: bool,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EmailBackend {
+    pub smtp_host: String,
+    #[serde(default = "default_smtp_port")]
+    pub smtp_port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Use STARTTLS (default); disable only for local relays
+    #[serde(default = "default_true")]
+    pub starttls: bool,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn default_smtp_port() -> u16 {
+    587
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_ntfy_url() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+impl NotificationTarget {
+    pub fn kind(&self) -> &'static str {
+        match self.backend {
+            Backend::Ntfy { .. } => "ntfy",
+            Backend::Gotify { .. } => "gotify",
+            Backend::Telegram { .. } => "telegram",
+            Backend::Email(_) => "email",
+            Backend::Desktop { .. } => "desktop",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.kind())
+    }
+
+    /// Whether this target wants to hear about the given notification, honoring a job's routing
+    pub fn accepts(&self, notification: &Notification, routing: Option<&JobNotifications>) -> bool {
+        if let Some(targets) = routing.and_then(|r| r.targets.as_ref()) {
+            if !targets.iter().any(|name| name == self.name()) {
+                return false;
+            }
+        }
+
+        let on = routing.and_then(|r| r.on).unwrap_or(self.on);
+        match notification.event {
+            // Start notifications are only useful for someone sitting at the machine
+            RunEvent::Started => matches!(self.backend, Backend::Desktop { on_start: true }),
+            RunEvent::Succeeded => on <= NotifyOn::Success,
+            RunEvent::Warning => on <= NotifyOn::Warning,
+            RunEvent::Failed | RunEvent::Interrupted => true,
+        }
+    }
+
+    /// Title and body for this target, using its templates where configured
+    pub fn render(&self, notification: &Notification) -> (String, String) {
+        let variables = notification.template_variables();
+        let render = |template: &Option<String>, fallback: String| match template {
+            Some(template) => render_template(template, &variables).unwrap_or_else(|e| {
+                error!("Invalid {} notification template: {}", self.name(), e);
+                fallback
+            }),
+            None => fallback,
+        };
+        (
+            render(&self.title_template, notification.title()),
+            render(&self.body_template, notification.body()),
+        )
+    }
+}
+
+/// Render a Handlebars template without HTML escaping (notifications are plain text)
+pub fn render_template(template: &str, variables: &serde_json::Value) -> Result<String> {
+    let mut handlebars = handlebars::Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    Ok(handlebars.render_template(template, variables)?)
+}
+
+/// Point in a run's lifecycle a notification is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunEvent {
+    Started,
+    Succeeded,
+    /// Snapshot created, but some files could not be read
+    Warning,
+    Failed,
+    /// The run was cut short by a crash or power loss and found on the next start
+    Interrupted,
+}
+
+impl RunEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunEvent::Started => "started",
+            RunEvent::Succeeded => "succeeded",
+            RunEvent::Warning => "warning",
+            RunEvent::Failed => "failed",
+            RunEvent::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// What happened in a run, ready to be sent
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub job: String,
+    pub event: RunEvent,
+    pub duration_secs: f64,
+    pub error: Option<String>,
+    pub summary: Option<BackupSummary>,
+    /// The repository is nearing or over `restic.size_limit`
+    pub repository_warning: Option<String>,
+    /// The backup processed far more or less than the dry run before it predicted
+    pub prediction_warning: Option<String>,
+}
+
+impl Notification {
+    pub fn started(job: &str) -> Self {
+        Notification {
+            job: job.to_string(),
+            event: RunEvent::Started,
+            duration_secs: 0.0,
+            error: None,
+            summary: None,
+            repository_warning: None,
+            prediction_warning: None,
+        }
+    }
+
+    /// Variables available to notification templates
+    pub fn template_variables(&self) -> serde_json::Value {
+        let summary = self.summary.clone().unwrap_or_default();
+        json!({
+            "job": self.job,
+            "hostname": crate::hostname(),
+            "status": self.event.as_str(),
+            "success": self.is_success(),
+            "snapshot_id": summary.snapshot_id.as_deref().unwrap_or(""),
+            "short_id": summary.snapshot_id.as_deref().map(restic::short_id).unwrap_or(""),
+            "bytes_added": summary.data_added,
+            "bytes_added_human": restic::format_bytes(summary.data_added),
+            "files_new": summary.files_new,
+            "files_changed": summary.files_changed,
+            "duration": format!("{:.1}s", self.duration_secs),
+            "duration_secs": self.duration_secs,
+            "warnings": summary.warnings,
+            "warning_count": summary.warnings.len(),
+            "error": self.error.as_deref().unwrap_or(""),
+            "repository_warning": self.repository_warning.as_deref().unwrap_or(""),
+            "prediction_warning": self.prediction_warning.as_deref().unwrap_or(""),
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        !matches!(self.event, RunEvent::Failed | RunEvent::Interrupted)
+    }
+
+    pub fn title(&self) -> String {
+        match self.event {
+            RunEvent::Started => format!("⏳ Backup '{}' started on {}", self.job, crate::hostname()),
+            RunEvent::Succeeded => format!("✅ Backup '{}' succeeded on {}", self.job, crate::hostname()),
+            RunEvent::Warning => format!("⚠️ Backup '{}' finished with warnings on {}", self.job, crate::hostname()),
+            RunEvent::Failed => format!("❌ Backup '{}' failed on {}", self.job, crate::hostname()),
+            RunEvent::Interrupted => format!("💥 Backup '{}' was interrupted on {}", self.job, crate::hostname()),
+        }
+    }
+
+    pub fn body(&self) -> String {
+        if self.event == RunEvent::Started {
+            return "Backup is running".to_string();
+        }
+        let mut body = format!("Duration: {:.1}s", self.duration_secs);
+        if let Some(ref summary) = self.summary {
+            body.push_str(&format!("\n{}", summary.describe()));
+            if !summary.warnings.is_empty() {
+                body.push_str(&format!("\n{} files could not be read", summary.warnings.len()));
+            }
+        }
+        if let Some(ref warning) = self.repository_warning {
+            body.push_str(&format!("\n{}", warning));
+        }
+        if let Some(ref warning) = self.prediction_warning {
+            body.push_str(&format!("\n{}", warning));
+        }
+        if let Some(ref error) = self.error {
+            body.push_str(&format!("\nError: {}", error));
+        }
+        body
+    }
+}
+
+async fn send(client: &reqwest::Client, target: &NotificationTarget, title: String, body: String, event: RunEvent) -> Result<()> {
+    let request = match target.backend {
+        Backend::Ntfy { ref url, ref topic, ref token } => {
+            let mut request = client
+                .post(format!("{}/{}", url.trim_end_matches('/'), topic))
+                .header("Title", title)
+                .header("Priority", match event {
+                    RunEvent::Failed | RunEvent::Interrupted => "high",
+                    _ => "default",
+                })
+                .header("Tags", match event {
+                    RunEvent::Started => "hourglass",
+                    RunEvent::Succeeded => "white_check_mark",
+                    RunEvent::Warning => "warning",
+                    RunEvent::Failed => "rotating_light",
+                    RunEvent::Interrupted => "boom",
+                })
+                .body(body);
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            request
+        }
+        Backend::Gotify { ref url, ref token } => client
+            .post(format!("{}/message", url.trim_end_matches('/')))
+            .header("X-Gotify-Key", token)
+            .json(&json!({
+                "title": title,
+                "message": body,
+                "priority": match event {
+                    RunEvent::Failed | RunEvent::Interrupted => 8,
+                    RunEvent::Warning => 5,
+                    _ => 2,
+                },
+            })),
+        Backend::Telegram { ref bot_token, ref chat_id } => client
+            .post(format!("https://api.telegram.org/bot{}/sendMessage", bot_token))
+            .json(&json!({
+                "chat_id": chat_id,
+                "text": format!("{}\n{}", title, body),
+            })),
+        Backend::Desktop { .. } => return show_desktop(title, body, !matches!(event, RunEvent::Failed | RunEvent::Interrupted)).await,
+        Backend::Email(ref email) => return send_email(email, title, body).await,
+    };
+
+    // The URL may hold a secret, such as Telegram's bot token, so it stays out of the error
+    let response = request.send().await.map_err(|e| e.without_url())?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("{} returned {}: {}", target.name(), status, text.trim()));
+    }
+    Ok(())
+}
+
+/// Send a plain-text email over SMTP
+async fn send_email(email: &EmailBackend, title: String, body: String) -> Result<()> {
+    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+    let mut builder = Message::builder().from(email.from.parse()?).subject(title);
+    for to in &email.to {
+        builder = builder.to(to.parse()?);
+    }
+    let message = builder.body(body)?;
+
+    let mut transport = if email.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&email.smtp_host)?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&email.smtp_host)
+    }
+    .port(email.smtp_port);
+    if let (Some(username), Some(password)) = (&email.username, &email.password) {
+        transport = transport.credentials(lettre::transport::smtp::authentication::Credentials::new(
+            username.clone(),
+            password.clone(),
+        ));
+    }
+
+    transport.build().send(message).await?;
+    Ok(())
+}
+
+/// Show a native desktop notification; notify-rust blocks, so it runs off the async runtime
+async fn show_desktop(title: String, body: String, success: bool) -> Result<()> {
+    tokio::task::spawn_blocking(move || {
+        let mut desktop = notify_rust::Notification::new();
+        desktop.appname("Better Restic Client").summary(&title).body(&body);
+        if !success {
+            desktop.timeout(notify_rust::Timeout::Never);
+        }
+        desktop.show().map(|_| ())
+    })
+    .await?
+    .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+fn client() -> reqwest::Client {
+    reqwest::Client::builder().timeout(SEND_TIMEOUT).build().unwrap_or_default()
+}
+
+/// Send a notification to every target whose filter accepts it. Failures are logged, never fatal.
+pub async fn send_all(targets: &[NotificationTarget], routing: Option<&JobNotifications>, notification: &Notification) {
+    let client = client();
+
+    for target in targets {
+        if !target.accepts(notification, routing) {
+            debug!("Skipping {} notification (filtered out)", target.name());
+            continue;
+        }
+        let (title, body) = target.render(notification);
+        match send(&client, target, title, body, notification.event).await {
+            Ok(()) => info!("Sent {} notification", target.name()),
+            Err(e) => error!("Failed to send {} notification: {}", target.name(), e),
+        }
+    }
+}
+
+/// Send a free-form message (e.g. a digest) to the named targets, bypassing level filters and templates.
+/// Returns the names of the targets it couldn't be delivered to.
+pub async fn send_message(targets: &[NotificationTarget], names: &[String], title: &str, body: &str, event: RunEvent) -> Vec<String> {
+    let client = client();
+    let mut failed = Vec::new();
+
+    for target in targets.iter().filter(|t| names.iter().any(|name| name == t.name())) {
+        match send(&client, target, title.to_string(), body.to_string(), event).await {
+            Ok(()) => info!("Sent message to {}", target.name()),
+            Err(e) => {
+                error!("Failed to send message to {}: {}", target.name(), e);
+                failed.push(target.name().to_string());
+            }
+        }
+    }
+    failed
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::Config;
+
+/// Maintenance of a repository in a bucket with object lock (S3, B2, Wasabi...), where nothing can be
+/// deleted until its retention period has passed
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ObjectLockConfig {
+    /// The bucket's default retention in restic's duration syntax, e.g. "30d" or "1y"
+    pub retention: String,
+    #[serde(default)]
+    pub prune: PruneMode,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PruneMode {
+    /// Prune at most once per retention period, deleting only packs no snapshot uses anymore
+    #[default]
+    Aligned,
+    /// Never prune; expired objects are left to the bucket's lifecycle rules
+    Skip,
+}
+
+/// Days in a restic duration like "1y6m" or "30d12h"; months count as 30 days and years as 365
+pub fn duration_days(value: &str) -> Option<f64> {
+    let mut days = 0.0;
+    let mut number = String::new();
+    for c in value.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let count: f64 = number.parse().ok()?;
+        number.clear();
+        days += count
+            * match c {
+                'y' => 365.0,
+                'm' => 30.0,
+                'd' => 1.0,
+                'h' => 1.0 / 24.0,
+                _ => return None,
+            };
+    }
+    (number.is_empty() && days > 0.0).then_some(days)
+}
+
+/// Make `restic forget` keep every snapshot still under retention, raising `--keep-within` if needed,
+/// so it never tries to delete locked snapshot files
+pub fn align_forget(args: &mut Vec<String>, lock: &ObjectLockConfig) {
+    let Some(retention) = duration_days(&lock.retention) else { return };
+    if args.is_empty() {
+        return;
+    }
+    match args.iter().position(|arg| arg == "--keep-within") {
+        Some(i) if duration_days(&args[i + 1]).is_some_and(|days| days >= retention) => {}
+        Some(i) => {
+            warn!("keep_within {} is shorter than the object lock retention; keeping {} instead", args[i + 1], lock.retention);
+            args[i + 1] = lock.retention.clone();
+        }
+        None => {
+            args.push("--keep-within".to_string());
+            args.push(lock.retention.clone());
+        }
+    }
+}
+
+/// `restic prune` arguments that only delete packs and never repack, since repacking deletes packs still in use
+pub fn prune_args() -> Vec<String> {
+    vec!["--max-unused".to_string(), "unlimited".to_string()]
+}
+
+/// When each repository was last pruned, next to the logs
+fn state_path(config: &Config) -> PathBuf {
+    crate::expand_tilde(&config.logging.directory.join("last-prune.json"))
+}
+
+fn load(config: &Config) -> BTreeMap<String, DateTime<Local>> {
+    std::fs::read(state_path(config))
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Why prune shouldn't run now, if it shouldn't
+pub fn prune_skipped(config: &Config, lock: &ObjectLockConfig) -> Option<String> {
+    if lock.prune == PruneMode::Skip {
+        return Some("the bucket has object lock and maintenance.object_lock.prune is skip".to_string());
+    }
+    let retention = duration_days(&lock.retention)?;
+    let last = *load(config).get(&config.restic.repository_url())?;
+    let next = last + chrono::Duration::seconds((retention * 86400.0) as i64);
+    (Local::now() < next).then(|| {
+        format!(
+            "last pruned {}; with {} object lock retention the next prune is due {}",
+            last.format("%Y-%m-%d"),
+            lock.retention,
+            next.format("%Y-%m-%d")
+        )
+    })
+}
+
+pub fn record_prune(config: &Config) {
+    let mut pruned = load(config);
+    pruned.insert(config.restic.repository_url(), Local::now());
+    let path = state_path(config);
+    let written = serde_json::to_vec_pretty(&pruned)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(std::fs::write(&path, content)?));
+    if let Err(e) = written {
+        warn!("Couldn't update {:?}: {}", path, e);
+    }
+}
+
+/// Guidance for a restic error caused by object lock refusing a delete or overwrite
+pub fn explain(stderr: &str) -> Option<String> {
+    let lower = stderr.to_lowercase();
+    let markers = ["object lock", "objectlock", "worm protected", "object is locked", "locked until", "file is locked", "retention period"];
+    if !markers.iter().any(|marker| lower.contains(marker)) {
+        return None;
+    }
+    Some(
+        "the bucket's object lock refused to delete data still under retention; set maintenance.object_lock.retention \
+         to the bucket's retention so forget keeps snapshots that long and prune waits for the locks to expire"
+            .to_string(),
+    )
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+
+/// One step of a `--set` path: a mapping key or a list index
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Parse `backup.directories[0]` into its segments
+fn parse_path(path: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let (key, mut rest) = match part.find('[') {
+            Some(bracket) => part.split_at(bracket),
+            None => (part, ""),
+        };
+        if key.is_empty() {
+            return Err(anyhow::anyhow!("Invalid path '{}': empty key", path));
+        }
+        segments.push(Segment::Key(key.to_string()));
+        while let Some(inner) = rest.strip_prefix('[') {
+            let close = inner.find(']').ok_or_else(|| anyhow::anyhow!("Invalid path '{}': missing ']'", path))?;
+            let index = inner[..close]
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid path '{}': '{}' is not an index", path, &inner[..close]))?;
+            segments.push(Segment::Index(index));
+            rest = &inner[close + 1..];
+        }
+        if !rest.is_empty() {
+            return Err(anyhow::anyhow!("Invalid path '{}'", path));
+        }
+    }
+    Ok(segments)
+}
+
+/// Set the value at `segments`, creating missing maps and lists on the way.
+/// An index one past the end of a list appends.
+fn set(target: &mut Value, segments: &[Segment], value: Value, path: &str) -> Result<()> {
+    let Some((first, rest)) = segments.split_first() else {
+        *target = value;
+        return Ok(());
+    };
+    match first {
+        Segment::Key(key) => {
+            if target.is_null() {
+                *target = Value::Mapping(Mapping::new());
+            }
+            let Value::Mapping(mapping) = target else {
+                return Err(anyhow::anyhow!("Can't set '{}': '{}' is not inside a map", path, key));
+            };
+            let key = Value::String(key.clone());
+            if !mapping.contains_key(&key) {
+                mapping.insert(key.clone(), Value::Null);
+            }
+            set(mapping.get_mut(&key).expect("inserted above"), rest, value, path)
+        }
+        Segment::Index(index) => {
+            if target.is_null() {
+                *target = Value::Sequence(Vec::new());
+            }
+            let Value::Sequence(items) = target else {
+                return Err(anyhow::anyhow!("Can't set '{}': [{}] is not a list", path, index));
+            };
+            if *index == items.len() {
+                items.push(Value::Null);
+            }
+            let len = items.len();
+            let item = items
+                .get_mut(*index)
+                .ok_or_else(|| anyhow::anyhow!("Can't set '{}': index {} is past the end of a list of {}", path, index, len))?;
+            set(item, rest, value, path)
+        }
+    }
+}
+
+/// Append to a list field of every job, e.g. `tags` or `exclude`
+fn append_to_jobs(document: &mut Value, field: &str, values: &[&String]) -> Result<()> {
+    let mut paths = vec![format!("backup.{}", field)];
+    if let Some(jobs) = document.get("jobs").and_then(Value::as_sequence) {
+        paths.extend((0..jobs.len()).map(|i| format!("jobs[{}].{}", i, field)));
+    }
+    for path in paths {
+        let segments = parse_path(&path)?;
+        for value in values {
+            let mut segments = segments.clone();
+            let len = lookup(document, &segments).and_then(Value::as_sequence).map(Vec::len).unwrap_or(0);
+            segments.push(Segment::Index(len));
+            set(document, &segments, Value::String(value.to_string()), &path)?;
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'a>(value: &'a Value, segments: &[Segment]) -> Option<&'a Value> {
+    segments.iter().try_fold(value, |value, segment| match segment {
+        Segment::Key(key) => value.get(key.as_str()),
+        Segment::Index(index) => value.get(*index),
+    })
+}
+
+/// Whether the command line has any config overrides
+pub fn present(args: &[String]) -> bool {
+    args.iter().any(|arg| matches!(arg.as_str(), "--set" | "--repo" | "--restic-binary" | "--tag" | "--exclude"))
+}
+
+/// Apply `--repo`, `--restic-binary`, `--tag`, `--exclude` and then `--set path=value` to the parsed config document.
+/// Values are read as YAML, so `--set backup.one_file_system=true` sets a boolean.
+pub fn apply(document: &mut Value, args: &[String]) -> Result<()> {
+    if let Some(repository) = crate::flag_value(args, "--repo") {
+        set(document, &parse_path("restic.repository")?, Value::String(repository.clone()), "restic.repository")?;
+    }
+    if let Some(binary) = crate::flag_value(args, "--restic-binary") {
+        set(document, &parse_path("restic.binary")?, Value::String(binary.clone()), "restic.binary")?;
+    }
+    append_to_jobs(document, "tags", &crate::flag_values(args, "--tag"))?;
+    append_to_jobs(document, "exclude", &crate::flag_values(args, "--exclude"))?;
+
+    for assignment in crate::flag_values(args, "--set") {
+        let (path, raw) = assignment
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("--set needs path=value, got '{}'", assignment))?;
+        let value = if raw.is_empty() {
+            Value::String(String::new())
+        } else {
+            serde_yaml::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()))
+        };
+        set(document, &parse_path(path)?, value, path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_path_splits_keys_and_indexes() {
+        assert_eq!(
+            parse_path("backup.directories[0]").unwrap(),
+            vec![Segment::Key("backup".to_string()), Segment::Key("directories".to_string()), Segment::Index(0)]
+        );
+        assert_eq!(
+            parse_path("jobs[1].tags[2]").unwrap(),
+            vec![Segment::Key("jobs".to_string()), Segment::Index(1), Segment::Key("tags".to_string()), Segment::Index(2)]
+        );
+        assert_eq!(parse_path("matrix[0][1]").unwrap(), vec![Segment::Key("matrix".to_string()), Segment::Index(0), Segment::Index(1)]);
+    }
+
+    #[test]
+    fn parse_path_rejects_malformed_paths() {
+        for path in ["", "backup..time", "[0]", "jobs[x]", "jobs[0", "jobs[0]x"] {
+            assert!(parse_path(path).is_err(), "{} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn apply_sets_values_as_yaml() {
+        let mut document: Value = serde_yaml::from_str("backup:\n  directories: [/home]\n").unwrap();
+        let args: Vec<String> = ["--set", "backup.one_file_system=true", "--set", "backup.directories[1]=/srv"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        apply(&mut document, &args).unwrap();
+        assert_eq!(document["backup"]["one_file_system"], Value::Bool(true));
+        assert_eq!(document["backup"]["directories"][1], Value::String("/srv".to_string()));
+        assert!(apply(&mut document, &["--set".to_string(), "backup.directories[5]=/x".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use anyhow::Result;
+use log::warn;
+
+use crate::Config;
+
+/// Environment variables restic reads the repository password from
+const PASSWORD_VARIABLES: &[&str] = &["RESTIC_PASSWORD", "RESTIC_PASSWORD_FILE", "RESTIC_PASSWORD_COMMAND"];
+
+/// Whether restic gets the password from the config, our environment or every job's environment
+fn configured(config: &Config) -> bool {
+    let restic = &config.restic;
+    if restic.password.is_some() || restic.password_command.is_some() || restic.password_file.is_some() {
+        return true;
+    }
+    if PASSWORD_VARIABLES.iter().any(|variable| std::env::var_os(variable).is_some()) {
+        return true;
+    }
+    let jobs = config.jobs();
+    !jobs.is_empty() && jobs.iter().all(|job| PASSWORD_VARIABLES.iter().any(|variable| job.env.contains_key(*variable)))
+}
+
+/// Whether everyone on the machine can read the file; only known on Unix
+#[cfg(unix)]
+pub fn world_readable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o004 != 0)
+}
+
+#[cfg(not(unix))]
+pub fn world_readable(_path: &Path) -> bool {
+    false
+}
+
+/// Read a line without echoing it
+#[cfg(unix)]
+fn read_hidden() -> Result<String> {
+    let fd = libc::STDIN_FILENO;
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut hidden = original;
+    hidden.c_lflag &= !libc::ECHO;
+    hidden.c_lflag |= libc::ECHONL;
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) };
+    let mut line = String::new();
+    let read = std::io::stdin().lock().read_line(&mut line);
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    read?;
+    Ok(line)
+}
+
+#[cfg(not(unix))]
+fn read_hidden() -> Result<String> {
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line)
+}
+
+/// For the web UI and the agent, which can't ask: without a configured password, restic fails on
+/// each command that opens the repository, and the failures are reported like any other
+pub fn warn_if_missing(config: &Config) {
+    if !configured(config) {
+        warn!("No repository password configured; commands that open the repository will fail until restic.password_command, restic.password_file or restic.password is set");
+    }
+}
+
+/// Without a configured password, ask for it on a terminal, so restic doesn't ask for it on every
+/// command, and fail right away otherwise, where restic would wait for input under cron
+pub fn ensure(config: &mut Config) -> Result<()> {
+    if let Some(ref password_file) = config.restic.password_file {
+        let path = crate::expand_tilde(password_file);
+        if world_readable(&path) {
+            warn!("Password file {:?} can be read by everyone; restrict it with chmod 600", path);
+        }
+    }
+    if configured(config) {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!(
+            "No repository password configured; set restic.password_command, restic.password_file or restic.password, or RESTIC_PASSWORD_FILE in the environment"
+        ));
+    }
+
+    if cfg!(unix) {
+        eprint!("Password for {}: ", config.restic.repository_url());
+    } else {
+        eprint!("Password for {} (shown as you type): ", config.restic.repository_url());
+    }
+    std::io::stderr().flush()?;
+    let password = read_hidden()?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        return Err(anyhow::anyhow!("The password must not be empty"));
+    }
+    config.restic.password = Some(password.to_string());
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::path::{Path, PathBuf};
+
+use crate::BackupConfig;
+
+/// A path in the backup set the current user can't read
+#[derive(Debug, Clone)]
+pub struct UnreadablePath {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Walk a job's directories and collect everything restic would fail to read as the current user.
+/// Excluded paths and symlinks are not followed.
+pub fn unreadable_paths(job: &BackupConfig) -> Vec<UnreadablePath> {
+    let excludes = crate::ignore_files::exclude_patterns(job);
+    let mut unreadable = Vec::new();
+    let mut stack: Vec<PathBuf> = job.effective_directories().iter().map(|p| job.resolve_path(p)).collect();
+
+    while let Some(path) = stack.pop() {
+        if crate::ignore_files::is_excluded(&excludes, &path) {
+            continue;
+        }
+        let metadata = match std::fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                unreadable.push(UnreadablePath { path, error: e.to_string() });
+                continue;
+            }
+        };
+
+        if metadata.is_dir() {
+            match std::fs::read_dir(&path) {
+                Ok(entries) => {
+                    for entry in entries.flatten() {
+                        let child = entry.path();
+                        match entry.file_type() {
+                            Ok(file_type) if file_type.is_dir() => stack.push(child),
+                            Ok(file_type) if file_type.is_file() => check_file(&child, &mut unreadable),
+                            _ => {}
+                        }
+                    }
+                }
+                Err(e) => unreadable.push(UnreadablePath { path, error: e.to_string() }),
+            }
+        } else if metadata.is_file() {
+            check_file(&path, &mut unreadable);
+        }
+    }
+
+    unreadable.sort_by(|a, b| a.path.cmp(&b.path));
+    unreadable
+}
+
+fn check_file(path: &Path, unreadable: &mut Vec<UnreadablePath>) {
+    if let Err(e) = std::fs::File::open(path) {
+        unreadable.push(UnreadablePath {
+            path: path.to_path_buf(),
+            error: e.to_string(),
+        });
+    }
+}
+
+/// Whether this process runs as root
+pub fn is_root() -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(metadata) = std::fs::metadata("/proc/self") {
+            return metadata.uid() == 0;
+        }
+    }
+    std::env::var("USER").map(|user| user == "root").unwrap_or(false)
+}
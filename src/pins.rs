@@ -0,0 +1,31 @@
+use anyhow::Result;
+use log::info;
+
+use crate::ResticConfig;
+
+/// Tag marking a snapshot that retention must never remove
+pub const PIN_TAG: &str = "keep-forever";
+
+/// Snapshot ids are hex (full or short); `latest` is accepted too
+pub fn is_valid_snapshot_id(id: &str) -> bool {
+    id == "latest" || (!id.is_empty() && id.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Add or remove the pin tag on a snapshot. restic rewrites tagged snapshots, so the id changes.
+pub async fn set_pinned(restic_config: &ResticConfig, snapshot_id: &str, pinned: bool) -> Result<()> {
+    if !is_valid_snapshot_id(snapshot_id) {
+        return Err(anyhow::anyhow!("Invalid snapshot id '{}'", snapshot_id));
+    }
+
+    let mut cmd = crate::restic_command(restic_config, "tag");
+    cmd.arg(if pinned { "--add" } else { "--remove" }).arg(PIN_TAG);
+    cmd.arg(snapshot_id);
+    info!("{} snapshot {}", if pinned { "Pinning" } else { "Unpinning" }, snapshot_id);
+
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic tag failed: {}", stderr.trim()));
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::restic::{format_bytes, BackupSummary};
+use crate::Config;
+
+/// A dry run older than this says little about the next backup
+const MAX_AGE_HOURS: i64 = 24;
+/// Relative difference that counts as a discrepancy...
+const MAX_DEVIATION: f64 = 0.10;
+/// ...once it is also this large in absolute terms
+const MIN_FILES_DIFFERENCE: u64 = 50;
+const MIN_BYTES_DIFFERENCE: u64 = 100 * 1024 * 1024;
+
+/// What a dry run expected the next backup of the job to process
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Prediction {
+    pub run_id: String,
+    pub at: DateTime<Local>,
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// A backup compared to the dry run before it
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PredictionCheck {
+    pub dry_run_id: String,
+    pub predicted_at: DateTime<Local>,
+    pub predicted_files: u64,
+    pub actual_files: u64,
+    pub predicted_bytes: u64,
+    pub actual_bytes: u64,
+    /// The backup processed noticeably more or less than predicted
+    pub discrepancy: bool,
+}
+
+impl PredictionCheck {
+    pub fn describe(&self) -> String {
+        format!(
+            "The dry run at {} predicted {} files ({}); the backup processed {} files ({})",
+            self.predicted_at.format("%H:%M"),
+            self.predicted_files,
+            format_bytes(self.predicted_bytes),
+            self.actual_files,
+            format_bytes(self.actual_bytes)
+        )
+    }
+}
+
+/// Latest dry run per job, next to the logs
+pub fn path(config: &Config) -> PathBuf {
+    crate::expand_tilde(&config.logging.directory.join("dry-run-predictions.json"))
+}
+
+fn load(config: &Config) -> BTreeMap<String, Prediction> {
+    std::fs::read(path(config))
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(config: &Config, predictions: &BTreeMap<String, Prediction>) {
+    let path = path(config);
+    let written = serde_json::to_vec_pretty(predictions)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(std::fs::write(&path, content)?));
+    if let Err(e) = written {
+        warn!("Couldn't update {:?}: {}", path, e);
+    }
+}
+
+/// Keep a dry run's summary to compare the job's next backup with
+pub fn record(config: &Config, job: &str, run_id: &str, summary: &BackupSummary) {
+    let mut predictions = load(config);
+    predictions.insert(
+        job.to_string(),
+        Prediction {
+            run_id: run_id.to_string(),
+            at: Local::now(),
+            files: summary.total_files_processed,
+            bytes: summary.total_bytes_processed,
+        },
+    );
+    save(config, &predictions);
+}
+
+fn deviates(predicted: u64, actual: u64, min_difference: u64) -> bool {
+    let difference = predicted.abs_diff(actual);
+    difference >= min_difference && difference as f64 > predicted.max(1) as f64 * MAX_DEVIATION
+}
+
+/// Compare a backup with the dry run of the job before it, using up the prediction. None without a
+/// recent dry run.
+pub fn compare(config: &Config, job: &str, summary: &BackupSummary) -> Option<PredictionCheck> {
+    let mut predictions = load(config);
+    let prediction = predictions.remove(job)?;
+    save(config, &predictions);
+    if Local::now() - prediction.at > chrono::Duration::hours(MAX_AGE_HOURS) {
+        return None;
+    }
+    let (actual_files, actual_bytes) = (summary.total_files_processed, summary.total_bytes_processed);
+    Some(PredictionCheck {
+        discrepancy: deviates(prediction.files, actual_files, MIN_FILES_DIFFERENCE)
+            || deviates(prediction.bytes, actual_bytes, MIN_BYTES_DIFFERENCE),
+        dry_run_id: prediction.run_id,
+        predicted_at: prediction.at,
+        predicted_files: prediction.files,
+        actual_files,
+        predicted_bytes: prediction.bytes,
+        actual_bytes,
+    })
+}
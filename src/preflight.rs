@@ -0,0 +1,123 @@
+use anyhow::Result;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::repo_health::{self, RepoHealthConfig};
+use crate::restic::format_bytes;
+use crate::tcc::ProtectedDataAction;
+use crate::{BackupConfig, ResticConfig};
+
+/// Checks run before restic is started, so a full disk fails the run up front instead of halfway through
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct PreflightConfig {
+    /// Minimum free space on the filesystem holding the restic cache, e.g. "2GB"
+    #[serde(default)]
+    pub min_cache_free: Option<String>,
+    /// Minimum free space on the repository filesystem, e.g. "20GB" (local repositories only)
+    #[serde(default)]
+    pub min_repo_free: Option<String>,
+    /// Inspect a local repository's layout, permissions and mount before backing up
+    #[serde(default)]
+    pub repo_health: Option<RepoHealthConfig>,
+    /// macOS: what to do about protected folders (Mail, Messages, ...) restic isn't allowed to read
+    #[serde(default)]
+    pub protected_data: ProtectedDataAction,
+}
+
+/// Backends restic reaches over the network; anything else is a local path
+const REMOTE_BACKENDS: &[&str] = &["sftp:", "rest:", "s3:", "b2:", "azure:", "gs:", "swift:", "rclone:"];
+
+/// Filesystem path of a local repository (`local:/path` or a plain path), `None` for remote backends
+pub fn local_repository_path(repository: &str) -> Option<PathBuf> {
+    if let Some(path) = repository.strip_prefix("local:") {
+        return Some(crate::expand_tilde(Path::new(path)));
+    }
+    if REMOTE_BACKENDS.iter().any(|backend| repository.starts_with(backend)) {
+        return None;
+    }
+    Some(crate::expand_tilde(Path::new(repository)))
+}
+
+/// Directory restic uses for its cache, following restic's own lookup order
+pub fn cache_dir(restic_config: &ResticConfig) -> Option<PathBuf> {
+    if let Some(ref dir) = restic_config.cache_dir {
+        return Some(crate::expand_tilde(dir));
+    }
+    if let Ok(dir) = std::env::var("RESTIC_CACHE_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if let Ok(dir) = std::env::var("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(dir).join("restic"));
+    }
+    let home = PathBuf::from(std::env::var("HOME").ok()?);
+    if cfg!(target_os = "macos") {
+        Some(home.join("Library/Caches/restic"))
+    } else {
+        Some(home.join(".cache/restic"))
+    }
+}
+
+/// Free space on the filesystem holding `path`, measured at the nearest existing ancestor
+/// since the cache or repository directory may not have been created yet
+pub fn available_space(path: &Path) -> Result<(PathBuf, u64)> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .ok_or_else(|| anyhow::anyhow!("No existing parent directory for {:?}", path))?;
+    let available = fs2::available_space(existing)
+        .map_err(|e| anyhow::anyhow!("Failed to read free space of {:?}: {}", existing, e))?;
+    Ok((existing.to_path_buf(), available))
+}
+
+fn check_free_space(what: &str, key: &str, path: &Path, minimum: &str) -> Result<()> {
+    let required = crate::parse_size(minimum)
+        .map_err(|e| anyhow::anyhow!("Invalid preflight.{} '{}': {}", key, minimum, e))?;
+    let (measured, available) = available_space(path)?;
+    debug!("{} {:?}: {} free (measured at {:?})", what, path, format_bytes(available), measured);
+
+    if available < required {
+        return Err(anyhow::anyhow!(
+            "Not enough free space for the {} at {:?}: {} available, {} required (preflight.{})",
+            what,
+            path,
+            format_bytes(available),
+            format_bytes(required),
+            key
+        ));
+    }
+    Ok(())
+}
+
+/// Run all configured preflight checks, failing on the first one that doesn't pass.
+/// A job's own `cache_dir` is checked instead of the repository-wide one.
+pub fn check(config: &PreflightConfig, restic_config: &ResticConfig, job: Option<&BackupConfig>) -> Result<()> {
+    if let Some(ref minimum) = config.min_cache_free {
+        let job_cache = job.and_then(|job| job.cache_dir.as_ref().map(|dir| job.resolve_path(dir)));
+        if let Some(dir) = job_cache.or_else(|| cache_dir(restic_config)) {
+            check_free_space("restic cache", "min_cache_free", &dir, minimum)?;
+        }
+    }
+
+    if let Some(ref minimum) = config.min_repo_free {
+        match local_repository_path(&restic_config.repository_url()) {
+            Some(path) => check_free_space("repository", "min_repo_free", &path, minimum)?,
+            None => debug!("Skipping repository space check for remote repository {}", restic_config.repository_url()),
+        }
+    }
+
+    if let Some(ref health_config) = config.repo_health {
+        match local_repository_path(&restic_config.repository_url()) {
+            Some(path) => {
+                let report = repo_health::inspect(&path, health_config);
+                if !report.is_healthy() {
+                    return Err(anyhow::anyhow!("Repository health check failed: {}", report.failures()));
+                }
+            }
+            None => debug!("Skipping repository health check for remote repository {}", restic_config.repository_url()),
+        }
+    }
+
+    info!("Preflight checks passed");
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::reports::{self, RunReport};
+use crate::{BackupConfig, Config};
+
+/// Runs per job looked at without `--runs`
+const DEFAULT_RUNS: usize = 30;
+/// Failures needed to count as chronic without `--min-runs`
+const DEFAULT_MIN_RUNS: usize = 3;
+/// Chronic paths sharing a directory before the directory is suggested instead
+const PATHS_PER_DIRECTORY: usize = 3;
+
+/// A path restic failed to read in several runs of a job
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub path: String,
+    /// "permission denied", "vanished" or "other"
+    pub kind: String,
+    /// Runs it failed in, out of the job's runs looked at
+    pub runs: usize,
+    pub last_seen: DateTime<Local>,
+    pub last_message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JobProblems {
+    pub job: String,
+    pub runs_checked: usize,
+    pub problems: Vec<Problem>,
+    /// Entries for the job's `exclude` list that would silence them
+    pub suggested_excludes: Vec<String>,
+}
+
+/// Split a report warning into the path and restic's error, skipping warnings that aren't about a file
+fn parse_warning(warning: &str) -> Option<(&str, &str)> {
+    let (path, message) = warning.split_once(": ")?;
+    (path.contains('/') || path.contains('\\')).then_some((path, message))
+}
+
+fn classify(message: &str) -> &'static str {
+    let message = message.to_lowercase();
+    if message.contains("permission denied") || message.contains("access is denied") {
+        "permission denied"
+    } else if message.contains("no such file") || message.contains("cannot find the") {
+        "vanished"
+    } else {
+        "other"
+    }
+}
+
+/// Excludes for the chronic paths; a directory with many of them is excluded as a whole
+fn suggest_excludes(problems: &[Problem]) -> Vec<String> {
+    let mut by_directory: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    for problem in problems {
+        let directory = Path::new(&problem.path).parent().map(|parent| parent.to_string_lossy().to_string()).unwrap_or_default();
+        by_directory.entry(directory).or_default().push(&problem.path);
+    }
+    let mut excludes = Vec::new();
+    for (directory, paths) in by_directory {
+        if paths.len() >= PATHS_PER_DIRECTORY && !directory.is_empty() && directory != "/" {
+            excludes.push(directory);
+        } else {
+            excludes.extend(paths.into_iter().map(String::from));
+        }
+    }
+    excludes
+}
+
+/// Paths that failed in at least `min_runs` of a job's last `runs` non-dry runs
+pub fn job_problems(all_reports: &[RunReport], job: &str, runs: usize, min_runs: usize) -> JobProblems {
+    let job_reports: Vec<&RunReport> = all_reports.iter().filter(|report| report.job == job && !report.dry_run).take(runs).collect();
+
+    // Reports are newest first, so the first message seen for a path is its latest
+    let mut seen: HashMap<&str, Problem> = HashMap::new();
+    for report in &job_reports {
+        let mut in_this_run = Vec::new();
+        for (path, message) in report.warnings.iter().filter_map(|warning| parse_warning(warning)) {
+            if in_this_run.contains(&path) {
+                continue;
+            }
+            in_this_run.push(path);
+            seen.entry(path)
+                .or_insert_with(|| Problem {
+                    path: path.to_string(),
+                    kind: classify(message).to_string(),
+                    runs: 0,
+                    last_seen: report.started_at,
+                    last_message: message.to_string(),
+                })
+                .runs += 1;
+        }
+    }
+
+    let mut problems: Vec<Problem> = seen.into_values().filter(|problem| problem.runs >= min_runs).collect();
+    problems.sort_by(|a, b| b.runs.cmp(&a.runs).then_with(|| a.path.cmp(&b.path)));
+    let suggested_excludes = suggest_excludes(&problems);
+    JobProblems {
+        job: job.to_string(),
+        runs_checked: job_reports.len(),
+        problems,
+        suggested_excludes,
+    }
+}
+
+pub fn render_text(jobs: &[JobProblems], min_runs: usize) -> String {
+    let mut out = String::new();
+    for job in jobs {
+        if job.problems.is_empty() {
+            out.push_str(&format!("{}: no path failed in {} or more of the last {} runs\n\n", job.job, min_runs, job.runs_checked));
+            continue;
+        }
+        out.push_str(&format!(
+            "{}: {} paths failed in {} or more of the last {} runs\n",
+            job.job,
+            job.problems.len(),
+            min_runs,
+            job.runs_checked
+        ));
+        for problem in &job.problems {
+            out.push_str(&format!(
+                "  {:>3}/{:<3} {:<18} {}  (last {})\n",
+                problem.runs,
+                job.runs_checked,
+                problem.kind,
+                problem.path,
+                problem.last_seen.format("%Y-%m-%d")
+            ));
+        }
+        out.push_str("  Suggested excludes:\n    exclude:\n");
+        for exclude in &job.suggested_excludes {
+            out.push_str(&format!("      - {}\n", exclude));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn count_flag(args: &[String], flag: &str, default: usize) -> Result<usize> {
+    match crate::flag_value(args, flag) {
+        Some(value) => value.parse().map_err(|_| anyhow::anyhow!("{} needs a number, got '{}'", flag, value)),
+        None => Ok(default),
+    }
+}
+
+/// `report problems [--job NAME] [--runs N] [--min-runs N] [--format text|json]`
+pub async fn run(config: &Config, jobs: &[&BackupConfig], args: &[String]) -> Result<()> {
+    let runs = count_flag(args, "--runs", DEFAULT_RUNS)?;
+    let min_runs = count_flag(args, "--min-runs", DEFAULT_MIN_RUNS)?.max(1);
+    let reports_config = config
+        .reports
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("The problems report reads run reports; configure 'reports.directory'"))?;
+    let all_reports = reports::list(&crate::expand_tilde(&reports_config.directory));
+    let problems: Vec<JobProblems> = jobs.iter().map(|job| job_problems(&all_reports, &job.name, runs, min_runs)).collect();
+
+    match crate::flag_value(args, "--format").map(String::as_str).unwrap_or("text") {
+        "text" => print!("{}", render_text(&problems, min_runs)),
+        "json" => println!("{}", serde_json::to_string_pretty(&problems)?),
+        other => return Err(anyhow::anyhow!("Unknown format '{}'; use text or json", other)),
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Job presets with curated directories and excludes
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// The whole machine: `/` on its own filesystem, without virtual filesystems, temp files and caches
+    System,
+    /// The user's home directory without caches, trash and rebuildable build output, per platform
+    Home,
+}
+
+/// Pseudo filesystems, temp files and caches that never belong in a full-system backup
+const SYSTEM_EXCLUDES: &[&str] = &[
+    "/proc",
+    "/sys",
+    "/dev",
+    "/run",
+    "/tmp",
+    "/var/tmp",
+    "/var/run",
+    "/var/lock",
+    "/var/cache",
+    "/var/lib/apt/lists",
+    "/var/lib/dnf",
+    "/var/lib/pacman/sync",
+    "/mnt",
+    "/media",
+    "/lost+found",
+    "/swapfile",
+    "/swap.img",
+    "/root/.cache",
+    "/home/*/.cache",
+    "/home/*/.local/share/Trash",
+];
+
+/// Excluded everywhere in a home directory (patterns without a slash match any path component)
+const HOME_EXCLUDES: &[&str] = &["node_modules", "__pycache__", ".DS_Store"];
+
+/// Relative to the home directory on Linux and other Unixes
+const LINUX_HOME_EXCLUDES: &[&str] = &[
+    ".cache",
+    ".local/share/Trash",
+    ".thumbnails",
+    ".npm/_cacache",
+    ".cargo/registry",
+    ".gradle/caches",
+    ".m2/repository",
+    ".config/*/Cache",
+    ".config/*/Code Cache",
+    ".config/*/GPUCache",
+    ".mozilla/firefox/*/cache2",
+    ".var/app/*/cache",
+];
+
+/// Relative to the home directory on macOS
+const MACOS_HOME_EXCLUDES: &[&str] = &[
+    "Library/Caches",
+    "Library/Logs",
+    ".Trash",
+    "Library/Containers/*/Data/Library/Caches",
+    "Library/Application Support/*/Cache",
+    "Library/Application Support/*/Code Cache",
+    "Library/Application Support/Google/Chrome/*/Cache",
+    "Library/Developer/Xcode/DerivedData",
+    "Library/Developer/CoreSimulator/Caches",
+    ".npm/_cacache",
+    ".cargo/registry",
+    ".gradle/caches",
+    ".m2/repository",
+];
+
+impl Profile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::System => "system",
+            Profile::Home => "home",
+        }
+    }
+
+    /// Directories backed up when the job lists none
+    pub fn default_directories(&self) -> Vec<PathBuf> {
+        match self {
+            Profile::System => vec![PathBuf::from("/")],
+            Profile::Home => vec![crate::expand_tilde(Path::new("~"))],
+        }
+    }
+
+    /// Excludes for this profile; home-relative ones are made absolute since restic doesn't expand `~`
+    pub fn default_excludes(&self) -> Vec<PathBuf> {
+        match self {
+            Profile::System => SYSTEM_EXCLUDES.iter().map(PathBuf::from).collect(),
+            Profile::Home => {
+                let home = crate::expand_tilde(Path::new("~"));
+                let platform = if cfg!(target_os = "macos") { MACOS_HOME_EXCLUDES } else { LINUX_HOME_EXCLUDES };
+                platform
+                    .iter()
+                    .map(|relative| home.join(relative))
+                    .chain(HOME_EXCLUDES.iter().map(PathBuf::from))
+                    .collect()
+            }
+        }
+    }
+
+    /// Whether restic should stay on the filesystems of the backed up directories
+    pub fn one_file_system(&self) -> bool {
+        match self {
+            Profile::System => true,
+            Profile::Home => false,
+        }
+    }
+}
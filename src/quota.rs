@@ -0,0 +1,82 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use serde::Deserialize;
+
+use crate::restic::format_bytes;
+use crate::ResticConfig;
+
+/// The part of `restic stats --mode raw-data --json` we need
+#[derive(Debug, Deserialize)]
+struct RawDataStats {
+    total_size: u64,
+}
+
+/// Size of the repository's data as stored, after deduplication and compression
+pub async fn repository_size(restic_config: &ResticConfig) -> Result<u64> {
+    let mut cmd = crate::restic_command(restic_config, "stats");
+    cmd.args(["--mode", "raw-data", "--json", "--no-lock"]);
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic stats failed: {}", stderr.trim()));
+    }
+    let stats: RawDataStats = serde_json::from_slice(&output.stdout)?;
+    Ok(stats.total_size)
+}
+
+fn threshold(value: &Option<String>, key: &str) -> Result<Option<u64>> {
+    value
+        .as_deref()
+        .map(|size| crate::parse_size(size).map_err(|e| anyhow::anyhow!("Invalid restic.{} '{}': {}", key, size, e)))
+        .transpose()
+}
+
+/// What to tell the user about a repository of `size` bytes, if anything
+pub fn assess(restic_config: &ResticConfig, size: u64) -> Result<Option<String>> {
+    let limit = threshold(&restic_config.size_limit, "size_limit")?;
+    let warning = threshold(&restic_config.size_warning, "size_warning")?;
+    let message = match (warning, limit) {
+        (_, Some(limit)) if size >= limit => {
+            format!("Repository is {}, over its size limit of {}", format_bytes(size), format_bytes(limit))
+        }
+        (Some(warning), _) if size < warning => return Ok(None),
+        (Some(_), Some(limit)) => format!(
+            "Repository is {}, {:.0}% of its size limit of {}",
+            format_bytes(size),
+            size as f64 / limit as f64 * 100.0,
+            format_bytes(limit)
+        ),
+        (Some(warning), None) => {
+            format!("Repository is {}, above the warning size of {}", format_bytes(size), format_bytes(warning))
+        }
+        (None, _) => return Ok(None),
+    };
+    Ok(Some(message))
+}
+
+/// Measure the repository after a backup and compare it against `size_warning` and `size_limit`.
+/// Returns the size and a warning; a failed measurement is only logged, the backup itself went fine.
+pub async fn check(restic_config: &ResticConfig) -> (Option<u64>, Option<String>) {
+    let size = match repository_size(restic_config).await {
+        Ok(size) => size,
+        Err(e) => {
+            warn!("Couldn't measure the repository size: {}", e);
+            return (None, None);
+        }
+    };
+    debug!("Repository size: {}", format_bytes(size));
+    match assess(restic_config, size) {
+        Ok(Some(message)) => {
+            warn!("{}", message);
+            (Some(size), Some(message))
+        }
+        Ok(None) => {
+            info!("Repository size: {}", format_bytes(size));
+            (Some(size), None)
+        }
+        Err(e) => {
+            warn!("{}", e);
+            (Some(size), None)
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::{shell_quote, ResticConfig};
+
+/// What restic passes to rclone when nothing is configured
+const DEFAULT_ARGS: &str = "serve restic --stdio --b2-hard-delete";
+
+fn default_true() -> bool {
+    true
+}
+
+/// How restic starts rclone for an `rclone:remote:path` repository
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RcloneConfig {
+    /// rclone binary, if it isn't `rclone` in PATH
+    #[serde(default)]
+    pub program: Option<PathBuf>,
+    /// rclone.conf to use instead of rclone's default
+    #[serde(default)]
+    pub config_file: Option<PathBuf>,
+    /// Bandwidth limit in rclone's `--bwlimit` syntax, e.g. "10M" or "08:00,2M 19:00,off"
+    #[serde(default)]
+    pub bwlimit: Option<String>,
+    /// Parallel uploads (`--transfers`)
+    #[serde(default)]
+    pub transfers: Option<u32>,
+    /// More rclone flags, e.g. ["--s3-upload-concurrency", "8"]
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Make sure the remote exists (`rclone listremotes`) before each backup
+    #[serde(default = "default_true")]
+    pub check_remote: bool,
+}
+
+/// The remote of an `rclone:remote:path` repository
+pub fn remote(repository: &str) -> Option<&str> {
+    let rest = repository.strip_prefix("rclone:")?;
+    let (remote, _) = rest.split_once(':')?;
+    Some(remote).filter(|remote| !remote.is_empty())
+}
+
+pub fn program(rclone: &RcloneConfig) -> PathBuf {
+    rclone.program.as_ref().map(|program| crate::expand_tilde(program)).unwrap_or_else(|| PathBuf::from("rclone"))
+}
+
+/// restic's `rclone.args` for the configured flags
+pub fn rclone_args(rclone: &RcloneConfig) -> String {
+    let mut args = vec![DEFAULT_ARGS.to_string()];
+    if let Some(ref config_file) = rclone.config_file {
+        args.push(format!("--config {}", shell_quote(&crate::expand_tilde(config_file).to_string_lossy())));
+    }
+    if let Some(ref bwlimit) = rclone.bwlimit {
+        args.push(format!("--bwlimit {}", shell_quote(bwlimit)));
+    }
+    if let Some(transfers) = rclone.transfers {
+        args.push(format!("--transfers {}", transfers));
+    }
+    args.extend(rclone.args.iter().map(|arg| shell_quote(arg)));
+    args.join(" ")
+}
+
+/// restic `-o` options for `restic.rclone`, when the repository is an rclone: one
+pub fn options(restic_config: &ResticConfig) -> Vec<String> {
+    let Some(ref rclone) = restic_config.rclone else { return Vec::new() };
+    if remote(&restic_config.repository_url()).is_none() {
+        return Vec::new();
+    }
+    let mut options = Vec::new();
+    if rclone.program.is_some() {
+        options.push(format!("rclone.program={}", program(rclone).to_string_lossy()));
+    }
+    options.push(format!("rclone.args={}", rclone_args(rclone)));
+    options
+}
+
+/// Pass `restic.rclone` to restic for an rclone: repository
+pub fn apply(cmd: &mut Command, restic_config: &ResticConfig) {
+    for option in options(restic_config) {
+        cmd.arg("-o").arg(option);
+    }
+}
+
+/// Make sure rclone runs and knows the repository's remote, before restic fails with a vaguer error
+pub async fn check_remote(restic_config: &ResticConfig) -> Result<()> {
+    let Some(ref rclone) = restic_config.rclone else { return Ok(()) };
+    let repository = restic_config.repository_url();
+    let Some(remote) = remote(&repository) else { return Ok(()) };
+    if !rclone.check_remote {
+        return Ok(());
+    }
+
+    let program = program(rclone);
+    let mut cmd = Command::new(&program);
+    cmd.arg("listremotes");
+    if let Some(ref config_file) = rclone.config_file {
+        cmd.arg("--config").arg(crate::expand_tilde(config_file));
+    }
+    debug!("Checking the rclone remote: {:?}", cmd);
+    let output = cmd
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("rclone: can't run {:?} ({}); install rclone or set restic.rclone.program", program, e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("rclone: listremotes failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let remotes: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.trim().trim_end_matches(':').to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    if !remotes.iter().any(|known| known == remote) {
+        return Err(anyhow::anyhow!(
+            "rclone: there is no remote '{}'; configured remotes: {}. Add it with `rclone config`.",
+            remote,
+            if remotes.is_empty() { "none".to_string() } else { remotes.join(", ") }
+        ));
+    }
+    Ok(())
+}
+
+/// What went wrong on rclone's side of a failed restic command, if anything
+pub fn explain(repository: &str, stderr: &str) -> Option<String> {
+    let remote = remote(repository)?;
+    let lower = stderr.to_lowercase();
+    let hint = if lower.contains("executable file not found") || (lower.contains("no such file or directory") && lower.contains("rclone")) {
+        "rclone isn't installed or not in PATH; install it or set restic.rclone.program".to_string()
+    } else if lower.contains("didn't find section in config file") {
+        format!("rclone has no remote '{}'; check `rclone listremotes` and restic.rclone.config_file", remote)
+    } else if ["accessdenied", "invalidaccesskeyid", "signaturedoesnotmatch", "401 unauthorized", "403 forbidden"]
+        .iter()
+        .any(|marker| lower.contains(marker))
+    {
+        format!("the storage behind rclone remote '{}' refused its credentials; try `rclone lsd {}:`", remote, remote)
+    } else if lower.contains("nosuchbucket") || lower.contains("bucket does not exist") || lower.contains("directory not found") {
+        format!("the bucket or path of rclone remote '{}' doesn't exist", remote)
+    } else if let Some(line) = stderr.lines().rev().find(|line| line.trim_start().starts_with("rclone:")) {
+        line.trim().to_string()
+    } else if lower.contains("error talking http to rclone") || lower.contains("rclone stdio connection closed") {
+        "rclone stopped unexpectedly; run restic with --verbose to see rclone's own output".to_string()
+    } else {
+        return None;
+    };
+    Some(if hint.starts_with("rclone:") { hint } else { format!("rclone: {}", hint) })
+}
@@ -0,0 +1,223 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Extra expectations for a local repository, checked before each backup
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct RepoHealthConfig {
+    /// UUID of the filesystem the repository must live on (see `lsblk -f` or `blkid`),
+    /// so a backup never lands on the wrong drive
+    #[serde(default)]
+    pub filesystem_uuid: Option<String>,
+}
+
+/// Directories restic creates in every repository (`locks` may be missing until the first lock)
+const REPO_DIRECTORIES: &[&str] = &["data", "index", "keys", "snapshots"];
+
+/// Parent directories removable drives and network shares are usually mounted under
+const MOUNT_ROOTS: &[&str] = &["/mnt", "/media", "/run/media", "/Volumes"];
+
+#[derive(Debug, Serialize, Clone)]
+pub struct HealthCheck {
+    pub check: String,
+    pub passed: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub path: PathBuf,
+    pub mount_point: Option<PathBuf>,
+    pub device: Option<String>,
+    pub filesystem: Option<String>,
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    fn push(&mut self, check: &str, passed: bool, message: impl Into<String>) {
+        self.checks.push(HealthCheck {
+            check: check.to_string(),
+            passed,
+            message: message.into(),
+        });
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+
+    /// Messages of all failed checks, joined for an error
+    pub fn failures(&self) -> String {
+        self.checks
+            .iter()
+            .filter(|check| !check.passed)
+            .map(|check| check.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// A mounted filesystem, as listed in /proc/self/mountinfo
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub mount_point: PathBuf,
+    pub device: String,
+    pub filesystem: String,
+}
+
+/// All mounted filesystems (Linux only; empty elsewhere)
+pub fn mounts() -> Vec<Mount> {
+    let Ok(content) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return Vec::new();
+    };
+
+    content
+        .lines()
+        .filter_map(|line| {
+            // <id> <parent> <major:minor> <root> <mount point> <options> [optional fields] - <fs type> <source> <super options>
+            let (before, after) = line.split_once(" - ")?;
+            let mount_point = before.split(' ').nth(4)?;
+            let mut after = after.split(' ');
+            let filesystem = after.next()?;
+            let device = after.next()?;
+            Some(Mount {
+                mount_point: PathBuf::from(unescape_mountinfo(mount_point)),
+                device: unescape_mountinfo(device),
+                filesystem: filesystem.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// mountinfo escapes spaces, tabs, newlines and backslashes as octal (`\040`)
+fn unescape_mountinfo(field: &str) -> String {
+    let mut out = String::new();
+    let mut chars = field.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code: String = chars.clone().take(3).collect();
+            if let Ok(byte) = u8::from_str_radix(&code, 8) {
+                out.push(byte as char);
+                chars.nth(2);
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// The filesystem `path` lives on: the mount with the longest mount point containing it
+pub fn mount_for(path: &Path) -> Option<Mount> {
+    mounts()
+        .into_iter()
+        .filter(|mount| path.starts_with(&mount.mount_point))
+        .max_by_key(|mount| mount.mount_point.components().count())
+}
+
+/// Filesystem UUID of a block device, looked up through /dev/disk/by-uuid
+pub fn filesystem_uuid(device: &str) -> Option<String> {
+    let device = std::fs::canonicalize(device).ok()?;
+    std::fs::read_dir("/dev/disk/by-uuid")
+        .ok()?
+        .flatten()
+        .find(|entry| std::fs::canonicalize(entry.path()).ok().as_ref() == Some(&device))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+}
+
+/// Inspect a local repository directory: structure, write access, the filesystem it is on
+/// and, if configured, whether that filesystem is the expected one
+pub fn inspect(path: &Path, config: &RepoHealthConfig) -> HealthReport {
+    let mut report = HealthReport {
+        path: path.to_path_buf(),
+        mount_point: None,
+        device: None,
+        filesystem: None,
+        checks: Vec::new(),
+    };
+
+    if !path.is_dir() {
+        let hint = if MOUNT_ROOTS.iter().any(|root| path.starts_with(root)) {
+            " (is the drive mounted?)"
+        } else {
+            ""
+        };
+        report.push("exists", false, format!("Repository directory {:?} does not exist{}", path, hint));
+        return report;
+    }
+    report.push("exists", true, format!("Repository directory {:?} exists", path));
+
+    // Mount checks: an unmounted drive leaves an empty directory on the root filesystem
+    let canonical = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if let Some(mount) = mount_for(&canonical) {
+        report.mount_point = Some(mount.mount_point.clone());
+        report.device = Some(mount.device.clone());
+        report.filesystem = Some(mount.filesystem.clone());
+
+        let under_mount_root = MOUNT_ROOTS.iter().any(|root| canonical.starts_with(root));
+        if under_mount_root && mount.mount_point == Path::new("/") {
+            report.push(
+                "mounted",
+                false,
+                format!("{:?} is on the root filesystem; the drive it belongs on is probably not mounted", path),
+            );
+        } else {
+            report.push(
+                "mounted",
+                true,
+                format!("On {} ({}) mounted at {:?}", mount.device, mount.filesystem, mount.mount_point),
+            );
+        }
+
+        if let Some(ref expected) = config.filesystem_uuid {
+            match filesystem_uuid(&mount.device) {
+                Some(ref uuid) if uuid.eq_ignore_ascii_case(expected) => {
+                    report.push("filesystem_uuid", true, format!("Filesystem UUID {} matches", uuid));
+                }
+                Some(uuid) => report.push(
+                    "filesystem_uuid",
+                    false,
+                    format!("Filesystem UUID is {}, expected {}; refusing to back up to the wrong drive", uuid, expected),
+                ),
+                None => report.push(
+                    "filesystem_uuid",
+                    false,
+                    format!("Could not determine the UUID of {}, expected {}", mount.device, expected),
+                ),
+            }
+        }
+    } else if config.filesystem_uuid.is_some() {
+        report.push("filesystem_uuid", false, "Mount information is not available on this platform");
+    }
+
+    // Structure: an empty directory here is the classic sign of a missing mount
+    let missing: Vec<&str> = std::iter::once("config")
+        .filter(|name| !path.join(name).is_file())
+        .chain(REPO_DIRECTORIES.iter().copied().filter(|name| !path.join(name).is_dir()))
+        .collect();
+    if missing.is_empty() {
+        report.push("structure", true, "Repository layout is complete");
+    } else {
+        report.push(
+            "structure",
+            false,
+            format!("Not a restic repository, missing: {} (run `restic init` or mount the drive)", missing.join(", ")),
+        );
+    }
+
+    // Permissions: restic needs to write new packs, indexes, snapshots and locks
+    let probe = path.join(format!(".better-restic-write-test-{}", std::process::id()));
+    match std::fs::write(&probe, b"").and_then(|_| std::fs::remove_file(&probe)) {
+        Ok(()) => report.push("writable", true, "Repository is writable"),
+        Err(e) => report.push("writable", false, format!("Repository is not writable: {}", e)),
+    }
+
+    report
+}
+
+/// Inspect the configured repository, failing for remote repositories which have no local disk to check
+pub fn inspect_repository(repository: &str, config: &RepoHealthConfig) -> Result<HealthReport> {
+    let path = crate::preflight::local_repository_path(repository)
+        .ok_or_else(|| anyhow::anyhow!("Repository {} is not local; repo-health only checks local repositories", repository))?;
+    Ok(inspect(&path, config))
+}
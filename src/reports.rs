@@ -0,0 +1,156 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::access_rights::AccessRightsSummary;
+use crate::prediction::PredictionCheck;
+use crate::restic::BackupSummary;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ReportsConfig {
+    /// Directory for `summary-<runid>.json` files
+    pub directory: PathBuf,
+}
+
+/// How long one phase of a run took
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub duration_secs: f64,
+}
+
+/// Machine-readable record of a single run, written after the run finishes
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RunReport {
+    pub run_id: String,
+    pub job: String,
+    pub hostname: String,
+    pub dry_run: bool,
+    /// "succeeded", "warning" or "failed"
+    pub status: String,
+    pub started_at: DateTime<Local>,
+    pub finished_at: DateTime<Local>,
+    pub duration_secs: f64,
+    pub snapshot_id: Option<String>,
+    /// Free-text note given with `--note` or the API, also stored on the snapshot as a `note:` tag
+    #[serde(default)]
+    pub note: Option<String>,
+    pub summary: Option<BackupSummary>,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+    pub phases: Vec<PhaseTiming>,
+    /// Stored size of the repository after the run, measured when a size threshold is configured
+    #[serde(default)]
+    pub repository_size: Option<u64>,
+    /// Extended attributes and ACLs found in the job's files, for jobs with `access_rights`
+    #[serde(default)]
+    pub access_rights: Option<AccessRightsSummary>,
+    /// The backup compared to the dry run before it
+    #[serde(default)]
+    pub prediction: Option<PredictionCheck>,
+}
+
+impl RunReport {
+    pub fn file_name(&self) -> String {
+        format!("summary-{}.json", self.run_id)
+    }
+}
+
+/// Records phase durations while a run is in progress
+pub struct PhaseTimer {
+    phases: Vec<PhaseTiming>,
+    current: Option<(String, std::time::Instant)>,
+}
+
+impl PhaseTimer {
+    pub fn new() -> Self {
+        PhaseTimer { phases: Vec::new(), current: None }
+    }
+
+    /// Start a phase, ending the previous one
+    pub fn start(&mut self, phase: &str) {
+        self.finish();
+        self.current = Some((phase.to_string(), std::time::Instant::now()));
+    }
+
+    /// End the current phase, if any, and return all recorded phases
+    pub fn finish(&mut self) -> Vec<PhaseTiming> {
+        if let Some((phase, started)) = self.current.take() {
+            self.phases.push(PhaseTiming {
+                phase,
+                duration_secs: started.elapsed().as_secs_f64(),
+            });
+        }
+        self.phases.clone()
+    }
+}
+
+impl Default for PhaseTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write a run report into the reports directory
+pub fn write(directory: &Path, report: &RunReport) -> Result<PathBuf> {
+    std::fs::create_dir_all(directory)
+        .map_err(|e| anyhow::anyhow!("Failed to create reports directory {:?}: {}", directory, e))?;
+    let path = directory.join(report.file_name());
+    std::fs::write(&path, serde_json::to_string_pretty(report)?)
+        .map_err(|e| anyhow::anyhow!("Failed to write run report {:?}: {}", path, e))?;
+    Ok(path)
+}
+
+/// Write a run report, logging instead of failing the run
+pub fn save(config: &ReportsConfig, report: &RunReport) {
+    let directory = crate::expand_tilde(&config.directory);
+    match write(&directory, report) {
+        Ok(path) => info!("Run report written to {:?}", path),
+        Err(e) => error!("{}", e),
+    }
+}
+
+/// All reports in the directory, newest first
+pub fn list(directory: &Path) -> Vec<RunReport> {
+    let mut reports = Vec::new();
+
+    if let Ok(entries) = std::fs::read_dir(directory) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !(name.starts_with("summary-") && name.ends_with(".json")) {
+                continue;
+            }
+            match std::fs::read_to_string(entry.path())
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<RunReport>(&content)?))
+            {
+                Ok(report) => reports.push(report),
+                Err(e) => debug!("Skipping unreadable report {}: {}", name, e),
+            }
+        }
+    }
+
+    reports.sort_by_key(|report| std::cmp::Reverse(report.finished_at));
+    reports
+}
+
+/// Snapshots a job created, newest first. Snapshots don't record their job, but the run reports do.
+pub fn snapshot_ids<'a>(reports: &'a [RunReport], job: &str) -> Vec<&'a str> {
+    reports
+        .iter()
+        .filter(|report| report.job == job && !report.dry_run)
+        .filter_map(|report| report.snapshot_id.as_deref())
+        .collect()
+}
+
+/// Read a single report by run id
+pub fn read(directory: &Path, run_id: &str) -> Result<RunReport> {
+    if run_id.is_empty() || !run_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(anyhow::anyhow!("Invalid run id: {}", run_id));
+    }
+    let path = directory.join(format!("summary-{}.json", run_id));
+    let content = std::fs::read_to_string(&path)?;
+    Ok(serde_json::from_str(&content)?)
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::restic::format_bytes;
+
+/// How often the restic process is sampled while it runs
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// CPU, memory and disk IO of restic (and the processes it started, like rclone or a sandbox wrapper's
+/// children) over a run, sampled from /proc on Linux
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ResourceUsage {
+    /// Highest resident memory seen, summed over the processes
+    pub peak_rss_bytes: u64,
+    /// Highest CPU use between two samples, where 100 is one core
+    pub peak_cpu_percent: f64,
+    /// User and system CPU time
+    pub cpu_seconds: f64,
+    /// Bytes read from and written to storage, not counting the page cache
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    /// Page faults that had to read from disk; many of them mean restic is short of memory
+    pub major_faults: u64,
+    pub samples: u64,
+}
+
+impl ResourceUsage {
+    /// Combine another restic run's usage, e.g. for a job made of several restic runs one after another
+    pub fn merge(&mut self, other: &ResourceUsage) {
+        self.peak_rss_bytes = self.peak_rss_bytes.max(other.peak_rss_bytes);
+        self.peak_cpu_percent = self.peak_cpu_percent.max(other.peak_cpu_percent);
+        self.cpu_seconds += other.cpu_seconds;
+        self.read_bytes += other.read_bytes;
+        self.write_bytes += other.write_bytes;
+        self.major_faults += other.major_faults;
+        self.samples += other.samples;
+    }
+
+    /// One-line human readable description
+    pub fn describe(&self) -> String {
+        format!(
+            "restic peaked at {} memory and {:.0}% CPU; {:.1}s CPU time, {} read, {} written, {} major page faults",
+            format_bytes(self.peak_rss_bytes),
+            self.peak_cpu_percent,
+            self.cpu_seconds,
+            format_bytes(self.read_bytes),
+            format_bytes(self.write_bytes),
+            self.major_faults,
+        )
+    }
+}
+
+/// Counters of one process, as last read
+#[derive(Debug, Clone, Copy, Default)]
+struct ProcessSample {
+    cpu_ticks: u64,
+    major_faults: u64,
+    rss_bytes: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+#[cfg(target_os = "linux")]
+mod proc {
+    use super::ProcessSample;
+
+    /// Parent pid, fault and CPU counters from /proc/<pid>/stat; the fields after the command name,
+    /// which may contain spaces, start with the state. CPU time includes the children the process
+    /// waited for, so the time of processes that came and went between samples still counts.
+    fn stat(pid: u32) -> Option<(u32, ProcessSample)> {
+        let content = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        let fields: Vec<&str> = content[content.rfind(')')? + 2..].split_whitespace().collect();
+        let number = |i: usize| fields.get(i).and_then(|field| field.parse::<u64>().ok());
+        let sample = ProcessSample {
+            major_faults: number(9)?,
+            cpu_ticks: number(11)? + number(12)? + number(13)? + number(14)?,
+            ..ProcessSample::default()
+        };
+        Some((number(1)? as u32, sample))
+    }
+
+    fn rss_bytes(pid: u32) -> u64 {
+        let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).unwrap_or_default();
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            .map(|kib| kib * 1024)
+            .unwrap_or(0)
+    }
+
+    /// Storage IO; unreadable for processes that changed user, like a sandbox wrapper's children may
+    fn io(pid: u32) -> (u64, u64) {
+        let io = std::fs::read_to_string(format!("/proc/{}/io", pid)).unwrap_or_default();
+        let counter = |name: &str| {
+            io.lines()
+                .find_map(|line| line.strip_prefix(name))
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        (counter("read_bytes:"), counter("write_bytes:"))
+    }
+
+    /// The process and its descendants
+    pub fn sample(root: u32) -> Vec<(u32, ProcessSample)> {
+        let mut parents = Vec::new();
+        if let Ok(entries) = std::fs::read_dir("/proc") {
+            for entry in entries.flatten() {
+                let Some(pid) = entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) else { continue };
+                if let Some((parent, sample)) = stat(pid) {
+                    parents.push((pid, parent, sample));
+                }
+            }
+        }
+
+        let mut tree = vec![root];
+        let mut i = 0;
+        while i < tree.len() {
+            let parent = tree[i];
+            tree.extend(parents.iter().filter(|(_, ppid, _)| *ppid == parent).map(|(pid, _, _)| *pid));
+            i += 1;
+        }
+        parents
+            .into_iter()
+            .filter(|(pid, _, _)| tree.contains(pid))
+            .map(|(pid, _, mut sample)| {
+                sample.rss_bytes = rss_bytes(pid);
+                (sample.read_bytes, sample.write_bytes) = io(pid);
+                (pid, sample)
+            })
+            .collect()
+    }
+
+    pub fn ticks_per_second() -> f64 {
+        // SAFETY: sysconf has no preconditions
+        match unsafe { libc::sysconf(libc::_SC_CLK_TCK) } {
+            ticks if ticks > 0 => ticks as f64,
+            _ => 100.0,
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod proc {
+    use super::ProcessSample;
+
+    pub fn sample(_root: u32) -> Vec<(u32, ProcessSample)> {
+        Vec::new()
+    }
+
+    pub fn ticks_per_second() -> f64 {
+        100.0
+    }
+}
+
+/// Totals over every process seen so far; the IO and fault counters of processes that exited stay at their last values
+#[derive(Default)]
+struct Tracker {
+    processes: HashMap<u32, ProcessSample>,
+    usage: ResourceUsage,
+    last: Option<(Instant, u64)>,
+}
+
+impl Tracker {
+    fn record(&mut self, samples: Vec<(u32, ProcessSample)>, ticks_per_second: f64) {
+        if samples.is_empty() {
+            return;
+        }
+        // An exited process's CPU time moves to its parent's children time, so only live processes are added up
+        let rss: u64 = samples.iter().map(|(_, sample)| sample.rss_bytes).sum();
+        let ticks: u64 = samples.iter().map(|(_, sample)| sample.cpu_ticks).sum();
+        self.processes.extend(samples);
+
+        let now = Instant::now();
+        if let Some((then, previous)) = self.last {
+            let elapsed = now.duration_since(then).as_secs_f64();
+            if elapsed > 0.0 {
+                let percent = ticks.saturating_sub(previous) as f64 / ticks_per_second / elapsed * 100.0;
+                self.usage.peak_cpu_percent = self.usage.peak_cpu_percent.max(percent);
+            }
+        }
+        self.last = Some((now, ticks));
+
+        self.usage.peak_rss_bytes = self.usage.peak_rss_bytes.max(rss);
+        self.usage.cpu_seconds = self.usage.cpu_seconds.max(ticks as f64 / ticks_per_second);
+        self.usage.read_bytes = self.processes.values().map(|sample| sample.read_bytes).sum();
+        self.usage.write_bytes = self.processes.values().map(|sample| sample.write_bytes).sum();
+        self.usage.major_faults = self.processes.values().map(|sample| sample.major_faults).sum();
+        self.usage.samples += 1;
+    }
+}
+
+/// Samples a running process in the background until finished
+pub struct Sampler {
+    task: Option<tokio::task::JoinHandle<()>>,
+    tracker: Arc<Mutex<Tracker>>,
+}
+
+impl Sampler {
+    /// Start sampling the process, if it's still running
+    pub fn start(pid: Option<u32>) -> Sampler {
+        let tracker = Arc::new(Mutex::new(Tracker::default()));
+        let task = pid.map(|pid| {
+            let tracker = tracker.clone();
+            tokio::spawn(async move {
+                let ticks_per_second = proc::ticks_per_second();
+                let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    let samples = tokio::task::spawn_blocking(move || proc::sample(pid)).await.unwrap_or_default();
+                    tracker.lock().unwrap().record(samples, ticks_per_second);
+                }
+            })
+        });
+        Sampler { task, tracker }
+    }
+
+    /// Stop sampling and return the usage, if the process could be sampled at all.
+    /// Counters are as of the last sample, at most a second before the process exited.
+    pub fn finish(mut self) -> Option<ResourceUsage> {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        let usage = self.tracker.lock().unwrap().usage.clone();
+        (usage.samples > 0).then_some(usage)
+    }
+}
@@ -0,0 +1,170 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::resources::ResourceUsage;
+use crate::throughput::Throughput;
+
+/// restic exit code for "snapshot created, but some source files could not be read"
+pub const EXIT_INCOMPLETE_SNAPSHOT: i32 = 3;
+/// restic exit code (0.17 and later) for "repository does not exist"
+pub const EXIT_NO_REPOSITORY: i32 = 10;
+
+/// Final `summary` message printed by `restic backup --json`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct BackupSummary {
+    pub files_new: u64,
+    pub files_changed: u64,
+    pub files_unmodified: u64,
+    pub dirs_new: u64,
+    pub dirs_changed: u64,
+    pub dirs_unmodified: u64,
+    pub data_blobs: i64,
+    pub tree_blobs: i64,
+    pub data_added: u64,
+    pub total_files_processed: u64,
+    pub total_bytes_processed: u64,
+    pub total_duration: f64,
+    pub snapshot_id: Option<String>,
+    /// Files restic reported errors for (not part of restic's summary message)
+    pub warnings: Vec<String>,
+    /// What the restic process used while it ran, where it could be measured
+    pub resources: Option<ResourceUsage>,
+    /// Processing and upload rates, from restic's status messages
+    pub throughput: Option<Throughput>,
+}
+
+impl BackupSummary {
+    /// Find the summary message among restic's JSON output lines
+    pub fn from_output(stdout: &[u8]) -> Option<Self> {
+        summary_message(stdout)
+    }
+
+    /// Add up another run's counts, e.g. for a job made of several restic runs.
+    /// The snapshot id becomes the other run's.
+    pub fn merge(&mut self, other: BackupSummary) {
+        match (&mut self.throughput, other.throughput) {
+            (Some(throughput), Some(ref other_throughput)) => throughput.merge(self.total_duration, other_throughput, other.total_duration),
+            (throughput @ None, other_throughput) => *throughput = other_throughput,
+            (Some(_), None) => {}
+        }
+        self.files_new += other.files_new;
+        self.files_changed += other.files_changed;
+        self.files_unmodified += other.files_unmodified;
+        self.dirs_new += other.dirs_new;
+        self.dirs_changed += other.dirs_changed;
+        self.dirs_unmodified += other.dirs_unmodified;
+        self.data_blobs += other.data_blobs;
+        self.tree_blobs += other.tree_blobs;
+        self.data_added += other.data_added;
+        self.total_files_processed += other.total_files_processed;
+        self.total_bytes_processed += other.total_bytes_processed;
+        self.total_duration += other.total_duration;
+        if other.snapshot_id.is_some() {
+            self.snapshot_id = other.snapshot_id;
+        }
+        self.warnings.extend(other.warnings);
+        match (&mut self.resources, other.resources) {
+            (Some(resources), Some(other)) => resources.merge(&other),
+            (resources @ None, other) => *resources = other,
+            (Some(_), None) => {}
+        }
+    }
+
+    /// One-line human readable description
+    pub fn describe(&self) -> String {
+        let snapshot = match self.snapshot_id {
+            Some(ref id) => format!("Snapshot {}", short_id(id)),
+            None => "No snapshot".to_string(),
+        };
+        format!(
+            "{}: {} new, {} changed, {} unmodified files; {} added ({} processed) in {:.1}s",
+            snapshot,
+            self.files_new,
+            self.files_changed,
+            self.files_unmodified,
+            format_bytes(self.data_added),
+            format_bytes(self.total_bytes_processed),
+            self.total_duration,
+        )
+    }
+}
+
+/// Final `summary` message printed by `restic restore --json`
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct RestoreSummary {
+    pub seconds_elapsed: u64,
+    pub total_files: u64,
+    pub files_restored: u64,
+    pub files_skipped: u64,
+    pub total_bytes: u64,
+    pub bytes_restored: u64,
+    pub bytes_skipped: u64,
+}
+
+impl RestoreSummary {
+    pub fn from_output(stdout: &[u8]) -> Option<Self> {
+        summary_message(stdout)
+    }
+
+    /// One-line human readable description
+    pub fn describe(&self) -> String {
+        format!(
+            "Restored {} of {} files ({} of {}), {} skipped as unchanged, in {}s",
+            self.files_restored,
+            self.total_files,
+            format_bytes(self.bytes_restored),
+            format_bytes(self.total_bytes),
+            self.files_skipped,
+            self.seconds_elapsed,
+        )
+    }
+}
+
+fn summary_message<T: DeserializeOwned>(stdout: &[u8]) -> Option<T> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .find(|message| message["message_type"] == "summary")
+        .and_then(|message| serde_json::from_value(message).ok())
+}
+
+/// Collect the `error` messages restic reports for files it couldn't read or write
+pub fn errors_from_output(stdout: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter(|message| message["message_type"] == "error")
+        .map(|message| {
+            let item = message["item"].as_str().unwrap_or("");
+            let error = message["error"]["message"].as_str().unwrap_or("unknown error");
+            if item.is_empty() {
+                error.to_string()
+            } else {
+                format!("{}: {}", item, error)
+            }
+        })
+        .collect()
+}
+
+/// First 8 characters of a snapshot id, as restic prints them
+pub fn short_id(id: &str) -> &str {
+    &id[..id.len().min(8)]
+}
+
+/// Human readable byte size, e.g. "1.5 GiB"
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,739 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use log::{error, info, warn};
+use serde::Serialize;
+
+use crate::ls::{list_nodes, Node};
+use crate::snapshots::Snapshot;
+use crate::staging::{self, StagingArea};
+use crate::{ignore_files, mount, pins, restic, BackupConfig, Config, ResticConfig};
+
+/// Tag of the snapshot taken of the current files before an in-place restore
+pub const SAFETY_TAG: &str = "pre-restore";
+
+/// Suffix of the copies `--force` keeps of files an in-place restore overwrites
+const COPY_SUFFIX: &str = ".pre-restore";
+
+/// Overwritten files listed in the summary; the rest are only counted
+const LISTED_FILES: usize = 20;
+
+/// How often restore progress is logged when stderr isn't a terminal
+const PROGRESS_LOG_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// What an in-place restore would do to the files currently on disk
+#[derive(Debug, Default)]
+struct InPlacePlan {
+    overwritten: Vec<PathBuf>,
+    unchanged: usize,
+    created: usize,
+}
+
+impl InPlacePlan {
+    /// Compare the snapshot's files with the ones on disk by size and modification time
+    fn new(nodes: &[Node]) -> Self {
+        let mut plan = InPlacePlan::default();
+        for node in nodes.iter().filter(|node| node.kind == "file") {
+            let path = PathBuf::from(&node.path);
+            let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+                plan.created += 1;
+                continue;
+            };
+            let modified = metadata.modified().ok().map(|time| DateTime::<Local>::from(time).timestamp());
+            if metadata.is_file() && metadata.len() == node.size && modified == node.mtime.map(|time| time.timestamp()) {
+                plan.unchanged += 1;
+            } else {
+                plan.overwritten.push(path);
+            }
+        }
+        plan
+    }
+
+    fn print(&self, verb: &str) {
+        println!(
+            "{} {} existing file(s), {} created, {} already up to date",
+            verb,
+            self.overwritten.len(),
+            self.created,
+            self.unchanged
+        );
+        for path in self.overwritten.iter().take(LISTED_FILES) {
+            println!("  {}", path.display());
+        }
+        if self.overwritten.len() > LISTED_FILES {
+            println!("  ... and {} more", self.overwritten.len() - LISTED_FILES);
+        }
+    }
+}
+
+async fn restic_output(cmd: &mut tokio::process::Command, what: &str) -> Result<Vec<u8>> {
+    info!("Restic command: {:?}", cmd);
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic {} failed: {}", what, stderr.trim()));
+    }
+    Ok(output.stdout)
+}
+
+/// Which snapshot to restore: an id, or the newest one at or before `at`, among those matching the filters
+struct Selector<'a> {
+    snapshot_id: Option<&'a str>,
+    at: Option<DateTime<Local>>,
+    hosts: Vec<&'a String>,
+    tags: Vec<&'a String>,
+    paths: Vec<&'a String>,
+}
+
+impl<'a> Selector<'a> {
+    fn from_args(args: &'a [String]) -> Result<Self> {
+        let snapshot_id = crate::positional_args(&args[2..]).first().map(|id| id.as_str());
+        if let Some(id) = snapshot_id.filter(|id| !pins::is_valid_snapshot_id(id)) {
+            return Err(anyhow::anyhow!("Invalid snapshot id '{}'", id));
+        }
+        let at = crate::flag_value(args, "--at").map(|at| parse_time(at)).transpose()?;
+        if snapshot_id.is_some() && at.is_some() {
+            return Err(anyhow::anyhow!("Give either a snapshot id or --at, not both"));
+        }
+        Ok(Selector {
+            snapshot_id,
+            at,
+            hosts: crate::flag_values(args, "--host"),
+            tags: crate::flag_values(args, "--tag"),
+            paths: crate::flag_values(args, "--path"),
+        })
+    }
+
+    async fn resolve(&self, restic_config: &ResticConfig) -> Result<Snapshot> {
+        let mut cmd = crate::restic_command(restic_config, "snapshots");
+        // With --at every matching snapshot is needed to pick from
+        if self.at.is_none() {
+            cmd.arg(self.snapshot_id.unwrap_or("latest"));
+        }
+        for (flag, values) in [("--host", &self.hosts), ("--tag", &self.tags), ("--path", &self.paths)] {
+            for value in values {
+                cmd.arg(flag).arg(value);
+            }
+        }
+        cmd.arg("--json");
+        let stdout = restic_output(&mut cmd, "snapshots").await?;
+        let snapshots: Vec<Snapshot> =
+            serde_json::from_slice(&stdout).map_err(|e| anyhow::anyhow!("Failed to parse restic snapshots output: {}", e))?;
+
+        match self.at {
+            Some(at) => snapshots
+                .into_iter()
+                .filter(|snapshot| snapshot.time <= at)
+                .max_by_key(|snapshot| snapshot.time)
+                .ok_or_else(|| anyhow::anyhow!("No matching snapshot at or before {}", at.format("%Y-%m-%d %H:%M"))),
+            None => snapshots.into_iter().next().ok_or_else(|| {
+                anyhow::anyhow!("No snapshot '{}' in {}", self.snapshot_id.unwrap_or("latest"), restic_config.repository_url())
+            }),
+        }
+    }
+}
+
+/// Local time like "2024-03-01 12:00", "2024-03-01 12:00:30" or "2024-03-01" (midnight), or RFC 3339
+fn parse_time(value: &str) -> Result<DateTime<Local>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)))
+        .ok_or_else(|| anyhow::anyhow!("Invalid time '{}'; use e.g. \"2024-03-01 12:00\"", value))?;
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .ok_or_else(|| anyhow::anyhow!("'{}' doesn't exist in the local time zone", value))
+}
+
+/// Options passed on to `restic restore`, and what to change on the restored files afterwards
+#[derive(Debug, Serialize, Default)]
+pub struct RestoreOptions {
+    /// restic include/exclude patterns, e.g. `/etc/nginx` or `*.qcow2`
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+    /// Restore runs of zeros as holes, e.g. for VM images
+    pub sparse: bool,
+    /// Give the files to the user running the restore instead of the snapshot's owners
+    pub no_owner: bool,
+    /// Reset modes to what new files get under the umask, or 0600/0700 for private ones, instead of the snapshot's
+    pub no_permissions: bool,
+}
+
+impl RestoreOptions {
+    fn from_args(args: &[String]) -> Self {
+        let has = |flag: &str| args.iter().any(|arg| arg == flag);
+        RestoreOptions {
+            includes: crate::flag_values(args, "--include").into_iter().cloned().collect(),
+            excludes: crate::flag_values(args, "--exclude").into_iter().cloned().collect(),
+            sparse: has("--sparse"),
+            no_owner: has("--no-owner"),
+            no_permissions: has("--no-permissions"),
+        }
+    }
+
+    /// The includes, if they are all plain absolute paths rather than patterns
+    fn include_paths(&self) -> Option<Vec<&String>> {
+        let plain = |include: &&String| include.starts_with('/') && !include.contains(['*', '?', '[']);
+        self.includes.iter().all(|include| plain(&include)).then(|| self.includes.iter().collect())
+    }
+
+    /// Whether restic restores the path with these includes and excludes
+    fn selects(&self, path: &str) -> bool {
+        (self.includes.is_empty() || self.includes.iter().any(|include| ignore_files::pattern_matches(include, path)))
+            && !self.excludes.iter().any(|exclude| ignore_files::pattern_matches(exclude, path))
+    }
+
+    /// Apply `--no-owner` and `--no-permissions` to the restored nodes, returning what failed
+    fn post_process(&self, target: &Path, nodes: &[Node]) -> Vec<String> {
+        let mut errors = Vec::new();
+        if !self.no_owner && !self.no_permissions {
+            return errors;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{MetadataExt, PermissionsExt};
+            let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+            let umask = umask();
+            for node in nodes {
+                let path = target.join(node.path.trim_start_matches('/'));
+                let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+                    continue; // Verification reports it as missing
+                };
+                if self.no_owner {
+                    if let Err(e) = std::os::unix::fs::lchown(&path, Some(uid), Some(gid)) {
+                        errors.push(format!("{}: changing the owner failed: {}", path.display(), e));
+                    }
+                }
+                if self.no_permissions && !metadata.is_symlink() {
+                    let mode = reset_mode(metadata.mode(), metadata.is_dir(), umask);
+                    if let Err(e) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)) {
+                        errors.push(format!("{}: changing the mode failed: {}", path.display(), e));
+                    }
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// The mode `--no-permissions` gives a restored file: what a new file gets under the umask, but
+/// 0600/0700 if the snapshot kept it from group or others, so restored keys don't become readable
+#[cfg(unix)]
+fn reset_mode(original: u32, is_dir: bool, umask: u32) -> u32 {
+    let executable = is_dir || original & 0o111 != 0;
+    if original & 0o044 != 0o044 {
+        return if executable { 0o700 } else { 0o600 };
+    }
+    (if executable { 0o777 } else { 0o666 }) & !umask
+}
+
+/// The process's umask, from /proc so it doesn't have to be changed to be read; the strictest one
+/// where /proc isn't there
+#[cfg(unix)]
+fn umask() -> u32 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            let line = status.lines().find(|line| line.starts_with("Umask:"))?;
+            u32::from_str_radix(line.trim_start_matches("Umask:").trim(), 8).ok()
+        })
+        .unwrap_or(0o077)
+}
+
+/// Back up the paths about to be overwritten, returning the new snapshot's id
+async fn safety_snapshot(config: &Config, roots: Vec<PathBuf>) -> Result<Option<String>> {
+    let mut job = BackupConfig::adhoc(roots, Vec::new(), vec![SAFETY_TAG.to_string()])?;
+    job.name = SAFETY_TAG.to_string();
+    info!("Taking a safety snapshot of {:?}", job.directories);
+    let summary = crate::execute_restic_backup(&job, &config.restic, false, false, None).await?;
+    for warning in &summary.warnings {
+        warn!("Safety snapshot: {}", warning);
+    }
+    Ok(summary.snapshot_id)
+}
+
+/// Copy each file next to itself as `<file>.pre-restore`
+pub fn write_copies(paths: &[PathBuf]) -> Result<()> {
+    for path in paths {
+        let mut copy = path.clone().into_os_string();
+        copy.push(COPY_SUFFIX);
+        std::fs::copy(path, &copy).map_err(|e| anyhow::anyhow!("Failed to copy {:?} before restoring: {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// How `restic restore` went: its summary, the files it reported errors for, and why it failed if it did
+struct RestoreOutput {
+    summary: Option<restic::RestoreSummary>,
+    errors: Vec<String>,
+    failure: Option<String>,
+}
+
+/// "45% 120/300 files, 1.2 GiB of 3.0 GiB" from a restore status message
+fn describe_progress(status: &serde_json::Value) -> String {
+    let number = |key: &str| status[key].as_u64().unwrap_or(0);
+    format!(
+        "{:.0}% {}/{} files, {} of {}",
+        status["percent_done"].as_f64().unwrap_or(0.0) * 100.0,
+        number("files_restored"),
+        number("total_files"),
+        restic::format_bytes(number("bytes_restored")),
+        restic::format_bytes(number("total_bytes"))
+    )
+}
+
+/// Run `restic restore --json`, showing its progress on a terminal or in the log as it goes
+async fn run_restore(restic_config: &ResticConfig, snapshot_id: &str, target: &Path, options: &RestoreOptions) -> Result<RestoreOutput> {
+    use std::io::IsTerminal;
+    use std::process::Stdio;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    let mut cmd = crate::restic_command(restic_config, "restore");
+    cmd.arg(snapshot_id).arg("--target").arg(target);
+    for include in &options.includes {
+        cmd.arg("--include").arg(include);
+    }
+    for exclude in &options.excludes {
+        cmd.arg("--exclude").arg(exclude);
+    }
+    if options.sparse {
+        cmd.arg("--sparse");
+    }
+    cmd.arg("--json");
+    info!("Restic command: {:?}", cmd);
+
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = cmd.spawn().map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    let read_stdout = async {
+        let on_terminal = std::io::stderr().is_terminal();
+        let mut last_logged = std::time::Instant::now();
+        let mut collected = Vec::new();
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next_line().await? {
+            if let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) {
+                if message["message_type"] == "status" {
+                    if on_terminal {
+                        eprint!("\r{:<60}", describe_progress(&message));
+                    } else if last_logged.elapsed() >= PROGRESS_LOG_INTERVAL {
+                        info!("Restore progress: {}", describe_progress(&message));
+                        last_logged = std::time::Instant::now();
+                    }
+                    continue;
+                }
+            }
+            collected.extend_from_slice(line.as_bytes());
+            collected.push(b'\n');
+        }
+        if on_terminal {
+            eprintln!();
+        }
+        Ok::<_, std::io::Error>(collected)
+    };
+    let read_stderr = async {
+        let mut collected = Vec::new();
+        stderr.read_to_end(&mut collected).await?;
+        Ok::<_, std::io::Error>(collected)
+    };
+
+    let (stdout, stderr) = tokio::try_join!(read_stdout, read_stderr)?;
+    let status = child.wait().await?;
+    let failure = (!status.success()).then(|| {
+        let stderr = String::from_utf8_lossy(&stderr);
+        format!("restic restore failed ({}): {}", status, stderr.trim())
+    });
+    Ok(RestoreOutput {
+        summary: restic::RestoreSummary::from_output(&stdout),
+        errors: restic::errors_from_output(&stdout),
+        failure,
+    })
+}
+
+/// Record of a restore, written to the reports directory as `restore-<runid>.json`
+#[derive(Debug, Serialize)]
+pub struct RestoreReport {
+    pub run_id: String,
+    pub hostname: String,
+    pub repository: String,
+    pub snapshot_id: String,
+    pub snapshot_time: DateTime<Local>,
+    pub target: PathBuf,
+    pub in_place: bool,
+    pub options: RestoreOptions,
+    /// "succeeded", "warning" or "failed"
+    pub status: String,
+    pub started_at: DateTime<Local>,
+    pub finished_at: DateTime<Local>,
+    pub duration_secs: f64,
+    pub safety_snapshot_id: Option<String>,
+    pub overwritten: Vec<PathBuf>,
+    pub summary: Option<restic::RestoreSummary>,
+    /// Files restic couldn't restore
+    pub errors: Vec<String>,
+    /// Files listed in the snapshot, and how many of them are on disk with the right size afterwards
+    pub expected_files: usize,
+    pub expected_bytes: u64,
+    pub verified_files: usize,
+    pub missing: Vec<PathBuf>,
+    pub size_mismatches: Vec<PathBuf>,
+    pub error: Option<String>,
+}
+
+impl RestoreReport {
+    pub fn file_name(&self) -> String {
+        format!("restore-{}.json", self.run_id)
+    }
+
+    /// Check every file of the snapshot's listing against the restored copy under `target`
+    fn verify(&mut self, nodes: &[Node]) {
+        for node in nodes.iter().filter(|node| node.kind == "file") {
+            let path = self.target.join(node.path.trim_start_matches('/'));
+            match std::fs::metadata(&path) {
+                Ok(metadata) if metadata.len() == node.size => self.verified_files += 1,
+                Ok(_) => self.size_mismatches.push(path),
+                Err(_) => self.missing.push(path),
+            }
+        }
+    }
+
+    fn print(&self) {
+        if let Some(ref summary) = self.summary {
+            println!("{}", summary.describe());
+        }
+        println!("Verified {} of {} files on disk", self.verified_files, self.expected_files);
+        for (label, paths) in [("Missing", &self.missing), ("Size differs", &self.size_mismatches)] {
+            for path in paths.iter().take(LISTED_FILES) {
+                println!("  {}: {}", label, path.display());
+            }
+            if paths.len() > LISTED_FILES {
+                println!("  ... and {} more", paths.len() - LISTED_FILES);
+            }
+        }
+        for error in self.errors.iter().take(LISTED_FILES) {
+            println!("  Error: {}", error);
+        }
+        if self.errors.len() > LISTED_FILES {
+            println!("  ... and {} more errors", self.errors.len() - LISTED_FILES);
+        }
+    }
+}
+
+/// Keep the current state of what an in-place restore overwrites: a safety snapshot, returning its id,
+/// or `.pre-restore` copies with `--force`
+async fn keep_previous(config: &Config, snapshot: &Snapshot, options: &RestoreOptions, plan: &InPlacePlan, force: bool) -> Result<Option<String>> {
+    if force {
+        write_copies(&plan.overwritten)?;
+        return Ok(None);
+    }
+    // Include patterns can't be backed up, so then the whole snapshot's paths are
+    let roots: Vec<PathBuf> = match options.include_paths().filter(|paths| !paths.is_empty()) {
+        Some(paths) => paths.into_iter().map(PathBuf::from).collect(),
+        None => snapshot.paths.iter().map(PathBuf::from).collect(),
+    };
+    let roots: Vec<PathBuf> = roots.into_iter().filter(|root| root.exists()).collect();
+    if roots.is_empty() {
+        info!("None of the paths exist, so there is nothing to back up before restoring");
+        return Ok(None);
+    }
+    safety_snapshot(config, roots).await.map_err(|e| {
+        anyhow::anyhow!("Safety snapshot failed, nothing was restored ({}); pass --force to keep .pre-restore copies instead", e)
+    })
+}
+
+/// The snapshot's files the options select; the manifest restored files are verified against
+async fn listing(config: &Config, snapshot: &Snapshot, options: &RestoreOptions) -> Result<Vec<Node>> {
+    Ok(list_nodes(&config.restic, &snapshot.id, &options.include_paths().unwrap_or_default())
+        .await?
+        .into_iter()
+        .filter(|node| options.selects(&node.path))
+        .collect())
+}
+
+/// Restore into a new staging area, to be committed or discarded with `staging`
+async fn stage_snapshot(config: &Config, snapshot: &Snapshot, options: RestoreOptions, nodes: &[Node]) -> Result<StagingArea> {
+    let id = crate::events::new_run_id();
+    let target = staging::area_path(config, &id);
+    std::fs::create_dir_all(&target).map_err(|e| anyhow::anyhow!("Failed to create staging area {:?}: {}", target, e))?;
+    let output = match run_restore(&config.restic, &snapshot.id, &target, &options).await {
+        Ok(output) => output,
+        Err(e) => {
+            let _ = staging::remove(config, &id);
+            return Err(e);
+        }
+    };
+    if let Some(failure) = output.failure {
+        let _ = staging::remove(config, &id);
+        return Err(anyhow::anyhow!(failure));
+    }
+    let mut errors = output.errors;
+    errors.extend(options.post_process(&target, nodes));
+
+    let files = nodes.iter().filter(|node| node.kind == "file");
+    let (files, bytes) = files.fold((0, 0), |(count, bytes), node| (count + 1, bytes + node.size));
+    let area = StagingArea {
+        id,
+        snapshot_id: snapshot.id.clone(),
+        snapshot_time: snapshot.time.with_timezone(&Local),
+        created_at: Local::now(),
+        includes: options.includes,
+        excludes: options.excludes,
+        files,
+        bytes,
+        errors,
+    };
+    staging::save(config, &area)?;
+    Ok(area)
+}
+
+async fn restore(config: &Config, args: &[String], dry_run: bool) -> Result<()> {
+    let selector = Selector::from_args(args)?;
+    let in_place = args.iter().any(|arg| arg == "--in-place");
+    let stage = args.iter().any(|arg| arg == "--stage");
+    let force = args.iter().any(|arg| arg == "--force");
+    let options = RestoreOptions::from_args(args);
+    let target = match (crate::flag_value(args, "--target"), in_place, stage) {
+        (Some(_), true, _) => return Err(anyhow::anyhow!("--target and --in-place can't be combined")),
+        (Some(_), false, true) | (None, true, true) => {
+            return Err(anyhow::anyhow!("--stage restores into a staging area; choose the destination later with `staging commit`"))
+        }
+        (Some(target), false, false) => crate::expand_tilde(Path::new(target)),
+        (None, true, false) => PathBuf::from("/"),
+        (None, false, true) => staging::directory(config),
+        (None, false, false) => {
+            return Err(anyhow::anyhow!(
+                "Usage: restore [SNAPSHOT | --at TIME] [--host H] [--tag T] [--path P] --target DIR | --in-place [--force] | --stage [--include PATTERN] [--exclude PATTERN] [--sparse] [--no-owner] [--no-permissions]"
+            ))
+        }
+    };
+
+    let snapshot = selector.resolve(&config.restic).await?;
+    println!(
+        "Snapshot {} from {} ({})",
+        restic::short_id(&snapshot.id),
+        snapshot.time.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        snapshot.paths.join(" ")
+    );
+
+    let nodes = listing(config, &snapshot, &options).await?;
+    let files = nodes.iter().filter(|node| node.kind == "file");
+    let (expected_files, expected_bytes) = files.fold((0, 0), |(count, bytes), node| (count + 1, bytes + node.size));
+    let plan = in_place.then(|| InPlacePlan::new(&nodes));
+    if dry_run {
+        match plan {
+            Some(plan) => plan.print("Dry run: would overwrite"),
+            None => println!(
+                "Dry run: would restore {} files ({}) to {:?}",
+                expected_files,
+                restic::format_bytes(expected_bytes),
+                target
+            ),
+        }
+        return Ok(());
+    }
+    if stage {
+        let area = stage_snapshot(config, &snapshot, options, &nodes).await?;
+        println!(
+            "Staged {} files ({}) as {} in {:?}",
+            area.files,
+            restic::format_bytes(area.bytes),
+            area.id,
+            staging::area_path(config, &area.id)
+        );
+        for error in &area.errors {
+            println!("  Error: {}", error);
+        }
+        println!(
+            "Inspect them with `staging show {id}`, then `staging commit {id} --target DIR | --in-place` or `staging discard {id}`",
+            id = area.id
+        );
+        return Ok(());
+    }
+
+    let started_at = Local::now();
+    let started = std::time::Instant::now();
+    let mut report = RestoreReport {
+        run_id: crate::events::new_run_id(),
+        hostname: crate::hostname(),
+        repository: config.restic.repository_url(),
+        snapshot_id: snapshot.id.clone(),
+        snapshot_time: snapshot.time.with_timezone(&Local),
+        target: target.clone(),
+        in_place,
+        status: String::new(),
+        started_at,
+        finished_at: started_at,
+        duration_secs: 0.0,
+        safety_snapshot_id: None,
+        overwritten: plan.as_ref().map(|plan| plan.overwritten.clone()).unwrap_or_default(),
+        summary: None,
+        errors: Vec::new(),
+        expected_files,
+        expected_bytes,
+        verified_files: 0,
+        missing: Vec::new(),
+        size_mismatches: Vec::new(),
+        error: None,
+        options,
+    };
+
+    // Only a failure before restic ran stops the report from being filled in
+    let previous = match plan {
+        Some(ref plan) => keep_previous(config, &snapshot, &report.options, plan, force).await,
+        None => Ok(None),
+    };
+    let restored = match previous {
+        Ok(safety_snapshot_id) => {
+            report.safety_snapshot_id = safety_snapshot_id;
+            let output = run_restore(&config.restic, &snapshot.id, &target, &report.options).await;
+            let post_process_errors = report.options.post_process(&target, &nodes);
+            report.verify(&nodes);
+            output.map(|output| {
+                report.summary = output.summary;
+                report.errors = output.errors;
+                report.errors.extend(post_process_errors);
+                report.error = output.failure;
+            })
+        }
+        Err(e) => Err(e),
+    };
+    let ran = restored.is_ok();
+    if let Err(e) = restored {
+        report.error = Some(e.to_string());
+    }
+
+    report.finished_at = Local::now();
+    report.duration_secs = started.elapsed().as_secs_f64();
+    let complete = report.errors.is_empty() && report.missing.is_empty() && report.size_mismatches.is_empty();
+    report.status = match (&report.error, complete) {
+        (Some(_), _) => "failed",
+        (None, false) => "warning",
+        (None, true) => "succeeded",
+    }
+    .to_string();
+
+    if ran {
+        report.print();
+        if let Some(ref plan) = plan {
+            plan.print("Overwrote");
+            match report.safety_snapshot_id {
+                Some(ref id) => println!("The previous state is in snapshot {} (tag {})", restic::short_id(id), SAFETY_TAG),
+                None if force => println!("Copies of the overwritten files end in {}", COPY_SUFFIX),
+                None => {}
+            }
+        }
+    }
+    if let Some(ref reports_config) = config.reports {
+        let directory = crate::expand_tilde(&reports_config.directory);
+        let path = directory.join(report.file_name());
+        let written = std::fs::create_dir_all(&directory)
+            .and_then(|_| std::fs::write(&path, serde_json::to_string_pretty(&report)?));
+        match written {
+            Ok(()) => println!("Restore report written to {:?}", path),
+            Err(e) => error!("Failed to write restore report {:?}: {}", path, e),
+        }
+    }
+
+    match report.error {
+        Some(error) => Err(anyhow::anyhow!(error)),
+        None if !complete => Err(anyhow::anyhow!(
+            "Restore incomplete: {} errors, {} files missing, {} with the wrong size",
+            report.errors.len(),
+            report.missing.len(),
+            report.size_mismatches.len()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Run `work` with the repository's mount, if it has one, mounted
+async fn with_mount<T>(config: &Config, work: impl std::future::Future<Output = Result<T>>) -> Result<T> {
+    let mounted = match config.restic.mount {
+        Some(ref mount_config) => mount::ensure_mounted(mount_config).await?,
+        None => false,
+    };
+    let result = work.await;
+    if mounted {
+        if let Some(ref mount_config) = config.restic.mount {
+            mount::unmount(mount_config).await;
+        }
+    }
+    result
+}
+
+/// Restore a snapshot to `--target`, over the original paths with `--in-place`, or into a staging area
+/// with `--stage`. The snapshot is given by id, or picked with `--at` as the newest one at or before that time.
+/// An in-place restore first takes a safety snapshot of the current files, or with `--force`
+/// keeps `.pre-restore` copies of the files it overwrites instead. Afterwards the restored files are
+/// checked against the snapshot's listing, and a report goes to the reports directory.
+pub async fn run(config: &Config, args: &[String], dry_run: bool) -> Result<()> {
+    with_mount(config, restore(config, args, dry_run)).await
+}
+
+/// Restore a snapshot's files into a new staging area, for the web UI
+pub async fn stage(config: &Config, snapshot_id: &str, options: RestoreOptions) -> Result<StagingArea> {
+    with_mount(config, async {
+        let selector = Selector { snapshot_id: Some(snapshot_id), at: None, hosts: Vec::new(), tags: Vec::new(), paths: Vec::new() };
+        let snapshot = selector.resolve(&config.restic).await?;
+        let nodes = listing(config, &snapshot, &options).await?;
+        stage_snapshot(config, &snapshot, options, &nodes).await
+    })
+    .await
+}
+
+/// Restore all of a snapshot under `target`, for `verify --restore-test`; returns the files restic couldn't restore
+pub async fn restore_all(config: &Config, snapshot_id: &str, target: &Path) -> Result<Vec<String>> {
+    with_mount(config, async {
+        let output = run_restore(&config.restic, snapshot_id, target, &RestoreOptions::default()).await?;
+        match output.failure {
+            Some(failure) => Err(anyhow::anyhow!(failure)),
+            None => Ok(output.errors),
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> RestoreOptions {
+        RestoreOptions::from_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn selects_follows_includes_and_excludes() {
+        let restore = options(&["--include", "/etc/nginx", "--exclude", "*.bak"]);
+        assert!(restore.selects("/etc/nginx/nginx.conf"));
+        assert!(!restore.selects("/etc/nginx/nginx.conf.bak"));
+        assert!(!restore.selects("/etc/ssh/sshd_config"));
+        assert!(options(&[]).selects("/anything"));
+    }
+
+    #[test]
+    fn include_paths_only_for_plain_paths() {
+        assert_eq!(options(&["--include", "/etc", "--include", "/srv/www"]).include_paths().map(|paths| paths.len()), Some(2));
+        assert!(options(&["--include", "/etc", "--include", "*.conf"]).include_paths().is_none());
+        assert!(options(&["--include", "etc"]).include_paths().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reset_mode_keeps_private_files_private() {
+        // Readable by everyone: what a new file gets under the umask
+        assert_eq!(reset_mode(0o100644, false, 0o022), 0o644);
+        assert_eq!(reset_mode(0o100755, false, 0o027), 0o750);
+        assert_eq!(reset_mode(0o040755, true, 0o022), 0o755);
+        // Kept from group or others in the snapshot: owner only
+        assert_eq!(reset_mode(0o100600, false, 0o022), 0o600);
+        assert_eq!(reset_mode(0o100640, false, 0o000), 0o600);
+        assert_eq!(reset_mode(0o040750, true, 0o022), 0o700);
+        assert_eq!(reset_mode(0o100711, false, 0o022), 0o700);
+    }
+}
@@ -0,0 +1,127 @@
+use std::future::Future;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use log::{error, info};
+
+use crate::run_journal::RunRecord;
+use crate::{audit, reports, window, BackupConfig, Config};
+
+/// Tag on the snapshots of follow-up runs
+pub const TAG: &str = "resumed";
+/// How often a follow-up that can't run yet checks its job's conditions and window again
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+
+/// The job with the `resumed` tag added, for its follow-up run
+pub fn tag(job: &BackupConfig) -> BackupConfig {
+    let mut tagged = job.clone();
+    if !tagged.tags.iter().any(|tag| tag == TAG) {
+        tagged.tags.push(TAG.to_string());
+    }
+    tagged
+}
+
+/// The config with the named jobs tagged, for code that looks jobs up by name
+fn tag_jobs(config: &Config, jobs: &[String]) -> Config {
+    let mut config = config.clone();
+    for job in std::iter::once(&mut config.backup).chain(config.jobs.iter_mut()) {
+        if jobs.contains(&job.name) {
+            *job = tag(job);
+        }
+    }
+    config
+}
+
+/// Jobs whose backups were interrupted, each once; dry runs don't need another go
+pub fn jobs(interrupted: &[RunRecord]) -> Vec<String> {
+    let mut jobs: Vec<String> = Vec::new();
+    for record in interrupted.iter().filter(|record| !record.dry_run) {
+        if !jobs.contains(&record.job) {
+            jobs.push(record.job.clone());
+        }
+    }
+    jobs
+}
+
+/// Jobs marked for catch-up because a run of theirs was interrupted
+pub fn pending(config: &Config) -> Vec<String> {
+    window::pending(config)
+        .into_iter()
+        .filter(|(_, catch_up)| catch_up.reason.starts_with(crate::run_journal::INTERRUPTED))
+        .map(|(job, _)| job)
+        .collect()
+}
+
+/// Whether a backup of the job finished since, e.g. because its schedule came first
+fn ran_since(config: &Config, job: &str, since: DateTime<Local>) -> bool {
+    let Some(ref reports_config) = config.reports else { return false };
+    reports::list(&crate::expand_tilde(&reports_config.directory))
+        .iter()
+        .any(|report| report.job == job && !report.dry_run && report.status != "failed" && report.started_at >= since)
+}
+
+/// Why the job can't run right now
+async fn blocked(job: &BackupConfig) -> Option<String> {
+    match crate::conditions::unmet(job).await {
+        Some(reason) => Some(reason),
+        None => window::deferral(job, Local::now()),
+    }
+}
+
+/// Run the interrupted jobs again, tagged `resumed`, as soon as their conditions and windows allow
+/// instead of at their next scheduled time. `run` starts the ready jobs, given a config in which
+/// they carry the tag.
+pub async fn follow_up<F, Fut>(config: Config, interrupted: Vec<RunRecord>, run: F)
+where
+    F: Fn(Config, Vec<String>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let since = Local::now();
+    let mut pending: Vec<String> = jobs(&interrupted).into_iter().filter(|name| config.find_job(name).is_some()).collect();
+    let mut waiting: Vec<String> = Vec::new();
+    while !pending.is_empty() {
+        pending.retain(|name| !ran_since(&config, name, since));
+        let mut ready = Vec::new();
+        for name in &pending {
+            let Some(job) = config.find_job(name) else { continue };
+            match blocked(job).await {
+                Some(reason) => {
+                    if !waiting.contains(name) {
+                        info!("Resuming job '{}' once it can run: {}", name, reason);
+                        waiting.push(name.clone());
+                    }
+                }
+                None => ready.push(name.clone()),
+            }
+        }
+        pending.retain(|name| !ready.contains(name));
+        if !ready.is_empty() {
+            info!("Resuming interrupted job(s): {}", ready.join(", "));
+            run(tag_jobs(&config, &ready), ready).await;
+        }
+        if !pending.is_empty() {
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+    }
+}
+
+/// Run the jobs one after another, for modes without the web UI's run tracking
+pub async fn run_jobs(config: Config, jobs: Vec<String>, verbose: bool) {
+    let selected: Vec<&BackupConfig> = jobs.iter().filter_map(|name| config.find_job(name)).collect();
+    let batch = crate::dependencies::order(&selected);
+    let mut failed = Vec::new();
+    for &job in &batch {
+        if let Some(reason) = crate::dependencies::unmet(&config, job, &batch, &failed) {
+            error!("Skipping follow-up of job '{}': {}", job.name, reason);
+            failed.push(job.name.clone());
+            continue;
+        }
+        let result = crate::run_backup(&config, job, false, verbose, None).await;
+        let entry = audit::AuditEntry::new(audit::Source::current_process(), None, "backup", Some(job.name.clone()));
+        audit::record(&config, &entry.outcome(&result));
+        if let Err(e) = result {
+            error!("Follow-up of job '{}' failed: {}", job.name, e);
+            failed.push(job.name.clone());
+        }
+    }
+}
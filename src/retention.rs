@@ -0,0 +1,335 @@
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Local, Months, Timelike};
+use log::info;
+use serde::Deserialize;
+
+use crate::maintenance::{ForgetRun, MaintenanceConfig, RetentionPolicy};
+use crate::{BackupConfig, Config};
+
+/// One snapshot group of `restic forget --dry-run --json`
+#[derive(Debug, Deserialize)]
+struct ForgetGroup {
+    #[serde(default)]
+    host: String,
+    #[serde(default)]
+    paths: Option<Vec<String>>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    keep: Option<Vec<ForgetSnapshot>>,
+    #[serde(default)]
+    remove: Option<Vec<ForgetSnapshot>>,
+    #[serde(default)]
+    reasons: Option<Vec<KeepReason>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ForgetSnapshot {
+    id: String,
+    time: DateTime<Local>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeepReason {
+    snapshot: ForgetSnapshot,
+    #[serde(default)]
+    matches: Vec<String>,
+}
+
+/// The configured policy with `--keep-*` flags from the command line taking precedence
+pub fn policy_with_overrides(mut policy: RetentionPolicy, args: &[String]) -> Result<RetentionPolicy> {
+    let counts = [
+        ("--keep-last", &mut policy.keep_last),
+        ("--keep-hourly", &mut policy.keep_hourly),
+        ("--keep-daily", &mut policy.keep_daily),
+        ("--keep-weekly", &mut policy.keep_weekly),
+        ("--keep-monthly", &mut policy.keep_monthly),
+        ("--keep-yearly", &mut policy.keep_yearly),
+    ];
+    for (flag, field) in counts {
+        if let Some(value) = crate::flag_value(args, flag) {
+            let count = value.parse().map_err(|_| anyhow::anyhow!("{} needs a number, got '{}'", flag, value))?;
+            *field = Some(count);
+        }
+    }
+    if let Some(within) = crate::flag_value(args, "--keep-within") {
+        parse_within(within)?;
+        policy.keep_within = Some(within.clone());
+    }
+    Ok(policy)
+}
+
+/// restic duration like "1y6m" or "30d12h" as (years, months, days, hours)
+fn parse_within(within: &str) -> Result<(u32, u32, u32, u32)> {
+    let mut parts = (0, 0, 0, 0);
+    let mut number = String::new();
+    for c in within.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let value: u32 = number.parse().map_err(|_| anyhow::anyhow!("Invalid duration '{}'", within))?;
+        match c {
+            'y' => parts.0 += value,
+            'm' => parts.1 += value,
+            'd' => parts.2 += value,
+            'h' => parts.3 += value,
+            _ => return Err(anyhow::anyhow!("Invalid duration '{}': unknown unit '{}'", within, c)),
+        }
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(anyhow::anyhow!("Invalid duration '{}': missing unit after {}", within, number));
+    }
+    Ok(parts)
+}
+
+fn subtract_within(time: DateTime<Local>, (years, months, days, hours): (u32, u32, u32, u32)) -> DateTime<Local> {
+    time.checked_sub_months(Months::new(years * 12 + months)).unwrap_or(time)
+        - chrono::Duration::days(days as i64)
+        - chrono::Duration::hours(hours as i64)
+}
+
+/// Which of the given snapshot times (newest first) the policy keeps, following restic's rules:
+/// every `keep_*` bucket keeps the newest snapshot of each of its last N periods.
+fn apply_policy(policy: &RetentionPolicy, times: &[DateTime<Local>]) -> Vec<bool> {
+    type Bucket = fn(&DateTime<Local>, usize) -> (i32, u32, u32, u32);
+    let buckets: [(Option<u32>, Bucket); 6] = [
+        (policy.keep_last, |_, index| (index as i32, 0, 0, 0)),
+        (policy.keep_hourly, |t, _| (t.year(), t.ordinal(), t.hour(), 0)),
+        (policy.keep_daily, |t, _| (t.year(), t.ordinal(), 0, 0)),
+        (policy.keep_weekly, |t, _| (t.iso_week().year(), t.iso_week().week(), 0, 0)),
+        (policy.keep_monthly, |t, _| (t.year(), t.month(), 0, 0)),
+        (policy.keep_yearly, |t, _| (t.year(), 0, 0, 0)),
+    ];
+    let mut remaining: Vec<u32> = buckets.iter().map(|(count, _)| count.unwrap_or(0)).collect();
+    let mut last: Vec<Option<(i32, u32, u32, u32)>> = vec![None; buckets.len()];
+    let cutoff = policy
+        .keep_within
+        .as_deref()
+        .and_then(|within| parse_within(within).ok())
+        .zip(times.first())
+        .map(|(within, newest)| subtract_within(*newest, within));
+
+    times
+        .iter()
+        .enumerate()
+        .map(|(index, time)| {
+            let mut keep = cutoff.is_some_and(|cutoff| *time >= cutoff);
+            for (i, (_, bucket)) in buckets.iter().enumerate() {
+                let value = bucket(time, index);
+                if remaining[i] > 0 && last[i] != Some(value) {
+                    keep = true;
+                    last[i] = Some(value);
+                    remaining[i] -= 1;
+                }
+            }
+            keep
+        })
+        .collect()
+}
+
+/// Snapshot count the policy settles at with one backup per `interval`, and how far back they reach
+pub fn steady_state(policy: &RetentionPolicy, interval: chrono::Duration) -> (usize, chrono::Duration) {
+    let count = |value: Option<u32>| value.unwrap_or(0) as i64;
+    let within = policy
+        .keep_within
+        .as_deref()
+        .and_then(|within| parse_within(within).ok())
+        .map(|(years, months, days, hours)| {
+            chrono::Duration::days(years as i64 * 366 + months as i64 * 31 + days as i64) + chrono::Duration::hours(hours as i64)
+        })
+        .unwrap_or_else(chrono::Duration::zero);
+    // Long enough that every bucket is full, plus one period of the coarsest one
+    let horizon = [
+        interval * (count(policy.keep_last) as i32 + 1),
+        chrono::Duration::hours(count(policy.keep_hourly) + 1),
+        chrono::Duration::days(count(policy.keep_daily) + 1),
+        chrono::Duration::weeks(count(policy.keep_weekly) + 1),
+        chrono::Duration::days(31 * (count(policy.keep_monthly) + 1)),
+        chrono::Duration::days(366 * (count(policy.keep_yearly) + 1)),
+        within,
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or_else(chrono::Duration::zero)
+        + interval;
+
+    let newest = Local::now();
+    let total = (horizon.num_seconds() / interval.num_seconds().max(1)).max(1);
+    let times: Vec<DateTime<Local>> = (0..total).map(|i| newest - interval * i as i32).collect();
+    let kept = apply_policy(policy, &times);
+    let oldest = times.iter().zip(&kept).filter(|(_, keep)| **keep).map(|(time, _)| *time).min().unwrap_or(newest);
+    (kept.iter().filter(|keep| **keep).count(), newest - oldest)
+}
+
+fn describe_span(span: chrono::Duration) -> String {
+    match span.num_days() {
+        0 => format!("{} hours", span.num_hours()),
+        days if days < 90 => format!("{} days", days),
+        days if days < 730 => format!("{} months", days / 30),
+        days => format!("{:.1} years", days as f64 / 365.0),
+    }
+}
+
+/// Ask restic which snapshots a `forget` run would remove, without changing anything
+async fn dry_run_forget(config: &Config, run: &ForgetRun) -> Result<Vec<ForgetGroup>> {
+    let mut cmd = crate::restic_command(&config.restic, "forget");
+    cmd.args(&run.args);
+    cmd.arg("--dry-run").arg("--json");
+    info!("Restic command: {:?}", cmd);
+
+    let output = cmd.output().await.map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("restic forget --dry-run failed: {}", stderr.trim()));
+    }
+    // Older restic versions print "Applying Policy" lines before the JSON
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json = stdout.lines().find(|line| line.starts_with('[')).unwrap_or("[]");
+    serde_json::from_str(json).map_err(|e| anyhow::anyhow!("Failed to parse restic forget output: {}", e))
+}
+
+/// The policy that applies to a job's snapshots: that of the first group selecting them, or the top-level one
+fn job_policy<'a>(maintenance: &'a MaintenanceConfig, job: &BackupConfig) -> &'a RetentionPolicy {
+    let hostname = crate::hostname();
+    maintenance
+        .groups
+        .iter()
+        .find(|group| {
+            !group.policy.is_empty()
+                && group.tags.iter().all(|tag| job.tags.contains(tag))
+                && group.host.as_ref().is_none_or(|host| *host == hostname)
+        })
+        .map(|group| &group.policy)
+        .unwrap_or(&maintenance.retention)
+}
+
+/// Print what `forget` would keep and remove today, and the steady state for each job's backup frequency
+pub async fn simulate(config: &Config, jobs: &[&BackupConfig], args: &[String]) -> Result<()> {
+    let mut maintenance = config.maintenance.clone().unwrap_or_default();
+    maintenance.retention = policy_with_overrides(maintenance.retention, args)?;
+    if !maintenance.has_policy() {
+        return Err(anyhow::anyhow!("No retention policy: set keep_* in the maintenance section or pass --keep-daily etc."));
+    }
+
+    let mut groups = Vec::new();
+    for run in crate::maintenance::forget_runs(config, &maintenance).await? {
+        println!("Policy for {}: {}", run.label, run.args.join(" "));
+        groups.extend(dry_run_forget(config, &run).await?);
+    }
+    println!();
+    let (mut total_keep, mut total_remove) = (0, 0);
+    for group in &groups {
+        let mut label = format!("host {}", group.host);
+        if let Some(ref paths) = group.paths {
+            label.push_str(&format!(", paths {}", paths.join(" ")));
+        }
+        if let Some(tags) = group.tags.as_ref().filter(|tags| !tags.is_empty()) {
+            label.push_str(&format!(", tags {}", tags.join(",")));
+        }
+        println!("{}", label);
+
+        let reasons = group.reasons.as_deref().unwrap_or_default();
+        let keep = group.keep.as_deref().unwrap_or_default();
+        let remove = group.remove.as_deref().unwrap_or_default();
+        let mut rows: Vec<(&ForgetSnapshot, bool)> = keep.iter().map(|s| (s, true)).chain(remove.iter().map(|s| (s, false))).collect();
+        rows.sort_by_key(|(snapshot, _)| std::cmp::Reverse(snapshot.time));
+        for (snapshot, kept) in rows {
+            let why = reasons
+                .iter()
+                .find(|reason| reason.snapshot.id == snapshot.id)
+                .map(|reason| reason.matches.join(", "))
+                .unwrap_or_default();
+            println!(
+                "  {}  {}  {}  {}",
+                if kept { "keep  " } else { "remove" },
+                &snapshot.id[..snapshot.id.len().min(8)],
+                snapshot.time.format("%Y-%m-%d %H:%M"),
+                why
+            );
+        }
+        println!("  {} kept, {} removed\n", keep.len(), remove.len());
+        total_keep += keep.len();
+        total_remove += remove.len();
+    }
+    println!("Now: {} snapshot(s) kept, {} removed", total_keep, total_remove);
+
+    for job in jobs {
+        match job.interval() {
+            Some(interval) => {
+                let (count, span) = steady_state(job_policy(&maintenance, job), interval);
+                println!(
+                    "Job '{}' ({} backups): settles at about {} snapshots reaching back {}",
+                    job.name,
+                    job.frequency,
+                    count,
+                    describe_span(span)
+                );
+            }
+            None => println!("Job '{}': no projection for frequency '{}'", job.name, job.frequency),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Snapshot times newest first, `hours` apart, starting at 2024-03-31 23:00
+    fn hourly(count: i64, hours: i64) -> Vec<DateTime<Local>> {
+        let newest = Local.with_ymd_and_hms(2024, 3, 31, 23, 0, 0).unwrap();
+        (0..count).map(|i| newest - chrono::Duration::hours(i * hours)).collect()
+    }
+
+    fn kept(keep: &[bool]) -> Vec<usize> {
+        keep.iter().enumerate().filter(|(_, keep)| **keep).map(|(index, _)| index).collect()
+    }
+
+    #[test]
+    fn keep_last_keeps_the_newest() {
+        let policy = RetentionPolicy { keep_last: Some(3), ..Default::default() };
+        assert_eq!(kept(&apply_policy(&policy, &hourly(10, 1))), [0, 1, 2]);
+    }
+
+    #[test]
+    fn keep_daily_keeps_the_newest_of_each_day() {
+        // Every 6 hours: 23:00, 17:00, 11:00, 05:00 on each day
+        let policy = RetentionPolicy { keep_daily: Some(3), ..Default::default() };
+        assert_eq!(kept(&apply_policy(&policy, &hourly(20, 6))), [0, 4, 8]);
+    }
+
+    #[test]
+    fn rules_add_up() {
+        let policy = RetentionPolicy { keep_last: Some(2), keep_daily: Some(2), ..Default::default() };
+        assert_eq!(kept(&apply_policy(&policy, &hourly(20, 6))), [0, 1, 4]);
+    }
+
+    #[test]
+    fn keep_monthly_spans_months() {
+        let policy = RetentionPolicy { keep_monthly: Some(2), ..Default::default() };
+        // Daily snapshots from March 31 back into February
+        assert_eq!(kept(&apply_policy(&policy, &hourly(40, 24))), [0, 31]);
+    }
+
+    #[test]
+    fn keep_within_is_relative_to_the_newest_snapshot() {
+        let policy = RetentionPolicy { keep_within: Some("1d12h".to_string()), ..Default::default() };
+        assert_eq!(kept(&apply_policy(&policy, &hourly(10, 12))), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn no_policy_keeps_nothing() {
+        assert!(kept(&apply_policy(&RetentionPolicy::default(), &hourly(5, 1))).is_empty());
+    }
+
+    #[test]
+    fn parse_within_reads_restic_durations() {
+        assert_eq!(parse_within("1y6m").unwrap(), (1, 6, 0, 0));
+        assert_eq!(parse_within("30d12h").unwrap(), (0, 0, 30, 12));
+        assert!(parse_within("5").is_err());
+        assert!(parse_within("3w").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use tokio::process::Command;
+
+/// A local user a job's commands run as
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub name: String,
+    pub uid: u32,
+    pub gid: u32,
+    /// Supplementary groups, as `id -G` lists them
+    pub groups: Vec<u32>,
+    pub home: PathBuf,
+}
+
+#[cfg(unix)]
+pub fn lookup(name: &str) -> Result<Account> {
+    use std::ffi::{CStr, CString};
+
+    let c_name = CString::new(name).map_err(|_| anyhow::anyhow!("Invalid user name '{}'", name))?;
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    let mut result = std::ptr::null_mut();
+    let status = unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut passwd, buffer.as_mut_ptr(), buffer.len(), &mut result) };
+    if status != 0 || result.is_null() {
+        return Err(anyhow::anyhow!("Unknown user '{}'", name));
+    }
+    let home = PathBuf::from(unsafe { CStr::from_ptr(passwd.pw_dir) }.to_string_lossy().to_string());
+
+    let output = std::process::Command::new("id")
+        .arg("-G")
+        .arg(name)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run id: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("id -G {} failed: {}", name, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let groups = String::from_utf8_lossy(&output.stdout).split_whitespace().filter_map(|group| group.parse().ok()).collect();
+
+    Ok(Account {
+        name: name.to_string(),
+        uid: passwd.pw_uid,
+        gid: passwd.pw_gid,
+        groups,
+        home,
+    })
+}
+
+#[cfg(not(unix))]
+pub fn lookup(name: &str) -> Result<Account> {
+    Err(anyhow::anyhow!("run_as '{}': switching users is only supported on Unix", name))
+}
+
+/// Run `cmd` as the account: supplementary groups, then group, then user are switched in the child
+/// before it starts, and HOME, USER and LOGNAME point at the account. Running as that user already
+/// needs nothing; anyone else needs root.
+#[cfg(unix)]
+pub fn apply(cmd: &mut Command, name: &str) -> Result<()> {
+    let account = lookup(name)?;
+    let euid = unsafe { libc::geteuid() };
+    if account.uid == euid {
+        return Ok(());
+    }
+    if euid != 0 {
+        return Err(anyhow::anyhow!("run_as '{}' needs the client to run as root", name));
+    }
+
+    cmd.env("HOME", &account.home).env("USER", &account.name).env("LOGNAME", &account.name);
+    let groups: Vec<libc::gid_t> = account.groups.iter().map(|&group| group as libc::gid_t).collect();
+    let (uid, gid) = (account.uid as libc::uid_t, account.gid as libc::gid_t);
+    // Only async-signal-safe calls between fork and exec; setuid last, since it gives up the right to the others
+    unsafe {
+        cmd.pre_exec(move || {
+            if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0 || libc::setgid(gid) != 0 || libc::setuid(uid) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn apply(_cmd: &mut Command, name: &str) -> Result<()> {
+    lookup(name).map(|_| ())
+}
+
+/// Hand a directory the client created for the job over to the account
+#[cfg(unix)]
+pub fn chown(path: &std::path::Path, name: &str) -> Result<()> {
+    let account = lookup(name)?;
+    std::os::unix::fs::chown(path, Some(account.uid), Some(account.gid))
+        .map_err(|e| anyhow::anyhow!("Failed to hand {:?} over to '{}': {}", path, name, e))
+}
+
+#[cfg(not(unix))]
+pub fn chown(_path: &std::path::Path, name: &str) -> Result<()> {
+    lookup(name).map(|_| ())
+}
@@ -0,0 +1,242 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{notify, reports, window, Config};
+
+/// Start of the catch-up reason of an interrupted job
+pub const INTERRUPTED: &str = "interrupted during";
+
+/// How far a run got; written before each step starts, so after a crash it names the step that was cut short
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    Started,
+    ResticRunning,
+    ResticFinished,
+    PostRunDone,
+    Notified,
+}
+
+impl RunState {
+    /// The step that was in progress in this state
+    pub fn step(&self) -> &'static str {
+        match self {
+            RunState::Started => "preparation (mount, preflight checks)",
+            RunState::ResticRunning => "the restic backup",
+            RunState::ResticFinished => "post-run steps (manifest, repository size, unmount)",
+            RunState::PostRunDone => "metrics and notifications",
+            RunState::Notified => "writing the run report",
+        }
+    }
+}
+
+/// A run in progress, as kept on disk in `<logging.directory>/runs`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub job: String,
+    pub dry_run: bool,
+    pub pid: u32,
+    /// Linux's boot ID; a different one means the machine restarted since
+    #[serde(default)]
+    pub boot_id: Option<String>,
+    pub started_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    pub state: RunState,
+}
+
+impl RunRecord {
+    /// Whether the process that wrote the record is gone
+    fn interrupted(&self, boot_id: Option<&str>) -> bool {
+        let rebooted = matches!((self.boot_id.as_deref(), boot_id), (Some(then), Some(now)) if then != now);
+        rebooted || !crate::daemon::process_alive(self.pid)
+    }
+}
+
+pub fn directory(config: &Config) -> PathBuf {
+    crate::expand_tilde(&config.logging.directory.join("runs"))
+}
+
+fn boot_id() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok().map(|id| id.trim().to_string())
+}
+
+/// Write a file so that a crash leaves either the old or the new content: write a temporary file,
+/// flush it to disk, then rename it over the old one
+fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let temporary = path.with_extension("json.tmp");
+    let mut file = std::fs::File::create(&temporary)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)?;
+    // The rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        std::fs::File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// The journal of one run. Failing to write it is logged but never fails the backup.
+pub struct Journal {
+    path: PathBuf,
+    record: RunRecord,
+}
+
+impl Journal {
+    pub fn begin(config: &Config, run_id: &str, job: &str, dry_run: bool) -> Journal {
+        let directory = directory(config);
+        let safe_job: String = job.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+        let now = Local::now();
+        let journal = Journal {
+            path: directory.join(format!("{}-{}.json", run_id, safe_job)),
+            record: RunRecord {
+                run_id: run_id.to_string(),
+                job: job.to_string(),
+                dry_run,
+                pid: std::process::id(),
+                boot_id: boot_id(),
+                started_at: now,
+                updated_at: now,
+                state: RunState::Started,
+            },
+        };
+        if let Err(e) = std::fs::create_dir_all(&directory) {
+            warn!("Couldn't create run journal directory {:?}: {}", directory, e);
+        }
+        journal.save();
+        journal
+    }
+
+    pub fn advance(&mut self, state: RunState) {
+        self.record.state = state;
+        self.record.updated_at = Local::now();
+        self.save();
+    }
+
+    /// The run finished and reported; nothing is left to recover
+    pub fn complete(self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Couldn't remove run journal {:?}: {}", self.path, e);
+            }
+        }
+    }
+
+    fn save(&self) {
+        let written = serde_json::to_vec_pretty(&self.record)
+            .map_err(std::io::Error::other)
+            .and_then(|content| write_atomic(&self.path, &content));
+        match written {
+            Ok(()) => debug!("Run {} of job '{}': {:?}", self.record.run_id, self.record.job, self.record.state),
+            Err(e) => warn!("Couldn't write run journal {:?}: {}", self.path, e),
+        }
+    }
+}
+
+/// The journal's records, with the files they were read from
+fn records(directory: &Path) -> Vec<(PathBuf, RunRecord)> {
+    let mut records = Vec::new();
+    for entry in std::fs::read_dir(directory).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        match std::fs::read(&path).map_err(anyhow::Error::from).and_then(|content| Ok(serde_json::from_slice::<RunRecord>(&content)?)) {
+            Ok(record) => records.push((path, record)),
+            Err(e) => warn!("Skipping unreadable run journal {:?}: {}", path, e),
+        }
+    }
+    records.sort_by_key(|(_, record)| record.started_at);
+    records
+}
+
+/// Find runs a crash or power loss cut short: send an "interrupted" alert for each, write a failed run
+/// report, and remove the stale repository locks restic left behind. Returns the interrupted runs.
+pub async fn recover(config: &Config) -> Vec<RunRecord> {
+    let current_boot = boot_id();
+    let mut interrupted = Vec::new();
+    for (path, record) in records(&directory(config)) {
+        if !record.interrupted(current_boot.as_deref()) {
+            continue;
+        }
+        // Removing the record claims it, so a daemon and a scheduled run starting together don't both report it
+        if std::fs::remove_file(&path).is_err() {
+            continue;
+        }
+        let error = format!("Interrupted during {}", record.state.step());
+        warn!(
+            "Run {} of job '{}' started {} was interrupted during {} (last update {})",
+            record.run_id,
+            record.job,
+            record.started_at.format("%Y-%m-%d %H:%M:%S"),
+            record.state.step(),
+            record.updated_at.format("%Y-%m-%d %H:%M:%S"),
+        );
+        let duration_secs = (record.updated_at - record.started_at).num_milliseconds().max(0) as f64 / 1000.0;
+
+        // Notified runs already sent their outcome; only their report is missing
+        if record.state == RunState::Notified {
+            warn!("Job '{}' notified its outcome but has no run report for run {}", record.job, record.run_id);
+            interrupted.push(record);
+            continue;
+        }
+
+        if !record.dry_run && !config.notifications.is_empty() {
+            let notification = notify::Notification {
+                job: record.job.clone(),
+                event: notify::RunEvent::Interrupted,
+                duration_secs,
+                error: Some(error.clone()),
+                summary: None,
+                repository_warning: None,
+                prediction_warning: None,
+            };
+            let routing = config.find_job(&record.job).and_then(|job| job.notifications.as_ref());
+            notify::send_all(&config.notifications, routing, &notification).await;
+        }
+
+        if let Some(ref reports_config) = config.reports {
+            let report = reports::RunReport {
+                run_id: record.run_id.clone(),
+                job: record.job.clone(),
+                hostname: crate::hostname(),
+                dry_run: record.dry_run,
+                status: notify::RunEvent::Failed.as_str().to_string(),
+                started_at: record.started_at,
+                finished_at: record.updated_at,
+                duration_secs,
+                snapshot_id: None,
+                note: None,
+                summary: None,
+                warnings: Vec::new(),
+                error: Some(error),
+                phases: Vec::new(),
+                repository_size: None,
+                access_rights: None,
+                prediction: None,
+            };
+            reports::save(reports_config, &report);
+        }
+        // Listed by `status` and run by `--catch-up` until a backup of the job completes
+        if !record.dry_run {
+            window::mark(config, &record.job, &format!("{} {}", INTERRUPTED, record.state.step()));
+        }
+        interrupted.push(record);
+    }
+
+    // restic only removes locks whose process is gone, so runs still going elsewhere keep theirs
+    if interrupted.iter().any(|record| record.state == RunState::ResticRunning) {
+        info!("Removing stale repository locks left by the interrupted run");
+        match crate::restic_command(&config.restic, "unlock").output().await {
+            Ok(output) if output.status.success() => info!("Stale locks removed"),
+            Ok(output) => error!("restic unlock failed: {}", String::from_utf8_lossy(&output.stderr).trim()),
+            Err(e) => error!("Failed to run restic unlock: {}", e),
+        }
+    }
+    interrupted
+}
@@ -0,0 +1,49 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Guards `maintain`, the client's only command that deletes snapshots, against being run by accident.
+/// Without the section, nothing asks, so existing cron jobs keep running.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SafetyConfig {
+    /// Make `maintain`, which forgets and prunes, ask for the repository to be typed in
+    #[serde(default = "default_require_confirmation")]
+    pub require_confirmation: bool,
+}
+
+fn default_require_confirmation() -> bool {
+    true
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        SafetyConfig {
+            require_confirmation: default_require_confirmation(),
+        }
+    }
+}
+
+/// Before an operation that deletes data from the repository, have the user type the repository
+/// if the `safety` section asks for it. `--yes` skips the question for automation; without a
+/// terminal it is required.
+pub fn confirm(safety: Option<&SafetyConfig>, repository: &str, action: &str, args: &[String]) -> Result<()> {
+    if !safety.is_some_and(|safety| safety.require_confirmation) || args.iter().any(|arg| arg == "--yes") {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow::anyhow!(
+            "Refusing to {} without confirmation; pass --yes when running unattended",
+            action
+        ));
+    }
+
+    eprint!("This will {} in {}.\nType the repository to continue: ", action, repository);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim() != repository {
+        return Err(anyhow::anyhow!("The repository didn't match; nothing was changed"));
+    }
+    Ok(())
+}
@@ -0,0 +1,342 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use crate::{run_as, BackupConfig, ResticConfig};
+
+/// Restrict the restic backup process to what a backup needs (Linux only): Landlock limits it
+/// to reading the system and the backup sources and writing the cache, temp and repository
+/// directories; seccomp blocks system calls no backup makes
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct SandboxConfig {
+    /// More paths restic may read, e.g. a password file or SSH keys outside `~/.ssh`
+    #[serde(default)]
+    pub allow_read: Vec<PathBuf>,
+    /// More paths restic may write to
+    #[serde(default)]
+    pub allow_write: Vec<PathBuf>,
+    /// TCP ports restic may connect to (kernel 6.7+). Without it, local repositories get no
+    /// network at all and remote ones are unrestricted.
+    #[serde(default)]
+    pub allowed_ports: Option<Vec<u16>>,
+    /// Fail the backup when the kernel can't sandbox it, instead of running without
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Read-only system locations: binaries, libraries, configuration (DNS, certificates) and /proc
+const SYSTEM_PATHS: &[&str] = &["/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/etc", "/opt", "/nix/store", "/proc", "/sys", "/dev/urandom", "/dev/random"];
+
+/// What the sandbox gives restic, resolved for one job
+#[derive(Debug, Default)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub struct Access {
+    pub read: Vec<PathBuf>,
+    pub write: Vec<PathBuf>,
+    /// `None` leaves the network alone; empty blocks every TCP connection
+    pub ports: Option<Vec<u16>>,
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn find_in_path(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path).map(|dir| dir.join(program)).find(|candidate| candidate.is_file())
+}
+
+/// The paths and ports a job's backup needs
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn access(sandbox_config: &SandboxConfig, job: &BackupConfig, restic_config: &ResticConfig) -> Result<Access> {
+    let home = match job.run_as {
+        Some(ref user) => Some(run_as::lookup(user)?.home),
+        None => std::env::var_os("HOME").map(PathBuf::from),
+    };
+
+    let mut read: Vec<PathBuf> = SYSTEM_PATHS.iter().map(PathBuf::from).collect();
+    let program = restic_config.program();
+    let restic = if program.components().count() > 1 { Some(program) } else { find_in_path(&program.to_string_lossy()) };
+    if let Some(dir) = restic.as_deref().and_then(|restic| restic.parent()) {
+        read.push(dir.to_path_buf());
+    }
+    if let Some(ref home) = home {
+        read.push(home.join(".ssh"));
+        read.push(home.join(".config/rclone"));
+    }
+    read.extend(job.effective_directories().iter().map(|dir| job.resolve_path(dir)));
+    read.extend(job.workdir.iter().map(|dir| crate::expand_tilde(dir)));
+    read.extend(restic_config.password_file.iter().map(|path| crate::expand_tilde(path)));
+    read.extend(sandbox_config.allow_read.iter().map(|path| crate::expand_tilde(path)));
+
+    let cache = match (&job.cache_dir, &restic_config.cache_dir, &home) {
+        (Some(dir), _, _) => Some(job.resolve_path(dir)),
+        (None, Some(dir), _) => Some(crate::expand_tilde(dir)),
+        (None, None, Some(home)) => Some(home.join(".cache/restic")),
+        _ => None,
+    };
+    let tmp = match job.tmp_dir {
+        Some(ref dir) => job.resolve_path(dir),
+        None => std::env::temp_dir(),
+    };
+    let mut write = vec![tmp, PathBuf::from("/dev/null")];
+    if let Some(cache) = cache {
+        // Rules need an existing directory; restic would create it anyway
+        if !cache.exists() {
+            std::fs::create_dir_all(&cache).map_err(|e| anyhow::anyhow!("Failed to create the restic cache {:?}: {}", cache, e))?;
+            if let Some(ref user) = job.run_as {
+                run_as::chown(&cache, user)?;
+            }
+        }
+        write.push(cache);
+    }
+    let local_repository = crate::preflight::local_repository_path(&restic_config.repository_url());
+    write.extend(local_repository.iter().cloned());
+    write.extend(sandbox_config.allow_write.iter().map(|path| crate::expand_tilde(path)));
+
+    let ports = match sandbox_config.allowed_ports {
+        Some(ref ports) => Some(ports.clone()),
+        None if local_repository.is_some() => Some(Vec::new()),
+        None => None,
+    };
+    Ok(Access { read, write, ports })
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use anyhow::Result;
+    use log::debug;
+
+    use super::Access;
+
+    // From linux/landlock.h
+    const CREATE_RULESET_VERSION: u32 = 1;
+    const RULE_PATH_BENEATH: u32 = 1;
+    const RULE_NET_PORT: u32 = 2;
+    const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    /// Everything up to MAKE_SYM, as handled since the first Landlock ABI
+    const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+    const ACCESS_NET_BIND_TCP: u64 = 1 << 0;
+    const ACCESS_NET_CONNECT_TCP: u64 = 1 << 1;
+    /// Rights that apply to a file rather than a directory
+    const ACCESS_FILE: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+        handled_access_net: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[repr(C)]
+    struct NetPortAttr {
+        allowed_access: u64,
+        port: u64,
+    }
+
+    // From linux/audit.h
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    /// System calls a backup never needs: debugging other processes, mounting, loading kernel
+    /// code, namespaces, keyrings and changing the clock or hostname
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_keyctl,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_unshare,
+        libc::SYS_setns,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_userfaultfd,
+        libc::SYS_acct,
+        libc::SYS_settimeofday,
+        libc::SYS_clock_settime,
+        libc::SYS_sethostname,
+        libc::SYS_setdomainname,
+    ];
+
+    /// Landlock ABI version of the running kernel, 0 without Landlock
+    pub fn abi_version() -> i64 {
+        let version = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, std::ptr::null::<RulesetAttr>(), 0usize, CREATE_RULESET_VERSION) };
+        version.max(0)
+    }
+
+    fn add_path(ruleset: &OwnedFd, path: &Path, access: u64) -> Result<()> {
+        let mut c_path = path.as_os_str().as_bytes().to_vec();
+        c_path.push(0);
+        let fd = unsafe { libc::open(c_path.as_ptr() as *const libc::c_char, libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            debug!("Sandbox: skipping {:?}: {}", path, std::io::Error::last_os_error());
+            return Ok(());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let access = if path.is_dir() { access } else { access & ACCESS_FILE };
+        let rule = PathBeneathAttr { allowed_access: access, parent_fd: fd.as_raw_fd() };
+        let status = unsafe { libc::syscall(libc::SYS_landlock_add_rule, ruleset.as_raw_fd(), RULE_PATH_BENEATH, &rule as *const PathBeneathAttr, 0u32) };
+        if status != 0 {
+            return Err(anyhow::anyhow!("Failed to add a sandbox rule for {:?}: {}", path, std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Build a Landlock ruleset for the access; restic enters it in `enter`
+    pub fn ruleset(access: &Access, abi: i64) -> Result<OwnedFd> {
+        let mut handled_fs = ACCESS_FS_V1;
+        if abi >= 2 {
+            handled_fs |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled_fs |= ACCESS_FS_TRUNCATE;
+        }
+        // Network rules need ABI 4; older kernels only get the filesystem restricted
+        let restrict_network = access.ports.is_some() && abi >= 4;
+        let attr = RulesetAttr {
+            handled_access_fs: handled_fs,
+            handled_access_net: if restrict_network { ACCESS_NET_BIND_TCP | ACCESS_NET_CONNECT_TCP } else { 0 },
+        };
+        let size = if restrict_network { std::mem::size_of::<RulesetAttr>() } else { std::mem::size_of::<u64>() };
+        let fd = unsafe { libc::syscall(libc::SYS_landlock_create_ruleset, &attr as *const RulesetAttr, size, 0u32) };
+        if fd < 0 {
+            return Err(anyhow::anyhow!("Failed to create the sandbox: {}", std::io::Error::last_os_error()));
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        for path in &access.read {
+            add_path(&ruleset, path, ACCESS_FS_EXECUTE | ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR)?;
+        }
+        for path in &access.write {
+            add_path(&ruleset, path, handled_fs)?;
+        }
+        if restrict_network {
+            for &port in access.ports.iter().flatten() {
+                let rule = NetPortAttr { allowed_access: ACCESS_NET_CONNECT_TCP, port: port as u64 };
+                let status = unsafe { libc::syscall(libc::SYS_landlock_add_rule, ruleset.as_raw_fd(), RULE_NET_PORT, &rule as *const NetPortAttr, 0u32) };
+                if status != 0 {
+                    return Err(anyhow::anyhow!("Failed to allow port {} in the sandbox: {}", port, std::io::Error::last_os_error()));
+                }
+            }
+        }
+        Ok(ruleset)
+    }
+
+    /// seccomp program returning EPERM for the denied system calls
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    pub fn seccomp_filter() -> Vec<libc::sock_filter> {
+        let statement = |code: u32, k: u32| libc::sock_filter { code: code as u16, jt: 0, jf: 0, k };
+        let jump = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter { code: code as u16, jt, jf, k };
+        let deny = statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32);
+
+        // struct seccomp_data: nr at offset 0, arch at 4
+        let mut filter = vec![
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 4),
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH, 1, 0),
+            statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, 0),
+        ];
+        if cfg!(target_arch = "x86_64") {
+            // x32 system calls would get around the list
+            filter.push(jump(libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K, 0x4000_0000, 0, 1));
+            filter.push(deny);
+        }
+        for &syscall in DENIED_SYSCALLS {
+            filter.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, syscall as u32, 0, 1));
+            filter.push(deny);
+        }
+        filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+        filter
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn seccomp_filter() -> Vec<libc::sock_filter> {
+        Vec::new()
+    }
+
+    /// Runs in the restic child between fork and exec: no privileges can be gained from here on,
+    /// then the system call filter and the Landlock ruleset apply. Only async-signal-safe calls.
+    pub fn enter(ruleset: Option<&OwnedFd>, filter: &mut [libc::sock_filter]) -> std::io::Result<()> {
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if !filter.is_empty() {
+            let program = libc::sock_fprog { len: filter.len() as u16, filter: filter.as_mut_ptr() };
+            if unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program as *const libc::sock_fprog) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(ruleset) = ruleset {
+            if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0u32) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sandbox the restic backup `cmd` will start. Without kernel support this only warns,
+/// unless `required` is set.
+#[cfg(target_os = "linux")]
+pub fn apply(cmd: &mut Command, sandbox_config: &SandboxConfig, job: &BackupConfig, restic_config: &ResticConfig) -> Result<()> {
+    let abi = linux::abi_version();
+    let ruleset = if abi == 0 {
+        if sandbox_config.required {
+            return Err(anyhow::anyhow!("restic.sandbox.required is set, but this kernel has no Landlock support (5.13+ with landlock enabled)"));
+        }
+        warn!("This kernel has no Landlock support; restic's filesystem access isn't restricted");
+        None
+    } else {
+        let access = access(sandbox_config, job, restic_config)?;
+        if access.ports.is_some() && abi < 4 {
+            warn!("Landlock ABI {} can't restrict network access (needs kernel 6.7+); only the filesystem is restricted", abi);
+        }
+        log::debug!("Sandbox (Landlock ABI {}): read {:?}, write {:?}, ports {:?}", abi, access.read, access.write, access.ports);
+        Some(linux::ruleset(&access, abi)?)
+    };
+    let mut filter = linux::seccomp_filter();
+    unsafe {
+        cmd.pre_exec(move || linux::enter(ruleset.as_ref(), &mut filter));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(_cmd: &mut Command, sandbox_config: &SandboxConfig, _job: &BackupConfig, _restic_config: &ResticConfig) -> Result<()> {
+    if sandbox_config.required {
+        return Err(anyhow::anyhow!("restic.sandbox.required is set, but sandboxing is only supported on Linux"));
+    }
+    warn!("restic.sandbox is only supported on Linux; restic runs without it");
+    Ok(())
+}
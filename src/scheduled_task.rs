@@ -0,0 +1,189 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::service::ServiceOptions;
+use crate::{eventlog, BackupConfig, Config};
+
+/// Task Scheduler folder the tasks are created in
+const TASK_FOLDER: &str = "better-restic-client";
+
+/// Power and network conditions of the generated Scheduled Tasks
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ScheduledTaskConfig {
+    /// Wake the computer from sleep for the backup
+    #[serde(default = "default_true")]
+    pub wake_to_run: bool,
+    /// Start while running on battery
+    #[serde(default)]
+    pub run_on_battery: bool,
+    /// Stop a running backup when the power cord is pulled
+    #[serde(default = "default_true")]
+    pub stop_on_battery: bool,
+    /// Only start with a network connection, e.g. for cloud repositories
+    #[serde(default)]
+    pub network_required: bool,
+    /// Stop backups running longer than this; no limit by default
+    #[serde(default)]
+    pub time_limit_hours: Option<u32>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ScheduledTaskConfig {
+    fn default() -> Self {
+        ScheduledTaskConfig {
+            wake_to_run: true,
+            run_on_battery: false,
+            stop_on_battery: true,
+            network_required: false,
+            time_limit_hours: None,
+        }
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Task Scheduler trigger for a job's frequency and time, matching the systemd timers
+pub fn trigger(job: &BackupConfig) -> Option<String> {
+    let (hour, minute) = crate::validation::parse_time(&job.time)?;
+    let today = chrono::Local::now().format("%Y-%m-%d");
+    let start = format!("<StartBoundary>{}T{:02}:{:02}:00</StartBoundary>", today, hour, minute);
+    let start_hourly = format!("<StartBoundary>{}T00:{:02}:00</StartBoundary>", today, minute);
+    let trigger = match job.frequency.to_lowercase().as_str() {
+        "hourly" => format!(
+            "<TimeTrigger>{}<Repetition><Interval>PT1H</Interval><StopAtDurationEnd>false</StopAtDurationEnd></Repetition></TimeTrigger>",
+            start_hourly
+        ),
+        "daily" => format!("<CalendarTrigger>{}<ScheduleByDay><DaysInterval>1</DaysInterval></ScheduleByDay></CalendarTrigger>", start),
+        "weekly" => format!(
+            "<CalendarTrigger>{}<ScheduleByWeek><WeeksInterval>1</WeeksInterval><DaysOfWeek><Monday /></DaysOfWeek></ScheduleByWeek></CalendarTrigger>",
+            start
+        ),
+        "monthly" => format!(
+            "<CalendarTrigger>{}<ScheduleByMonth><DaysOfMonth><Day>1</Day></DaysOfMonth><Months>\
+             <January /><February /><March /><April /><May /><June /><July /><August /><September /><October /><November /><December />\
+             </Months></ScheduleByMonth></CalendarTrigger>",
+            start
+        ),
+        _ => return None,
+    };
+    Some(trigger)
+}
+
+/// The principal: SYSTEM reads everything; other users run without being logged in (S4U),
+/// which means no access to their network drives or stored credentials
+fn principal(user: &str) -> String {
+    let account = if user.eq_ignore_ascii_case("system") {
+        "<UserId>S-1-5-18</UserId>".to_string()
+    } else {
+        format!("<UserId>{}</UserId><LogonType>S4U</LogonType>", xml_escape(user))
+    };
+    format!("<Principal id=\"Author\">{}<RunLevel>HighestAvailable</RunLevel></Principal>", account)
+}
+
+/// Task Scheduler XML for one job
+pub fn render_task(job: &BackupConfig, trigger: &str, task_config: &ScheduledTaskConfig, options: &ServiceOptions) -> String {
+    let time_limit = task_config.time_limit_hours.map(|hours| format!("PT{}H", hours)).unwrap_or_else(|| "PT0S".to_string());
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\r\n\
+         <Task version=\"1.2\" xmlns=\"http://schemas.microsoft.com/windows/2004/02/mit/task\">\r\n\
+         \x20 <RegistrationInfo><Description>Better Restic Client backup job '{description}' ({frequency} at {time})</Description></RegistrationInfo>\r\n\
+         \x20 <Triggers>{trigger}</Triggers>\r\n\
+         \x20 <Principals>{principal}</Principals>\r\n\
+         \x20 <Settings>\r\n\
+         \x20   <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>\r\n\
+         \x20   <DisallowStartIfOnBatteries>{disallow_on_battery}</DisallowStartIfOnBatteries>\r\n\
+         \x20   <StopIfGoingOnBatteries>{stop_on_battery}</StopIfGoingOnBatteries>\r\n\
+         \x20   <StartWhenAvailable>true</StartWhenAvailable>\r\n\
+         \x20   <RunOnlyIfNetworkAvailable>{network}</RunOnlyIfNetworkAvailable>\r\n\
+         \x20   <WakeToRun>{wake}</WakeToRun>\r\n\
+         \x20   <ExecutionTimeLimit>{time_limit}</ExecutionTimeLimit>\r\n\
+         \x20   <Priority>7</Priority>\r\n\
+         \x20 </Settings>\r\n\
+         \x20 <Actions Context=\"Author\">\r\n\
+         \x20   <Exec><Command>{binary}</Command><Arguments>--job \"{name}\"</Arguments><WorkingDirectory>{workdir}</WorkingDirectory></Exec>\r\n\
+         \x20 </Actions>\r\n\
+         </Task>\r\n",
+        description = xml_escape(&job.name),
+        frequency = xml_escape(&job.frequency),
+        time = xml_escape(&job.time),
+        trigger = trigger,
+        principal = principal(&options.user),
+        disallow_on_battery = !task_config.run_on_battery,
+        stop_on_battery = task_config.stop_on_battery,
+        network = task_config.network_required,
+        wake = task_config.wake_to_run,
+        time_limit = time_limit,
+        binary = xml_escape(&options.binary.display().to_string()),
+        name = xml_escape(&job.name),
+        workdir = xml_escape(&options.working_directory.display().to_string()),
+    )
+}
+
+/// schtasks reads task XML as UTF-16
+fn utf16_with_bom(xml: &str) -> Vec<u8> {
+    std::iter::once(0xFEFF).chain(xml.encode_utf16()).flat_map(u16::to_le_bytes).collect()
+}
+
+fn register(task_name: &str, xml_path: &Path) -> Result<()> {
+    let output = std::process::Command::new("schtasks")
+        .args(["/Create", "/F", "/TN", task_name, "/XML"])
+        .arg(xml_path)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Failed to run schtasks: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("schtasks failed for {}: {} (run from an elevated prompt)", task_name, stderr.trim()));
+    }
+    Ok(())
+}
+
+/// Create a Scheduled Task per job, or write (`output`) or print their XML
+pub fn install(config: &Config, jobs: &[&BackupConfig], options: &ServiceOptions, print: bool, output: Option<&Path>) -> Result<()> {
+    let task_config = config.scheduled_task.clone().unwrap_or_default();
+    let mut registered = Vec::new();
+
+    for job in jobs {
+        let trigger = trigger(job).ok_or_else(|| {
+            anyhow::anyhow!("Job '{}' has no schedule Task Scheduler understands ({} at {})", job.name, job.frequency, job.time)
+        })?;
+        let xml = render_task(job, &trigger, &task_config, options);
+        let task_name = format!("{}\\{}", TASK_FOLDER, crate::service::unit_name(job).trim_start_matches("better-restic-client-"));
+        if print {
+            println!("<!-- {} -->\n{}", task_name, xml.replace("\r\n", "\n"));
+            continue;
+        }
+        let path = output.unwrap_or(&std::env::temp_dir()).join(format!("{}.xml", crate::service::unit_name(job)));
+        std::fs::write(&path, utf16_with_bom(&xml)).map_err(|e| anyhow::anyhow!("Failed to write {:?}: {}", path, e))?;
+        if output.is_some() {
+            println!("Wrote {:?}", path);
+            continue;
+        }
+        let result = register(&task_name, &path);
+        let _ = std::fs::remove_file(&path);
+        result?;
+        println!("Created scheduled task {}", task_name);
+        registered.push(task_name);
+    }
+
+    if !registered.is_empty() && config.logging.event_log.is_some() {
+        // Registering the event source needs administrator rights, which this run has
+        if let Err(e) = eventlog::write(log::Level::Info, &format!("Scheduled tasks installed: {}", registered.join(", "))) {
+            println!("Couldn't register the '{}' event source: {}", eventlog::SOURCE, e);
+        }
+    }
+    if output.is_some() {
+        println!("\nImport with:\n  schtasks /Create /TN \"{}\\<job>\" /XML <file>", TASK_FOLDER);
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{Local, Timelike};
+use log::{debug, info, warn};
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+use crate::BackupConfig;
+
+/// Enough for any sensible script; an endless loop fails instead of hanging the run
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// The run as the script's `run` argument
+#[derive(Debug, Serialize)]
+struct RunInfo<'a> {
+    job: &'a str,
+    hostname: String,
+    directories: Vec<String>,
+    exclude: Vec<String>,
+    tags: Vec<String>,
+    /// RFC 3339, local time
+    now: String,
+    /// "Mon" to "Sun"
+    weekday: String,
+    hour: u32,
+}
+
+impl<'a> RunInfo<'a> {
+    fn new(job: &'a BackupConfig) -> RunInfo<'a> {
+        let now = Local::now();
+        let strings = |paths: &[PathBuf]| paths.iter().map(|path| path.display().to_string()).collect();
+        RunInfo {
+            job: &job.name,
+            hostname: crate::hostname(),
+            directories: strings(&job.effective_directories()),
+            exclude: strings(&job.exclude),
+            tags: job.tags.clone(),
+            now: now.to_rfc3339(),
+            weekday: now.format("%a").to_string(),
+            hour: now.hour(),
+        }
+    }
+}
+
+/// What the script's `prepare(run)` adds to the job for this run
+#[derive(Debug, Default, Deserialize)]
+struct Additions {
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// A sandboxed engine: no file or network access, limited operations, and a few functions for
+/// what the run context doesn't cover
+fn engine(job: &BackupConfig) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    let env = job.env.clone();
+    // The job's `env` first, like the commands the job runs
+    engine.register_fn("env", move |name: &str| -> Dynamic {
+        env.get(name).cloned().or_else(|| std::env::var(name).ok()).map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+    });
+    let resolver = job.clone();
+    engine.register_fn("path_exists", move |path: &str| resolver.resolve_path(std::path::Path::new(path)).exists());
+    engine.register_fn("on_ac_power", || -> Dynamic { crate::conditions::on_ac_power().map(Dynamic::from).unwrap_or(Dynamic::UNIT) });
+    engine.on_print(|text| info!("Script: {}", text));
+    engine
+}
+
+/// The job's script, compiled, if it has one
+fn load(job: &BackupConfig) -> Result<Option<(Engine, AST)>> {
+    let Some(ref script) = job.script else { return Ok(None) };
+    let path = job.resolve_path(script);
+    let source = std::fs::read_to_string(&path).map_err(|e| anyhow::anyhow!("Failed to read script {:?}: {}", path, e))?;
+    let engine = engine(job);
+    let ast = engine.compile(&source).map_err(|e| anyhow::anyhow!("Script {:?} doesn't compile: {}", path, e))?;
+    Ok(Some((engine, ast)))
+}
+
+fn defines(ast: &AST, name: &str) -> bool {
+    ast.iter_functions().any(|function| function.name == name && function.params.len() == 1)
+}
+
+fn call(engine: &Engine, ast: &AST, name: &str, job: &BackupConfig) -> Result<Dynamic> {
+    let run = rhai::serde::to_dynamic(RunInfo::new(job)).map_err(|e| anyhow::anyhow!("{}", e))?;
+    engine
+        .call_fn::<Dynamic>(&mut Scope::new(), ast, name, (run,))
+        .map_err(|e| anyhow::anyhow!("{}() in the script of job '{}' failed: {}", name, job.name, e))
+}
+
+/// Why the job's script wants this run skipped: `skip(run)` returned a reason or `true`.
+/// A broken script doesn't skip anything, so the run fails and the error is reported.
+pub fn skip(job: &BackupConfig) -> Option<String> {
+    let (engine, ast) = match load(job) {
+        Ok(loaded) => loaded?,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
+    if !defines(&ast, "skip") {
+        return None;
+    }
+    let decision = match call(&engine, &ast, "skip", job) {
+        Ok(decision) => decision,
+        Err(e) => {
+            warn!("{}", e);
+            return None;
+        }
+    };
+    if decision.is_string() {
+        Some(format!("script: {}", decision))
+    } else if decision.as_bool() == Ok(true) {
+        Some("the script's skip() returned true".to_string())
+    } else {
+        None
+    }
+}
+
+/// The job with what the script's `prepare(run)` returned, `#{ exclude: [...], tags: [...] }`,
+/// added to its excludes and tags; `None` if there's nothing to add
+pub fn prepare(job: &BackupConfig) -> Result<Option<BackupConfig>> {
+    let Some((engine, ast)) = load(job)? else { return Ok(None) };
+    if !defines(&ast, "prepare") {
+        return Ok(None);
+    }
+    let returned = call(&engine, &ast, "prepare", job)?;
+    if returned.is_unit() {
+        return Ok(None);
+    }
+    let additions: Additions = rhai::serde::from_dynamic(&returned)
+        .map_err(|e| anyhow::anyhow!("prepare() in the script of job '{}' must return #{{ exclude: [...], tags: [...] }}: {}", job.name, e))?;
+    if additions.exclude.is_empty() && additions.tags.is_empty() {
+        return Ok(None);
+    }
+    debug!("Script of job '{}' adds excludes {:?} and tags {:?}", job.name, additions.exclude, additions.tags);
+    let mut prepared = job.clone();
+    prepared.exclude.extend(additions.exclude.into_iter().map(PathBuf::from));
+    prepared.tags.extend(additions.tags);
+    Ok(Some(prepared))
+}
+
+/// Whether the job's script compiles, for validation
+pub fn check(job: &BackupConfig) -> Result<()> {
+    load(job).map(|_| ())
+}
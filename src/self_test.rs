@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::Result;
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::bootstrap::{print_summary, restic_step, restic_version, skipped, step};
+use crate::maintenance::{StepResult, StepStatus};
+use crate::{restic, snapshots, ResticConfig};
+
+/// Every step in order, so those left after a failure can be listed as skipped
+const STEPS: [&str; 8] = ["restic", "fixture", "init", "backup", "restore", "compare", "forget", "check"];
+
+/// The fixture tree: paths and sizes. The content is generated from the path, and the large file
+/// spans several of restic's chunks.
+const FIXTURE: [(&str, usize); 6] = [
+    ("empty", 0),
+    ("hello.txt", 13),
+    ("docs/notes.md", 4096),
+    ("docs/ünïcødé name.txt", 100),
+    ("data/small.bin", 777),
+    ("data/nested/deep/random.bin", 3 * 1024 * 1024),
+];
+
+/// Deterministic bytes for a fixture file, from a xorshift generator seeded by its path
+fn content(path: &str, size: usize) -> Vec<u8> {
+    let mut state = path.bytes().fold(0x9E37_79B9_7F4A_7C15u64, |state, byte| (state ^ byte as u64).wrapping_mul(0x100_0000_01B3));
+    (0..size)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn write_fixture(source: &Path) -> std::io::Result<usize> {
+    let mut total = 0;
+    for (path, size) in FIXTURE {
+        let file = source.join(path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&file, content(path, size))?;
+        total += size;
+    }
+    Ok(total)
+}
+
+/// SHA-256 of every file below `root`, by path relative to it
+fn checksums(root: &Path) -> std::io::Result<BTreeMap<String, String>> {
+    let mut sums = BTreeMap::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+                continue;
+            }
+            let relative = path.strip_prefix(root).unwrap_or(&path).components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            let sum = digest::digest(&digest::SHA256, &std::fs::read(&path)?);
+            sums.insert(relative, sum.as_ref().iter().map(|byte| format!("{:02x}", byte)).collect());
+        }
+    }
+    Ok(sums)
+}
+
+/// Where restic restores `source` below `target`: the whole absolute path, with a drive letter as a directory
+fn restored_path(target: &Path, source: &Path) -> PathBuf {
+    let mut path = target.to_path_buf();
+    for component in source.components() {
+        match component {
+            Component::Prefix(prefix) => path.push(prefix.as_os_str().to_string_lossy().trim_end_matches(':')),
+            Component::Normal(part) => path.push(part),
+            _ => {}
+        }
+    }
+    path
+}
+
+/// Differences between the fixture and its restored copy, at most a few
+fn compare(expected: &BTreeMap<String, String>, actual: &BTreeMap<String, String>) -> Vec<String> {
+    let mut differences: Vec<String> = expected
+        .iter()
+        .filter_map(|(path, sum)| match actual.get(path) {
+            None => Some(format!("{} is missing", path)),
+            Some(restored) if restored != sum => Some(format!("{} differs", path)),
+            _ => None,
+        })
+        .collect();
+    differences.extend(actual.keys().filter(|path| !expected.contains_key(*path)).map(|path| format!("{} wasn't backed up", path)));
+    differences.truncate(5);
+    differences
+}
+
+/// A backup of the fixture, returning the snapshot id and restic's summary
+async fn backup(restic_config: &ResticConfig, source: &Path) -> Result<(String, String), String> {
+    let stdout = restic_step(restic_config, "backup", &[&source.to_string_lossy(), "--json"]).await?;
+    let summary = restic::BackupSummary::from_output(&stdout).ok_or("restic printed no backup summary")?;
+    let id = summary.snapshot_id.clone().ok_or("restic's summary has no snapshot id")?;
+    Ok((id, summary.describe()))
+}
+
+/// Record a step's outcome, returning its value if it succeeded
+fn finish<T>(steps: &mut Vec<StepResult>, name: &str, started: Instant, result: Result<(T, String), String>) -> Option<T> {
+    match result {
+        Ok((value, message)) => {
+            steps.push(step(name, StepStatus::Succeeded, started, Some(message)));
+            Some(value)
+        }
+        Err(e) => {
+            steps.push(step(name, StepStatus::Failed, started, Some(e)));
+            None
+        }
+    }
+}
+
+/// The steps up to the first failure
+async fn run_steps(directory: &Path, binary: Option<&String>, steps: &mut Vec<StepResult>) {
+    let source = directory.join("source");
+    // A random password, so the throwaway repository is as encrypted as a real one
+    let mut random = [0u8; 24];
+    if SystemRandom::new().fill(&mut random).is_err() {
+        steps.push(skipped("restic", "no random numbers available for the password"));
+        return;
+    }
+    let password: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let restic_config: ResticConfig = match serde_json::from_value(serde_json::json!({
+        "repository": directory.join("repo"),
+        "password": password,
+        "cache_dir": directory.join("cache"),
+        "binary": binary,
+    })) {
+        Ok(restic_config) => restic_config,
+        Err(e) => {
+            steps.push(skipped("restic", &format!("can't configure the repository: {}", e)));
+            return;
+        }
+    };
+
+    let started = Instant::now();
+    if finish(steps, "restic", started, restic_version(&restic_config).await.map(|version| ((), version))).is_none() {
+        return;
+    }
+
+    println!("Generating the fixture tree...");
+    let started = Instant::now();
+    let written = write_fixture(&source)
+        .map(|total| ((), format!("{} files, {}", FIXTURE.len(), restic::format_bytes(total as u64))))
+        .map_err(|e| format!("Failed to write {:?}: {}", source, e));
+    if finish(steps, "fixture", started, written).is_none() {
+        return;
+    }
+
+    println!("Creating a repository in {:?}...", directory.join("repo"));
+    let started = Instant::now();
+    let init = restic_step(&restic_config, "init", &[]).await.map(|_| ((), "created the repository".to_string()));
+    if finish(steps, "init", started, init).is_none() {
+        return;
+    }
+
+    println!("Backing up the fixture...");
+    let started = Instant::now();
+    let Some(first) = finish(steps, "backup", started, backup(&restic_config, &source).await) else { return };
+
+    println!("Restoring it...");
+    let target = directory.join("restore");
+    let started = Instant::now();
+    let restore = restic_step(&restic_config, "restore", &[&first, "--target", &target.to_string_lossy()])
+        .await
+        .map(|_| ((), format!("restored snapshot {}", restic::short_id(&first))));
+    if finish(steps, "restore", started, restore).is_none() {
+        return;
+    }
+
+    let started = Instant::now();
+    let restored = restored_path(&target, &source);
+    let compared = match (checksums(&source), checksums(&restored)) {
+        (Ok(expected), Ok(actual)) => match compare(&expected, &actual) {
+            differences if differences.is_empty() => Ok(((), format!("{} files have the same SHA-256 as the originals", expected.len()))),
+            differences => Err(differences.join("; ")),
+        },
+        (Err(e), _) => Err(format!("Failed to read {:?}: {}", source, e)),
+        (_, Err(e)) => Err(format!("Failed to read {:?}: {}", restored, e)),
+    };
+    if finish(steps, "compare", started, compared).is_none() {
+        return;
+    }
+
+    // A second snapshot with a change, then forget the first one
+    println!("Testing forget and prune...");
+    let started = Instant::now();
+    let forgotten = async {
+        std::fs::write(source.join("hello.txt"), "changed since the first backup\n").map_err(|e| format!("Failed to change the fixture: {}", e))?;
+        let (second, _) = backup(&restic_config, &source).await?;
+        restic_step(&restic_config, "forget", &["--keep-last", "1", "--prune"]).await?;
+        let mut cmd = crate::restic_command(&restic_config, "snapshots");
+        cmd.arg("--json");
+        let left = snapshots::stream(cmd).await.map_err(|e| e.to_string())?;
+        match left.as_slice() {
+            [kept] if kept.id == second => Ok(((), format!("kept the newest of 2 snapshots ({}) and pruned", restic::short_id(&second)))),
+            _ => Err(format!("expected only snapshot {} to be left, found {}", restic::short_id(&second), left.len())),
+        }
+    };
+    if finish(steps, "forget", started, forgotten.await).is_none() {
+        return;
+    }
+
+    println!("Checking the repository...");
+    let started = Instant::now();
+    let checked = restic_step(&restic_config, "check", &["--read-data"]).await.map(|_| ((), "no errors found, all data read".to_string()));
+    finish(steps, "check", started, checked);
+}
+
+/// `self-test [--keep] [--restic-binary PATH]`: back up, restore and check a generated tree with a throwaway repository,
+/// to see that restic and the client work together, e.g. after installing or upgrading restic
+pub async fn run(args: &[String]) -> Result<()> {
+    let keep = args.iter().any(|arg| arg == "--keep");
+    let base = std::env::temp_dir();
+    // restic stores the path as given, so symlinks like macOS's /var are resolved first
+    let base = base.canonicalize().unwrap_or(base);
+    let directory = base.join(format!("better-restic-client-self-test-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).map_err(|e| anyhow::anyhow!("Failed to create {:?}: {}", directory, e))?;
+    println!("Self-test in {:?}\n", directory);
+
+    let mut steps = Vec::new();
+    run_steps(&directory, crate::flag_value(args, "--restic-binary"), &mut steps).await;
+    let failed = steps.iter().any(|step| step.status == StepStatus::Failed);
+    for name in &STEPS[steps.len()..] {
+        steps.push(skipped(name, "an earlier step failed"));
+    }
+    print_summary(&steps);
+
+    if keep || failed {
+        println!("\nThe test repository and files are left in {:?}", directory);
+    } else if let Err(e) = std::fs::remove_dir_all(&directory) {
+        println!("\nCouldn't remove {:?}: {}", directory, e);
+    }
+    if failed {
+        return Err(anyhow::anyhow!("Self-test failed"));
+    }
+    println!("\nrestic and the client work together.");
+    Ok(())
+}
@@ -0,0 +1,232 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::permissions::{self, UnreadablePath};
+use crate::{BackupConfig, Config};
+
+/// Where generated units go unless `--output` says otherwise
+pub const DEFAULT_UNIT_DIR: &str = "/etc/systemd/system";
+
+/// What `install-service` knows about the service user's access to the backup set
+pub enum ReadAccess {
+    /// The service runs as root and can read everything
+    Root,
+    /// Checked as the service user; these paths are unreadable
+    Checked(Vec<UnreadablePath>),
+    /// Generated as root for another user, so access couldn't be checked
+    Unknown,
+}
+
+pub struct ServiceOptions {
+    pub user: String,
+    pub binary: PathBuf,
+    pub working_directory: PathBuf,
+    pub read_access: ReadAccess,
+}
+
+/// Unit file base name for a job
+pub fn unit_name(job: &BackupConfig) -> String {
+    let name: String = job
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("better-restic-client-{}", name)
+}
+
+/// systemd `OnCalendar=` expression for a job's frequency and time
+pub fn on_calendar(job: &BackupConfig) -> Option<String> {
+    let (hour, minute) = crate::validation::parse_time(&job.time)?;
+    match job.frequency.to_lowercase().as_str() {
+        "hourly" => Some(format!("*-*-* *:{:02}:00", minute)),
+        "daily" => Some(format!("*-*-* {:02}:{:02}:00", hour, minute)),
+        "weekly" => Some(format!("Mon *-*-* {:02}:{:02}:00", hour, minute)),
+        "monthly" => Some(format!("*-*-01 {:02}:{:02}:00", hour, minute)),
+        _ => None,
+    }
+}
+
+/// Paths the hardened service still needs to write to
+fn writable_paths(config: &Config, job: &BackupConfig, options: &ServiceOptions) -> Vec<PathBuf> {
+    let mut paths = vec![options.working_directory.clone(), crate::expand_tilde(&config.logging.directory)];
+    paths.extend(job.tmp_dir.iter().chain(&job.cache_dir).map(|dir| job.resolve_path(dir)));
+    if let Some(ref reports) = config.reports {
+        paths.push(crate::expand_tilde(&reports.directory));
+    }
+    if let Some(ref dir) = config.restic.cache_dir {
+        paths.push(crate::expand_tilde(dir));
+    }
+    if let Some(repository) = crate::preflight::local_repository_path(&config.restic.repository_url()) {
+        paths.push(repository);
+    }
+    if let Some(ref metrics) = config.metrics {
+        if let Some(parent) = metrics.textfile.as_deref().and_then(Path::parent) {
+            paths.push(parent.to_path_buf());
+        }
+    }
+    paths
+}
+
+/// Capability lines granting read access to every file without running as root
+fn capability_section(read_access: &ReadAccess) -> String {
+    const CAPABILITIES: &str = "AmbientCapabilities=CAP_DAC_READ_SEARCH\nCapabilityBoundingSet=CAP_DAC_READ_SEARCH\n";
+    let commented = || CAPABILITIES.lines().map(|line| format!("#{}\n", line)).collect::<String>();
+
+    match read_access {
+        ReadAccess::Root => String::new(),
+        ReadAccess::Checked(unreadable) if !unreadable.is_empty() => format!(
+            "\n# {} path(s) in the backup set are not readable by this user, e.g. {:?}.\n\
+             # CAP_DAC_READ_SEARCH lets restic read them without running as root.\n{}",
+            unreadable.len(),
+            unreadable[0].path,
+            CAPABILITIES
+        ),
+        ReadAccess::Checked(_) => format!(
+            "\n# Everything in the backup set is readable by this user. For full-system backups,\n\
+             # uncomment to read every file without running as root.\n{}",
+            commented()
+        ),
+        ReadAccess::Unknown => format!(
+            "\n# Read access wasn't checked (generated as root). If this user can't read the whole\n\
+             # backup set (see `--check-permissions`), uncomment to read every file without root.\n{}",
+            commented()
+        ),
+    }
+}
+
+/// Double-quote a word for a unit file, escaping what systemd would otherwise unquote or expand:
+/// `\` and `"`, `%` specifiers, and with `variables` the `$` of ExecStart's environment variables
+fn quote(value: &str, variables: bool) -> String {
+    let mut escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('%', "%%");
+    if variables {
+        escaped = escaped.replace('$', "$$");
+    }
+    format!("\"{}\"", escaped)
+}
+
+/// Hardened oneshot service running a single job
+pub fn render_service(config: &Config, job: &BackupConfig, options: &ServiceOptions) -> String {
+    let writable: Vec<String> = writable_paths(config, job, options)
+        .iter()
+        // `-` keeps the unit starting when a path doesn't exist yet
+        .map(|path| quote(&format!("-{}", path.display()), false))
+        .collect();
+    // Without a configured cache dir, restic would use the service user's home, which is read-only here
+    let cache = if config.restic.cache_dir.is_none() && job.cache_dir.is_none() {
+        "CacheDirectory=better-restic-client\nEnvironment=RESTIC_CACHE_DIR=/var/cache/better-restic-client\n"
+    } else {
+        ""
+    };
+
+    format!(
+        "[Unit]\n\
+         Description=Better Restic Client backup job '{name}'\n\
+         Wants=network-online.target\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=oneshot\n\
+         User={user}\n\
+         WorkingDirectory={workdir}\n\
+         ExecStart={binary} --job {job}\n\
+         Nice=10\n\
+         IOSchedulingClass=idle\n\
+         {cache}\
+         \n\
+         # Hardening: the backup only needs to read the backup set and write its own state\n\
+         NoNewPrivileges=yes\n\
+         PrivateTmp=yes\n\
+         ProtectSystem=strict\n\
+         ProtectHome=read-only\n\
+         ReadWritePaths={writable}\n\
+         ProtectKernelTunables=yes\n\
+         ProtectKernelModules=yes\n\
+         ProtectControlGroups=yes\n\
+         RestrictSUIDSGID=yes\n\
+         LockPersonality=yes\n\
+         {capabilities}",
+        name = job.name,
+        user = options.user,
+        // WorkingDirectory takes the rest of the line as is, so only specifiers need escaping
+        workdir = options.working_directory.display().to_string().replace('%', "%%"),
+        binary = quote(&options.binary.to_string_lossy(), true),
+        job = quote(&job.name, true),
+        cache = cache,
+        writable = writable.join(" "),
+        capabilities = capability_section(&options.read_access),
+    )
+}
+
+pub fn render_timer(job: &BackupConfig, on_calendar: &str) -> String {
+    format!(
+        "[Unit]\n\
+         Description=Run better-restic-client job '{}' {}\n\
+         \n\
+         [Timer]\n\
+         OnCalendar={}\n\
+         Persistent=true\n\
+         \n\
+         [Install]\n\
+         WantedBy=timers.target\n",
+        job.name, job.frequency, on_calendar
+    )
+}
+
+/// Write (or print) a service and timer unit per job and explain how to enable them
+pub fn install(config: &Config, jobs: &[&BackupConfig], options: &ServiceOptions, output: Option<&Path>) -> Result<()> {
+    let mut timers = Vec::new();
+
+    for job in jobs {
+        let on_calendar = on_calendar(job).ok_or_else(|| {
+            anyhow::anyhow!("Job '{}' has no schedule systemd understands ({} at {})", job.name, job.frequency, job.time)
+        })?;
+        let name = unit_name(job);
+        let units = [
+            (format!("{}.service", name), render_service(config, job, options)),
+            (format!("{}.timer", name), render_timer(job, &on_calendar)),
+        ];
+
+        for (file_name, contents) in units {
+            match output {
+                Some(dir) => {
+                    let path = dir.join(&file_name);
+                    std::fs::write(&path, contents).map_err(|e| {
+                        anyhow::anyhow!("Failed to write {:?}: {} (run with sudo, or use --print)", path, e)
+                    })?;
+                    println!("Wrote {:?}", path);
+                }
+                None => println!("# {}\n{}", file_name, contents),
+            }
+        }
+        timers.push(format!("{}.timer", name));
+    }
+
+    if output.is_some() {
+        println!("\nEnable with:\n  sudo systemctl daemon-reload\n  sudo systemctl enable --now {}", timers.join(" "));
+    }
+    Ok(())
+}
+
+/// Work out the service user's read access to the jobs' directories
+pub fn read_access(user: &str, jobs: &[&BackupConfig]) -> ReadAccess {
+    if user == "root" {
+        ReadAccess::Root
+    } else if permissions::is_root() {
+        ReadAccess::Unknown
+    } else {
+        ReadAccess::Checked(jobs.iter().flat_map(|job| permissions::unreadable_paths(job)).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_escapes_for_systemd() {
+        assert_eq!(quote("/opt/my backups/brc", true), "\"/opt/my backups/brc\"");
+        assert_eq!(quote(r#"a"b\c"#, false), r#""a\"b\\c""#);
+        assert_eq!(quote("100%$HOME", true), "\"100%%$$HOME\"");
+        assert_eq!(quote("100%$HOME", false), "\"100%%$HOME\"");
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+use std::io::{BufReader, Read};
+use std::process::Stdio;
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+use crate::log_search::parse_time;
+use crate::{restic, Config};
+
+/// One entry of `restic snapshots --json`. The CLI's `--output json`, the web API and the other
+/// commands reading the snapshot list all use this, so they share one schema whatever restic adds.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Snapshot {
+    pub id: String,
+    #[serde(default)]
+    pub short_id: String,
+    pub time: DateTime<FixedOffset>,
+    pub tree: Tree,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub paths: Vec<String>,
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+    #[serde(default)]
+    pub excludes: Vec<String>,
+    /// restic leaves this out, or writes null, for untagged snapshots
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub tags: Vec<String>,
+    /// The snapshot this one was rewritten from, e.g. by a tag change
+    #[serde(default)]
+    pub original: Option<String>,
+    #[serde(default)]
+    pub program_version: Option<String>,
+    /// Written by restic 0.17 and later
+    #[serde(default)]
+    pub summary: Option<SnapshotSummary>,
+    /// The run's note, from its `note:` tag; not part of restic's output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// The id of a snapshot's root tree
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Tree(pub String);
+
+/// The backup statistics restic keeps with each snapshot
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct SnapshotSummary {
+    pub backup_start: Option<DateTime<FixedOffset>>,
+    pub backup_end: Option<DateTime<FixedOffset>>,
+    pub files_new: u64,
+    pub files_changed: u64,
+    pub files_unmodified: u64,
+    pub dirs_new: u64,
+    pub dirs_changed: u64,
+    pub dirs_unmodified: u64,
+    pub data_blobs: i64,
+    pub tree_blobs: i64,
+    pub data_added: u64,
+    pub data_added_packed: u64,
+    pub total_files_processed: u64,
+    pub total_bytes_processed: u64,
+}
+
+fn null_as_empty<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(Option::<Vec<String>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl Snapshot {
+    /// The snapshot with its note filled in from its tags
+    pub fn with_note(&self) -> Snapshot {
+        Snapshot {
+            note: crate::notes::from_tags(self.tags.iter().map(String::as_str)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Which snapshots to list, applied here rather than by restic so the CLI and the API agree
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Only the newest N of those matching the other filters
+    latest: Option<usize>,
+    since: Option<DateTime<FixedOffset>>,
+    until: Option<DateTime<FixedOffset>>,
+    /// Any of these hosts
+    hosts: Vec<String>,
+    /// Any of these tag lists, where "a,b" needs both tags like restic's `--tag`
+    tags: Vec<String>,
+    /// Snapshots that include all of these paths
+    paths: Vec<String>,
+}
+
+impl Filter {
+    pub fn new(
+        latest: Option<usize>,
+        since: Option<&str>,
+        until: Option<&str>,
+        hosts: Vec<String>,
+        tags: Vec<String>,
+        paths: Vec<String>,
+    ) -> Result<Filter, String> {
+        Ok(Filter {
+            latest,
+            since: since.map(|since| parse_time(since, false)).transpose()?,
+            until: until.map(|until| parse_time(until, true)).transpose()?,
+            hosts,
+            tags,
+            paths,
+        })
+    }
+
+    fn matches(&self, snapshot: &Snapshot) -> bool {
+        if !self.hosts.is_empty() && !self.hosts.contains(&snapshot.hostname) {
+            return false;
+        }
+        if !self.tags.is_empty() && !self.tags.iter().any(|list| list.split(',').all(|tag| snapshot.tags.iter().any(|t| t == tag))) {
+            return false;
+        }
+        if !self.paths.iter().all(|path| snapshot.paths.iter().any(|p| p == path.trim_end_matches('/'))) {
+            return false;
+        }
+        !(self.since.is_some_and(|since| snapshot.time < since) || self.until.is_some_and(|until| snapshot.time > until))
+    }
+
+    /// The matching snapshots, oldest first like restic lists them
+    pub fn apply<'a>(&self, snapshots: impl IntoIterator<Item = &'a Snapshot>) -> Vec<&'a Snapshot> {
+        let mut matching: Vec<&Snapshot> = snapshots.into_iter().filter(|snapshot| self.matches(snapshot)).collect();
+        if let Some(latest) = self.latest {
+            matching.sort_by_key(|snapshot| snapshot.time);
+            matching.drain(..matching.len().saturating_sub(latest));
+        }
+        matching
+    }
+}
+
+/// Snapshots per host, tag and day (local time), for overviews of repositories too large to list
+#[derive(Debug, Default, Serialize)]
+pub struct Counts {
+    pub total: usize,
+    pub hosts: BTreeMap<String, usize>,
+    pub tags: BTreeMap<String, usize>,
+    pub days: BTreeMap<String, usize>,
+}
+
+pub fn counts<'a>(snapshots: impl IntoIterator<Item = &'a Snapshot>) -> Counts {
+    let mut counts = Counts::default();
+    for snapshot in snapshots {
+        counts.total += 1;
+        *counts.hosts.entry(snapshot.hostname.clone()).or_default() += 1;
+        for tag in &snapshot.tags {
+            *counts.tags.entry(tag.clone()).or_default() += 1;
+        }
+        *counts.days.entry(snapshot.time.with_timezone(&chrono::Local).format("%Y-%m-%d").to_string()).or_default() += 1;
+    }
+    counts
+}
+
+/// Hands the chunks an async task reads from restic to serde_json's blocking reader
+struct ChannelReader {
+    chunks: tokio::sync::mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.chunk.len() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Run a `restic snapshots --json` command, parsing the snapshots as restic prints them so a list of
+/// tens of thousands is never held as one block of text next to its parsed form
+pub async fn stream(mut cmd: Command) -> Result<Vec<Snapshot>> {
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    info!("Restic command: {:?}", cmd);
+    let mut child = cmd.spawn().map_err(|e| anyhow::anyhow!("Failed to execute restic: {}", e))?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let errors = tokio::spawn(async move {
+        let mut errors = Vec::new();
+        let _ = stderr.read_to_end(&mut errors).await;
+        String::from_utf8_lossy(&errors).trim().to_string()
+    });
+
+    let (sender, chunks) = tokio::sync::mpsc::channel(16);
+    let parser = tokio::task::spawn_blocking(move || {
+        let reader = ChannelReader { chunks, chunk: Vec::new(), position: 0 };
+        serde_json::from_reader::<_, Vec<Snapshot>>(BufReader::new(reader))
+    });
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = stdout.read(&mut buffer).await.map_err(|e| anyhow::anyhow!("Failed to read restic output: {}", e))?;
+        // The parser stops at the first error, which it reports below
+        if n == 0 || sender.send(buffer[..n].to_vec()).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+    // Closing the pipe ends restic if the parser gave up before the output did
+    drop(stdout);
+
+    let status = child.wait().await.map_err(|e| anyhow::anyhow!("Failed to wait for restic: {}", e))?;
+    let errors = errors.await.unwrap_or_default();
+    if !status.success() {
+        return Err(anyhow::anyhow!("restic snapshots failed: {}", errors));
+    }
+    parser
+        .await?
+        .map_err(|e| anyhow::anyhow!("Failed to parse restic snapshots output: {}", e))
+}
+
+/// Every snapshot in the repository
+pub async fn list(config: &Config) -> Result<Vec<Snapshot>> {
+    let mut cmd = crate::restic_command(&config.restic, "snapshots");
+    cmd.arg("--json");
+    stream(cmd).await
+}
+
+/// `snapshots [--latest N] [--since TIME] [--until TIME] [--host H] [--tag T] [--path P] [--output json]`
+pub async fn run(config: &Config, args: &[String]) -> Result<()> {
+    let latest = crate::flag_value(args, "--latest")
+        .map(|value| value.parse::<usize>().map_err(|_| anyhow::anyhow!("--latest needs a number, got '{}'", value)))
+        .transpose()?;
+    let owned = |flag: &str| crate::flag_values(args, flag).into_iter().cloned().collect::<Vec<String>>();
+    let filter = Filter::new(
+        latest,
+        crate::flag_value(args, "--since").map(String::as_str),
+        crate::flag_value(args, "--until").map(String::as_str),
+        owned("--host"),
+        owned("--tag"),
+        owned("--path"),
+    )
+    .map_err(|e| anyhow::anyhow!(e))?;
+
+    let all = list(config).await?;
+    let snapshots = filter.apply(&all);
+    if crate::flag_value(args, "--output").is_some_and(|output| output == "json") {
+        let snapshots: Vec<Snapshot> = snapshots.into_iter().map(Snapshot::with_note).collect();
+        println!("{}", serde_json::to_string_pretty(&snapshots)?);
+        return Ok(());
+    }
+
+    let header = format!("{:<8}  {:<19}  {:<20}  {:<20}  Paths", "ID", "Time", "Host", "Tags");
+    println!("{}", crate::style::Painter::stdout().paint(crate::style::Tone::Heading, &header));
+    for snapshot in &snapshots {
+        println!(
+            "{:<8}  {:<19}  {:<20}  {:<20}  {}",
+            restic::short_id(&snapshot.id),
+            snapshot.time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string(),
+            snapshot.hostname,
+            snapshot.tags.join(","),
+            snapshot.paths.join(","),
+        );
+    }
+    println!("{} of {} snapshot(s)", snapshots.len(), all.len());
+    Ok(())
+}
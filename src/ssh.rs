@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+
+use crate::{shell_quote, ResticConfig};
+
+/// How long the connection check may take
+const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn default_true() -> bool {
+    true
+}
+
+/// How ssh treats host keys it doesn't know yet
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrictHostKeyChecking {
+    /// Only connect to hosts already in known_hosts
+    Yes,
+    /// Add unknown hosts, refuse changed keys
+    AcceptNew,
+    /// Connect whatever the key is
+    No,
+}
+
+impl StrictHostKeyChecking {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StrictHostKeyChecking::Yes => "yes",
+            StrictHostKeyChecking::AcceptNew => "accept-new",
+            StrictHostKeyChecking::No => "no",
+        }
+    }
+}
+
+/// SSH settings for an sftp: repository, turned into restic's `sftp.command`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SshConfig {
+    /// Private key to log in with (`-i`)
+    #[serde(default)]
+    pub identity_file: Option<PathBuf>,
+    /// Overrides the port from the repository URL and ~/.ssh/config
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// known_hosts file to check the server's key against, instead of ~/.ssh/known_hosts
+    #[serde(default)]
+    pub known_hosts_file: Option<PathBuf>,
+    #[serde(default)]
+    pub strict_host_key_checking: Option<StrictHostKeyChecking>,
+    /// Share one connection between the restic commands of a run (ControlMaster)
+    #[serde(default)]
+    pub multiplex: bool,
+    /// More `-o` options, e.g. `ServerAliveInterval: "60"`
+    #[serde(default)]
+    pub options: BTreeMap<String, String>,
+    /// Open an SFTP session before each backup, failing early with a specific error
+    #[serde(default = "default_true")]
+    pub check_connection: bool,
+    /// Pin the server's key on the first connection and refuse a different one later. Keys are kept
+    /// in `known_hosts_file`, or in the client's own known_hosts file without one.
+    #[serde(default)]
+    pub trust_on_first_use: bool,
+}
+
+impl Default for SshConfig {
+    fn default() -> Self {
+        SshConfig {
+            identity_file: None,
+            port: None,
+            known_hosts_file: None,
+            strict_host_key_checking: None,
+            multiplex: false,
+            options: BTreeMap::new(),
+            check_connection: true,
+            trust_on_first_use: false,
+        }
+    }
+}
+
+/// The client's known_hosts file for trust on first use, in its state directory
+pub fn pinned_hosts_file() -> PathBuf {
+    let state_dir = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from).unwrap_or_else(std::env::temp_dir)
+    } else {
+        std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| crate::expand_tilde(std::path::Path::new("~/.local/state")))
+    };
+    state_dir.join("better_restic_client").join("known_hosts")
+}
+
+impl SshConfig {
+    /// The known_hosts file ssh checks the server against, when not ssh's default
+    pub fn known_hosts(&self) -> Option<PathBuf> {
+        match self.known_hosts_file {
+            Some(ref file) => Some(crate::expand_tilde(file)),
+            None if self.trust_on_first_use => Some(pinned_hosts_file()),
+            None => None,
+        }
+    }
+}
+
+/// Where an sftp: repository lives
+#[derive(Debug, Clone, PartialEq)]
+pub struct SftpTarget {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl SftpTarget {
+    /// `user@host` as ssh takes it
+    pub fn destination(&self) -> String {
+        match self.user {
+            Some(ref user) => format!("{}@{}", user, self.host),
+            None => self.host.clone(),
+        }
+    }
+}
+
+/// The server of an `sftp:[user@]host:path` or `sftp://[user@]host[:port]//path` repository
+pub fn target(repository: &str) -> Option<SftpTarget> {
+    let rest = repository.strip_prefix("sftp:")?;
+    let (authority, port) = match rest.strip_prefix("//") {
+        Some(url) => {
+            let authority = url.split('/').next().unwrap_or_default();
+            match authority.rsplit_once(':') {
+                Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) && !port.is_empty() => {
+                    (host, port.parse().ok())
+                }
+                _ => (authority, None),
+            }
+        }
+        None => (rest.split(':').next().unwrap_or_default(), None),
+    };
+    let (user, host) = match authority.rsplit_once('@') {
+        Some((user, host)) => (Some(user.to_string()), host),
+        None => (None, authority),
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    Some(SftpTarget {
+        user,
+        host: host.to_string(),
+        port,
+    })
+}
+
+/// ssh and its options for the repository's server, without the destination: `ssh_command`
+/// (or plain `ssh`) followed by what `restic.ssh` sets
+pub fn ssh_args(restic_config: &ResticConfig) -> Vec<String> {
+    let mut args: Vec<String> = match restic_config.ssh_command {
+        Some(ref command) => command.split_whitespace().map(str::to_string).collect(),
+        None => vec!["ssh".to_string()],
+    };
+    let ssh = restic_config.ssh.clone().unwrap_or_default();
+    let target = target(&restic_config.repository_url());
+    if let Some(port) = ssh.port.or(target.and_then(|target| target.port)) {
+        args.extend(["-p".to_string(), port.to_string()]);
+    }
+    if let Some(ref identity_file) = ssh.identity_file {
+        args.extend(["-i".to_string(), crate::expand_tilde(identity_file).to_string_lossy().to_string()]);
+        args.extend(["-o".to_string(), "IdentitiesOnly=yes".to_string()]);
+    }
+    if let Some(known_hosts) = ssh.known_hosts() {
+        args.extend(["-o".to_string(), format!("UserKnownHostsFile={}", known_hosts.to_string_lossy())]);
+    }
+    let checking = ssh.strict_host_key_checking.or(ssh.trust_on_first_use.then_some(StrictHostKeyChecking::AcceptNew));
+    if let Some(checking) = checking {
+        args.extend(["-o".to_string(), format!("StrictHostKeyChecking={}", checking.as_str())]);
+    }
+    if ssh.multiplex {
+        args.extend(["-o", "ControlMaster=auto", "-o", "ControlPath=~/.ssh/restic-%C", "-o", "ControlPersist=60"].map(str::to_string));
+    }
+    for (option, value) in &ssh.options {
+        args.extend(["-o".to_string(), format!("{}={}", option, value)]);
+    }
+    args
+}
+
+/// restic's `sftp.command` for the repository, when `restic.ssh` is set for an sftp: repository.
+/// restic then runs it as-is, so it names the server itself.
+pub fn sftp_command(restic_config: &ResticConfig) -> Option<String> {
+    restic_config.ssh.as_ref()?;
+    let target = target(&restic_config.repository_url())?;
+    let mut args = ssh_args(restic_config);
+    args.extend([target.destination(), "-s".to_string(), "sftp".to_string()]);
+    Some(args.iter().map(|arg| shell_quote(arg)).collect::<Vec<_>>().join(" "))
+}
+
+/// Point restic at the repository's SSH settings
+pub fn apply(cmd: &mut Command, restic_config: &ResticConfig) {
+    if let Some(command) = sftp_command(restic_config) {
+        cmd.arg("-o").arg(format!("sftp.command={}", command));
+    } else if let Some(ref ssh_cmd) = restic_config.ssh_command {
+        cmd.env("RESTIC_SSH_COMMAND", ssh_cmd);
+    }
+}
+
+/// Whether the known_hosts file has a key for the server, as ssh looks it up
+async fn host_known(known_hosts: &std::path::Path, target: &SftpTarget, port: Option<u16>) -> bool {
+    let host = match port {
+        Some(port) if port != 22 => format!("[{}]:{}", target.host, port),
+        _ => target.host.clone(),
+    };
+    Command::new("ssh-keygen")
+        .arg("-F")
+        .arg(&host)
+        .arg("-f")
+        .arg(known_hosts)
+        .output()
+        .await
+        .is_ok_and(|output| output.status.success() && !output.stdout.is_empty())
+}
+
+/// Turn ssh's error output into what to do about it
+fn explain(stderr: &str, target: &SftpTarget, port: Option<u16>, known_hosts: Option<&std::path::Path>) -> String {
+    let server = target.destination();
+    let lower = stderr.to_lowercase();
+    if lower.contains("remote host identification has changed") {
+        let file = known_hosts.map(|file| format!(" -f {:?}", file)).unwrap_or_default();
+        format!(
+            "The host key of {} changed since it was pinned, so this may not be your server. \
+             If it really was reinstalled, remove the old key with `ssh-keygen -R {}{}` and run again.",
+            target.host, target.host, file
+        )
+    } else if lower.contains("host key verification failed") {
+        format!(
+            "The host key of {} is unknown and unknown keys are refused; add it to known_hosts or set restic.ssh.trust_on_first_use",
+            target.host
+        )
+    } else if lower.contains("permission denied") || lower.contains("too many authentication failures") {
+        format!(
+            "{} refused the login. Check that restic.ssh.identity_file is the right key and that its public key is in ~{}/.ssh/authorized_keys on the server.",
+            server,
+            target.user.as_deref().unwrap_or("")
+        )
+    } else if lower.contains("could not resolve hostname") || lower.contains("name or service not known") {
+        format!("Can't resolve {}; check the host in restic.repository", target.host)
+    } else if lower.contains("connection refused") {
+        format!("{} refused the connection on port {}; check that sshd is running and the port is right", target.host, port.unwrap_or(22))
+    } else if lower.contains("timed out") || lower.contains("no route to host") || lower.contains("network is unreachable") {
+        format!("{} can't be reached on port {}; check the network, VPN or firewall", target.host, port.unwrap_or(22))
+    } else if lower.contains("subsystem request failed") {
+        format!("{} doesn't offer SFTP; enable the sftp Subsystem in its sshd_config", target.host)
+    } else {
+        let last_line = stderr.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("no error output");
+        format!("SSH connection to {} failed: {}", server, last_line.trim())
+    }
+}
+
+/// Open an SFTP session to the repository's server the way restic will, so that connection and login
+/// problems fail the run with a specific error instead of restic's generic "connection lost".
+/// With trust on first use, this connection pins the server's key.
+pub async fn check_connection(restic_config: &ResticConfig) -> Result<()> {
+    let Some(ref ssh) = restic_config.ssh else { return Ok(()) };
+    let Some(target) = target(&restic_config.repository_url()) else { return Ok(()) };
+    if !ssh.check_connection {
+        return Ok(());
+    }
+    let port = ssh.port.or(target.port);
+    let known_hosts = ssh.known_hosts();
+    if let Some(parent) = known_hosts.as_ref().and_then(|file| file.parent()) {
+        std::fs::create_dir_all(parent).map_err(|e| anyhow::anyhow!("Failed to create {:?}: {}", parent, e))?;
+    }
+    let known_hosts_file = known_hosts.clone().unwrap_or_default();
+    let pinning = ssh.trust_on_first_use && !host_known(&known_hosts_file, &target, port).await;
+
+    let args = ssh_args(restic_config);
+    let mut cmd = Command::new(&args[0]);
+    cmd.args(&args[1..])
+        .args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=15"])
+        .arg(target.destination())
+        .args(["-s", "sftp"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    debug!("Checking the SFTP connection: {:?}", cmd);
+    let mut child = cmd.spawn().map_err(|e| anyhow::anyhow!("Failed to run {}: {}", args[0], e))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+
+    // SSH_FXP_INIT for protocol version 3; a working server answers with SSH_FXP_VERSION (2)
+    let handshake = async {
+        stdin.write_all(&[0, 0, 0, 5, 1, 0, 0, 0, 3]).await?;
+        let mut header = [0u8; 5];
+        stdout.read_exact(&mut header).await?;
+        Ok::<bool, std::io::Error>(header[4] == 2)
+    };
+    let answered = matches!(tokio::time::timeout(CHECK_TIMEOUT, handshake).await, Ok(Ok(true)));
+    drop(stdin);
+    let _ = child.start_kill();
+    let output = child.wait_with_output().await?;
+    if !answered {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("{}", explain(&stderr, &target, port, known_hosts.as_deref())));
+    }
+    if pinning && host_known(&known_hosts_file, &target, port).await {
+        info!("Pinned the host key of {} in {:?}", target.host, known_hosts_file);
+    }
+    Ok(())
+}
@@ -0,0 +1,339 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{restic, Config};
+
+/// Conflicting files named in the error when a commit is refused
+const LISTED_CONFLICTS: usize = 10;
+
+/// Where `restore --stage` puts files until they are committed or discarded
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StagingConfig {
+    pub directory: PathBuf,
+}
+
+/// Files restored from a snapshot, waiting to be committed to their destination
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StagingArea {
+    pub id: String,
+    pub snapshot_id: String,
+    pub snapshot_time: DateTime<Local>,
+    pub created_at: DateTime<Local>,
+    pub includes: Vec<String>,
+    pub excludes: Vec<String>,
+    pub files: usize,
+    pub bytes: u64,
+    /// Files restic couldn't restore
+    pub errors: Vec<String>,
+}
+
+/// What committing does with a staged file
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Overwrite,
+    /// The destination already has the same size and modification time
+    Unchanged,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct StagedFile {
+    /// Where the file was backed up from, e.g. /home/me/report.docx
+    pub path: String,
+    pub symlink: bool,
+    pub size: u64,
+    pub mtime: Option<DateTime<Local>>,
+    /// Where committing puts it
+    pub destination: PathBuf,
+    pub action: Action,
+}
+
+/// What a commit did
+#[derive(Debug, Serialize, Default)]
+pub struct CommitSummary {
+    pub created: usize,
+    pub overwritten: Vec<PathBuf>,
+    pub unchanged: usize,
+}
+
+/// The configured staging directory, or `staging` next to the logs
+pub fn directory(config: &Config) -> PathBuf {
+    match config.staging {
+        Some(ref staging) => crate::expand_tilde(&staging.directory),
+        None => crate::expand_tilde(&config.logging.directory.join("staging")),
+    }
+}
+
+/// Staging area ids are run ids
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Directory the staged files of an area are restored into
+pub fn area_path(config: &Config, id: &str) -> PathBuf {
+    directory(config).join(id)
+}
+
+fn metadata_path(config: &Config, id: &str) -> PathBuf {
+    directory(config).join(format!("{}.json", id))
+}
+
+pub fn save(config: &Config, area: &StagingArea) -> Result<()> {
+    std::fs::write(metadata_path(config, &area.id), serde_json::to_vec_pretty(area)?)?;
+    Ok(())
+}
+
+pub fn load(config: &Config, id: &str) -> Result<StagingArea> {
+    if !is_valid_id(id) {
+        return Err(anyhow::anyhow!("Invalid staging area id '{}'", id));
+    }
+    let content = std::fs::read(metadata_path(config, id)).map_err(|_| anyhow::anyhow!("No staging area '{}'", id))?;
+    Ok(serde_json::from_slice(&content)?)
+}
+
+/// Staging areas, oldest first
+pub fn list(config: &Config) -> Vec<StagingArea> {
+    let Ok(entries) = std::fs::read_dir(directory(config)) else { return Vec::new() };
+    let mut areas: Vec<StagingArea> = entries
+        .flatten()
+        .filter_map(|entry| std::fs::read(entry.path().with_extension("json")).ok().filter(|_| entry.path().is_dir()))
+        .filter_map(|content| serde_json::from_slice(&content).ok())
+        .collect();
+    areas.sort_by_key(|area| area.created_at);
+    areas
+}
+
+/// Remove a staging area and its files
+pub fn discard(config: &Config, id: &str) -> Result<()> {
+    load(config, id)?;
+    remove(config, id)
+}
+
+pub fn remove(config: &Config, id: &str) -> Result<()> {
+    let path = area_path(config, id);
+    if path.exists() {
+        std::fs::remove_dir_all(&path).map_err(|e| anyhow::anyhow!("Failed to remove {:?}: {}", path, e))?;
+    }
+    let metadata = metadata_path(config, id);
+    if metadata.exists() {
+        std::fs::remove_file(&metadata)?;
+    }
+    Ok(())
+}
+
+/// What committing a staged file to `destination` would do
+fn action(staged: &std::fs::Metadata, staged_path: &Path, destination: &Path) -> Action {
+    let Ok(existing) = std::fs::symlink_metadata(destination) else {
+        return Action::Create;
+    };
+    let same = if staged.is_symlink() {
+        existing.is_symlink() && std::fs::read_link(staged_path).ok() == std::fs::read_link(destination).ok()
+    } else {
+        existing.is_file() && existing.len() == staged.len() && existing.modified().ok() == staged.modified().ok()
+    };
+    if same {
+        Action::Unchanged
+    } else {
+        Action::Overwrite
+    }
+}
+
+/// The staged files and what committing them under `target` ("/" for their original place) would do
+pub fn files(config: &Config, id: &str, target: &Path) -> Result<Vec<StagedFile>> {
+    load(config, id)?;
+    let root = area_path(config, id);
+    let mut files = Vec::new();
+    let mut stack = vec![root.clone()];
+    while let Some(path) = stack.pop() {
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else { continue };
+        if metadata.is_dir() {
+            if let Ok(entries) = std::fs::read_dir(&path) {
+                stack.extend(entries.flatten().map(|entry| entry.path()));
+            }
+            continue;
+        }
+        let Ok(relative) = path.strip_prefix(&root) else { continue };
+        let destination = target.join(relative);
+        files.push(StagedFile {
+            path: format!("/{}", relative.to_string_lossy().replace('\\', "/")),
+            symlink: metadata.is_symlink(),
+            size: metadata.len(),
+            mtime: metadata.modified().ok().map(DateTime::<Local>::from),
+            action: action(&metadata, &path, &destination),
+            destination,
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// Move a staged file into place, copying it when the destination is on another file system
+fn move_file(source: &Path, destination: &Path) -> Result<()> {
+    if std::fs::rename(source, destination).is_ok() {
+        return Ok(());
+    }
+    let metadata = std::fs::symlink_metadata(source)?;
+    if metadata.is_symlink() {
+        #[cfg(unix)]
+        {
+            let link = std::fs::read_link(source)?;
+            if std::fs::symlink_metadata(destination).is_ok() {
+                std::fs::remove_file(destination)?;
+            }
+            std::os::unix::fs::symlink(link, destination)?;
+        }
+    } else {
+        std::fs::copy(source, destination)?;
+        if let Ok(modified) = metadata.modified() {
+            std::fs::File::options().write(true).open(destination)?.set_modified(modified)?;
+        }
+    }
+    std::fs::remove_file(source)?;
+    Ok(())
+}
+
+/// Move a staging area's files under `target`. Files that exist there with different content stop the
+/// commit unless `overwrite` is set, which keeps `.pre-restore` copies of them. The area is removed afterwards.
+pub fn commit(config: &Config, id: &str, target: &Path, overwrite: bool) -> Result<CommitSummary> {
+    let files = files(config, id, target)?;
+    let conflicts: Vec<PathBuf> = files
+        .iter()
+        .filter(|file| file.action == Action::Overwrite)
+        .map(|file| file.destination.clone())
+        .collect();
+    if !conflicts.is_empty() && !overwrite {
+        let mut listed: Vec<String> = conflicts.iter().take(LISTED_CONFLICTS).map(|path| path.display().to_string()).collect();
+        if conflicts.len() > LISTED_CONFLICTS {
+            listed.push(format!("and {} more", conflicts.len() - LISTED_CONFLICTS));
+        }
+        return Err(anyhow::anyhow!(
+            "{} staged files would overwrite existing ones ({}); pass --overwrite to replace them and keep .pre-restore copies",
+            conflicts.len(),
+            listed.join(", ")
+        ));
+    }
+    let regular: Vec<PathBuf> = conflicts.iter().filter(|path| path.is_file()).cloned().collect();
+    crate::restore::write_copies(&regular)?;
+
+    let root = area_path(config, id);
+    let mut summary = CommitSummary::default();
+    for file in &files {
+        if file.action == Action::Unchanged {
+            summary.unchanged += 1;
+            continue;
+        }
+        if let Some(parent) = file.destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let source = root.join(file.path.trim_start_matches('/'));
+        move_file(&source, &file.destination)
+            .map_err(|e| anyhow::anyhow!("Failed to move {:?} to {:?}: {}", source, file.destination, e))?;
+        match file.action {
+            Action::Overwrite => summary.overwritten.push(file.destination.clone()),
+            _ => summary.created += 1,
+        }
+    }
+    remove(config, id)?;
+    Ok(summary)
+}
+
+fn print_files(files: &[StagedFile]) {
+    for file in files {
+        let action = match file.action {
+            Action::Create => "new",
+            Action::Overwrite => "OVERWRITES",
+            Action::Unchanged => "unchanged",
+        };
+        println!("  {:<10} {:>10}  {}", action, restic::format_bytes(file.size), file.destination.display());
+    }
+}
+
+/// `--target DIR` or `--in-place` from the command line
+fn target(args: &[String]) -> Result<PathBuf> {
+    let in_place = args.iter().any(|arg| arg == "--in-place");
+    match (crate::flag_value(args, "--target"), in_place) {
+        (Some(_), true) => Err(anyhow::anyhow!("--target and --in-place can't be combined")),
+        (Some(target), false) => Ok(crate::expand_tilde(Path::new(target))),
+        (None, true) => Ok(PathBuf::from("/")),
+        (None, false) => Err(anyhow::anyhow!("Choose where the files go with --target DIR or --in-place")),
+    }
+}
+
+/// `staging list`, `staging show ID [--target DIR]`, `staging commit ID (--target DIR | --in-place) [--overwrite]`
+/// and `staging discard ID`
+pub fn run(config: &Config, args: &[String], dry_run: bool) -> Result<()> {
+    let action = args.get(2).map(String::as_str).unwrap_or("list");
+    let given_id = crate::positional_args(&args[2..]).get(1).map(|id| id.to_string());
+    let id = || given_id.clone().ok_or_else(|| anyhow::anyhow!("Usage: staging {} ID", action));
+    match action {
+        "list" => {
+            let areas = list(config);
+            if areas.is_empty() {
+                println!("Nothing is staged; restore into a staging area with `restore ... --stage`");
+            }
+            for area in areas {
+                println!(
+                    "{}  snapshot {} from {}, {} files ({}), staged {}",
+                    area.id,
+                    restic::short_id(&area.snapshot_id),
+                    area.snapshot_time.format("%Y-%m-%d %H:%M"),
+                    area.files,
+                    restic::format_bytes(area.bytes),
+                    area.created_at.format("%Y-%m-%d %H:%M")
+                );
+            }
+        }
+        "show" => {
+            let id = id()?;
+            let area = load(config, &id)?;
+            let target = crate::flag_value(args, "--target").map(|target| crate::expand_tilde(Path::new(target))).unwrap_or_else(|| PathBuf::from("/"));
+            println!(
+                "Staging area {}: snapshot {} from {}, in {:?}",
+                area.id,
+                restic::short_id(&area.snapshot_id),
+                area.snapshot_time.format("%Y-%m-%d %H:%M"),
+                area_path(config, &id)
+            );
+            print_files(&files(config, &id, &target)?);
+            for error in &area.errors {
+                println!("  Error: {}", error);
+            }
+        }
+        "commit" => {
+            let id = id()?;
+            let target = target(args)?;
+            let overwrite = args.iter().any(|arg| arg == "--overwrite");
+            if dry_run {
+                println!("Dry run: would commit staging area {} to {:?}", id, target);
+                print_files(&files(config, &id, &target)?);
+                return Ok(());
+            }
+            let summary = commit(config, &id, &target, overwrite)?;
+            println!(
+                "Committed staging area {}: {} created, {} overwritten, {} unchanged",
+                id,
+                summary.created,
+                summary.overwritten.len(),
+                summary.unchanged
+            );
+            if !summary.overwritten.is_empty() {
+                println!("Copies of the overwritten files end in .pre-restore");
+            }
+        }
+        "discard" => {
+            let id = id()?;
+            if dry_run {
+                println!("Dry run: would discard staging area {}", id);
+                return Ok(());
+            }
+            discard(config, &id)?;
+            println!("Discarded staging area {}", id);
+        }
+        other => return Err(anyhow::anyhow!("Unknown staging command '{}'; use list, show, commit or discard", other)),
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// What a piece of output means, which decides its color and icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+    Success,
+    Failure,
+    Warning,
+    /// Details that matter less, like durations
+    Muted,
+    Heading,
+}
+
+impl Tone {
+    fn color(self) -> &'static str {
+        match self {
+            Tone::Success => "32",
+            Tone::Failure => "1;31",
+            Tone::Warning => "33",
+            Tone::Muted => "2",
+            Tone::Heading => "1",
+        }
+    }
+
+    fn icon(self) -> Option<&'static str> {
+        match self {
+            Tone::Success => Some("✔"),
+            Tone::Failure => Some("✘"),
+            Tone::Warning => Some("⚠"),
+            Tone::Muted | Tone::Heading => None,
+        }
+    }
+
+    /// The tone of a run status such as "succeeded" or "failed"
+    pub fn of_status(status: &str) -> Tone {
+        match status {
+            "succeeded" | "ok" => Tone::Success,
+            "failed" | "interrupted" | "FAILED" => Tone::Failure,
+            "warning" => Tone::Warning,
+            _ => Tone::Muted,
+        }
+    }
+}
+
+/// Whether the environment allows colors: `NO_COLOR` (https://no-color.org) isn't set and the
+/// terminal isn't a dumb one
+fn colors_allowed() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && std::env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
+/// Adds colors and icons to text bound for a terminal. Output that is piped or redirected, and text
+/// for notifications, stays exactly as it would be without this.
+#[derive(Debug, Clone, Copy)]
+pub struct Painter {
+    terminal: bool,
+    /// Off with `NO_COLOR`, which leaves the icons and layout alone
+    colors: bool,
+}
+
+impl Painter {
+    fn new(terminal: bool) -> Painter {
+        Painter { terminal, colors: terminal && colors_allowed() }
+    }
+
+    pub fn stdout() -> Painter {
+        static STDOUT: OnceLock<bool> = OnceLock::new();
+        Painter::new(*STDOUT.get_or_init(|| std::io::stdout().is_terminal()))
+    }
+
+    pub fn stderr() -> Painter {
+        static STDERR: OnceLock<bool> = OnceLock::new();
+        Painter::new(*STDERR.get_or_init(|| std::io::stderr().is_terminal()))
+    }
+
+    /// For text that never reaches a terminal directly
+    pub fn plain() -> Painter {
+        Painter::new(false)
+    }
+
+    /// Whether output may use a layout meant for people, like aligned columns
+    pub fn is_terminal(&self) -> bool {
+        self.terminal
+    }
+
+    /// The text in the tone's color
+    pub fn paint(&self, tone: Tone, text: &str) -> String {
+        if self.colors {
+            format!("\x1b[{}m{}\x1b[0m", tone.color(), text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// The text padded to `width` characters, then painted, so colored columns still line up
+    pub fn paint_padded(&self, tone: Tone, text: &str, width: usize) -> String {
+        self.paint(tone, &format!("{:<width$}", text, width = width))
+    }
+
+    /// A status line: the tone's icon, in its color, in front of the text
+    pub fn status(&self, tone: Tone, text: &str) -> String {
+        match tone.icon() {
+            Some(icon) if self.terminal => format!("{} {}", self.paint(tone, icon), text),
+            _ => text.to_string(),
+        }
+    }
+}
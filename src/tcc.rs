@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::BackupConfig;
+
+/// Folders macOS privacy protection (TCC) guards, relative to a home directory, by the category
+/// shown in System Settings
+const PROTECTED: &[(&str, &str)] = &[
+    ("Mail", "Library/Mail"),
+    ("Messages", "Library/Messages"),
+    ("Safari", "Library/Safari"),
+    ("Safari", "Library/Containers/com.apple.Safari"),
+    ("Calendars", "Library/Calendars"),
+    ("Contacts", "Library/Application Support/AddressBook"),
+    ("Reminders", "Library/Reminders"),
+    ("Photos", "Pictures/Photos Library.photoslibrary"),
+    ("Home", "Library/HomeKit"),
+    ("Cookies", "Library/Cookies"),
+    ("Mail and Messages attachments", "Library/Containers/com.apple.mail"),
+    ("Desktop Folder", "Desktop"),
+    ("Documents Folder", "Documents"),
+    ("Downloads Folder", "Downloads"),
+];
+
+/// Opens System Settings at Privacy & Security > Full Disk Access
+const FULL_DISK_ACCESS_URL: &str = "x-apple.systempreferences:com.apple.preference.security?Privacy_AllFiles";
+
+/// What to do when the backup set contains protected folders restic isn't allowed to read
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProtectedDataAction {
+    /// Log which categories are blocked and how to grant access, then back up anyway
+    #[default]
+    Warn,
+    /// Also leave them out of the backup, so they don't turn every run into a warning
+    Exclude,
+    /// Fail the run before restic starts
+    Fail,
+}
+
+/// A protected folder in the backup set this process can't read
+#[derive(Debug, Clone)]
+pub struct BlockedFolder {
+    pub category: &'static str,
+    pub path: PathBuf,
+}
+
+/// Home directories whose protected folders may be in the backup set
+fn homes() -> Vec<PathBuf> {
+    let mut homes: Vec<PathBuf> = std::fs::read_dir("/Users")
+        .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect())
+        .unwrap_or_default();
+    if let Ok(home) = std::env::var("HOME") {
+        let home = PathBuf::from(home);
+        if !homes.contains(&home) {
+            homes.push(home);
+        }
+    }
+    homes
+}
+
+/// TCC denies access with EPERM ("Operation not permitted"); ordinary permissions give EACCES
+fn blocked(path: &Path) -> bool {
+    const EPERM: i32 = 1;
+    matches!(std::fs::read_dir(path), Err(e) if e.raw_os_error() == Some(EPERM))
+}
+
+/// Protected folders inside the job's directories (and not excluded) that can't be read
+pub fn blocked_folders(job: &BackupConfig) -> Vec<BlockedFolder> {
+    let directories: Vec<PathBuf> = job.effective_directories().iter().map(|p| job.resolve_path(p)).collect();
+    let excludes: Vec<PathBuf> = job.effective_excludes().iter().map(|p| job.resolve_path(p)).collect();
+    let mut found = Vec::new();
+    for home in homes() {
+        for (category, relative) in PROTECTED {
+            let path = home.join(relative);
+            let in_backup_set = directories.iter().any(|directory| path.starts_with(directory));
+            let excluded = excludes.iter().any(|exclude| path.starts_with(exclude));
+            if in_backup_set && !excluded && path.exists() && blocked(&path) {
+                found.push(BlockedFolder { category, path });
+            }
+        }
+    }
+    found
+}
+
+/// How to fix it: Full Disk Access goes to the app that started us (Terminal, or this binary under launchd)
+pub fn guidance() -> String {
+    let binary = std::env::current_exe().map(|path| path.display().to_string()).unwrap_or_else(|_| "better_restic_client".to_string());
+    format!(
+        "Grant Full Disk Access in System Settings > Privacy & Security > Full Disk Access \
+         (open '{}'): add your terminal app when running by hand, or {} when it runs from launchd. \
+         Otherwise exclude these folders.",
+        FULL_DISK_ACCESS_URL, binary
+    )
+}
+
+pub fn describe(blocked: &[BlockedFolder]) -> String {
+    let mut categories: Vec<&str> = blocked.iter().map(|folder| folder.category).collect();
+    categories.sort_unstable();
+    categories.dedup();
+    format!(
+        "macOS privacy protection blocks {} in the backup set ({})",
+        categories.join(", "),
+        blocked.iter().map(|folder| folder.path.display().to_string()).collect::<Vec<_>>().join(", ")
+    )
+}
+
+/// Check the job's protected folders before backing up. Returns the folders to leave out;
+/// with `fail`, an error. Does nothing outside macOS.
+pub fn check(job: &BackupConfig, action: ProtectedDataAction) -> Result<Vec<PathBuf>> {
+    if !cfg!(target_os = "macos") {
+        return Ok(Vec::new());
+    }
+    let blocked = blocked_folders(job);
+    if blocked.is_empty() {
+        return Ok(Vec::new());
+    }
+    match action {
+        ProtectedDataAction::Fail => Err(anyhow::anyhow!("{}. {}", describe(&blocked), guidance())),
+        ProtectedDataAction::Warn => {
+            warn!("{}. {}", describe(&blocked), guidance());
+            Ok(Vec::new())
+        }
+        ProtectedDataAction::Exclude => {
+            warn!("{}; leaving them out. {}", describe(&blocked), guidance());
+            Ok(blocked.into_iter().map(|folder| folder.path).collect())
+        }
+    }
+}
@@ -0,0 +1,92 @@
+/// Placeholders that can be used in repository URLs, tags and stdin file names
+pub const PLACEHOLDERS: [&str; 3] = ["{hostname}", "{date}", "{job}"];
+
+fn date() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
+/// Replace `{hostname}`, `{date}` (YYYY-MM-DD, local time) and, when a job is given, `{job}`
+pub fn expand(template: &str, job: Option<&str>) -> String {
+    if !template.contains('{') {
+        return template.to_string();
+    }
+    let mut expanded = template.replace("{hostname}", &crate::hostname()).replace("{date}", &date());
+    if let Some(job) = job {
+        expanded = expanded.replace("{job}", job);
+    }
+    expanded
+}
+
+/// Placeholder-like `{...}` words in a template that aren't supported, e.g. a misspelled `{host}`.
+/// `extra` lists placeholders only valid in this field.
+pub fn unknown_placeholders(template: &str, extra: &[&str]) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else { break };
+        let word = &rest[start..start + end + 1];
+        let name = &word[1..word.len() - 1];
+        let known = PLACEHOLDERS.contains(&word) || extra.contains(&word);
+        if !known && !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            unknown.push(word.to_string());
+        }
+        rest = &rest[start + end + 1..];
+    }
+    unknown
+}
+
+fn escape_double_quoted(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A double-quoted shell word that expands the placeholders when the script runs
+pub fn to_shell(template: &str, job: Option<&str>) -> String {
+    let mut word = escape_double_quoted(template)
+        .replace("{hostname}", "$(hostname)")
+        .replace("{date}", "$(date +%Y-%m-%d)");
+    if let Some(job) = job {
+        word = word.replace("{job}", &escape_double_quoted(job));
+    }
+    format!("\"{}\"", word)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expand_replaces_placeholders() {
+        let hostname = crate::hostname();
+        assert_eq!(expand("sftp:nas:/restic/{hostname}", None), format!("sftp:nas:/restic/{}", hostname));
+        assert_eq!(expand("{job}-{date}", Some("home")), format!("home-{}", date()));
+        assert_eq!(expand("{hostname}/{hostname}", None), format!("{}/{}", hostname, hostname));
+    }
+
+    #[test]
+    fn expand_leaves_job_without_a_job() {
+        assert_eq!(expand("{job}", None), "{job}");
+        assert_eq!(expand("{unknown} and plain", Some("home")), "{unknown} and plain");
+        assert_eq!(expand("no placeholders", Some("home")), "no placeholders");
+    }
+
+    #[test]
+    fn unknown_placeholders_finds_misspellings() {
+        assert_eq!(unknown_placeholders("/srv/{host}/{date}", &[]), ["{host}"]);
+        assert_eq!(unknown_placeholders("{snapshot}.tar", &["{snapshot}"]), Vec::<String>::new());
+        // Not placeholder-like: empty braces, spaces, JSON
+        assert!(unknown_placeholders("{} {a b} {\"x\": 1}", &[]).is_empty());
+    }
+
+    #[test]
+    fn to_shell_quotes_and_expands_at_runtime() {
+        assert_eq!(to_shell("/srv/{hostname}/$HOME", Some("a\"b")), "\"/srv/$(hostname)/\\$HOME\"");
+        assert_eq!(to_shell("{job}-{date}", Some("a\"b")), "\"a\\\"b-$(date +%Y-%m-%d)\"");
+    }
+}
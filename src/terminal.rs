@@ -0,0 +1,271 @@
+use std::process::Stdio;
+
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Child;
+
+use crate::{audit, Config};
+
+/// restic subcommands the terminal may run; all of them only read the repository
+const ALLOWED_COMMANDS: [&str; 8] = ["snapshots", "ls", "find", "diff", "stats", "check", "list", "version"];
+
+/// A flag the terminal accepts: its long name, its one-letter form and whether it takes a value
+struct Flag {
+    long: &'static str,
+    short: Option<char>,
+    value: bool,
+}
+
+const fn flag(long: &'static str, short: Option<char>, value: bool) -> Flag {
+    Flag { long, short, value }
+}
+
+/// Global flags that change how restic prints, not which repository or credentials it uses
+const GLOBAL_FLAGS: &[Flag] = &[
+    flag("json", None, false),
+    flag("quiet", Some('q'), false),
+    flag("verbose", Some('v'), false),
+    flag("no-lock", None, false),
+    flag("no-cache", None, false),
+    flag("limit-download", None, true),
+    flag("help", Some('h'), false),
+];
+
+/// Flags selecting snapshots by host, tag and path
+const FILTER_FLAGS: &[Flag] = &[flag("host", Some('H'), true), flag("tag", None, true), flag("path", None, true)];
+
+const SNAPSHOTS_FLAGS: &[Flag] = &[flag("latest", None, true), flag("compact", Some('c'), false), flag("group-by", Some('g'), true)];
+const LS_FLAGS: &[Flag] = &[flag("long", Some('l'), false), flag("recursive", None, false), flag("human-readable", None, false), flag("ncdu", None, false)];
+const FIND_FLAGS: &[Flag] = &[
+    flag("long", Some('l'), false),
+    flag("ignore-case", Some('i'), false),
+    flag("newest", Some('N'), true),
+    flag("oldest", Some('O'), true),
+    flag("snapshot", Some('s'), true),
+    flag("pack", None, false),
+    flag("blob", None, false),
+    flag("tree", None, false),
+    flag("show-pack-id", None, false),
+    flag("human-readable", None, false),
+];
+const DIFF_FLAGS: &[Flag] = &[flag("metadata", None, false)];
+const STATS_FLAGS: &[Flag] = &[flag("mode", None, true)];
+const CHECK_FLAGS: &[Flag] = &[flag("read-data", None, false), flag("read-data-subset", None, true), flag("with-cache", None, false)];
+
+/// The flags each subcommand accepts besides the global ones. Anything else, e.g. `-o sftp.command=...`
+/// or `--password-command`, is refused, however it's spelled.
+fn subcommand_flags(subcommand: &str) -> &'static [Flag] {
+    match subcommand {
+        "snapshots" => SNAPSHOTS_FLAGS,
+        "ls" => LS_FLAGS,
+        "find" => FIND_FLAGS,
+        "diff" => DIFF_FLAGS,
+        "stats" => STATS_FLAGS,
+        "check" => CHECK_FLAGS,
+        _ => &[],
+    }
+}
+
+fn allowed_flags(subcommand: &str) -> impl Iterator<Item = &'static Flag> + '_ {
+    let filters = if matches!(subcommand, "snapshots" | "ls" | "find" | "stats") { FILTER_FLAGS } else { &[] };
+    GLOBAL_FLAGS.iter().chain(filters).chain(subcommand_flags(subcommand))
+}
+
+/// Check every flag against the allow-list the way restic parses them: `--flag=value`, a value in
+/// the next word, and combined short flags like `-lv` or `-Hhost`. Everything after `--` is an argument.
+fn check_flags(subcommand: &str, args: &[String]) -> Result<()> {
+    let refused = |flag: &str| anyhow::anyhow!("{} is not allowed here; the configured repository and credentials are used", flag);
+    let mut words = args.iter();
+    while let Some(word) = words.next() {
+        if word == "--" {
+            break;
+        }
+        if let Some(long) = word.strip_prefix("--") {
+            let (name, inline_value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (long, None),
+            };
+            let flag = allowed_flags(subcommand).find(|flag| flag.long == name).ok_or_else(|| refused(&format!("--{}", name)))?;
+            if flag.value && inline_value.is_none() {
+                words.next();
+            }
+        } else if let Some(shorts) = word.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+            let mut letters = shorts.chars();
+            while let Some(letter) = letters.next() {
+                let flag = allowed_flags(subcommand)
+                    .find(|flag| flag.short == Some(letter))
+                    .ok_or_else(|| refused(&format!("-{}", letter)))?;
+                // The rest of the word is the value, e.g. `-Hhost`; otherwise it's the next word
+                if flag.value {
+                    if letters.as_str().is_empty() {
+                        words.next();
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What the terminal sends back, one JSON message each
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Reply {
+    Output { line: String },
+    Exit { code: Option<i32> },
+    Error { message: String },
+}
+
+/// Split a command line into words, honouring single and double quotes
+fn split_words(line: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow::anyhow!("Unterminated quote"));
+    }
+    words.extend(current);
+    Ok(words)
+}
+
+/// The subcommand and its arguments, if the line is allowed
+fn parse_command(line: &str) -> Result<(String, Vec<String>)> {
+    let mut words = split_words(line)?;
+    if words.first().map(String::as_str) == Some("restic") {
+        words.remove(0);
+    }
+    let Some(subcommand) = words.first().cloned() else {
+        return Err(anyhow::anyhow!("Type a restic command, e.g. `find '*.conf'`"));
+    };
+    if !ALLOWED_COMMANDS.contains(&subcommand.as_str()) {
+        return Err(anyhow::anyhow!("'{}' is not allowed here; allowed: {}", subcommand, ALLOWED_COMMANDS.join(", ")));
+    }
+    check_flags(&subcommand, &words[1..])?;
+    Ok((subcommand, words[1..].to_vec()))
+}
+
+async fn send(socket: &mut WebSocket, reply: Reply) -> bool {
+    let text = serde_json::to_string(&reply).unwrap_or_default();
+    socket.send(Message::Text(text)).await.is_ok()
+}
+
+/// Stream a running command's output to the socket. Returns false if the client went away;
+/// the child is killed when it's dropped then.
+async fn stream_output(socket: &mut WebSocket, mut child: Child) -> bool {
+    let stdout = child.stdout.take().expect("piped");
+    let stderr = child.stderr.take().expect("piped");
+    let mut stdout = BufReader::new(stdout).lines();
+    let mut stderr = BufReader::new(stderr).lines();
+    let (mut stdout_open, mut stderr_open) = (true, true);
+    loop {
+        let line = tokio::select! {
+            line = stdout.next_line(), if stdout_open => line.ok().flatten().or_else(|| {
+                stdout_open = false;
+                None
+            }),
+            line = stderr.next_line(), if stderr_open => line.ok().flatten().or_else(|| {
+                stderr_open = false;
+                None
+            }),
+            else => break,
+        };
+        if let Some(line) = line {
+            if !send(socket, Reply::Output { line }).await {
+                return false;
+            }
+        }
+    }
+    let code = child.wait().await.ok().and_then(|status| status.code());
+    send(socket, Reply::Exit { code }).await
+}
+
+/// Run commands sent over the socket one at a time until it closes
+pub async fn handle(mut socket: WebSocket, config: Config, user: Option<String>) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let line = match message {
+            Message::Text(line) => line,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let (subcommand, args) = match parse_command(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                if !send(&mut socket, Reply::Error { message: e.to_string() }).await {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        let mut cmd = crate::restic_command(&config.restic, &subcommand);
+        cmd.args(&args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+        let entry = audit::AuditEntry::new(audit::Source::Web, user.clone(), "terminal", Some(line.trim().to_string()));
+        let child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let message = format!("Failed to execute restic: {}", e);
+                audit::record(&config, &entry.outcome::<()>(&Err(anyhow::anyhow!(message.clone()))));
+                if !send(&mut socket, Reply::Error { message }).await {
+                    break;
+                }
+                continue;
+            }
+        };
+        audit::record(&config, &entry);
+        if !stream_output(&mut socket, child).await {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parses(line: &str) -> bool {
+        parse_command(line).is_ok()
+    }
+
+    #[test]
+    fn accepts_read_only_commands_and_their_flags() {
+        assert!(parses("restic snapshots --host laptop --json"));
+        assert!(parses("ls -lH laptop latest /etc"));
+        assert!(parses("find --host=laptop -i '*.conf'"));
+        assert!(parses("find -Hlaptop -l x"));
+        assert!(parses("ls latest -- -o"));
+    }
+
+    #[test]
+    fn refuses_other_commands() {
+        assert!(!parses("forget --keep-last 1"));
+        assert!(!parses(""));
+    }
+
+    #[test]
+    fn refuses_flags_however_they_are_spelled() {
+        assert!(!parses("ls -o sftp.command=sh latest"));
+        assert!(!parses("ls -vo sftp.command=sh latest"));
+        assert!(!parses("snapshots -qr /tmp/other"));
+        assert!(!parses("snapshots -r/tmp/other"));
+        assert!(!parses("snapshots --option=sftp.command=sh"));
+        assert!(!parses("snapshots --password-command 'sh -c id'"));
+        assert!(!parses("stats --cache-dir=/tmp"));
+        // A flag of another subcommand
+        assert!(!parses("diff --long a b"));
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::restic::format_bytes;
+
+/// Seconds of progress the live rate is averaged over, so it doesn't jump with every file
+const LIVE_WINDOW_SECS: f64 = 10.0;
+/// Length of the intervals the run's rate percentiles are computed from
+const SAMPLE_SECS: f64 = 5.0;
+
+/// How fast a backup went, from restic's `--json` status messages
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Throughput {
+    /// Bytes restic read and processed per second over the whole run
+    pub average_bytes_per_sec: f64,
+    /// Slowest, median and fastest processing rates over the run's 5 second intervals
+    pub p10_bytes_per_sec: f64,
+    pub p50_bytes_per_sec: f64,
+    pub p90_bytes_per_sec: f64,
+    /// Bytes added to the repository per second, i.e. what was uploaded after deduplication and compression
+    pub upload_bytes_per_sec: f64,
+}
+
+impl Throughput {
+    /// One-line human readable description
+    pub fn describe(&self) -> String {
+        format!(
+            "{} processed (p10 {}, median {}, p90 {}), {} uploaded",
+            rate(self.average_bytes_per_sec),
+            rate(self.p10_bytes_per_sec),
+            rate(self.p50_bytes_per_sec),
+            rate(self.p90_bytes_per_sec),
+            rate(self.upload_bytes_per_sec),
+        )
+    }
+
+    /// Combine another restic run's rates, weighting both by how long they took. The percentiles become
+    /// an approximation then.
+    pub fn merge(&mut self, duration: f64, other: &Throughput, other_duration: f64) {
+        let total = duration + other_duration;
+        if total <= 0.0 {
+            return;
+        }
+        let weigh = |ours: f64, theirs: f64| (ours * duration + theirs * other_duration) / total;
+        self.average_bytes_per_sec = weigh(self.average_bytes_per_sec, other.average_bytes_per_sec);
+        self.p10_bytes_per_sec = weigh(self.p10_bytes_per_sec, other.p10_bytes_per_sec);
+        self.p50_bytes_per_sec = weigh(self.p50_bytes_per_sec, other.p50_bytes_per_sec);
+        self.p90_bytes_per_sec = weigh(self.p90_bytes_per_sec, other.p90_bytes_per_sec);
+        self.upload_bytes_per_sec = weigh(self.upload_bytes_per_sec, other.upload_bytes_per_sec);
+    }
+}
+
+/// A byte rate, e.g. "12.50 MiB/s"
+pub fn rate(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_sec.max(0.0) as u64))
+}
+
+/// Follows restic's progress while it runs
+#[derive(Default)]
+pub struct Meter {
+    /// (seconds elapsed, bytes done) of the status messages in the live window
+    recent: VecDeque<(f64, u64)>,
+    /// Rates of the completed intervals
+    rates: Vec<f64>,
+    interval_start: Option<(f64, u64)>,
+    last: Option<(f64, u64)>,
+}
+
+impl Meter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in a status message and return the current rate, once there's enough progress to tell
+    pub fn observe(&mut self, status: &serde_json::Value) -> Option<f64> {
+        let elapsed = status["seconds_elapsed"].as_f64()?;
+        let bytes = status["bytes_done"].as_u64().unwrap_or(0);
+        self.last = Some((elapsed, bytes));
+
+        match self.interval_start {
+            Some((start, start_bytes)) if elapsed - start >= SAMPLE_SECS => {
+                self.rates.push(bytes.saturating_sub(start_bytes) as f64 / (elapsed - start));
+                self.interval_start = Some((elapsed, bytes));
+            }
+            Some(_) => {}
+            None => self.interval_start = Some((elapsed, bytes)),
+        }
+
+        self.recent.push_back((elapsed, bytes));
+        while self.recent.len() > 2 && self.recent.front().is_some_and(|(then, _)| elapsed - then > LIVE_WINDOW_SECS) {
+            self.recent.pop_front();
+        }
+        let (then, then_bytes) = *self.recent.front()?;
+        (elapsed > then).then(|| bytes.saturating_sub(then_bytes) as f64 / (elapsed - then))
+    }
+
+    /// Statistics for the run, given its final summary; None when restic sent no status messages
+    pub fn finish(&self, data_added: u64, total_bytes_processed: u64, total_duration: f64) -> Option<Throughput> {
+        let (elapsed, bytes) = self.last?;
+        let duration = if total_duration > 0.0 { total_duration } else { elapsed };
+        if duration <= 0.0 {
+            return None;
+        }
+        let mut rates = self.rates.clone();
+        if rates.is_empty() {
+            rates.push(bytes as f64 / duration);
+        }
+        rates.sort_by(f64::total_cmp);
+        let percentile = |p: f64| rates[((rates.len() - 1) as f64 * p).round() as usize];
+        Some(Throughput {
+            average_bytes_per_sec: total_bytes_processed.max(bytes) as f64 / duration,
+            p10_bytes_per_sec: percentile(0.1),
+            p50_bytes_per_sec: percentile(0.5),
+            p90_bytes_per_sec: percentile(0.9),
+            upload_bytes_per_sec: data_added as f64 / duration,
+        })
+    }
+}
@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Result;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Local};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of every token, so leaked ones are easy to grep for
+const TOKEN_PREFIX: &str = "brc_";
+
+/// What an API token may be used for
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    /// Everything a viewer can see: status, snapshots, logs, reports and events
+    ReadStatus,
+    TriggerBackup,
+    PinSnapshots,
+    ReadAudit,
+    /// Check in as an agent and receive triggered jobs
+    Agent,
+}
+
+impl Scope {
+    const ALL: [Scope; 5] = [Scope::ReadStatus, Scope::TriggerBackup, Scope::PinSnapshots, Scope::ReadAudit, Scope::Agent];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadStatus => "read-status",
+            Scope::TriggerBackup => "trigger-backup",
+            Scope::PinSnapshots => "pin-snapshots",
+            Scope::ReadAudit => "read-audit",
+            Scope::Agent => "agent",
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == s).ok_or_else(|| {
+            let known: Vec<&str> = Scope::ALL.iter().map(Scope::as_str).collect();
+            anyhow::anyhow!("Unknown scope '{}'; supported: {}", s, known.join(", "))
+        })
+    }
+}
+
+/// A long-lived API token. Only the SHA-256 of the secret is stored; tokens are random, so that's enough.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: Option<String>,
+    pub scopes: Vec<Scope>,
+    pub created: DateTime<Local>,
+    hash: String,
+}
+
+/// Token store next to the config file, e.g. `config.yaml` -> `.config.yaml.tokens.json`
+pub fn tokens_path(config_path: &str) -> PathBuf {
+    let path = Path::new(config_path);
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "config.yaml".to_string());
+    path.parent().unwrap_or_else(|| Path::new("")).join(format!(".{}.tokens.json", file_name))
+}
+
+fn sha256_hex(secret: &str) -> String {
+    digest::digest(&digest::SHA256, secret.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn load(path: &Path) -> Result<Vec<ApiToken>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(serde_json::from_str(&content)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write the store readable only by its owner, replacing the old one atomically
+fn save(path: &Path, tokens: &[ApiToken]) -> Result<()> {
+    let temp = path.with_extension("json.tmp");
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(&temp)?;
+    serde_json::to_writer_pretty(&file, tokens)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Issue a new token; the returned secret is shown once and can't be recovered
+pub fn create(path: &Path, name: Option<String>, scopes: Vec<Scope>) -> Result<(ApiToken, String)> {
+    let mut random = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut random)
+        .map_err(|_| anyhow::anyhow!("No random numbers available for the token"))?;
+    let secret = format!("{}{}", TOKEN_PREFIX, URL_SAFE_NO_PAD.encode(random));
+    let hash = sha256_hex(&secret);
+    let token = ApiToken {
+        id: hash[..8].to_string(),
+        name,
+        scopes,
+        created: Local::now(),
+        hash,
+    };
+    let mut tokens = load(path)?;
+    tokens.push(token.clone());
+    save(path, &tokens)?;
+    Ok((token, secret))
+}
+
+/// Remove a token by id; false if there was none
+pub fn revoke(path: &Path, id: &str) -> Result<bool> {
+    let mut tokens = load(path)?;
+    let before = tokens.len();
+    tokens.retain(|token| token.id != id);
+    if tokens.len() == before {
+        return Ok(false);
+    }
+    save(path, &tokens)?;
+    Ok(true)
+}
+
+/// The token a secret belongs to
+pub fn find(path: &Path, secret: &str) -> Option<ApiToken> {
+    if !secret.starts_with(TOKEN_PREFIX) {
+        return None;
+    }
+    let hash = sha256_hex(secret);
+    load(path).ok()?.into_iter().find(|token| token.hash == hash)
+}
+
+/// `token create --scope a,b [--name NAME]`, `token list` and `token revoke ID`
+pub fn run(config_path: &str, args: &[String]) -> Result<Option<String>> {
+    let path = tokens_path(config_path);
+    match args.get(2).map(String::as_str) {
+        Some("create") => {
+            let scopes = crate::flag_values(args, "--scope")
+                .into_iter()
+                .flat_map(|value| value.split(','))
+                .map(|scope| scope.trim().parse())
+                .collect::<Result<Vec<Scope>>>()?;
+            if scopes.is_empty() {
+                return Err(anyhow::anyhow!("Give the token at least one --scope"));
+            }
+            let (token, secret) = create(&path, crate::flag_value(args, "--name").cloned(), scopes)?;
+            eprintln!("Created token {}. It is shown only once:", token.id);
+            println!("{}", secret);
+            Ok(Some(token.id))
+        }
+        Some("list") => {
+            for token in load(&path)? {
+                let scopes: Vec<&str> = token.scopes.iter().map(Scope::as_str).collect();
+                println!(
+                    "{}  {:<20}  {}  {}",
+                    token.id,
+                    token.name.as_deref().unwrap_or("-"),
+                    token.created.format("%Y-%m-%d %H:%M"),
+                    scopes.join(",")
+                );
+            }
+            Ok(None)
+        }
+        Some("revoke") => {
+            let id = args.get(3).ok_or_else(|| anyhow::anyhow!("Usage: token revoke ID"))?;
+            if !revoke(&path, id)? {
+                return Err(anyhow::anyhow!("No token with id '{}'", id));
+            }
+            println!("Token {} revoked", id);
+            Ok(Some(id.clone()))
+        }
+        _ => Err(anyhow::anyhow!("Usage: token create --scope SCOPE[,SCOPE] [--name NAME] | token list | token revoke ID")),
+    }
+}
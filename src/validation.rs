@@ -0,0 +1,604 @@
+use serde::Serialize;
+
+use crate::{templates, BackupConfig, Config};
+
+/// A single problem found while validating a config, located by dotted key path
+#[derive(Debug, Serialize, Clone)]
+pub struct ValidationIssue {
+    pub path: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl ValidationIssue {
+    fn new(path: &str, message: impl Into<String>) -> Self {
+        ValidationIssue {
+            path: path.to_string(),
+            message: message.into(),
+            line: None,
+            column: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<ValidationIssue>,
+    pub warnings: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+const KNOWN_FREQUENCIES: &[&str] = &["hourly", "daily", "weekly", "monthly"];
+
+/// Check YAML the way it would be loaded: upgrade it to the current schema, parse it into a Config and
+/// run the semantic checks. Returns the upgraded YAML, which is what gets saved, the parsed config
+/// (if it deserialized) and the report.
+pub fn validate_yaml(yaml: &str) -> (String, Option<Config>, ValidationReport) {
+    let mut report = ValidationReport::default();
+
+    let yaml = match crate::migrations::migrate_yaml(yaml) {
+        Ok((migrated, _)) => migrated,
+        // Syntax errors are reported with their location by the parse below
+        Err(e) if e.downcast_ref::<serde_yaml::Error>().is_some() => yaml.to_string(),
+        Err(e) => {
+            report.errors.push(ValidationIssue::new("version", e.to_string()));
+            return (yaml.to_string(), None, report);
+        }
+    };
+
+    let config: Config = match serde_yaml::from_str(&yaml) {
+        Ok(config) => config,
+        Err(e) => {
+            let location = e.location();
+            report.errors.push(ValidationIssue {
+                path: String::new(),
+                message: e.to_string(),
+                line: location.as_ref().map(|l| l.line()),
+                column: location.as_ref().map(|l| l.column()),
+            });
+            return (yaml, None, report);
+        }
+    };
+
+    validate_config(&config, &mut report);
+    (yaml, Some(config), report)
+}
+
+/// Semantic checks that serde can't express
+pub fn validate_config(config: &Config, report: &mut ValidationReport) {
+    if config.version > crate::migrations::CURRENT_CONFIG_VERSION {
+        report.errors.push(ValidationIssue::new(
+            "version",
+            format!(
+                "Config version {} is newer than this client supports ({})",
+                config.version,
+                crate::migrations::CURRENT_CONFIG_VERSION
+            ),
+        ));
+    }
+
+    let mut seen_names = std::collections::HashSet::new();
+    for (i, job) in config.jobs().into_iter().enumerate() {
+        let prefix = if i == 0 { "backup".to_string() } else { format!("jobs[{}]", i - 1) };
+        if !seen_names.insert(job.name.as_str()) {
+            report.errors.push(ValidationIssue::new(
+                &format!("{}.name", prefix),
+                format!("Duplicate job name '{}'", job.name),
+            ));
+        }
+        validate_job(config, job, &prefix, report);
+    }
+    for (name, message) in crate::dependencies::problems(config) {
+        let index = config.jobs().iter().position(|job| job.name == name).unwrap_or(0);
+        let prefix = if index == 0 { "backup".to_string() } else { format!("jobs[{}]", index - 1) };
+        report.errors.push(ValidationIssue::new(&format!("{}.after", prefix), message));
+    }
+
+    if let Err(e) = crate::parse_size(&config.logging.max_size) {
+        report.errors.push(ValidationIssue::new(
+            "logging.max_size",
+            format!("Invalid size '{}': {}", config.logging.max_size, e),
+        ));
+    }
+    if let Some(ref level) = config.logging.event_log {
+        if crate::eventlog::parse_level(level).is_none() {
+            report.errors.push(ValidationIssue::new(
+                "logging.event_log",
+                format!("Unknown level '{}', expected off, error, warn or info", level),
+            ));
+        } else if !cfg!(windows) {
+            report.warnings.push(ValidationIssue::new("logging.event_log", "The Event Log only exists on Windows; this is ignored here"));
+        }
+    }
+
+    let filters = &config.logging.filters;
+    for (module, level) in &filters.modules {
+        if crate::log_filters::parse_level(level).is_none() {
+            report.errors.push(ValidationIssue::new(
+                &format!("logging.filters.modules.{}", module),
+                format!("Unknown level '{}', expected off, error, warn, info, debug or trace", level),
+            ));
+        }
+    }
+    if let Some(ref level) = filters.console {
+        if crate::log_filters::parse_level(level).is_none() {
+            report.errors.push(ValidationIssue::new(
+                "logging.filters.console",
+                format!("Unknown level '{}', expected off, error, warn, info, debug or trace", level),
+            ));
+        } else if config.logging.duplicate_to_stderr.is_some() {
+            report.warnings.push(ValidationIssue::new(
+                "logging.filters.console",
+                "logging.duplicate_to_stderr is set too and takes precedence; this is ignored",
+            ));
+        }
+    }
+    if let Some(ref duplicate) = config.logging.duplicate_to_stderr {
+        if crate::log_filters::parse_level(&duplicate.level).is_none() {
+            report.errors.push(ValidationIssue::new(
+                "logging.duplicate_to_stderr.level",
+                format!("Unknown level '{}', expected off, error, warn, info, debug or trace", duplicate.level),
+            ));
+        }
+    }
+
+    if let Some(ref preflight) = config.preflight {
+        let thresholds = [("min_cache_free", &preflight.min_cache_free), ("min_repo_free", &preflight.min_repo_free)];
+        for (key, value) in thresholds {
+            if let Some(value) = value {
+                if let Err(e) = crate::parse_size(value) {
+                    report.errors.push(ValidationIssue::new(
+                        &format!("preflight.{}", key),
+                        format!("Invalid size '{}': {}", value, e),
+                    ));
+                }
+            }
+        }
+    }
+
+    if config.restic.repository.trim().is_empty() {
+        report.errors.push(ValidationIssue::new("restic.repository", "Repository must not be empty"));
+    }
+    let thresholds = [("size_warning", &config.restic.size_warning), ("size_limit", &config.restic.size_limit)];
+    for (key, value) in thresholds {
+        if let Some(value) = value {
+            if let Err(e) = crate::parse_size(value) {
+                report.errors.push(ValidationIssue::new(
+                    &format!("restic.{}", key),
+                    format!("Invalid size '{}': {}", value, e),
+                ));
+            }
+        }
+    }
+    let warning = config.restic.size_warning.as_deref().and_then(|size| crate::parse_size(size).ok());
+    let limit = config.restic.size_limit.as_deref().and_then(|size| crate::parse_size(size).ok());
+    if let (Some(warning), Some(limit)) = (warning, limit) {
+        if warning >= limit {
+            report.warnings.push(ValidationIssue::new(
+                "restic.size_warning",
+                "size_warning is not below size_limit, so it never warns ahead of the limit",
+            ));
+        }
+    }
+
+    if let Some(ref cost) = config.cost {
+        for (key, price) in [("price_per_gb_month", cost.price_per_gb_month), ("upload_price_per_gb", cost.upload_price_per_gb)] {
+            if price.is_nan() || price < 0.0 {
+                report.errors.push(ValidationIssue::new(&format!("cost.{}", key), "Price must be zero or more"));
+            }
+        }
+    }
+
+    if config.restic.repository.contains("{job}") {
+        report.errors.push(ValidationIssue::new(
+            "restic.repository",
+            "{job} can't be used in the repository; all jobs share it (use it in tags instead)",
+        ));
+    }
+    for placeholder in templates::unknown_placeholders(&config.restic.repository, &["{job}"]) {
+        report.warnings.push(ValidationIssue::new(
+            "restic.repository",
+            format!("Unknown placeholder {}; supported: {}", placeholder, templates::PLACEHOLDERS.join(", ")),
+        ));
+    }
+
+    for (i, user) in config.users.iter().enumerate() {
+        if config.users[..i].iter().any(|other| other.name == user.name) {
+            report.errors.push(ValidationIssue::new(
+                &format!("users[{}].name", i),
+                format!("Duplicate user '{}'", user.name),
+            ));
+        }
+        if !crate::accounts::is_valid_hash(&user.password_hash) {
+            report.errors.push(ValidationIssue::new(
+                &format!("users[{}].password_hash", i),
+                "Not a password hash; create one with `better_restic_client hash-password`",
+            ));
+        }
+    }
+
+    if let Some(ref web) = config.web {
+        if web.listen.parse::<std::net::SocketAddr>().is_err() {
+            report.errors.push(ValidationIssue::new(
+                "web.listen",
+                format!("Invalid address '{}', expected IP:PORT such as 0.0.0.0:3000", web.listen),
+            ));
+        }
+        if let Some(ref grpc_listen) = web.grpc_listen {
+            if grpc_listen.parse::<std::net::SocketAddr>().is_err() {
+                report.errors.push(ValidationIssue::new(
+                    "web.grpc_listen",
+                    format!("Invalid address '{}', expected IP:PORT such as 127.0.0.1:50051", grpc_listen),
+                ));
+            }
+        }
+    }
+
+    if let Some(ref agent) = config.agent {
+        if !(agent.server.starts_with("http://") || agent.server.starts_with("https://")) {
+            report.errors.push(ValidationIssue::new(
+                "agent.server",
+                format!("Server '{}' must be an http:// or https:// URL", agent.server),
+            ));
+        }
+        if config.reports.is_none() {
+            report.warnings.push(ValidationIssue::new(
+                "agent",
+                "Run results reach the server through run reports; configure `reports` too",
+            ));
+        }
+    }
+
+    if config.restic.password.is_some() && config.restic.password_command.is_some() {
+        report.warnings.push(ValidationIssue::new(
+            "restic.password",
+            "Both password and password_command are set; password_command takes precedence",
+        ));
+    }
+    if let Some(ref ssh) = config.restic.ssh {
+        if crate::ssh::target(&config.restic.repository_url()).is_none() {
+            report.warnings.push(ValidationIssue::new("restic.ssh", "SSH options only apply to sftp: repositories"));
+        }
+        if let Some(ref identity_file) = ssh.identity_file {
+            let path = crate::expand_tilde(identity_file);
+            if !path.is_file() {
+                report.errors.push(ValidationIssue::new("restic.ssh.identity_file", format!("{:?} doesn't exist or isn't a file", path)));
+            }
+        }
+        if ssh.trust_on_first_use && ssh.strict_host_key_checking.is_some_and(|checking| checking != crate::ssh::StrictHostKeyChecking::AcceptNew) {
+            report.warnings.push(ValidationIssue::new(
+                "restic.ssh.strict_host_key_checking",
+                "trust_on_first_use pins keys with accept-new; strict_host_key_checking overrides it",
+            ));
+        }
+        if ssh.port == Some(0) {
+            report.errors.push(ValidationIssue::new("restic.ssh.port", "Port must be between 1 and 65535"));
+        }
+    }
+    if let Some(ref rclone) = config.restic.rclone {
+        if crate::rclone::remote(&config.restic.repository_url()).is_none() {
+            report.warnings.push(ValidationIssue::new("restic.rclone", "rclone options only apply to rclone:remote:path repositories"));
+        }
+        if rclone.transfers == Some(0) {
+            report.errors.push(ValidationIssue::new("restic.rclone.transfers", "transfers must be at least 1"));
+        }
+    }
+    if let Some(ref password_file) = config.restic.password_file {
+        let path = crate::expand_tilde(password_file);
+        if !path.is_file() {
+            report.errors.push(ValidationIssue::new("restic.password_file", format!("{:?} doesn't exist or isn't a file", path)));
+        } else if crate::password::world_readable(&path) {
+            report.warnings.push(ValidationIssue::new(
+                "restic.password_file",
+                format!("{:?} can be read by everyone; restrict it with chmod 600", path),
+            ));
+        }
+        if config.restic.password_command.is_some() {
+            report.warnings.push(ValidationIssue::new(
+                "restic.password_file",
+                "Both password_file and password_command are set; password_command takes precedence",
+            ));
+        }
+    }
+    if let Some(ref maintenance) = config.maintenance {
+        if let Some(ref group_by) = maintenance.group_by {
+            if let Some(unknown) = group_by.split(',').map(str::trim).find(|part| !part.is_empty() && !["host", "paths", "tags"].contains(part)) {
+                report.errors.push(ValidationIssue::new(
+                    "maintenance.group_by",
+                    format!("Unknown grouping '{}', expected a comma-separated list of host, paths and tags", unknown),
+                ));
+            }
+        }
+        for (i, group) in maintenance.groups.iter().enumerate() {
+            let field = format!("maintenance.groups[{}]", i);
+            if group.tags.is_empty() && group.host.is_none() {
+                report.errors.push(ValidationIssue::new(&field, "A retention group needs tags or a host to select its snapshots"));
+            } else if group.policy.is_empty() {
+                report.warnings.push(ValidationIssue::new(&field, format!("No keep_* rule, so snapshots with {} are never forgotten", group.describe())));
+            }
+        }
+    }
+    if let Some(maintenance) = config.maintenance.as_ref().filter(|maintenance| maintenance.read_data_rotation.is_some()) {
+        if maintenance.read_data_rotation == Some(0) {
+            report.errors.push(ValidationIssue::new("maintenance.read_data_rotation", "Must be at least 1"));
+        } else if maintenance.read_data_subset.is_some() {
+            report.warnings.push(ValidationIssue::new(
+                "maintenance.read_data_subset",
+                "Both read_data_subset and read_data_rotation are set; read_data_rotation takes precedence",
+            ));
+        }
+    }
+    if let Some(lock) = config.maintenance.as_ref().and_then(|maintenance| maintenance.object_lock.as_ref()) {
+        match crate::object_lock::duration_days(&lock.retention) {
+            None => report.errors.push(ValidationIssue::new(
+                "maintenance.object_lock.retention",
+                format!("Invalid duration '{}', expected e.g. 30d or 1y", lock.retention),
+            )),
+            Some(retention) => {
+                let keep_within = config.maintenance.as_ref().and_then(|maintenance| maintenance.retention.keep_within.as_deref());
+                if let Some(keep_within) = keep_within.filter(|value| crate::object_lock::duration_days(value).is_some_and(|days| days < retention)) {
+                    report.warnings.push(ValidationIssue::new(
+                        "maintenance.keep_within",
+                        format!("{} is shorter than the object lock retention; forget keeps {} instead", keep_within, lock.retention),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn validate_job(config: &Config, job: &BackupConfig, prefix: &str, report: &mut ValidationReport) {
+    if !KNOWN_FREQUENCIES.contains(&job.frequency.to_lowercase().as_str()) {
+        report.warnings.push(ValidationIssue::new(
+            &format!("{}.frequency", prefix),
+            format!("Unknown frequency '{}', expected one of: {}", job.frequency, KNOWN_FREQUENCIES.join(", ")),
+        ));
+    }
+
+    if parse_time(&job.time).is_none() {
+        report.errors.push(ValidationIssue::new(
+            &format!("{}.time", prefix),
+            format!("Invalid time '{}', expected HH:MM (24-hour)", job.time),
+        ));
+    }
+
+    if job.effective_directories().is_empty() && job.docker_volumes.is_none() && job.kubernetes.is_none() && job.cold_export.is_none() {
+        report.errors.push(ValidationIssue::new(
+            &format!("{}.directories", prefix),
+            "At least one directory must be configured",
+        ));
+    }
+    if let Some(ref window) = job.window {
+        for (key, value) in [("start", &window.start), ("end", &window.end)] {
+            if parse_time(value).is_none() {
+                report.errors.push(ValidationIssue::new(
+                    &format!("{}.window.{}", prefix, key),
+                    format!("Invalid time '{}', expected HH:MM (24-hour)", value),
+                ));
+            }
+        }
+        if window.start == window.end {
+            report.errors.push(ValidationIssue::new(&format!("{}.window", prefix), "The window's start and end are the same"));
+        } else if job.frequency.to_lowercase() != "hourly" && crate::window::parse(&job.time).is_some_and(|time| !window.contains(time)) {
+            report.warnings.push(ValidationIssue::new(
+                &format!("{}.time", prefix),
+                format!("Scheduled at {}, outside its window ({}-{}); every run will be deferred", job.time, window.start, window.end),
+            ));
+        }
+    }
+    if let Some(ref user) = job.run_as {
+        if let Err(e) = crate::run_as::lookup(user) {
+            report.errors.push(ValidationIssue::new(&format!("{}.run_as", prefix), e.to_string()));
+        }
+    }
+    if let Some(ref workdir) = job.workdir {
+        if !crate::expand_tilde(workdir).is_dir() {
+            report.errors.push(ValidationIssue::new(
+                &format!("{}.workdir", prefix),
+                format!("Working directory {:?} does not exist", workdir),
+            ));
+        }
+    }
+    for name in job.env.keys() {
+        let valid = !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            report.errors.push(ValidationIssue::new(
+                &format!("{}.env.{}", prefix, name),
+                format!("'{}' is not a valid environment variable name", name),
+            ));
+        }
+    }
+
+    if job.script.is_some() {
+        if let Err(e) = crate::scripting::check(job) {
+            report.errors.push(ValidationIssue::new(&format!("{}.script", prefix), e.to_string()));
+        }
+    }
+
+    for (i, dir) in job.directories.iter().enumerate() {
+        if !job.resolve_path(dir).exists() {
+            report.warnings.push(ValidationIssue::new(
+                &format!("{}.directories[{}]", prefix, i),
+                format!("Directory {:?} does not exist", dir),
+            ));
+        }
+    }
+
+    let sources = [
+        ("docker_volumes", job.docker_volumes.is_some()),
+        ("kubernetes", job.kubernetes.is_some()),
+        ("fs_snapshot", job.fs_snapshot.is_some()),
+        ("cold_export", job.cold_export.is_some()),
+    ];
+    let configured: Vec<&str> = sources.iter().filter(|(_, set)| *set).map(|(name, _)| *name).collect();
+    if configured.len() > 1 {
+        report.errors.push(ValidationIssue::new(
+            &format!("{}.{}", prefix, configured[1]),
+            format!("A job can use only one of docker_volumes, kubernetes, fs_snapshot and cold_export (has {})", configured.join(", ")),
+        ));
+    }
+
+    if let Some(ref snapshot) = job.fs_snapshot {
+        for (i, dir) in job.effective_directories().iter().enumerate() {
+            if !crate::expand_tilde(dir).starts_with(&snapshot.source) {
+                report.errors.push(ValidationIssue::new(
+                    &format!("{}.directories[{}]", prefix, i),
+                    format!("{:?} is not below fs_snapshot.source {:?}", dir, snapshot.source),
+                ));
+            }
+        }
+    }
+
+    for (i, tag) in job.tags.iter().enumerate() {
+        for placeholder in templates::unknown_placeholders(tag, &[]) {
+            report.warnings.push(ValidationIssue::new(
+                &format!("{}.tags[{}]", prefix, i),
+                format!("Unknown placeholder {}; supported: {}", placeholder, templates::PLACEHOLDERS.join(", ")),
+            ));
+        }
+    }
+
+    if let Some(ref export) = job.cold_export {
+        let export_prefix = format!("{}.cold_export", prefix);
+        if export.paths.is_empty() {
+            report.errors.push(ValidationIssue::new(&format!("{}.paths", export_prefix), "At least one path must be configured"));
+        }
+        for (i, path) in export.paths.iter().enumerate() {
+            if !path.starts_with('/') {
+                report.errors.push(ValidationIssue::new(
+                    &format!("{}.paths[{}]", export_prefix, i),
+                    format!("'{}' must be absolute, as it appears in the snapshots", path),
+                ));
+            }
+        }
+        match (&export.directory, &export.rclone) {
+            (None, None) => report.errors.push(ValidationIssue::new(&export_prefix, "Set directory or rclone as the destination")),
+            (Some(_), Some(_)) => report.errors.push(ValidationIssue::new(&export_prefix, "Set only one of directory and rclone")),
+            (Some(directory), None) if !crate::expand_tilde(directory).is_dir() => report.warnings.push(ValidationIssue::new(
+                &format!("{}.directory", export_prefix),
+                format!("Directory {:?} does not exist", directory),
+            )),
+            _ => {}
+        }
+        match (&export.recipient, &export.passphrase_file) {
+            (None, None) => report.errors.push(ValidationIssue::new(
+                &export_prefix,
+                "Set recipient (a gpg key) or passphrase_file to encrypt the archives",
+            )),
+            (Some(_), Some(_)) => report.errors.push(ValidationIssue::new(&export_prefix, "Set only one of recipient and passphrase_file")),
+            (None, Some(passphrase_file)) if !crate::expand_tilde(passphrase_file).is_file() => report.errors.push(ValidationIssue::new(
+                &format!("{}.passphrase_file", export_prefix),
+                format!("Passphrase file {:?} does not exist", passphrase_file),
+            )),
+            _ => {}
+        }
+        if !job.directories.is_empty() {
+            report.warnings.push(ValidationIssue::new(
+                &format!("{}.directories", prefix),
+                "A cold_export job backs nothing up; its directories are ignored",
+            ));
+        }
+    }
+
+    if let Some(ref kubernetes) = job.kubernetes {
+        if kubernetes.dumps.is_empty() && !kubernetes.backup_pvcs {
+            report.warnings.push(ValidationIssue::new(
+                &format!("{}.kubernetes", prefix),
+                "Nothing to back up: add dumps or set backup_pvcs",
+            ));
+        }
+        for (i, dump) in kubernetes.dumps.iter().enumerate() {
+            for placeholder in templates::unknown_placeholders(&dump.file_name, &["{pod}", "{name}"]) {
+                report.warnings.push(ValidationIssue::new(
+                    &format!("{}.kubernetes.dumps[{}].file_name", prefix, i),
+                    format!("Unknown placeholder {}; supported: {}, {{pod}}, {{name}}", placeholder, templates::PLACEHOLDERS.join(", ")),
+                ));
+            }
+        }
+    }
+
+    if let Some(ref routing) = job.notifications {
+        for name in routing.targets.iter().flatten() {
+            if !config.notifications.iter().any(|target| target.name() == name) {
+                report.errors.push(ValidationIssue::new(
+                    &format!("{}.notifications.targets", prefix),
+                    format!("Unknown notification target '{}'", name),
+                ));
+            }
+        }
+    }
+}
+
+/// Check that the repository can be opened with the configured credentials
+pub async fn check_repository(config: &Config, report: &mut ValidationReport) {
+    let mut cmd = crate::restic_command(&config.restic, "cat");
+    cmd.arg("config");
+
+    match cmd.output().await {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            report.errors.push(ValidationIssue::new(
+                "restic.repository",
+                format!("Repository is not reachable: {}", stderr.trim()),
+            ));
+        }
+        Err(e) => {
+            report.errors.push(ValidationIssue::new(
+                "restic.repository",
+                format!("Failed to run restic: {}", e),
+            ));
+        }
+    }
+}
+
+/// Parse an "HH:MM" time into (hour, minute)
+pub fn parse_time(time: &str) -> Option<(u32, u32)> {
+    let (hour, minute) = time.trim().split_once(':')?;
+    let hour: u32 = hour.parse().ok()?;
+    let minute: u32 = minute.parse().ok()?;
+    if hour < 24 && minute < 60 {
+        Some((hour, minute))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = "backup:\n  frequency: daily\n  time: \"02:00\"\n  directories: [/srv]\nlogging:\n  directory: /tmp/logs\n  max_size: 10MB\nrestic:\n  repository: /tmp/repo\n";
+
+    #[test]
+    fn validate_yaml_upgrades_old_configs() {
+        let (yaml, config, report) = validate_yaml(MINIMAL);
+        assert!(report.is_valid(), "{:?}", report.errors);
+        assert!(config.is_some());
+        assert!(yaml.contains(&format!("version: {}", crate::migrations::CURRENT_CONFIG_VERSION)));
+    }
+
+    #[test]
+    fn validate_yaml_rejects_newer_versions() {
+        let (_, config, report) = validate_yaml(&format!("version: 99\n{}", MINIMAL));
+        assert!(config.is_none());
+        assert_eq!(report.errors[0].path, "version");
+    }
+
+    #[test]
+    fn validate_yaml_reports_syntax_errors_with_their_location() {
+        let (_, config, report) = validate_yaml("backup: [unclosed\n");
+        assert!(config.is_none());
+        assert!(report.errors[0].line.is_some());
+    }
+}
@@ -1,59 +1,341 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::{Html, IntoResponse, Json, Response},
+    extract::{ws::WebSocketUpgrade, Path, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
     routing::{get, post},
-    Router,
+    Extension, Router,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::convert::Infallible;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
 
-use crate::{Config, BackupConfig, ResticConfig};
+use crate::accounts::{CurrentUser, Role};
+use crate::tokens::Scope;
+use crate::{accounts, agent, archive, audit, cache, calendar, config_history, diagnostics, events, file_history, log_search, reports, snapshots, staging, validation, Config};
 
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<RwLock<Config>>,
     pub config_path: String,
     pub log_dir: std::path::PathBuf,
+    pub events: events::EventBus,
+    pub cache: cache::RepoCache,
+    pub auth: accounts::AuthCache,
+    pub agents: agent::AgentRegistry,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WebConfig {
+    /// Address the web UI listens on. Anything but loopback, e.g. `0.0.0.0:3000` for agents on other
+    /// machines, needs `users`, since without them every request gets through.
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// Also serve the gRPC control API on this address, e.g. `127.0.0.1:50051`
+    #[serde(default)]
+    pub grpc_listen: Option<String>,
+    #[serde(default)]
+    pub theme: Option<ThemeConfig>,
+    /// Show the snapshots of every host in the repository, grouped by host, instead of only this machine's
+    #[serde(default)]
+    pub all_hosts: bool,
+}
+
+/// Branding for the dashboard
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ThemeConfig {
+    /// Directory with `custom.css` and/or `logo.svg`, `logo.png` or `logo.jpg`, served under `/theme/`
+    pub directory: PathBuf,
+    /// Replaces "Better Restic Client" in the header
+    #[serde(default)]
+    pub title: Option<String>,
+}
+
+/// Logo file names looked for in the theme directory, in order
+const THEME_LOGOS: [&str; 3] = ["logo.svg", "logo.png", "logo.jpg"];
+
+/// The web UI files, compiled into the binary
+#[derive(rust_embed::Embed)]
+#[folder = "static/"]
+struct Assets;
+
+fn default_listen() -> String {
+    "127.0.0.1:3000".to_string()
 }
 
 #[derive(Deserialize)]
 pub struct UpdateYamlRequest {
     pub yaml: String,
+    /// Only compute the diff against the current config, don't write anything
+    #[serde(default)]
+    pub preview: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ValidateRequest {
+    pub yaml: String,
+    /// Also check that the repository can be opened (slow for remote repos)
+    #[serde(default)]
+    pub check_repository: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RollbackRequest {
+    /// Version id to restore; defaults to the most recent saved version
+    pub version: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct BackupRequest {
     pub dry_run: Option<bool>,
+    /// Job to run; all jobs when not given
+    #[serde(default)]
+    pub job: Option<String>,
+    /// Note stored with the snapshot, e.g. "pre-upgrade to Ubuntu 24.04"
+    #[serde(default)]
+    pub note: Option<String>,
 }
 
 pub async fn run_web_server(state: AppState) -> Result<(), Box<dyn std::error::Error>> {
-    let app = Router::new()
+    let web_config = state.config.read().await.web.clone();
+    let listen = web_config.as_ref().map(|web| web.listen.clone()).unwrap_or_else(default_listen);
+    // Without users the config editor, terminal and staging commits are open to everyone who can connect
+    if state.config.read().await.users.is_empty() && !is_loopback(&listen) {
+        return Err(format!(
+            "Refusing to listen on {} without `users`: anyone who can reach it could edit the config and run commands. \
+             Add users (see `hash-password`) or listen on 127.0.0.1",
+            listen
+        )
+        .into());
+    }
+    tokio::spawn(refresh_loop(state.clone()));
+    if let Some(grpc_listen) = web_config.and_then(|web| web.grpc_listen) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::grpc::serve(state, grpc_listen).await {
+                eprintln!("gRPC server error: {}", e);
+            }
+        });
+    }
+
+    let viewer = Router::new()
         .route("/", get(index))
+        .route("/static/*path", get(static_asset))
+        .route("/theme/*path", get(theme_file))
+        .route("/api/theme", get(get_theme))
+        .route("/api/me", get(get_me))
         .route("/api/config", get(get_config))
         .route("/api/logs", get(get_logs))
         .route("/api/status", get(get_status))
         .route("/api/snapshots", get(get_snapshots))
+        .route("/api/snapshots/counts", get(get_snapshot_counts))
         .route("/api/stats", get(get_stats))
         .route("/api/stats/:snapshot_id", get(get_stats_for_snapshot_handler))
+        .route("/api/snapshots/:snapshot_id/ls", get(list_snapshot))
+        .route("/api/file-history", get(get_file_history))
+        .route("/api/events", get(event_stream))
+        .route("/api/reports", get(get_reports))
+        .route("/api/reports/:run_id", get(get_report))
+        .route("/api/history/calendar", get(get_history_calendar))
+        .route("/api/agents", get(get_agents))
+        .route("/api/staging", get(get_staging_areas))
+        .route("/api/staging/:id", get(get_staging_area))
+        .route_layer(middleware::from_fn_with_state(Access::new(&state, Role::Viewer, Some(Scope::ReadStatus)), authorize));
+    let pins = Router::new()
+        .route("/api/snapshots/:snapshot_id/pin", post(pin_snapshot))
+        .route("/api/snapshots/:snapshot_id/unpin", post(unpin_snapshot))
+        .route_layer(middleware::from_fn_with_state(Access::new(&state, Role::Operator, Some(Scope::PinSnapshots)), authorize));
+    let backups = Router::new()
+        .route("/api/backup/trigger", post(trigger_backup))
+        .route("/api/agents/:hostname/trigger", post(trigger_agent_backup))
+        .route_layer(middleware::from_fn_with_state(Access::new(&state, Role::Operator, Some(Scope::TriggerBackup)), authorize));
+    let operator = Router::new()
+        .route("/api/cache/invalidate", post(invalidate_cache))
+        .route("/api/logs/bundle", get(get_log_bundle))
+        .route("/api/snapshots/:snapshot_id/export", get(export_archive))
+        .route("/api/staging", post(create_staging_area))
+        .route("/api/staging/:id/discard", post(discard_staging_area))
+        .route_layer(middleware::from_fn_with_state(Access::new(&state, Role::Operator, None), authorize));
+    let agents = Router::new()
+        .route("/api/agents/checkin", post(agent_check_in))
+        .route_layer(middleware::from_fn_with_state(Access::token(&state, Scope::Agent), authorize));
+    let audit_log = Router::new()
+        .route("/api/audit", get(get_audit))
+        .route_layer(middleware::from_fn_with_state(Access::new(&state, Role::Admin, Some(Scope::ReadAudit)), authorize));
+    let admin = Router::new()
         .route("/api/config/yaml", get(get_config_yaml))
         .route("/api/config/yaml", post(update_config_yaml))
-        .route("/api/backup/trigger", post(trigger_backup))
-        .with_state(state);
+        .route("/api/config/validate", post(validate_config_yaml))
+        .route("/api/config/history", get(get_config_history))
+        .route("/api/config/rollback", post(rollback_config))
+        .route("/api/terminal", get(terminal_socket))
+        // Committing writes anywhere the server user can, e.g. over /etc/shadow with `in_place`
+        .route("/api/staging/:id/commit", post(commit_staging_area))
+        .route_layer(middleware::from_fn_with_state(Access::new(&state, Role::Admin, None), authorize));
+    let app = viewer.merge(pins).merge(backups).merge(agents).merge(operator).merge(audit_log).merge(admin).with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
-    println!("🌐 Web UI available at http://127.0.0.1:3000");
+    let listener = tokio::net::TcpListener::bind(&listen).await?;
+    println!("🌐 Web UI available at http://{}", listen);
     println!("   Press Ctrl+C to stop the server");
 
     axum::serve(listener, app).await?;
     Ok(())
 }
 
-async fn index() -> Html<&'static str> {
-    Html(include_str!("../static/index.html"))
+/// Whether a `listen` address only accepts connections from this machine
+fn is_loopback(listen: &str) -> bool {
+    match listen.parse::<std::net::SocketAddr>() {
+        Ok(address) => address.ip().is_loopback(),
+        Err(_) => listen.rsplit_once(':').is_some_and(|(host, _)| host == "localhost"),
+    }
+}
+
+/// Who may use a group of routes: users with at least `role`, or API tokens with `scope`
+#[derive(Clone)]
+pub struct Access {
+    state: AppState,
+    role: Role,
+    scope: Option<Scope>,
+    /// Only a token with `scope` gets in, whether or not users are configured
+    tokens_only: bool,
+}
+
+impl Access {
+    pub fn new(state: &AppState, role: Role, scope: Option<Scope>) -> Self {
+        Access { state: state.clone(), role, scope, tokens_only: false }
+    }
+
+    /// Routes for machines rather than people, e.g. agent check-ins, which always need a token
+    pub fn token(state: &AppState, scope: Scope) -> Self {
+        Access { state: state.clone(), role: Role::Admin, scope: Some(scope), tokens_only: true }
+    }
+
+    /// The user or token the credentials belong to, if they're allowed in
+    pub fn check(&self, users: &[accounts::UserAccount], authorization: &str) -> Result<CurrentUser, StatusCode> {
+        if let Some(secret) = authorization.strip_prefix("Bearer ") {
+            let token = crate::tokens::find(&crate::tokens::tokens_path(&self.state.config_path), secret.trim())
+                .ok_or(StatusCode::UNAUTHORIZED)?;
+            if !self.scope.is_some_and(|scope| token.scopes.contains(&scope)) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            // Tokens only reach what their scopes allow, so they count as viewers for the UI
+            return Ok(CurrentUser {
+                name: format!("token:{}", token.name.unwrap_or(token.id)),
+                role: Role::Viewer,
+            });
+        }
+        if self.tokens_only {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        let user = self.state.auth.authenticate(users, authorization).ok_or(StatusCode::UNAUTHORIZED)?;
+        if user.role < self.role {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        Ok(user)
+    }
+}
+
+/// Let the request through if its Basic auth credentials belong to a user with the required role, or
+/// its Bearer token has the required scope. Without any configured users, everyone gets through,
+/// except on token-only routes.
+async fn authorize(State(access): State<Access>, mut request: Request, next: Next) -> Response {
+    let users = access.state.config.read().await.users.clone();
+    if users.is_empty() && !access.tokens_only {
+        return next.run(request).await;
+    }
+    let authorization = request.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()).unwrap_or("");
+    match access.check(&users, authorization) {
+        Ok(user) => {
+            request.extensions_mut().insert(user);
+            next.run(request).await
+        }
+        Err(StatusCode::UNAUTHORIZED) => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"Better Restic Client\"")],
+        )
+            .into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Who the UI is logged in as, so it can hide what the role can't do
+async fn get_me(user: Option<Extension<CurrentUser>>) -> Json<serde_json::Value> {
+    match user {
+        Some(Extension(user)) => Json(json!({ "user": user.name, "role": user.role })),
+        None => Json(json!({ "user": null, "role": Role::Admin })),
+    }
+}
+
+/// An embedded file with its content type. Browsers revalidate on every load and get
+/// `304 Not Modified` while the ETag (the file's hash) matches, so upgrades show up at once.
+fn embedded_asset(path: &str, headers: &HeaderMap) -> Response {
+    let Some(file) = Assets::get(path) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let hash: String = file.metadata.sha256_hash().iter().map(|byte| format!("{:02x}", byte)).collect();
+    let etag = format!("\"{}\"", hash);
+    let cache_headers = [(header::ETAG, etag.clone()), (header::CACHE_CONTROL, "no-cache".to_string())];
+    if headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok()) == Some(etag.as_str()) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    (
+        [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+        cache_headers,
+        file.data,
+    )
+        .into_response()
+}
+
+async fn index(headers: HeaderMap) -> Response {
+    embedded_asset("index.html", &headers)
+}
+
+async fn static_asset(Path(path): Path<String>, headers: HeaderMap) -> Response {
+    embedded_asset(&path, &headers)
+}
+
+/// A file from the configured theme directory; only plain relative paths, so nothing outside it is served
+async fn theme_file(State(state): State<AppState>, Path(path): Path<String>) -> Response {
+    let config = state.config.read().await;
+    let Some(theme) = config.web.as_ref().and_then(|web| web.theme.as_ref()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let directory = crate::expand_tilde(&theme.directory);
+    drop(config);
+    let relative = std::path::Path::new(&path);
+    if !relative.components().all(|component| matches!(component, std::path::Component::Normal(_))) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    match tokio::fs::read(directory.join(relative)).await {
+        Ok(content) => {
+            let mime = mime_guess::from_path(relative).first_or_octet_stream();
+            ([(header::CONTENT_TYPE, mime.to_string()), (header::CACHE_CONTROL, "no-cache".to_string())], content).into_response()
+        }
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Which theme files exist, so the UI only loads what's there
+async fn get_theme(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let config = state.config.read().await;
+    let Some(theme) = config.web.as_ref().and_then(|web| web.theme.as_ref()) else {
+        return Json(json!({ "title": null, "css": null, "logo": null }));
+    };
+    let directory = crate::expand_tilde(&theme.directory);
+    let css = directory.join("custom.css").is_file().then_some("/theme/custom.css");
+    let logo = THEME_LOGOS
+        .iter()
+        .find(|name| directory.join(name).is_file())
+        .map(|name| format!("/theme/{}", name));
+    Json(json!({ "title": theme.title, "css": css, "logo": logo }))
 }
 
 async fn get_config(State(state): State<AppState>) -> Json<serde_json::Value> {
@@ -64,88 +346,431 @@ async fn get_config(State(state): State<AppState>) -> Json<serde_json::Value> {
             "time": config.backup.time,
             "directories": config.backup.directories.iter().map(|d: &PathBuf| d.to_string_lossy().to_string()).collect::<Vec<_>>(),
             "exclude": config.backup.exclude.iter().map(|d: &PathBuf| d.to_string_lossy().to_string()).collect::<Vec<_>>(),
+            "profile": config.backup.profile,
         },
         "logging": {
             "directory": config.logging.directory.to_string_lossy(),
             "max_size": config.logging.max_size,
         },
+        "jobs": config.jobs().iter().map(|job| job.name.clone()).collect::<Vec<_>>(),
         "restic": {
-            "repository": config.restic.repository,
+            "repository": config.restic.repository_url(),
             "has_ssh_command": config.restic.ssh_command.is_some(),
+            "has_ssh_options": config.restic.ssh.is_some(),
             "has_password_command": config.restic.password_command.is_some(),
+            "has_password_file": config.restic.password_file.is_some(),
             "has_password": config.restic.password.is_some(),
         }
     }))
 }
 
-async fn get_config_yaml(State(state): State<AppState>) -> Result<String, StatusCode> {
-    std::fs::read_to_string(&state.config_path)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+async fn get_config_yaml(State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
+    let yaml = std::fs::read_to_string(&state.config_path)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(([(header::ETAG, config_history::etag(&yaml))], yaml))
+}
+
+/// Refuse the write if the client sent an If-Match header that no longer matches the file on disk
+fn check_if_match(headers: &HeaderMap, current_yaml: &str) -> Result<(), StatusCode> {
+    if let Some(if_match) = headers.get(header::IF_MATCH) {
+        let if_match = if_match.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+        let current = config_history::etag(current_yaml);
+        if if_match.trim() != "*" && !if_match.split(',').any(|tag| tag.trim() == current) {
+            eprintln!("Config changed on disk since it was loaded (If-Match: {}, current: {})", if_match, current);
+            return Err(StatusCode::PRECONDITION_FAILED);
+        }
+    }
+    Ok(())
+}
+
+/// Who made an API request: the logged-in user, or without configured users, the user from the header
+/// named in `audit.trusted_proxy_header`, set by an authenticating reverse proxy. Clients can send any
+/// header, so without that setting the user is unknown.
+fn request_user(config: &Config, headers: &HeaderMap, user: Option<&CurrentUser>) -> Option<String> {
+    user.map(|user| user.name.clone()).or_else(|| {
+        let header = config.audit.as_ref()?.trusted_proxy_header.as_deref()?;
+        headers.get(header).and_then(|value| value.to_str().ok()).map(str::to_string)
+    })
+}
+
+fn audit_entry(
+    config: &Config,
+    headers: &HeaderMap,
+    user: Option<&CurrentUser>,
+    action: &str,
+    target: Option<String>,
+) -> audit::AuditEntry {
+    audit::AuditEntry::new(audit::Source::Web, request_user(config, headers, user), action, target)
 }
 
 async fn update_config_yaml(
     State(state): State<AppState>,
-    Json(payload): Json<UpdateYamlRequest>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+    Json(mut payload): Json<UpdateYamlRequest>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    // Validate YAML by trying to parse it
-    let new_config: Config = serde_yaml::from_str(&payload.yaml)
+    // The same checks as /api/config/validate; pasted configs from older versions are upgraded before they're saved
+    let (yaml, new_config, report) = validation::validate_yaml(&payload.yaml);
+    let new_config = match new_config {
+        Some(config) if report.is_valid() => config,
+        _ => {
+            for issue in &report.errors {
+                eprintln!("Config validation error: {}: {}", issue.path, issue.message);
+            }
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    payload.yaml = yaml;
+
+    let current_yaml = std::fs::read_to_string(&state.config_path).unwrap_or_default();
+    check_if_match(&headers, &current_yaml)?;
+    let changes = config_history::diff_yaml(&current_yaml, &payload.yaml)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    if payload.preview {
+        return Ok(Json(json!({
+            "success": true,
+            "preview": true,
+            "changes": changes,
+        })));
+    }
+
+    // Keep the previous version around before overwriting it
+    let saved_version = config_history::save_version(&state.config_path)
         .map_err(|e| {
-            eprintln!("YAML validation error: {}", e);
-            StatusCode::BAD_REQUEST
+            eprintln!("Failed to save config version: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
     // Write to file
-    std::fs::write(&state.config_path, &payload.yaml)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    config_history::write_config(&state.config_path, &payload.yaml)
+        .map_err(|e| {
+            eprintln!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    audit::record(&new_config, &audit_entry(&new_config, &headers, user.as_deref(), "config.update", Some(state.config_path.clone())));
 
     // Update in-memory config
     *state.config.write().await = new_config;
+    state.events.publish(events::Event::ConfigChanged { source: "web".to_string() });
 
     Ok(Json(json!({
         "success": true,
-        "message": "Configuration updated successfully"
+        "message": "Configuration updated successfully",
+        "changes": changes,
+        "previous_version": saved_version,
+        "etag": config_history::etag(&payload.yaml),
     })))
 }
 
-async fn trigger_backup(
+async fn validate_config_yaml(Json(payload): Json<ValidateRequest>) -> Json<serde_json::Value> {
+    let (_, config, mut report) = validation::validate_yaml(&payload.yaml);
+
+    // Only worth reaching out to the repository if the config itself is sound
+    if payload.check_repository && report.is_valid() {
+        if let Some(ref config) = config {
+            validation::check_repository(config, &mut report).await;
+        }
+    }
+
+    Json(json!({
+        "valid": report.is_valid(),
+        "errors": report.errors,
+        "warnings": report.warnings,
+    }))
+}
+
+async fn get_config_history(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    let versions = config_history::list_versions(&state.config_path)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(json!({
+        "versions": versions,
+        "limit": config_history::CONFIG_HISTORY_LIMIT,
+    })))
+}
+
+async fn rollback_config(
     State(state): State<AppState>,
-    Json(payload): Json<BackupRequest>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+    Json(payload): Json<RollbackRequest>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    let dry_run = payload.dry_run.unwrap_or(false);
-    let config = state.config.read().await;
-    
-    // Spawn backup in background
-    let backup_config = BackupConfig {
-        frequency: config.backup.frequency.clone(),
-        time: config.backup.time.clone(),
-        directories: config.backup.directories.clone(),
-        exclude: config.backup.exclude.clone(),
-    };
-    
-    let restic_config = ResticConfig {
-        repository: config.restic.repository.clone(),
-        ssh_command: config.restic.ssh_command.clone(),
-        password_command: config.restic.password_command.clone(),
-        password: config.restic.password.clone(),
+    let version = match payload.version {
+        Some(version) => version,
+        None => config_history::list_versions(&state.config_path)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .into_iter()
+            .next()
+            .ok_or(StatusCode::NOT_FOUND)?,
     };
-    
-    drop(config); // Release the lock
-    
-    // Execute backup in a tokio task
+
+    let yaml = config_history::read_version(&state.config_path, &version)
+        .map_err(|e| {
+            eprintln!("Config rollback error: {}", e);
+            StatusCode::NOT_FOUND
+        })?;
+    // Versions saved before an upgrade are upgraded again on the way back
+    let yaml = crate::migrations::migrate_yaml(&yaml)
+        .map_err(|e| {
+            eprintln!("Saved config version {} can't be upgraded: {}", version, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?
+        .0;
+
+    let restored_config: Config = serde_yaml::from_str(&yaml)
+        .map_err(|e| {
+            eprintln!("Saved config version {} is invalid: {}", version, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?;
+
+    let current_yaml = std::fs::read_to_string(&state.config_path).unwrap_or_default();
+    check_if_match(&headers, &current_yaml)?;
+    let changes = config_history::diff_yaml(&current_yaml, &yaml)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // The config being replaced is saved too, so a rollback can itself be undone
+    let saved_version = config_history::save_version(&state.config_path)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    config_history::write_config(&state.config_path, &yaml)
+        .map_err(|e| {
+            eprintln!("{}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    audit::record(&restored_config, &audit_entry(&restored_config, &headers, user.as_deref(), "config.rollback", Some(version.clone())));
+    *state.config.write().await = restored_config;
+    state.events.publish(events::Event::ConfigChanged { source: "rollback".to_string() });
+
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Configuration rolled back to version {}", version),
+        "restored_version": version,
+        "previous_version": saved_version,
+        "changes": changes,
+        "etag": config_history::etag(&yaml),
+    })))
+}
+
+/// Run jobs one after another in a background task, publishing events as they go.
+/// Shared by the REST and gRPC APIs; returns the reporters, which carry the run ids.
+pub fn start_backups(
+    state: &AppState,
+    config: Config,
+    job_names: &[String],
+    dry_run: bool,
+    note: Option<String>,
+    user: Option<String>,
+) -> Vec<events::RunReporter> {
+    let reporters: Vec<events::RunReporter> = job_names.iter().map(|name| state.events.reporter(name)).collect();
+
+    let state = state.clone();
+    let runs = reporters.clone();
     tokio::spawn(async move {
-        if let Err(e) = crate::execute_restic_backup(&backup_config, &restic_config, dry_run, true).await {
-            eprintln!("Backup error: {}", e);
+        let selected: Vec<&crate::BackupConfig> = runs.iter().filter_map(|reporter| config.find_job(&reporter.job)).collect();
+        let batch = crate::dependencies::order(&selected);
+        let mut failed = Vec::new();
+        for job in batch.iter().copied() {
+            let Some(reporter) = runs.iter().find(|reporter| reporter.job == job.name) else { continue };
+            if let Some(reason) = crate::dependencies::unmet(&config, job, &batch, &failed) {
+                eprintln!("Skipping job '{}': {}", job.name, reason);
+                reporter.failed(&format!("Skipped: {}", reason));
+                failed.push(job.name.clone());
+                continue;
+            }
+            if let Some(reason) = crate::conditions::unmet(job).await {
+                reporter.skipped(&reason);
+                continue;
+            }
+            if let Some(reason) = crate::window::defer(&config, job, dry_run) {
+                reporter.skipped(&format!("deferred, {}", reason));
+                continue;
+            }
+            // Checked before starting, so annotating can't fail here
+            let annotated = note.as_deref().and_then(|note| crate::notes::annotate(job, note).ok());
+            let job = annotated.as_ref().unwrap_or(job);
+            reporter.started(dry_run);
+            let result = crate::run_backup(&config, job, dry_run, true, Some(reporter)).await;
+            if !dry_run {
+                state.cache.invalidate_repository(&config.restic.repository_url());
+                let entry = audit::AuditEntry::new(audit::Source::Web, user.clone(), "backup", Some(job.name.clone()));
+                audit::record(&config, &entry.outcome(&result));
+            }
+            match result {
+                Ok(_) => reporter.finished(),
+                Err(e) => {
+                    eprintln!("Backup error in job '{}': {}", job.name, e);
+                    reporter.failed(&e.to_string());
+                    failed.push(job.name.clone());
+                }
+            }
+        }
+
+        // Repopulate right away so the dashboard doesn't wait on restic for the new snapshot
+        if !dry_run && config.cache.as_ref().is_some_and(|c| c.refresh_secs.is_some()) {
+            refresh_repository_metadata(&state).await;
         }
     });
-    
+    reporters
+}
+
+async fn trigger_backup(
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+    Json(payload): Json<BackupRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let dry_run = payload.dry_run.unwrap_or(false);
+    // Snapshot the config so the lock isn't held for the whole run
+    let config = state.config.read().await.clone();
+
+    let job_names: Vec<String> = match payload.job {
+        Some(ref name) => {
+            let job = config.find_job(name).ok_or(StatusCode::NOT_FOUND)?;
+            vec![job.name.clone()]
+        }
+        None => config.jobs().iter().map(|job| job.name.clone()).collect(),
+    };
+    if let Some(ref note) = payload.note {
+        crate::notes::tag(note).map_err(|_| StatusCode::BAD_REQUEST)?;
+    }
+    let user = request_user(&config, &headers, user.as_deref());
+    let runs: Vec<serde_json::Value> = start_backups(&state, config, &job_names, dry_run, payload.note.clone(), user)
+        .iter()
+        .map(|reporter| json!({ "job": reporter.job, "run_id": reporter.run_id }))
+        .collect();
+
     Ok(Json(json!({
         "success": true,
         "message": if dry_run { "Dry run backup triggered" } else { "Backup triggered" },
-        "dry_run": dry_run
+        "dry_run": dry_run,
+        "runs": runs,
+    })))
+}
+
+/// An agent reporting in; the response carries the jobs triggered for it since its last check-in.
+/// A token checks in only as the host it is named after, so one machine can't speak for another.
+async fn agent_check_in(
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    Json(check_in): Json<agent::CheckIn>,
+) -> Result<Json<agent::CheckInResponse>, StatusCode> {
+    // The route only lets tokens in, so there is always one to compare
+    let token = user.as_ref().and_then(|user| user.name.strip_prefix("token:")).unwrap_or_default();
+    if token != check_in.hostname {
+        eprintln!("Token '{}' tried to check in as agent '{}'", token, check_in.hostname);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(Json(state.agents.check_in(check_in)))
+}
+
+async fn get_agents(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(json!({ "agents": state.agents.list() }))
+}
+
+#[derive(Deserialize)]
+pub struct AgentTriggerRequest {
+    /// Job to run; all of the agent's jobs when not given
+    #[serde(default)]
+    pub job: Option<String>,
+}
+
+/// Queue a backup on an agent; it runs on the agent's next check-in
+async fn trigger_agent_backup(
+    Path(hostname): Path<String>,
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+    Json(payload): Json<AgentTriggerRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let jobs = state.agents.trigger(&hostname, payload.job.as_deref()).ok_or(StatusCode::NOT_FOUND)?;
+    let config = state.config.read().await;
+    for job in &jobs {
+        let entry = audit_entry(&config, &headers, user.as_deref(), "agent.backup", Some(format!("{}/{}", hostname, job)));
+        audit::record(&config, &entry);
+    }
+    Ok(Json(json!({ "success": true, "hostname": hostname, "queued": jobs })))
+}
+
+/// Latest run report overall and per job
+async fn get_reports(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+    let directory = match state.config.read().await.reports {
+        Some(ref reports_config) => crate::expand_tilde(&reports_config.directory),
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let all = reports::list(&directory);
+    let mut latest_per_job: Vec<&reports::RunReport> = Vec::new();
+    for report in &all {
+        if !latest_per_job.iter().any(|r| r.job == report.job) {
+            latest_per_job.push(report);
+        }
+    }
+
+    Ok(Json(json!({
+        "latest": all.first(),
+        "jobs": latest_per_job,
+        "runs": all.iter().map(|r| json!({
+            "run_id": r.run_id,
+            "job": r.job,
+            "status": r.status,
+            "dry_run": r.dry_run,
+            "finished_at": r.finished_at,
+        })).collect::<Vec<_>>(),
+    })))
+}
+
+/// Successful, failed and missed runs per job and day, for a heatmap of backup consistency
+async fn get_history_calendar(State(state): State<AppState>, Query(query): Query<CalendarQuery>) -> Result<Json<serde_json::Value>, StatusCode> {
+    if query.days == 0 || query.days > calendar::MAX_DAYS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let config = state.config.read().await;
+    let directory = match config.reports {
+        Some(ref reports_config) => crate::expand_tilde(&reports_config.directory),
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let now = chrono::Local::now();
+    let jobs = calendar::build(&config.jobs(), &reports::list(&directory), query.days, now);
+    Ok(Json(json!({
+        "days": query.days,
+        "from": now.date_naive() - chrono::Duration::days(query.days as i64 - 1),
+        "to": now.date_naive(),
+        "jobs": jobs,
     })))
 }
 
-async fn get_logs(State(state): State<AppState>) -> Json<serde_json::Value> {
+async fn get_report(
+    Path(run_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<reports::RunReport>, StatusCode> {
+    let directory = match state.config.read().await.reports {
+        Some(ref reports_config) => crate::expand_tilde(&reports_config.directory),
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    reports::read(&directory, &run_id)
+        .map(Json)
+        .map_err(|_| StatusCode::NOT_FOUND)
+}
+
+async fn event_stream(State(state): State<AppState>) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let stream = BroadcastStream::new(state.events.subscribe()).filter_map(|event| {
+        // Lagged subscribers just skip the events they missed
+        let event = event.ok()?;
+        SseEvent::default().event(event.name()).json_data(&event).ok().map(Ok)
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn get_logs(State(state): State<AppState>, Query(query): Query<log_search::LogQuery>) -> Result<Json<serde_json::Value>, StatusCode> {
+    if !query.is_empty() {
+        return search_logs(&state, query).await;
+    }
     let log_dir = &state.log_dir;
     let mut log_files = Vec::new();
     
@@ -184,122 +809,783 @@ async fn get_logs(State(state): State<AppState>) -> Json<serde_json::Value> {
         "No log files found".to_string()
     };
     
-    Json(json!({
+    Ok(Json(json!({
         "files": log_files,
         "latest_content": latest_log_content,
-    }))
+    })))
+}
+
+/// Zip of recent logs, the redacted config, versions and the latest run reports, for a bug report
+async fn get_log_bundle(
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    let config = state.config.read().await.clone();
+    let result = diagnostics::bundle(&config, &state.config_path).await;
+    audit::record(&config, &audit_entry(&config, &headers, user.as_deref(), "diagnostics.bundle", None).outcome(&result));
+    let bundle = result.map_err(|e| {
+        eprintln!("Failed to collect diagnostics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let disposition = format!("attachment; filename=\"{}\"", diagnostics::file_name());
+    Ok(([(header::CONTENT_TYPE, "application/zip".to_string()), (header::CONTENT_DISPOSITION, disposition)], bundle))
+}
+
+/// Matching entries across the current and rotated log files
+async fn search_logs(state: &AppState, query: log_search::LogQuery) -> Result<Json<serde_json::Value>, StatusCode> {
+    let config = state.config.read().await.clone();
+    // A job's lines are the ones logged while its runs were in progress
+    let runs = match (&query.job, &config.reports) {
+        (Some(job), Some(reports_config)) => reports::list(&crate::expand_tilde(&reports_config.directory))
+            .into_iter()
+            .filter(|report| &report.job == job)
+            .map(|report| (report.started_at, report.finished_at))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let filter = log_search::Filter::new(&query, runs).map_err(|e| {
+        eprintln!("Invalid log query: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    let log_dir = state.log_dir.clone();
+    let result = tokio::task::spawn_blocking(move || log_search::search(&log_dir, &filter))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(json!(result)))
 }
 
-async fn get_status(State(_state): State<AppState>) -> Json<serde_json::Value> {
+async fn get_status(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let config = state.config.read().await.clone();
+    let all_hosts = config.web.as_ref().is_some_and(|web| web.all_hosts);
+    // Every machine backing up to the repository, from the cached snapshot list
+    let hosts = if all_hosts {
+        match cached_snapshots(&state, false).await {
+            Ok(output) => Some(host_summary(&output.value)),
+            Err(_) => None,
+        }
+    } else {
+        None
+    };
+    let reports = config
+        .reports
+        .as_ref()
+        .map(|reports_config| reports::list(&crate::expand_tilde(&reports_config.directory)))
+        .unwrap_or_default();
+    let cost = match config.cost {
+        Some(_) => match reports.iter().find_map(|report| report.repository_size) {
+            Some(size) => crate::cost::estimate(&config, &reports, size),
+            None => cached_repository_size(&state, &config).await.and_then(|size| crate::cost::estimate(&config, &reports, size)),
+        },
+        None => None,
+    };
     Json(json!({
         "status": "running",
         "uptime": "N/A",
         "last_backup": "N/A",
+        "hosts": hosts,
+        "cost": cost,
     }))
 }
 
-async fn get_snapshots(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
+/// The repository's stored size for the cost estimate, when no run report measured it. `restic stats
+/// --mode raw-data` reads every pack header, so it is cached with the other repository queries
+/// rather than run on every status request.
+async fn cached_repository_size(state: &AppState, config: &Config) -> Option<u64> {
+    let cache_config = config.cache.clone().unwrap_or_default();
+    let key = cache::CacheKey::new(&config.restic.repository_url(), "stats", None, "--mode raw-data");
+    if let Some((size, _)) = state.cache.get::<u64>(&key, std::time::Duration::from_secs(cache_config.ttl_secs)) {
+        return Some(*size);
+    }
+    match crate::quota::repository_size(&config.restic).await {
+        Ok(size) => {
+            state.cache.insert(key, Arc::new(size));
+            Some(size)
+        }
+        Err(e) => {
+            eprintln!("Couldn't measure the repository size for the cost estimate: {}", e);
+            None
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct CacheQuery {
+    /// Bypass the cache and ask restic again
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+#[derive(Deserialize, Default)]
+pub struct SnapshotsQuery {
+    #[serde(default)]
+    pub refresh: bool,
+    /// Overrides `web.all_hosts`
+    #[serde(default)]
+    pub all_hosts: Option<bool>,
+    /// Only the newest N of the matching snapshots
+    #[serde(default)]
+    pub latest: Option<usize>,
+    /// Times as in the log search, e.g. "2024-03-05T14:00" or "2024-03-05"
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub until: Option<String>,
+    /// A host; implies all hosts
+    #[serde(default)]
+    pub host: Option<String>,
+    /// A tag, or "a,b" for snapshots with both
+    #[serde(default)]
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Pages count back from the newest snapshots, starting at 1
+    #[serde(default)]
+    pub page: Option<usize>,
+    #[serde(default)]
+    pub per_page: Option<usize>,
+}
+
+#[derive(Deserialize)]
+pub struct CalendarQuery {
+    /// Days back from today, today included
+    #[serde(default = "default_calendar_days")]
+    pub days: u32,
+}
+
+fn default_calendar_days() -> u32 {
+    365
+}
+
+#[derive(Deserialize)]
+pub struct LsQuery {
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Parsed restic output and how fresh it is
+pub struct CachedOutput<T = serde_json::Value> {
+    pub value: Arc<T>,
+    pub age: std::time::Duration,
+    /// Older than the cache TTL; only served while a background refresh is pending
+    pub stale: bool,
+}
+
+/// Run a restic command with `--json`, serving the parsed output from the cache while it is fresh.
+/// With background refresh enabled, cached output is served however old it is and marked stale.
+async fn cached_restic_json(
+    state: &AppState,
+    command: &str,
+    snapshot: Option<&str>,
+    args: &[&str],
+    refresh: bool,
+) -> Result<CachedOutput, StatusCode> {
+    cached(state, command, snapshot, args, refresh, |cmd| restic_json(command, cmd)).await
+}
+
+/// The repository's snapshot list, cached like `cached_restic_json`. The list can be huge, so it's
+/// parsed as restic prints it.
+async fn cached_snapshots(state: &AppState, refresh: bool) -> Result<CachedOutput<Vec<snapshots::Snapshot>>, StatusCode> {
+    cached(state, "snapshots", None, &[], refresh, |cmd| async move {
+        snapshots::stream(cmd).await.map_err(|e| {
+            eprintln!("Restic snapshots error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+    })
+    .await
+}
+
+/// Serve a restic command's output from the cache, or run the command with `--json` and parse its
+/// output with `parse`
+async fn cached<T, F, Fut>(
+    state: &AppState,
+    command: &str,
+    snapshot: Option<&str>,
+    args: &[&str],
+    refresh: bool,
+    parse: F,
+) -> Result<CachedOutput<T>, StatusCode>
+where
+    T: Send + Sync + 'static,
+    F: FnOnce(tokio::process::Command) -> Fut,
+    Fut: std::future::Future<Output = Result<T, StatusCode>>,
+{
     let config = state.config.read().await;
-    
-    // Build restic snapshots command
-    let mut cmd = tokio::process::Command::new("restic");
-    cmd.arg("snapshots");
-    cmd.arg("--repo").arg(&config.restic.repository);
-    cmd.arg("--json"); // Get JSON output for easier parsing
-    
-    // Handle password
-    if let Some(ref password_cmd) = config.restic.password_command {
-        cmd.arg("--password-command").arg(password_cmd);
-    } else if let Some(ref password) = config.restic.password {
-        cmd.env("RESTIC_PASSWORD", password);
+    let cache_config = config.cache.clone().unwrap_or_default();
+    let ttl = std::time::Duration::from_secs(cache_config.ttl_secs);
+
+    // Only snapshot ids are immutable; "latest" changes with every backup
+    let cache_snapshot = snapshot.filter(|id| id.chars().all(|c| c.is_ascii_hexdigit()));
+    let key = cache::CacheKey::new(&config.restic.repository_url(), command, cache_snapshot, &args.join(" "));
+    if !refresh {
+        let cached = if cache_config.refresh_secs.is_some() {
+            state.cache.get_any(&key)
+        } else {
+            state.cache.get(&key, ttl)
+        };
+        if let Some((value, age)) = cached {
+            return Ok(CachedOutput { value, age, stale: age >= ttl });
+        }
     }
-    
-    // Set SSH command if provided
-    if let Some(ref ssh_cmd) = config.restic.ssh_command {
-        cmd.env("RESTIC_SSH_COMMAND", ssh_cmd);
+
+    let mut cmd = crate::restic_command(&config.restic, command);
+    cmd.arg("--json"); // Get JSON output for easier parsing
+    // Snapshot ids, paths and patterns come from requests; after `--` none of them can be a flag
+    cmd.arg("--");
+    if let Some(snapshot) = snapshot {
+        cmd.arg(snapshot);
     }
-    
+    cmd.args(args);
+
     drop(config); // Release the lock
-    
+
+    let value = Arc::new(parse(cmd).await?);
+    state.cache.insert(key, value.clone());
+    Ok(CachedOutput {
+        value,
+        age: std::time::Duration::ZERO,
+        stale: false,
+    })
+}
+
+/// Run a restic command and parse its JSON output
+async fn restic_json(command: &str, mut cmd: tokio::process::Command) -> Result<serde_json::Value, StatusCode> {
     // Execute the command
     let output = cmd.output().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
+
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Restic snapshots error: {}", stderr);
+        eprintln!("Restic {} error: {}", command, stderr);
         return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
-    
-    // Parse JSON output from restic
+
+    // Parse JSON output from restic; `ls` prints one JSON object per line
     let stdout = String::from_utf8_lossy(&output.stdout);
-    let snapshots: Vec<serde_json::Value> = serde_json::from_str(&stdout)
-        .map_err(|e| {
-            eprintln!("Failed to parse snapshots JSON: {}", e);
+    if command == "ls" {
+        let mut lines = stdout.lines().filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok());
+        Ok(json!({
+            "snapshot": lines.next(),
+            "nodes": lines.collect::<Vec<_>>(),
+        }))
+    } else {
+        serde_json::from_str(&stdout).map_err(|e| {
+            eprintln!("Failed to parse {} JSON: {}", command, e);
             StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    
-    Ok(Json(json!({
-        "snapshots": snapshots,
-        "count": snapshots.len()
-    })))
+        })
+    }
+}
+
+/// `Age` header telling the client how old a cached answer is, plus a `Warning` once it is stale
+fn cache_headers<T>(output: &CachedOutput<T>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::AGE, output.age.as_secs().into());
+    if output.stale {
+        headers.insert(header::WARNING, header::HeaderValue::from_static("110 - \"Response is Stale\""));
+    }
+    headers
+}
+
+/// Re-fetch the snapshot list and repository stats into the cache
+pub async fn refresh_repository_metadata(state: &AppState) {
+    if let Err(status) = cached_snapshots(state, true).await {
+        eprintln!("Background refresh of restic snapshots failed: {}", status);
+    }
+    if let Err(status) = cached_restic_json(state, "stats", None, &[], true).await {
+        eprintln!("Background refresh of restic stats failed: {}", status);
+    }
+}
+
+/// Keep repository metadata warm so the dashboard never waits on restic
+async fn refresh_loop(state: AppState) {
+    loop {
+        let interval = state.config.read().await.cache.as_ref().and_then(|c| c.refresh_secs);
+        let Some(interval) = interval else {
+            // Refresh was turned off in the config; check again later in case it's turned back on
+            tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            continue;
+        };
+        refresh_repository_metadata(&state).await;
+        tokio::time::sleep(std::time::Duration::from_secs(interval.max(1))).await;
+    }
+}
+
+/// Number of snapshots and the latest one per host, this machine first
+fn host_summary(snapshots: &[snapshots::Snapshot]) -> Vec<serde_json::Value> {
+    let this_host = crate::hostname();
+    let mut hosts: BTreeMap<&str, (usize, Option<chrono::DateTime<chrono::FixedOffset>>)> = BTreeMap::new();
+    for snapshot in snapshots {
+        let entry = hosts.entry(&snapshot.hostname).or_default();
+        entry.0 += 1;
+        entry.1 = entry.1.max(Some(snapshot.time));
+    }
+    let mut summary: Vec<(&str, usize, Option<chrono::DateTime<chrono::FixedOffset>>)> =
+        hosts.into_iter().map(|(host, (count, latest))| (host, count, latest)).collect();
+    summary.sort_by_key(|(host, _, _)| *host != this_host);
+    summary
+        .into_iter()
+        .map(|(host, count, latest)| {
+            json!({
+                "hostname": host,
+                "count": count,
+                "latest": latest.map(|time| time.to_rfc3339()),
+                "this_host": host == this_host,
+            })
+        })
+        .collect()
+}
+
+impl SnapshotsQuery {
+    fn all_hosts(&self, default_all_hosts: bool) -> bool {
+        self.host.is_some() || self.all_hosts.unwrap_or(default_all_hosts)
+    }
+
+    fn filter(&self) -> Result<snapshots::Filter, StatusCode> {
+        snapshots::Filter::new(
+            self.latest,
+            self.since.as_deref(),
+            self.until.as_deref(),
+            self.host.iter().cloned().collect(),
+            self.tag.iter().cloned().collect(),
+            self.path.iter().cloned().collect(),
+        )
+        .map_err(|e| {
+            eprintln!("Invalid snapshots query: {}", e);
+            StatusCode::BAD_REQUEST
+        })
+    }
+}
+
+/// Snapshots per page unless the query asks for another size, and the most it may ask for
+const SNAPSHOTS_PER_PAGE: usize = 100;
+const MAX_SNAPSHOTS_PER_PAGE: usize = 1000;
+
+async fn get_snapshots(
+    State(state): State<AppState>,
+    Query(query): Query<SnapshotsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let default_all_hosts = state.config.read().await.web.as_ref().is_some_and(|web| web.all_hosts);
+    let all_hosts = query.all_hosts(default_all_hosts);
+    let filter = query.filter()?;
+    // The full list is cached once; hosts and the other filters are applied here rather than by restic
+    let output = cached_snapshots(&state, query.refresh).await?;
+    let all = output.value.as_slice();
+    let this_host = crate::hostname();
+    let matching = filter.apply(all.iter().filter(|snapshot| all_hosts || snapshot.hostname == this_host));
+
+    // Page 1 is the newest snapshots, still listed oldest first
+    let per_page = query.per_page.unwrap_or(SNAPSHOTS_PER_PAGE).clamp(1, MAX_SNAPSHOTS_PER_PAGE);
+    let pages = matching.len().div_ceil(per_page).max(1);
+    let page = query.page.unwrap_or(1).clamp(1, pages);
+    let end = matching.len().saturating_sub((page - 1) * per_page);
+    let snapshots: Vec<snapshots::Snapshot> = matching[end.saturating_sub(per_page)..end].iter().map(|snapshot| snapshot.with_note()).collect();
+
+    Ok((
+        cache_headers(&output),
+        Json(json!({
+            "snapshots": snapshots,
+            "count": matching.len(),
+            "page": page,
+            "pages": pages,
+            "per_page": per_page,
+            "hostname": this_host,
+            "all_hosts": all_hosts,
+            "hosts": host_summary(all),
+            "age_secs": output.age.as_secs(),
+            "stale": output.stale,
+        })),
+    ))
 }
 
-async fn get_stats(State(state): State<AppState>) -> Result<Json<serde_json::Value>, StatusCode> {
-    get_stats_for_snapshot_internal(state, None).await
+/// Snapshots per host, tag and day, with the filters of `/api/snapshots`
+async fn get_snapshot_counts(
+    State(state): State<AppState>,
+    Query(query): Query<SnapshotsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let default_all_hosts = state.config.read().await.web.as_ref().is_some_and(|web| web.all_hosts);
+    let all_hosts = query.all_hosts(default_all_hosts);
+    let filter = query.filter()?;
+    let output = cached_snapshots(&state, query.refresh).await?;
+    let this_host = crate::hostname();
+    let counts = snapshots::counts(filter.apply(output.value.iter().filter(|snapshot| all_hosts || snapshot.hostname == this_host)));
+
+    Ok((
+        cache_headers(&output),
+        Json(json!({
+            "total": counts.total,
+            "hosts": counts.hosts,
+            "tags": counts.tags,
+            "days": counts.days,
+            "all_hosts": all_hosts,
+            "age_secs": output.age.as_secs(),
+            "stale": output.stale,
+        })),
+    ))
+}
+
+async fn get_stats(
+    State(state): State<AppState>,
+    Query(query): Query<CacheQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let output = cached_restic_json(&state, "stats", None, &[], query.refresh).await?;
+    Ok((cache_headers(&output), Json(Arc::unwrap_or_clone(output.value))))
 }
 
 async fn get_stats_for_snapshot_handler(
     Path(snapshot_id): Path<String>,
     State(state): State<AppState>,
+    Query(query): Query<CacheQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !crate::pins::is_valid_snapshot_id(&snapshot_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let output = cached_restic_json(&state, "stats", Some(&snapshot_id), &[], query.refresh).await?;
+    Ok((cache_headers(&output), Json(Arc::unwrap_or_clone(output.value))))
+}
+
+/// List the contents of a directory in a snapshot
+async fn list_snapshot(
+    Path(snapshot_id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<LsQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let path = query.path.unwrap_or_else(|| "/".to_string());
+    if !crate::pins::is_valid_snapshot_id(&snapshot_id) || !path.starts_with('/') {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let output = cached_restic_json(&state, "ls", Some(&snapshot_id), &[&path], query.refresh).await?;
+    Ok((cache_headers(&output), Json(Arc::unwrap_or_clone(output.value))))
+}
+
+async fn get_staging_areas(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let areas = staging::list(&*state.config.read().await);
+    Json(json!({ "areas": areas }))
+}
+
+#[derive(Deserialize)]
+pub struct StagingQuery {
+    /// Directory the files would be committed under; their original place by default
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+/// A staging area's files and what committing them would do. Comparing against another `target`
+/// needs the admin role, like committing there, since it reveals which files exist under it.
+async fn get_staging_area(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    Query(query): Query<StagingQuery>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if query.target.is_some() && user.is_some_and(|user| user.role < Role::Admin) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let config = state.config.read().await;
+    let area = staging::load(&config, &id).map_err(|_| StatusCode::NOT_FOUND)?;
+    let target = query.target.map(|target| crate::expand_tilde(std::path::Path::new(&target))).unwrap_or_else(|| PathBuf::from("/"));
+    let files = staging::files(&config, &id, &target).map_err(|e| {
+        eprintln!("Failed to list staging area {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(json!({ "area": area, "target": target, "files": files })))
+}
+
+#[derive(Deserialize)]
+pub struct StageRequest {
+    pub snapshot_id: String,
+    #[serde(default)]
+    pub includes: Vec<String>,
+    #[serde(default)]
+    pub excludes: Vec<String>,
+}
+
+/// Restore files from a snapshot into a new staging area; answers once the restore is done
+async fn create_staging_area(
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+    Json(payload): Json<StageRequest>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
-    get_stats_for_snapshot_internal(state, Some(snapshot_id)).await
+    if !crate::pins::is_valid_snapshot_id(&payload.snapshot_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let config = state.config.read().await.clone();
+    let options = crate::restore::RestoreOptions { includes: payload.includes, excludes: payload.excludes, ..Default::default() };
+    let result = crate::restore::stage(&config, &payload.snapshot_id, options).await;
+    audit::record(&config, &audit_entry(&config, &headers, user.as_deref(), "staging.create", Some(payload.snapshot_id.clone())).outcome(&result));
+    match result {
+        Ok(area) => Ok(Json(json!({ "success": true, "area": area }))),
+        Err(e) => {
+            eprintln!("Failed to stage snapshot {}: {}", payload.snapshot_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CommitRequest {
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub in_place: bool,
+    /// Replace files that exist with different content, keeping `.pre-restore` copies
+    #[serde(default)]
+    pub overwrite: bool,
 }
 
-async fn get_stats_for_snapshot_internal(state: AppState, snapshot_id: Option<String>) -> Result<Json<serde_json::Value>, StatusCode> {
+/// Move a staging area's files to `target` or, with `in_place`, back where they came from.
+/// Answers 409 Conflict, moving nothing, when that would overwrite files and `overwrite` isn't set.
+async fn commit_staging_area(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+    Json(payload): Json<CommitRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let target = match (payload.target, payload.in_place) {
+        (Some(target), false) => crate::expand_tilde(std::path::Path::new(&target)),
+        (None, true) => PathBuf::from("/"),
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
     let config = state.config.read().await;
-    
-    // Build restic stats command
-    let mut cmd = tokio::process::Command::new("restic");
-    cmd.arg("stats");
-    
-    // If snapshot_id is provided, get stats for that specific snapshot
-    if let Some(ref snap_id) = snapshot_id {
-        cmd.arg(snap_id);
+    staging::load(&config, &id).map_err(|_| StatusCode::NOT_FOUND)?;
+    if !payload.overwrite {
+        let files = staging::files(&config, &id, &target).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if files.iter().any(|file| file.action == staging::Action::Overwrite) {
+            return Err(StatusCode::CONFLICT);
+        }
     }
-    
-    cmd.arg("--repo").arg(&config.restic.repository);
-    cmd.arg("--json"); // Get JSON output for easier parsing
-    
-    // Handle password
-    if let Some(ref password_cmd) = config.restic.password_command {
-        cmd.arg("--password-command").arg(password_cmd);
-    } else if let Some(ref password) = config.restic.password {
-        cmd.env("RESTIC_PASSWORD", password);
+    let result = staging::commit(&config, &id, &target, payload.overwrite);
+    audit::record(&config, &audit_entry(&config, &headers, user.as_deref(), "staging.commit", Some(id.clone())).outcome(&result));
+    match result {
+        Ok(summary) => Ok(Json(json!({ "success": true, "target": target, "summary": summary }))),
+        Err(e) => {
+            eprintln!("Failed to commit staging area {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
     }
-    
-    // Set SSH command if provided
-    if let Some(ref ssh_cmd) = config.restic.ssh_command {
-        cmd.env("RESTIC_SSH_COMMAND", ssh_cmd);
+}
+
+async fn discard_staging_area(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let config = state.config.read().await;
+    staging::load(&config, &id).map_err(|_| StatusCode::NOT_FOUND)?;
+    let result = staging::discard(&config, &id);
+    audit::record(&config, &audit_entry(&config, &headers, user.as_deref(), "staging.discard", Some(id.clone())).outcome(&result));
+    match result {
+        Ok(()) => Ok(Json(json!({ "success": true, "id": id }))),
+        Err(e) => {
+            eprintln!("Failed to discard staging area {}: {}", id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
     }
-    
-    drop(config); // Release the lock
-    
-    // Execute the command
-    let output = cmd.output().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        eprintln!("Restic stats error: {}", stderr);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveQuery {
+    pub path: String,
+    /// tar, tar.gz (the default) or zip
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// Download a subtree of a snapshot as an archive, streamed from `restic dump` as it is produced
+async fn export_archive(
+    Path(snapshot_id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<ArchiveQuery>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    use tokio::io::AsyncReadExt;
+
+    if !crate::pins::is_valid_snapshot_id(&snapshot_id) || !query.path.starts_with('/') {
+        return Err(StatusCode::BAD_REQUEST);
     }
-    
-    // Parse JSON output from restic
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stats: serde_json::Value = serde_json::from_str(&stdout)
-        .map_err(|e| {
-            eprintln!("Failed to parse stats JSON: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    
-    Ok(Json(stats))
+    let format = archive::ArchiveFormat::parse(query.format.as_deref().unwrap_or("tar.gz")).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let config = state.config.read().await.clone();
+    let mut dump = archive::Dump::spawn(&config.restic, &snapshot_id, &query.path, format, std::process::Stdio::piped()).map_err(|e| {
+        eprintln!("Failed to export {} from {}: {}", query.path, snapshot_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let mut stdout = dump.stdout().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let file_name = archive::file_name(&snapshot_id, &query.path, format).replace(['"', '\\'], "_");
+    let headers_out = [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+    ];
+    let target = Some(format!("{} {}", snapshot_id, query.path));
+
+    // Wait for the first bytes, so a dump that fails right away gets an error status instead of an empty download
+    let mut buffer = vec![0u8; 64 * 1024];
+    let first = stdout.read(&mut buffer).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if first == 0 || dump.failed() {
+        let result = dump.wait().await;
+        audit::record(&config, &audit_entry(&config, &headers, user.as_deref(), "snapshot.export", target).outcome(&result));
+        if let Err(e) = result {
+            eprintln!("Failed to export {} from {}: {}", query.path, snapshot_id, e);
+            return Err(StatusCode::NOT_FOUND);
+        }
+        return Ok((headers_out, axum::body::Body::empty()).into_response());
+    }
+    audit::record(&config, &audit_entry(&config, &headers, user.as_deref(), "snapshot.export", target));
+
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Result<Vec<u8>, std::io::Error>>(8);
+    let path = query.path.clone();
+    tokio::spawn(async move {
+        let mut read = first;
+        while read > 0 {
+            if sender.send(Ok(buffer[..read].to_vec())).await.is_err() {
+                return; // The client went away; dropping the dump stops it
+            }
+            read = match stdout.read(&mut buffer).await {
+                Ok(read) => read,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    return;
+                }
+            };
+        }
+        if let Err(e) = dump.wait().await {
+            // Ending the body with an error leaves the client with an incomplete download rather than a truncated archive it trusts
+            eprintln!("Export of {} from {} failed: {}", path, snapshot_id, e);
+            let _ = sender.send(Err(std::io::Error::other(e.to_string()))).await;
+        }
+    });
+
+    Ok((headers_out, axum::body::Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(receiver))).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct FileHistoryQuery {
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub refresh: bool,
+}
+
+/// Every snapshot's copy of one file, oldest first, for a version history view
+async fn get_file_history(
+    State(state): State<AppState>,
+    Query(query): Query<FileHistoryQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !query.path.starts_with('/') {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let pattern = file_history::pattern(&query.path);
+    let found = cached_restic_json(&state, "find", None, &[&pattern], query.refresh).await?;
+    let snapshots = cached_snapshots(&state, query.refresh).await?;
+    let versions = file_history::build(&query.path, &found.value, &snapshots.value);
+
+    Ok((
+        cache_headers(&found),
+        Json(json!({
+            "path": query.path,
+            "count": versions.len(),
+            "distinct": versions.iter().filter(|version| version.changed).count(),
+            "versions": versions,
+        })),
+    ))
+}
+
+async fn set_pinned(
+    state: &AppState,
+    headers: &HeaderMap,
+    user: Option<&CurrentUser>,
+    snapshot_id: &str,
+    pinned: bool,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    if !crate::pins::is_valid_snapshot_id(snapshot_id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let config = state.config.read().await;
+    let result = crate::pins::set_pinned(&config.restic, snapshot_id, pinned).await;
+    // Tagging rewrites the snapshot, so the cached snapshot list is out of date either way
+    state.cache.invalidate_repository(&config.restic.repository_url());
+    let action = if pinned { "pin" } else { "unpin" };
+    audit::record(&config, &audit_entry(&config, headers, user, action, Some(snapshot_id.to_string())).outcome(&result));
+    match result {
+        Ok(()) => Ok(Json(json!({ "success": true, "snapshot_id": snapshot_id, "pinned": pinned }))),
+        Err(e) => {
+            eprintln!("Failed to update pin on snapshot {}: {}", snapshot_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Protect a snapshot from retention by tagging it `keep-forever`
+async fn pin_snapshot(
+    Path(snapshot_id): Path<String>,
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    set_pinned(&state, &headers, user.as_deref(), &snapshot_id, true).await
+}
+
+async fn unpin_snapshot(
+    Path(snapshot_id): Path<String>,
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    set_pinned(&state, &headers, user.as_deref(), &snapshot_id, false).await
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    /// Most recent entries to return
+    #[serde(default = "default_audit_limit")]
+    pub limit: usize,
+}
+
+fn default_audit_limit() -> usize {
+    200
+}
+
+/// Latest audit log entries, newest first
+async fn get_audit(State(state): State<AppState>, Query(query): Query<AuditQuery>) -> Result<Json<serde_json::Value>, StatusCode> {
+    let path = audit::path(&*state.config.read().await);
+    let entries = audit::read(&path, query.limit).map_err(|e| {
+        eprintln!("Failed to read audit log {:?}: {}", path, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Json(json!({ "entries": entries })))
+}
+
+/// Whether a browser request comes from a page served by this server. Browsers send Basic auth
+/// credentials with cross-site WebSocket handshakes too, so those must be checked by hand.
+fn same_origin(headers: &HeaderMap) -> bool {
+    let Some(origin) = headers.get(header::ORIGIN).and_then(|value| value.to_str().ok()) else {
+        // Not a browser
+        return true;
+    };
+    let origin_host = origin.split_once("://").map(|(_, host)| host).unwrap_or(origin);
+    headers.get(header::HOST).and_then(|value| value.to_str().ok()) == Some(origin_host)
+}
+
+/// Interactive restic terminal: send a command line as a text message, receive its output as JSON messages
+async fn terminal_socket(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    user: Option<Extension<CurrentUser>>,
+    headers: HeaderMap,
+) -> Response {
+    if !same_origin(&headers) {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+    let config = state.config.read().await.clone();
+    let user = request_user(&config, &headers, user.as_deref());
+    ws.on_upgrade(move |socket| crate::terminal::handle(socket, config, user))
+}
+
+/// Drop all cached restic output
+async fn invalidate_cache(State(state): State<AppState>) -> Json<serde_json::Value> {
+    state.cache.clear();
+    Json(json!({ "success": true }))
 }
@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveTime};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{BackupConfig, Config};
+
+/// How long restic gets to stop after SIGINT before it is killed
+pub const STOP_GRACE: Duration = Duration::from_secs(120);
+
+/// Hours of the day a job may run in; `end` before `start` means the window spans midnight
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RunWindow {
+    pub start: String,
+    pub end: String,
+    /// Don't start with less than this many minutes of the window left
+    #[serde(default)]
+    pub min_remaining_minutes: u32,
+}
+
+/// A backup stopped because its window closed
+#[derive(Debug)]
+pub struct WindowClosed {
+    pub end: String,
+}
+
+impl std::fmt::Display for WindowClosed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stopped at the end of the backup window ({}); the next run catches up", self.end)
+    }
+}
+
+impl std::error::Error for WindowClosed {}
+
+pub fn parse(time: &str) -> Option<NaiveTime> {
+    let (hour, minute) = crate::validation::parse_time(time)?;
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+impl RunWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        match (parse(&self.start), parse(&self.end)) {
+            (Some(start), Some(end)) if start <= end => start <= time && time < end,
+            (Some(start), Some(end)) => time >= start || time < end,
+            _ => false,
+        }
+    }
+
+    /// Time left in the window at `now`; None outside it
+    pub fn remaining(&self, now: DateTime<Local>) -> Option<chrono::Duration> {
+        let (end, time) = (parse(&self.end)?, now.time());
+        if !self.contains(time) {
+            return None;
+        }
+        let until_end = end - time;
+        Some(if until_end < chrono::Duration::zero() { until_end + chrono::Duration::days(1) } else { until_end })
+    }
+}
+
+/// Why the job can't start now; None inside its window, or when it has none
+pub fn deferral(job: &BackupConfig, now: DateTime<Local>) -> Option<String> {
+    let window = job.window.as_ref()?;
+    match window.remaining(now) {
+        None => Some(format!("outside its window ({}-{})", window.start, window.end)),
+        Some(left) if left < chrono::Duration::minutes(window.min_remaining_minutes as i64) => Some(format!(
+            "only {} minutes of its window ({}-{}) left",
+            left.num_minutes(),
+            window.start,
+            window.end
+        )),
+        Some(_) => None,
+    }
+}
+
+/// When a run of the job starting now has to stop
+pub fn deadline(job: &BackupConfig, dry_run: bool) -> Option<(tokio::time::Instant, String)> {
+    let window = job.window.as_ref().filter(|_| !dry_run)?;
+    let left = window.remaining(Local::now())?.to_std().ok()?;
+    Some((tokio::time::Instant::now() + left, window.end.clone()))
+}
+
+/// A run that was deferred or cut short and still has to happen
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CatchUp {
+    pub since: DateTime<Local>,
+    pub reason: String,
+}
+
+/// Pending catch-ups, next to the logs
+pub fn path(config: &Config) -> PathBuf {
+    crate::expand_tilde(&config.logging.directory.join("catch-up.json"))
+}
+
+pub fn pending(config: &Config) -> BTreeMap<String, CatchUp> {
+    std::fs::read(path(config))
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(config: &Config, pending: &BTreeMap<String, CatchUp>) {
+    let path = path(config);
+    let written = serde_json::to_vec_pretty(pending)
+        .map_err(anyhow::Error::from)
+        .and_then(|content| Ok(std::fs::write(&path, content)?));
+    if let Err(e) = written {
+        warn!("Couldn't update {:?}: {}", path, e);
+    }
+}
+
+/// Remember that the job still has to run; the earliest reason is kept
+pub fn mark(config: &Config, job: &str, reason: &str) {
+    let mut jobs = pending(config);
+    if !jobs.contains_key(job) {
+        jobs.insert(job.to_string(), CatchUp { since: Local::now(), reason: reason.to_string() });
+        save(config, &jobs);
+    }
+}
+
+pub fn clear(config: &Config, job: &str) {
+    let mut jobs = pending(config);
+    if jobs.remove(job).is_some() {
+        info!("Job '{}' caught up", job);
+        save(config, &jobs);
+    }
+}
+
+/// Check the job's window before a run, marking it for catch-up when it has to wait.
+/// Dry runs ignore windows.
+pub fn defer(config: &Config, job: &BackupConfig, dry_run: bool) -> Option<String> {
+    if dry_run {
+        return None;
+    }
+    let reason = deferral(job, Local::now())?;
+    mark(config, &job.name, &reason);
+    Some(reason)
+}
+
+/// The error for a run its window stopped, or the I/O error back
+pub fn stopped(e: std::io::Error) -> Result<anyhow::Error, std::io::Error> {
+    if !e.get_ref().is_some_and(|inner| inner.is::<WindowClosed>()) {
+        return Err(e);
+    }
+    let inner = e.into_inner().expect("checked above");
+    Ok(match inner.downcast::<WindowClosed>() {
+        Ok(closed) => anyhow::Error::new(*closed),
+        Err(other) => anyhow::anyhow!(other),
+    })
+}
+
+/// Wait for the child, interrupting it like Ctrl-C at the deadline so restic stops cleanly,
+/// and killing it if it hasn't stopped after `STOP_GRACE`
+pub async fn wait_until(child: &mut tokio::process::Child, deadline: Option<(tokio::time::Instant, String)>) -> std::io::Result<(std::process::ExitStatus, Option<WindowClosed>)> {
+    let Some((deadline, end)) = deadline else {
+        return Ok((child.wait().await?, None));
+    };
+    tokio::select! {
+        status = child.wait() => return Ok((status?, None)),
+        _ = tokio::time::sleep_until(deadline) => {}
+    }
+
+    warn!("Backup window ends at {}; stopping restic", end);
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGINT);
+        }
+    }
+    #[cfg(not(unix))]
+    child.start_kill()?;
+    let status = match tokio::time::timeout(STOP_GRACE, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            warn!("restic didn't stop within {}s; killing it", STOP_GRACE.as_secs());
+            child.start_kill()?;
+            child.wait().await?
+        }
+    };
+    Ok((status, Some(WindowClosed { end })))
+}
@@ -0,0 +1,905 @@
+let logInterval;
+let configEtag = null; // ETag of the YAML as last loaded, sent back as If-Match on save
+
+// Role of the logged-in user; without configured users everyone is an admin
+let currentRole = 'admin';
+const ROLE_RANK = { viewer: 0, operator: 1, admin: 2 };
+
+function canDo(role) {
+    return ROLE_RANK[currentRole] >= ROLE_RANK[role];
+}
+
+// Hide what the user's role can't do
+async function loadCurrentUser() {
+    try {
+        const response = await fetch('/api/me');
+        const data = await response.json();
+        currentRole = data.role;
+    } catch (error) {
+        console.error('Failed to load current user:', error);
+    }
+    document.querySelectorAll('[data-role]').forEach(el => {
+        el.style.display = canDo(el.dataset.role) ? '' : 'none';
+    });
+}
+
+function switchTab(tabName) {
+    // Update tabs
+    document.querySelectorAll('.tab').forEach(t => t.classList.remove('active'));
+    event.target.classList.add('active');
+
+    // Update content
+    document.querySelectorAll('.tab-content').forEach(c => c.classList.remove('active'));
+    document.getElementById(tabName).classList.add('active');
+
+    // Load data for the active tab
+    if (tabName === 'config') {
+        loadConfig();
+    } else if (tabName === 'yaml') {
+        loadYaml();
+    } else if (tabName === 'snapshots') {
+        loadSnapshots();
+    } else if (tabName === 'logs') {
+        loadLogs();
+        // Auto-refresh logs every 5 seconds
+        if (logInterval) clearInterval(logInterval);
+        logInterval = setInterval(loadLogs, 5000);
+    } else if (tabName === 'status') {
+        loadStatus();
+    } else if (tabName === 'agents') {
+        loadAgents();
+    } else if (tabName === 'terminal') {
+        openTerminal();
+    }
+}
+
+async function loadConfig() {
+    try {
+        const response = await fetch('/api/config');
+        const data = await response.json();
+
+        const html = `
+            <div class="config-item">
+                <div class="config-label">Frequency:</div>
+                <div class="config-value">${data.backup.frequency}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">Time:</div>
+                <div class="config-value">${data.backup.time}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">Directories:</div>
+                <div class="config-value">${data.backup.directories.join('<br>')}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">Exclude:</div>
+                <div class="config-value">${data.backup.exclude.join('<br>') || 'None'}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">Repository:</div>
+                <div class="config-value">${data.restic.repository}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">SSH Command:</div>
+                <div class="config-value">${data.restic.has_ssh_command ? '<span class="badge badge-info">Configured</span>' : '<span class="badge">Not set</span>'}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">Password:</div>
+                <div class="config-value">${data.restic.has_password ? '<span class="badge badge-warning">Set</span>' : data.restic.has_password_command ? '<span class="badge badge-info">Command</span>' : '<span class="badge">Not set</span>'}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">Log Directory:</div>
+                <div class="config-value">${data.logging.directory}</div>
+            </div>
+            <div class="config-item">
+                <div class="config-label">Max Log Size:</div>
+                <div class="config-value">${data.logging.max_size}</div>
+            </div>
+        `;
+
+        document.getElementById('config-content').innerHTML = html;
+    } catch (error) {
+        document.getElementById('config-content').innerHTML = 
+            `<div class="error">Error loading configuration: ${error.message}</div>`;
+    }
+}
+
+let directoryIndex = 0;
+let excludeIndex = 0;
+
+function addDirectory(value = '') {
+    const list = document.getElementById('directories-list');
+    const div = document.createElement('div');
+    div.id = `dir-${directoryIndex}`;
+    div.style.cssText = 'display: flex; gap: 10px; margin-bottom: 10px; align-items: center;';
+    div.innerHTML = `
+        <input type="text" value="${value}" placeholder="/path/to/directory" 
+               style="flex: 1; padding: 8px; border: 1px solid #ddd; border-radius: 6px; font-size: 14px;"
+               onchange="updateDirectory(${directoryIndex}, this.value)">
+        <button type="button" onclick="removeDirectory(${directoryIndex})" 
+                style="background: #ef4444; color: white; border: none; padding: 8px 16px; border-radius: 6px; cursor: pointer;">Remove</button>
+    `;
+    list.appendChild(div);
+    directoryIndex++;
+}
+
+function removeDirectory(index) {
+    const elem = document.getElementById(`dir-${index}`);
+    if (elem) elem.remove();
+}
+
+function updateDirectory(index, value) {
+    // Store value if needed
+}
+
+function addExclude(value = '') {
+    const list = document.getElementById('exclude-list');
+    const div = document.createElement('div');
+    div.id = `exclude-${excludeIndex}`;
+    div.style.cssText = 'display: flex; gap: 10px; margin-bottom: 10px; align-items: center;';
+    div.innerHTML = `
+        <input type="text" value="${value}" placeholder="/path/to/exclude" 
+               style="flex: 1; padding: 8px; border: 1px solid #ddd; border-radius: 6px; font-size: 14px;"
+               onchange="updateExclude(${excludeIndex}, this.value)">
+        <button type="button" onclick="removeExclude(${excludeIndex})" 
+                style="background: #ef4444; color: white; border: none; padding: 8px 16px; border-radius: 6px; cursor: pointer;">Remove</button>
+    `;
+    list.appendChild(div);
+    excludeIndex++;
+}
+
+function removeExclude(index) {
+    const elem = document.getElementById(`exclude-${index}`);
+    if (elem) elem.remove();
+}
+
+function updateExclude(index, value) {
+    // Store value if needed
+}
+
+function toggleYamlView() {
+    const formEditor = document.getElementById('config-form-editor');
+    const yamlEditor = document.getElementById('yaml-raw-editor');
+    if (formEditor.style.display === 'none') {
+        formEditor.style.display = 'block';
+        yamlEditor.style.display = 'none';
+        loadConfigForm();
+    } else {
+        formEditor.style.display = 'none';
+        yamlEditor.style.display = 'block';
+        loadYaml();
+    }
+}
+
+async function loadConfigForm() {
+    try {
+        const response = await fetch('/api/config');
+        const data = await response.json();
+
+        // Populate form fields
+        document.getElementById('backup-frequency').value = data.backup.frequency || '';
+        document.getElementById('backup-time').value = data.backup.time || '';
+        document.getElementById('log-directory').value = data.logging.directory || '';
+        document.getElementById('log-max-size').value = data.logging.max_size || '';
+        document.getElementById('restic-repository').value = data.restic.repository || '';
+
+        // Clear and populate directories
+        document.getElementById('directories-list').innerHTML = '';
+        directoryIndex = 0;
+        if (data.backup.directories && data.backup.directories.length > 0) {
+            data.backup.directories.forEach(dir => addDirectory(dir));
+        } else {
+            addDirectory();
+        }
+
+        // Clear and populate excludes
+        document.getElementById('exclude-list').innerHTML = '';
+        excludeIndex = 0;
+        if (data.backup.exclude && data.backup.exclude.length > 0) {
+            data.backup.exclude.forEach(ex => addExclude(ex));
+        }
+
+        // Load password from YAML (we'll need to get it from raw YAML)
+        loadYamlForPassword();
+
+        document.getElementById('yaml-message').innerHTML = '';
+    } catch (error) {
+        document.getElementById('yaml-message').innerHTML = 
+            `<div class="error">Error loading configuration: ${error.message}</div>`;
+    }
+}
+
+async function loadYamlForPassword() {
+    try {
+        const response = await fetch('/api/config/yaml');
+        configEtag = response.headers.get('ETag');
+        const yaml = await response.text();
+        // Simple regex to extract password (not perfect but works for basic cases)
+        const passwordMatch = yaml.match(/password:\s*['"]([^'"]+)['"]/);
+        if (passwordMatch) {
+            document.getElementById('restic-password').value = passwordMatch[1];
+        }
+    } catch (error) {
+        // Ignore errors
+    }
+}
+
+async function saveConfigForm() {
+    const messageDiv = document.getElementById('yaml-message');
+
+    // Collect form data
+    const directories = Array.from(document.querySelectorAll('#directories-list input[type="text"]'))
+        .map(input => input.value.trim())
+        .filter(v => v.length > 0);
+
+    const excludes = Array.from(document.querySelectorAll('#exclude-list input[type="text"]'))
+        .map(input => input.value.trim())
+        .filter(v => v.length > 0);
+
+    // Build YAML
+    let yaml = `backup:
+  frequency: "${document.getElementById('backup-frequency').value}"
+  time: "${document.getElementById('backup-time').value}"
+  directories:
+`;
+    directories.forEach(dir => {
+        yaml += `    - ${dir}\n`;
+    });
+    yaml += `  exclude:\n`;
+    if (excludes.length > 0) {
+        excludes.forEach(ex => {
+            yaml += `    - ${ex}\n`;
+        });
+    } else {
+        yaml += `    - /tmp/cache\n`; // Default
+    }
+
+    yaml += `
+logging:
+  directory: ${document.getElementById('log-directory').value}
+  max_size: "${document.getElementById('log-max-size').value}"
+
+restic:
+  repository: '${document.getElementById('restic-repository').value}'`;
+
+    const password = document.getElementById('restic-password').value.trim();
+    const passwordCmd = document.getElementById('restic-password-command').value.trim();
+    const sshCmd = document.getElementById('restic-ssh-command').value.trim();
+
+    if (password) {
+        yaml += `\n  password: '${password}'`;
+    }
+    if (passwordCmd) {
+        yaml += `\n  password_command: '${passwordCmd}'`;
+    }
+    if (sshCmd) {
+        yaml += `\n  ssh_command: '${sshCmd}'`;
+    }
+
+    // Save via API
+    try {
+        const headers = {
+            'Content-Type': 'application/json',
+        };
+        if (configEtag) {
+            headers['If-Match'] = configEtag;
+        }
+        const response = await fetch('/api/config/yaml', {
+            method: 'POST',
+            headers: headers,
+            body: JSON.stringify({ yaml: yaml })
+        });
+
+        if (response.status === 412) {
+            messageDiv.innerHTML = `<div class="error">
+                ❌ The configuration file changed on disk since it was loaded. Reload it and apply your changes again.
+            </div>`;
+        } else if (response.ok) {
+            const result = await response.json();
+            configEtag = result.etag;
+            messageDiv.innerHTML = `<div style="background: #d1fae5; color: #065f46; padding: 10px; border-radius: 8px; border-left: 4px solid #10b981;">
+                ✅ ${result.message}
+            </div>`;
+            setTimeout(() => {
+                loadConfig();
+                messageDiv.innerHTML = '';
+            }, 2000);
+        } else {
+            const errorText = await response.text();
+            messageDiv.innerHTML = `<div class="error">
+                ❌ Error saving configuration: ${errorText}
+            </div>`;
+        }
+    } catch (error) {
+        messageDiv.innerHTML = `<div class="error">
+            ❌ Error: ${error.message}
+        </div>`;
+    }
+}
+
+async function loadYaml() {
+    try {
+        const response = await fetch('/api/config/yaml');
+        configEtag = response.headers.get('ETag');
+        const yaml = await response.text();
+        document.getElementById('yaml-editor').value = yaml;
+        document.getElementById('yaml-message').innerHTML = '';
+    } catch (error) {
+        document.getElementById('yaml-editor').value = 
+            `Error loading YAML: ${error.message}`;
+    }
+}
+
+
+async function triggerBackup(dryRun) {
+    const statusDiv = document.getElementById('backup-status');
+    statusDiv.innerHTML = `<div style="background: #dbeafe; color: #1e40af; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #3b82f6; text-align: center;">
+        ⏳ ${dryRun ? 'Dry run' : 'Backup'} triggered... Check logs tab for progress.
+    </div>`;
+
+    try {
+        const response = await fetch('/api/backup/trigger', {
+            method: 'POST',
+            headers: {
+                'Content-Type': 'application/json',
+            },
+            body: JSON.stringify({ dry_run: dryRun, note: document.getElementById('backup-note').value.trim() || undefined })
+        });
+
+        if (response.ok) {
+            const result = await response.json();
+            document.getElementById('backup-note').value = '';
+            statusDiv.innerHTML = `<div style="background: #d1fae5; color: #065f46; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #10b981; text-align: center;">
+                ✅ ${result.message}<br>
+                <small style="opacity: 0.8;">Check the Logs tab to see the backup progress.</small>
+            </div>`;
+            // Auto-switch to logs tab after a moment
+            setTimeout(() => {
+                const logsTab = Array.from(document.querySelectorAll('.tab')).find(t => t.textContent.includes('Logs'));
+                if (logsTab && !logsTab.classList.contains('active')) {
+                    document.querySelectorAll('.tab').forEach(t => t.classList.remove('active'));
+                    document.querySelectorAll('.tab-content').forEach(c => c.classList.remove('active'));
+                    logsTab.classList.add('active');
+                    document.getElementById('logs').classList.add('active');
+                    loadLogs();
+                    if (logInterval) clearInterval(logInterval);
+                    logInterval = setInterval(loadLogs, 2000); // Refresh more frequently during backup
+                }
+            }, 1500);
+        } else {
+            const errorText = await response.text();
+            statusDiv.innerHTML = `<div style="background: #fee; color: #c33; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #c33; text-align: center;">
+                ❌ Error triggering backup: ${errorText}
+            </div>`;
+        }
+    } catch (error) {
+        statusDiv.innerHTML = `<div style="background: #fee; color: #c33; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #c33; text-align: center;">
+            ❌ Error: ${error.message}
+        </div>`;
+    }
+}
+
+// Filters of the logs tab; the server searches all log files with them
+function logQuery() {
+    const params = new URLSearchParams();
+    const fields = { level: 'log-level', job: 'log-job', since: 'log-since', until: 'log-until', q: 'log-text' };
+    for (const [name, id] of Object.entries(fields)) {
+        const value = document.getElementById(id).value.trim();
+        if (value) params.set(name, value);
+    }
+    return params.toString();
+}
+
+function highlightLevels(content) {
+    return content
+        .replace(/(ERROR|FATAL)/g, '<span style="color: #f48771;">$1</span>')
+        .replace(/(WARN|WARNING)/g, '<span style="color: #dcdcaa;">$1</span>')
+        .replace(/(INFO)/g, '<span style="color: #4ec9b0;">$1</span>')
+        .replace(/(DEBUG)/g, '<span style="color: #9cdcfe;">$1</span>');
+}
+
+async function loadLogs() {
+    const query = logQuery();
+    const summary = document.getElementById('logs-summary');
+    try {
+        const response = await fetch(query ? `/api/logs?${query}` : '/api/logs');
+        if (!response.ok) {
+            summary.textContent = '';
+            document.getElementById('logs-content').textContent = `Invalid filter (${response.status})`;
+            return;
+        }
+        const data = await response.json();
+
+        if (data.entries) {
+            summary.textContent = data.truncated
+                ? `Showing the newest ${data.entries.length} of ${data.matched} matching entries`
+                : `${data.matched} matching entries`;
+            const lines = data.entries.map(entry =>
+                `[${escapeHtml(entry.time)}] ${entry.level} [${escapeHtml(entry.module)}] ${escapeHtml(entry.message)}`);
+            document.getElementById('logs-content').innerHTML = lines.length ? highlightLevels(lines.join('\n')) : 'No matching log entries';
+        } else if (data.latest_content) {
+            summary.textContent = '';
+            document.getElementById('logs-content').innerHTML = highlightLevels(data.latest_content);
+        } else {
+            document.getElementById('logs-content').textContent = 'No logs available';
+        }
+    } catch (error) {
+        document.getElementById('logs-content').textContent = 
+            `Error loading logs: ${error.message}`;
+    }
+}
+
+async function loadSnapshots() {
+    // Only load snapshots list, not stats
+    loadSnapshotsList();
+}
+
+async function loadStatsForSnapshot(snapshotId) {
+    const statsDiv = document.getElementById('stats-content');
+    statsDiv.style.display = 'block';
+    statsDiv.innerHTML = `
+        <div class="loading">
+            <div class="spinner"></div>
+            <p>Loading statistics for snapshot...</p>
+        </div>
+    `;
+
+    try {
+        const response = await fetch(`/api/stats/${snapshotId}`);
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}: ${await response.text()}`);
+        }
+        const data = await response.json();
+
+        const html = `
+            <div style="background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); color: white; padding: 25px; border-radius: 12px; margin-bottom: 20px;">
+                <h4 style="margin-bottom: 20px; font-size: 1.2em;">📊 Statistics for Snapshot: <code style="background: rgba(255,255,255,0.2); padding: 4px 8px; border-radius: 4px;">${snapshotId}</code></h4>
+                <div style="display: grid; grid-template-columns: repeat(auto-fit, minmax(200px, 1fr)); gap: 20px;">
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Total Size</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${formatBytes(data.total_size || 0)}</div>
+                    </div>
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Total File Count</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${(data.total_file_count || 0).toLocaleString()}</div>
+                    </div>
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Raw Data Size</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${formatBytes(data.total_uncompressed_size || 0)}</div>
+                    </div>
+                    ${data.compression_ratio ? `
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Compression Ratio</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${(data.compression_ratio * 100).toFixed(1)}%</div>
+                    </div>
+                    ` : ''}
+                </div>
+                ${data.total_blob_count ? `
+                <div style="margin-top: 15px; padding-top: 15px; border-top: 1px solid rgba(255,255,255,0.2);">
+                    <div style="font-size: 0.9em; opacity: 0.9;">Blob Count: ${data.total_blob_count.toLocaleString()}</div>
+                </div>
+                ` : ''}
+                <button onclick="document.getElementById('stats-content').style.display='none'" style="margin-top: 15px; background: rgba(255,255,255,0.2); color: white; border: 1px solid rgba(255,255,255,0.3); padding: 8px 16px; border-radius: 6px; cursor: pointer;">Close</button>
+            </div>
+        `;
+        statsDiv.innerHTML = html;
+    } catch (error) {
+        statsDiv.innerHTML = 
+            `<div class="error">Error loading statistics: ${error.message}</div>`;
+    }
+}
+
+async function loadStats() {
+    try {
+        const response = await fetch('/api/stats');
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}: ${await response.text()}`);
+        }
+        const data = await response.json();
+
+        const html = `
+            <div style="background: linear-gradient(135deg, #667eea 0%, #764ba2 100%); color: white; padding: 25px; border-radius: 12px; margin-bottom: 20px;">
+                <h4 style="margin-bottom: 20px; font-size: 1.2em;">📊 Repository Statistics</h4>
+                <div style="display: grid; grid-template-columns: repeat(auto-fit, minmax(200px, 1fr)); gap: 20px;">
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Total Size</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${formatBytes(data.total_size || 0)}</div>
+                    </div>
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Total File Count</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${(data.total_file_count || 0).toLocaleString()}</div>
+                    </div>
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Raw Data Size</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${formatBytes(data.total_uncompressed_size || 0)}</div>
+                    </div>
+                    ${data.compression_ratio ? `
+                    <div style="background: rgba(255,255,255,0.1); padding: 15px; border-radius: 8px;">
+                        <div style="font-size: 0.85em; opacity: 0.9; margin-bottom: 5px;">Compression Ratio</div>
+                        <div style="font-size: 1.8em; font-weight: 700;">${(data.compression_ratio * 100).toFixed(1)}%</div>
+                    </div>
+                    ` : ''}
+                </div>
+                ${data.total_blob_count ? `
+                <div style="margin-top: 15px; padding-top: 15px; border-top: 1px solid rgba(255,255,255,0.2);">
+                    <div style="font-size: 0.9em; opacity: 0.9;">Blob Count: ${data.total_blob_count.toLocaleString()}</div>
+                </div>
+                ` : ''}
+            </div>
+        `;
+        document.getElementById('stats-content').innerHTML = html;
+    } catch (error) {
+        document.getElementById('stats-content').innerHTML = 
+            `<div class="error">Error loading statistics: ${error.message}</div>`;
+    }
+}
+
+// Until the checkbox is used, the server's `web.all_hosts` setting decides
+let snapshotsAllHostsChosen = false;
+// Page 1 holds the newest snapshots
+let snapshotPage = 1;
+
+// Notes are free text, so they're escaped before going into the table
+function escapeHtml(text) {
+    const div = document.createElement('div');
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+function snapshotRow(snap) {
+    const time = new Date(snap.time || snap.short_id || '').toLocaleString();
+    const host = snap.hostname || 'N/A';
+    const paths = (snap.paths || []).join(', ') || 'N/A';
+    const size = snap.summary && snap.summary.total_bytes_processed ? formatBytes(snap.summary.total_bytes_processed) : 'N/A';
+    const id = snap.short_id || snap.id || 'N/A';
+    const fullId = snap.id || id;
+    const pinned = (snap.tags || []).includes('keep-forever');
+    const note = snap.note ? `<div style="color: #4b5563; font-size: 0.85em; white-space: normal;">📝 ${escapeHtml(snap.note)}</div>` : '';
+    return `
+        <tr style="border-bottom: 1px solid #e9ecef; cursor: pointer;" onclick="loadStatsForSnapshot('${fullId}')" onmouseover="this.style.background='#f8f9fa'" onmouseout="this.style.background='white'">
+            <td style="padding: 12px; font-family: monospace; font-size: 0.9em;">${id}</td>
+            <td style="padding: 12px;">${time}</td>
+            <td style="padding: 12px;">${host}</td>
+            <td style="padding: 12px; max-width: 300px; overflow: hidden; text-overflow: ellipsis; white-space: nowrap;" title="${paths}">${paths}${note}</td>
+            <td style="padding: 12px; text-align: right;">${size}</td>
+            <td style="padding: 12px; text-align: center;">
+                ${canDo('operator') ? `<button class="refresh-btn" style="padding: 4px 10px; font-size: 0.85em;" title="${pinned ? 'Unpin' : 'Pin: never remove with retention'}" onclick="event.stopPropagation(); togglePin('${fullId}', ${pinned})">${pinned ? '📌' : 'Pin'}</button>` : (pinned ? '📌' : '')}
+            </td>
+        </tr>
+    `;
+}
+
+// One heading row per host, followed by its snapshots
+function groupedSnapshotRows(data) {
+    return data.hosts.map(host => {
+        const snapshots = data.snapshots.filter(snap => (snap.hostname || '') === host.hostname);
+        const latest = host.latest ? new Date(host.latest).toLocaleString() : 'N/A';
+        return `
+            <tr style="background: #eef2ff;">
+                <td colspan="6" style="padding: 10px 12px; font-weight: 600;">
+                    🖥️ ${host.hostname || 'unknown host'}${host.this_host ? ' (this machine)' : ''}
+                    <span style="font-weight: normal; color: #666; margin-left: 10px;">${host.count} snapshot${host.count !== 1 ? 's' : ''}, latest ${latest}</span>
+                </td>
+            </tr>
+            ${snapshots.map(snapshotRow).join('')}
+        `;
+    }).join('');
+}
+
+function snapshotQuery() {
+    const params = new URLSearchParams();
+    const checkbox = document.getElementById('snapshots-all-hosts');
+    if (snapshotsAllHostsChosen) params.set('all_hosts', checkbox.checked);
+    const fields = { latest: 'snapshot-latest', since: 'snapshot-since', until: 'snapshot-until', host: 'snapshot-host', tag: 'snapshot-tag', path: 'snapshot-path' };
+    for (const [name, id] of Object.entries(fields)) {
+        const value = document.getElementById(id).value.trim();
+        if (value) params.set(name, value);
+    }
+    if (snapshotPage > 1) params.set('page', snapshotPage);
+    const query = params.toString();
+    return query ? `?${query}` : '';
+}
+
+async function loadSnapshotsList() {
+    try {
+        const checkbox = document.getElementById('snapshots-all-hosts');
+        const response = await fetch(`/api/snapshots${snapshotQuery()}`);
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}: ${await response.text()}`);
+        }
+        const data = await response.json();
+        checkbox.checked = data.all_hosts;
+        const otherHosts = data.hosts.filter(host => !host.this_host).length;
+
+        if (data.snapshots && data.snapshots.length > 0) {
+            const html = `
+                <div style="margin-bottom: 15px;">
+                    <span class="badge badge-info">Total: ${data.count} snapshot${data.count !== 1 ? 's' : ''}</span>
+                    ${!data.all_hosts && otherHosts > 0 ? `<span class="badge badge-info">${otherHosts} other host${otherHosts !== 1 ? 's' : ''} in this repository</span>` : ''}
+                    ${data.stale ? `<span class="badge badge-warning">Cached ${Math.round(data.age_secs / 60)} min ago, refreshing</span>` : ''}
+                    ${data.pages > 1 ? `
+                        <span style="margin-left: 10px;">
+                            <button class="refresh-btn" style="padding: 4px 10px;" ${data.page <= 1 ? 'disabled' : ''} onclick="snapshotPage = ${data.page - 1}; loadSnapshotsList()">Newer</button>
+                            Page ${data.page} of ${data.pages}
+                            <button class="refresh-btn" style="padding: 4px 10px;" ${data.page >= data.pages ? 'disabled' : ''} onclick="snapshotPage = ${data.page + 1}; loadSnapshotsList()">Older</button>
+                        </span>` : ''}
+                </div>
+                <div style="overflow-x: auto;">
+                    <table style="width: 100%; border-collapse: collapse; background: white; border-radius: 8px;">
+                        <thead>
+                            <tr style="background: #f8f9fa; border-bottom: 2px solid #e9ecef;">
+                                <th style="padding: 12px; text-align: left; font-weight: 600; color: #667eea;">ID</th>
+                                <th style="padding: 12px; text-align: left; font-weight: 600; color: #667eea;">Time</th>
+                                <th style="padding: 12px; text-align: left; font-weight: 600; color: #667eea;">Host</th>
+                                <th style="padding: 12px; text-align: left; font-weight: 600; color: #667eea;">Paths</th>
+                                <th style="padding: 12px; text-align: right; font-weight: 600; color: #667eea;">Size</th>
+                                <th style="padding: 12px; text-align: center; font-weight: 600; color: #667eea;">Pinned</th>
+                            </tr>
+                        </thead>
+                        <tbody>
+                            ${data.all_hosts ? groupedSnapshotRows(data) : data.snapshots.map(snapshotRow).join('')}
+                        </tbody>
+                    </table>
+                </div>
+            `;
+            document.getElementById('snapshots-content').innerHTML = html;
+        } else {
+            const hint = !data.all_hosts && otherHosts > 0 ? ` ${otherHosts} other host${otherHosts !== 1 ? 's have' : ' has'} snapshots here; tick "All hosts" to see them.` : '';
+            document.getElementById('snapshots-content').innerHTML = 
+                `<div style="text-align: center; padding: 40px; color: #666;">No snapshots found. Run a backup to create snapshots.${hint}</div>`;
+        }
+    } catch (error) {
+        document.getElementById('snapshots-content').innerHTML = 
+            `<div class="error">Error loading snapshots: ${error.message}</div>`;
+    }
+}
+
+async function togglePin(snapshotId, pinned) {
+    try {
+        const response = await fetch(`/api/snapshots/${snapshotId}/${pinned ? 'unpin' : 'pin'}`, { method: 'POST' });
+        if (!response.ok) {
+            throw new Error(`HTTP ${response.status}`);
+        }
+        loadSnapshotsList();
+    } catch (error) {
+        alert(`Failed to ${pinned ? 'unpin' : 'pin'} snapshot: ${error.message}`);
+    }
+}
+
+function formatBytes(bytes) {
+    if (!bytes || bytes === 0) return '0 B';
+    const k = 1024;
+    const sizes = ['B', 'KB', 'MB', 'GB', 'TB'];
+    const i = Math.floor(Math.log(bytes) / Math.log(k));
+    return Math.round(bytes / Math.pow(k, i) * 100) / 100 + ' ' + sizes[i];
+}
+
+async function loadAgents() {
+    try {
+        const response = await fetch('/api/agents');
+        const data = await response.json();
+
+        if (data.agents.length === 0) {
+            document.getElementById('agents-content').innerHTML =
+                '<p>No agents have checked in. Run <code>better_restic_client agent</code> on a machine with an <code>agent</code> section pointing here.</p>';
+            return;
+        }
+        const th = 'style="padding: 12px; text-align: left; font-weight: 600; color: #667eea;"';
+        document.getElementById('agents-content').innerHTML = `
+            <div style="overflow-x: auto;">
+                <table style="width: 100%; border-collapse: collapse; background: white; border-radius: 8px;">
+                    <thead>
+                        <tr style="background: #f8f9fa; border-bottom: 2px solid #e9ecef;">
+                            <th ${th}>Host</th>
+                            <th ${th}>Last Seen</th>
+                            <th ${th}>Repository</th>
+                            <th ${th}>Last Run</th>
+                            <th ${th}></th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        ${data.agents.map(agent => {
+                            const run = agent.runs[0];
+                            const badge = run ? (run.status === 'succeeded' ? 'badge-success' : 'badge-warning') : 'badge-info';
+                            const lastRun = run ? `<span class="badge ${badge}">${run.job}: ${run.status}</span> ${new Date(run.finished_at).toLocaleString()}` : 'None reported';
+                            const pending = agent.pending.length > 0 ? ` <span class="badge badge-info">Queued: ${agent.pending.join(', ')}</span>` : '';
+                            return `
+                                <tr style="border-bottom: 1px solid #e9ecef;">
+                                    <td style="padding: 12px;"><span class="badge ${agent.online ? 'badge-success' : 'badge-warning'}">${agent.online ? 'online' : 'offline'}</span> ${agent.hostname}</td>
+                                    <td style="padding: 12px;">${new Date(agent.last_seen).toLocaleString()}</td>
+                                    <td style="padding: 12px; font-family: monospace; font-size: 0.9em;">${agent.repository}</td>
+                                    <td style="padding: 12px;">${lastRun}${pending}</td>
+                                    <td style="padding: 12px; text-align: center;">
+                                        ${canDo('operator') ? `<button class="refresh-btn" style="padding: 4px 10px; font-size: 0.85em;" onclick="triggerAgentBackup('${agent.hostname}')">▶️ Run</button>` : ''}
+                                    </td>
+                                </tr>
+                            `;
+                        }).join('')}
+                    </tbody>
+                </table>
+            </div>
+        `;
+    } catch (error) {
+        document.getElementById('agents-content').innerHTML =
+            `<div class="error">Error loading agents: ${error.message}</div>`;
+    }
+}
+
+// Queue all of an agent's jobs; they run when it next checks in
+async function triggerAgentBackup(hostname) {
+    try {
+        const response = await fetch(`/api/agents/${encodeURIComponent(hostname)}/trigger`, {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({})
+        });
+        if (!response.ok) throw new Error(`HTTP ${response.status}`);
+        loadAgents();
+    } catch (error) {
+        alert(`Failed to trigger backup on ${hostname}: ${error.message}`);
+    }
+}
+
+let terminalSocket = null;
+
+function appendTerminal(text, color) {
+    const output = document.getElementById('terminal-output');
+    const line = document.createElement('div');
+    line.textContent = text;
+    if (color) line.style.color = color;
+    output.appendChild(line);
+    output.scrollTop = output.scrollHeight;
+}
+
+// Connect once; the socket stays open while the page is
+function openTerminal() {
+    if (terminalSocket && terminalSocket.readyState <= WebSocket.OPEN) return;
+    const scheme = window.location.protocol === 'https:' ? 'wss' : 'ws';
+    terminalSocket = new WebSocket(`${scheme}://${window.location.host}/api/terminal`);
+    terminalSocket.onmessage = (e) => {
+        const data = JSON.parse(e.data);
+        if (data.type === 'output') {
+            appendTerminal(data.line);
+        } else if (data.type === 'exit') {
+            appendTerminal(`[exit ${data.code ?? 'signal'}]`, data.code === 0 ? '#10b981' : '#f59e0b');
+        } else if (data.type === 'error') {
+            appendTerminal(data.message, '#f87171');
+        }
+    };
+    terminalSocket.onclose = () => appendTerminal('[disconnected]', '#9ca3af');
+}
+
+function runTerminalCommand() {
+    const input = document.getElementById('terminal-input');
+    const command = input.value.trim();
+    if (!command) return;
+    openTerminal();
+    appendTerminal(`$ restic ${command.replace(/^restic\s+/, '')}`, '#93c5fd');
+    const send = () => terminalSocket.send(command);
+    if (terminalSocket.readyState === WebSocket.OPEN) {
+        send();
+    } else {
+        terminalSocket.addEventListener('open', send, { once: true });
+    }
+    input.value = '';
+}
+
+async function loadStatus() {
+    try {
+        const response = await fetch('/api/status');
+        const data = await response.json();
+
+        const html = `
+            <div class="status-card">
+                <h4>Status</h4>
+                <div class="value">
+                    <span class="badge badge-success">${data.status}</span>
+                </div>
+            </div>
+            <div class="status-card">
+                <h4>Uptime</h4>
+                <div class="value">${data.uptime}</div>
+            </div>
+            <div class="status-card">
+                <h4>Last Backup</h4>
+                <div class="value">${data.last_backup}</div>
+            </div>
+            ${data.cost ? `
+                <div class="status-card">
+                    <h4>Estimated Cost (${data.cost.storage_class})</h4>
+                    <div class="value">~${data.cost.monthly_storage.toFixed(2)} ${data.cost.currency}/month</div>
+                    <div style="color: #666; font-size: 0.9em;">${formatBytes(data.cost.repository_size)} stored; last upload ${formatBytes(data.cost.uploaded)}, ${data.cost.upload.toFixed(2)} ${data.cost.currency}</div>
+                </div>
+            ` : ''}
+            ${(data.hosts || []).map(host => `
+                <div class="status-card">
+                    <h4>🖥️ ${host.hostname || 'unknown host'}${host.this_host ? ' (this machine)' : ''}</h4>
+                    <div class="value">${host.latest ? new Date(host.latest).toLocaleString() : 'N/A'}</div>
+                    <div style="color: #666; font-size: 0.9em;">${host.count} snapshot${host.count !== 1 ? 's' : ''}</div>
+                </div>
+            `).join('')}
+        `;
+
+        document.getElementById('status-content').innerHTML = html;
+    } catch (error) {
+        document.getElementById('status-content').innerHTML = 
+            `<div class="error">Error loading status: ${error.message}</div>`;
+    }
+}
+
+// Live job and config notifications from the server
+function subscribeToEvents() {
+    const events = new EventSource('/api/events');
+    const statusDiv = document.getElementById('backup-status');
+
+    events.addEventListener('job_progress', (e) => {
+        const data = JSON.parse(e.data);
+        const percent = (data.percent_done * 100).toFixed(1);
+        const speed = data.bytes_per_sec > 0 ? `, ${(data.bytes_per_sec / 1e6).toFixed(1)} MB/s` : '';
+        statusDiv.innerHTML = `<div style="background: #dbeafe; color: #1e40af; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #3b82f6; text-align: center;">
+            ⏳ Backing up: ${percent}% (${data.files_done}/${data.total_files} files, ${formatBytes(data.bytes_done)} of ${formatBytes(data.total_bytes)}${speed})
+        </div>`;
+    });
+
+    events.addEventListener('job_finished', (e) => {
+        const data = JSON.parse(e.data);
+        statusDiv.innerHTML = `<div style="background: #d1fae5; color: #065f46; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #10b981; text-align: center;">
+            ✅ ${data.job} finished in ${data.duration_secs.toFixed(1)}s
+        </div>`;
+    });
+
+    events.addEventListener('job_failed', (e) => {
+        const data = JSON.parse(e.data);
+        statusDiv.innerHTML = `<div style="background: #fee; color: #c33; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #c33; text-align: center;">
+            ❌ ${data.job} failed: ${data.error}
+        </div>`;
+    });
+
+    events.addEventListener('job_skipped', (e) => {
+        const data = JSON.parse(e.data);
+        statusDiv.innerHTML = `<div style="background: #f3f4f6; color: #374151; padding: 12px 20px; border-radius: 8px; border-left: 4px solid #9ca3af; text-align: center;">
+            ⏭️ ${data.job} skipped: ${data.reason}
+        </div>`;
+    });
+
+    events.addEventListener('config_changed', () => {
+        loadConfig();
+    });
+}
+
+// Apply the configured branding: title, logo and custom stylesheet
+async function loadTheme() {
+    try {
+        const response = await fetch('/api/theme');
+        const theme = await response.json();
+        if (theme.title) {
+            document.getElementById('title').textContent = theme.title;
+            document.title = theme.title;
+        }
+        if (theme.logo) {
+            const logo = document.getElementById('theme-logo');
+            logo.src = theme.logo;
+            logo.style.display = '';
+        }
+        if (theme.css) {
+            const link = document.createElement('link');
+            link.rel = 'stylesheet';
+            link.href = theme.css;
+            document.head.appendChild(link);
+        }
+    } catch (error) {
+        console.error('Failed to load theme:', error);
+    }
+}
+
+// Load initial data
+loadTheme();
+loadCurrentUser();
+loadConfig();
+subscribeToEvents();
+
+// Auto-refresh logs if on logs tab
+if (window.location.hash === '#logs') {
+    loadLogs();
+    logInterval = setInterval(loadLogs, 5000);
+}
@@ -0,0 +1,250 @@
+//! End-to-end runs of the client against tests/fixtures/fake-restic, which prints canned restic
+//! output, so the backup, maintenance and bootstrap flows run without a real repository.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// A working directory with a config.yaml pointing at a repository that only exists in name
+struct Sandbox {
+    directory: PathBuf,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Sandbox {
+        let directory = std::env::temp_dir().join(format!("better-restic-client-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(directory.join("data")).unwrap();
+        std::fs::write(directory.join("data/file.txt"), "fixture\n").unwrap();
+        let config = format!(
+            "version: 1\n\
+             backup:\n  frequency: daily\n  time: \"02:00\"\n  directories: [{data}]\n\
+             logging:\n  directory: {logs}\n  max_size: 10MB\n\
+             restic:\n  repository: {repo}\n  password: fixture\n\
+             maintenance:\n  keep_last: 2\n",
+            data = directory.join("data").display(),
+            logs = directory.join("logs").display(),
+            repo = directory.join("repo").display(),
+        );
+        std::fs::write(directory.join("config.yaml"), config).unwrap();
+        Sandbox { directory }
+    }
+
+    /// Run the client with `args`, restic replaced by the fake and `env` passed on to it
+    fn run(&self, args: &[&str], env: &[(&str, &str)]) -> Output {
+        let fake = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/fake-restic");
+        Command::new(env!("CARGO_BIN_EXE_better_restic_client"))
+            .args(args)
+            .arg("--restic-binary")
+            .arg(fake)
+            .current_dir(&self.directory)
+            .env("FAKE_RESTIC_LOG", self.directory.join("restic.log"))
+            .env("NO_COLOR", "1")
+            .envs(env.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    /// The restic commands the fake was asked to run, one line each
+    fn restic_calls(&self) -> Vec<String> {
+        std::fs::read_to_string(self.directory.join("restic.log")).unwrap_or_default().lines().map(String::from).collect()
+    }
+
+    /// An executable shell script in hooks.d/<phase>
+    fn write_hook(&self, phase: &str, name: &str, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        let path = self.directory.join("hooks.d").join(phase).join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn read(&self, name: &str) -> String {
+        std::fs::read_to_string(self.directory.join(name)).unwrap_or_default()
+    }
+
+    fn data(&self) -> String {
+        self.directory.join("data").display().to_string()
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.directory);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn backup_reports_the_summary_and_speed() {
+    let sandbox = Sandbox::new("backup");
+    let output = sandbox.run(&["backup", &sandbox.data()], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Snapshot 3c0f1a2b: 4 new, 0 changed, 0 unmodified files"), "{}", stdout);
+    // The speed comes from restic's status lines
+    assert!(stdout.contains("Speed: "), "{}", stdout);
+    assert!(sandbox.restic_calls().iter().any(|call| call.starts_with("backup ") && call.ends_with(" --json")));
+}
+
+#[test]
+fn unreadable_files_are_warnings() {
+    let sandbox = Sandbox::new("incomplete");
+    let output = sandbox.run(&["backup", &sandbox.data()], &[("FAKE_RESTIC_OUTPUT_BACKUP", "backup-unreadable"), ("FAKE_RESTIC_EXIT_BACKUP", "3")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Snapshot 7a8b9c0d"), "{}", stdout);
+    assert!(stdout.contains("Warning: /srv/data/private.key: open /srv/data/private.key: permission denied"), "{}", stdout);
+}
+
+#[test]
+fn fatal_errors_fail_the_backup() {
+    let sandbox = Sandbox::new("fatal");
+    let output = sandbox.run(
+        &["backup", &sandbox.data()],
+        &[
+            ("FAKE_RESTIC_OUTPUT_BACKUP", "empty"),
+            ("FAKE_RESTIC_EXIT_BACKUP", "1"),
+            ("FAKE_RESTIC_STDERR_BACKUP", "Fatal: wrong password or no key found"),
+        ],
+    );
+    assert!(!output.status.success());
+    let stderr = stderr(&output);
+    assert!(stderr.contains("Restic backup failed (exit code: Some(1)): Fatal: wrong password or no key found"), "{}", stderr);
+    assert!(stderr.contains("PASSWORD ERROR"), "{}", stderr);
+}
+
+#[test]
+fn maintain_forgets_prunes_and_checks() {
+    let sandbox = Sandbox::new("maintain");
+    let output = sandbox.run(&["maintain", "--yes"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Repository maintenance succeeded"), "{}", stdout(&output));
+    let subcommands: Vec<String> = sandbox.restic_calls().iter().filter_map(|call| call.split(' ').next().map(String::from)).collect();
+    assert_eq!(subcommands, ["unlock", "forget", "prune", "check"]);
+    assert!(sandbox.restic_calls()[1].contains("--keep-last 2"));
+}
+
+#[test]
+fn failed_check_fails_maintenance() {
+    let sandbox = Sandbox::new("check");
+    let output = sandbox.run(
+        &["maintain", "--yes"],
+        &[("FAKE_RESTIC_EXIT_CHECK", "1"), ("FAKE_RESTIC_STDERR_CHECK", "Fatal: pack 0123abcd: not referenced in any index")],
+    );
+    assert!(!output.status.success());
+    let stdout = stdout(&output);
+    assert!(stdout.contains("Repository maintenance FAILED"), "{}", stdout);
+    assert!(stdout.contains("check   FAILED"), "{}", stdout);
+    assert!(stdout.contains("not referenced in any index"), "{}", stdout);
+}
+
+#[test]
+fn bootstrap_creates_a_missing_repository() {
+    let sandbox = Sandbox::new("bootstrap");
+    let output = sandbox.run(
+        &["bootstrap"],
+        &[
+            ("FAKE_RESTIC_OUTPUT_CAT", "empty"),
+            ("FAKE_RESTIC_EXIT_CAT", "10"),
+            ("FAKE_RESTIC_STDERR_CAT", "Fatal: repository does not exist: unable to open config file"),
+        ],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = stdout(&output);
+    assert!(stdout.contains("restic 0.17.3"), "{}", stdout);
+    assert!(stdout.contains("created the repository"), "{}", stdout);
+    assert!(stdout.contains("3 in the repository"), "{}", stdout);
+    assert!(sandbox.restic_calls().iter().any(|call| call.starts_with("init ")));
+}
+
+#[test]
+fn hooks_run_in_order_with_the_run_on_stdin() {
+    let sandbox = Sandbox::new("hooks");
+    sandbox.write_hook("pre", "20-second", "echo pre-20 >> hooks.log");
+    sandbox.write_hook("pre", "10-first", "echo pre-10 >> hooks.log");
+    sandbox.write_hook("post", "zabbix", "cat > post.json");
+    sandbox.write_hook("failure", "alert", "echo failure >> hooks.log");
+    let output = sandbox.run(&["backup", &sandbox.data()], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(sandbox.read("hooks.log"), "pre-10\npre-20\n");
+    let context: serde_json::Value = serde_json::from_str(&sandbox.read("post.json")).unwrap();
+    assert_eq!(context["hook"], "post");
+    assert_eq!(context["status"], "succeeded");
+    assert_eq!(context["summary"]["snapshot_id"].as_str().map(|id| &id[..8]), Some("3c0f1a2b"));
+}
+
+#[test]
+fn failing_pre_hook_fails_the_run() {
+    let sandbox = Sandbox::new("pre-hook");
+    sandbox.write_hook("pre", "check", "echo not today >&2; exit 1");
+    sandbox.write_hook("failure", "alert", "cat > failure.json");
+    let output = sandbox.run(&["backup", &sandbox.data()], &[]);
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("not today"), "{}", stderr(&output));
+    assert!(!sandbox.restic_calls().iter().any(|call| call.starts_with("backup ")));
+    let context: serde_json::Value = serde_json::from_str(&sandbox.read("failure.json")).unwrap();
+    assert_eq!(context["status"], "failed");
+}
+
+#[test]
+fn script_adds_excludes_and_tags_or_skips_the_run() {
+    let sandbox = Sandbox::new("script");
+    std::fs::write(
+        sandbox.directory.join("policy.rhai"),
+        r#"
+        fn skip(run) {
+            if env("SKIP_BACKUP") != () { return "asked to skip"; }
+        }
+        fn prepare(run) {
+            #{ exclude: ["*.iso"], tags: [run.job + "-scripted"] }
+        }
+        "#,
+    )
+    .unwrap();
+    let script = format!("backup.script={}", sandbox.directory.join("policy.rhai").display());
+    let output = sandbox.run(&["--set", &script], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let calls = sandbox.restic_calls();
+    let backup = calls.iter().find(|call| call.starts_with("backup ")).expect("a backup ran");
+    assert!(backup.contains("--exclude *.iso") && backup.contains("--tag backup-scripted"), "{}", backup);
+
+    std::fs::remove_file(sandbox.directory.join("restic.log")).unwrap();
+    let output = sandbox.run(&["--set", &script], &[("SKIP_BACKUP", "1")]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!sandbox.restic_calls().iter().any(|call| call.starts_with("backup ")));
+}
+
+#[test]
+fn restore_test_compares_the_restore_with_the_manifest() {
+    let sandbox = Sandbox::new("restore-test");
+    let reports = format!("reports.directory={}", sandbox.directory.join("reports").display());
+    let settings = ["--set", "backup.manifest=true", "--set", &reports];
+    let with = |args: &[&'static str]| -> Vec<&str> { args.iter().copied().chain(settings.iter().copied()).collect() };
+    let output = sandbox.run(&with(&[]), &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let data = sandbox.data();
+    let restore_from = [("FAKE_RESTIC_RESTORE_FROM", data.as_str())];
+    let output = sandbox.run(&with(&["verify", "--restore-test"]), &restore_from);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("against a test restore: 1 matching, 0 modified, 0 missing"), "{}", stdout(&output));
+    assert!(sandbox.restic_calls().iter().any(|call| call.starts_with("restore ") && call.contains(" 3c0f1a2b")));
+
+    // The fake restores the files as they are now, so a changed file stands in for a bad restore
+    std::fs::write(sandbox.directory.join("data/file.txt"), "corrupt\n").unwrap();
+    let output = sandbox.run(&with(&["verify", "--restore-test"]), &restore_from);
+    assert!(!output.status.success());
+    let expected = format!("RESTORED COPY DIFFERS: {}/file.txt", data);
+    assert!(stdout(&output).contains(&expected), "{}", stdout(&output));
+    assert!(stderr(&output).contains("1 file(s) weren't restored as they were backed up"), "{}", stderr(&output));
+    // The restored copy is removed again
+    let staging = sandbox.directory.join("logs/staging");
+    assert_eq!(std::fs::read_dir(staging).map(|entries| entries.count()).unwrap_or(0), 0);
+}